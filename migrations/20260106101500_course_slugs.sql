@@ -0,0 +1,43 @@
+-- Slugs para URLs amigables de cursos
+ALTER TABLE courses ADD COLUMN IF NOT EXISTS slug TEXT;
+
+-- Rellenar los cursos existentes a partir del título
+WITH base AS (
+    SELECT
+        id,
+        COALESCE(
+            NULLIF(
+                trim(BOTH '-' FROM regexp_replace(
+                    translate(lower(title), 'áàäâãéèëêíìïîóòöôõúùüûñç', 'aaaaaeeeeiiiiooooouuuunc'),
+                    '[^a-z0-9]+', '-', 'g'
+                )),
+                ''
+            ),
+            'curso'
+        ) AS slug,
+        created_at
+    FROM courses
+),
+numbered AS (
+    SELECT
+        id,
+        slug,
+        ROW_NUMBER() OVER (PARTITION BY slug ORDER BY created_at, id) AS n
+    FROM base
+)
+UPDATE courses c
+SET slug = CASE WHEN numbered.n = 1 THEN numbered.slug ELSE numbered.slug || '-' || numbered.n END
+FROM numbered
+WHERE c.id = numbered.id;
+
+ALTER TABLE courses ALTER COLUMN slug SET NOT NULL;
+ALTER TABLE courses ADD CONSTRAINT courses_slug_unique UNIQUE (slug);
+
+-- Slugs anteriores de un curso (redirecciones tras cambiar el título)
+CREATE TABLE IF NOT EXISTS course_slug_history (
+    slug TEXT PRIMARY KEY,
+    course_id UUID NOT NULL REFERENCES courses(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_course_slug_history_course_id ON course_slug_history(course_id);
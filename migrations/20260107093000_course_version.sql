@@ -0,0 +1,2 @@
+-- Control de concurrencia optimista para la edición de cursos
+ALTER TABLE courses ADD COLUMN IF NOT EXISTS version INTEGER NOT NULL DEFAULT 1;
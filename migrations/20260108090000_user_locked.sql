@@ -0,0 +1,2 @@
+-- Bloqueo administrativo de cuentas
+ALTER TABLE users ADD COLUMN IF NOT EXISTS locked BOOLEAN NOT NULL DEFAULT FALSE;
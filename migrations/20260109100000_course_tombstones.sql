@@ -0,0 +1,8 @@
+-- Registro de cursos eliminados para la sincronización incremental de clientes
+CREATE TABLE IF NOT EXISTS course_tombstones (
+    course_id UUID PRIMARY KEY,
+    deleted_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_course_tombstones_deleted_at ON course_tombstones (deleted_at);
+CREATE INDEX IF NOT EXISTS idx_courses_updated_at ON courses (updated_at);
@@ -0,0 +1,13 @@
+-- Lecciones de muestra gratuita visibles en la vista previa del curso
+ALTER TABLE lessons ADD COLUMN IF NOT EXISTS is_preview BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Conserva el comportamiento anterior: la primera lección de cada curso era la muestra
+UPDATE lessons l
+SET is_preview = TRUE
+FROM (
+    SELECT DISTINCT ON (m.course_id) l2.id
+    FROM lessons l2
+    JOIN modules m ON m.id = l2.module_id
+    ORDER BY m.course_id, m."order" ASC, l2."order" ASC
+) first_lessons
+WHERE l.id = first_lessons.id;
@@ -0,0 +1,15 @@
+-- Historial de cambios de plan de las suscripciones (auditoría)
+CREATE TABLE IF NOT EXISTS subscription_plan_changes (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    subscription_id UUID NOT NULL REFERENCES subscription(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    from_plan_id VARCHAR(255),
+    to_plan_id VARCHAR(255) NOT NULL,
+    -- 'applied' o 'pending_approval' (PayPal pidió aprobación del cliente)
+    status VARCHAR(32) NOT NULL,
+    approval_url TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    applied_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_subscription_plan_changes_subscription ON subscription_plan_changes (subscription_id, created_at);
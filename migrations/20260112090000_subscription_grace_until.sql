@@ -0,0 +1,2 @@
+-- Periodo de gracia tras un pago fallido: se mantiene el acceso hasta esta fecha
+ALTER TABLE subscription ADD COLUMN IF NOT EXISTS grace_until TIMESTAMPTZ;
@@ -0,0 +1,17 @@
+-- Seguimiento de aperturas y clics de los correos de campaña
+CREATE TABLE IF NOT EXISTS email_events (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    -- Identifica un correo enviado; se repite en sus eventos 'open' y 'click'
+    token UUID NOT NULL,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    campaign VARCHAR(255) NOT NULL,
+    event_type VARCHAR(16) NOT NULL, -- 'sent' | 'open' | 'click'
+    url TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_email_events_sent_token ON email_events (token) WHERE event_type = 'sent';
+CREATE INDEX IF NOT EXISTS idx_email_events_campaign ON email_events (campaign, event_type);
+
+-- Preferencia del usuario para no ser rastreado en los correos
+ALTER TABLE user_settings ADD COLUMN IF NOT EXISTS no_tracking BOOLEAN NOT NULL DEFAULT FALSE;
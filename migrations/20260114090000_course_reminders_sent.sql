@@ -0,0 +1,10 @@
+-- Recordatorios de curso enviados (evita duplicados y limita la frecuencia por usuario)
+CREATE TABLE IF NOT EXISTS course_reminders_sent (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    course_id UUID NOT NULL REFERENCES courses(id) ON DELETE CASCADE,
+    lesson_id UUID REFERENCES lessons(id) ON DELETE SET NULL,
+    sent_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_course_reminders_sent_user ON course_reminders_sent (user_id, sent_at);
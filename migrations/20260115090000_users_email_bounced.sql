@@ -0,0 +1,5 @@
+-- Correos que rebotan o reciben quejas: se suprimen los envíos no críticos
+ALTER TABLE users ADD COLUMN IF NOT EXISTS email_bounced_at TIMESTAMPTZ;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS email_bounce_reason TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_users_email_bounced_at ON users (email_bounced_at) WHERE email_bounced_at IS NOT NULL;
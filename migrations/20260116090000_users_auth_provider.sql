@@ -0,0 +1,4 @@
+-- Cuentas sin contraseña (SSO, invitaciones): la contraseña pasa a ser opcional
+ALTER TABLE users ALTER COLUMN password DROP NOT NULL;
+-- 'local' para cuentas con contraseña; el nombre del proveedor para las externas
+ALTER TABLE users ADD COLUMN IF NOT EXISTS auth_provider VARCHAR(32) NOT NULL DEFAULT 'local';
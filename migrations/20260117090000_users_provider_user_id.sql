@@ -0,0 +1,5 @@
+-- Identificador de la cuenta en el proveedor externo (p. ej. el `sub` de Google)
+ALTER TABLE users ADD COLUMN IF NOT EXISTS provider_user_id VARCHAR(255);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_users_provider_user_id
+    ON users (provider_user_id)
+    WHERE provider_user_id IS NOT NULL;
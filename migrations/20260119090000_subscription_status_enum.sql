@@ -0,0 +1,17 @@
+-- El estado de la suscripción pasa de booleano a los estados que distingue PayPal
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM pg_type WHERE typname = 'subscription_status') THEN
+        CREATE TYPE subscription_status AS ENUM ('pending', 'active', 'suspended', 'cancelled', 'expired');
+    END IF;
+END$$;
+
+ALTER TABLE subscription ALTER COLUMN status DROP DEFAULT;
+ALTER TABLE subscription ALTER COLUMN status TYPE subscription_status USING (
+    CASE
+        WHEN status THEN 'active'
+        WHEN end_time IS NOT NULL AND end_time <= NOW() THEN 'expired'
+        ELSE 'cancelled'
+    END
+)::subscription_status;
+ALTER TABLE subscription ALTER COLUMN status SET DEFAULT 'pending';
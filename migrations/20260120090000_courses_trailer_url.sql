@@ -0,0 +1,2 @@
+-- Vídeo de presentación del curso, visible también para quien no lo ha comprado
+ALTER TABLE courses ADD COLUMN IF NOT EXISTS trailer_url TEXT;
@@ -0,0 +1,6 @@
+-- Búsqueda difusa de usuarios para soporte (nombre, correo, teléfono)
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+-- La misma expresión se usa en `search_users`; si cambia, hay que cambiar ambas
+CREATE INDEX IF NOT EXISTS idx_users_search_trgm
+    ON users USING gin ((lower(name) || ' ' || lower(email) || ' ' || COALESCE(phone, '')) gin_trgm_ops);
@@ -0,0 +1,23 @@
+-- Reportes de contenido de los usuarios y su revisión por parte de los administradores
+CREATE TYPE report_status AS ENUM ('open', 'resolved', 'dismissed');
+
+CREATE TABLE IF NOT EXISTS content_reports (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    reporter_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    course_id UUID NOT NULL REFERENCES courses(id) ON DELETE CASCADE,
+    lesson_id UUID REFERENCES lessons(id) ON DELETE SET NULL,
+    reason VARCHAR(100) NOT NULL,
+    details TEXT,
+    status report_status NOT NULL DEFAULT 'open',
+    resolved_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    resolved_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Cola de revisión (por estado, los más recientes primero)
+CREATE INDEX IF NOT EXISTS idx_content_reports_status_created
+    ON content_reports (status, created_at DESC);
+
+-- Límite de envíos por usuario
+CREATE INDEX IF NOT EXISTS idx_content_reports_reporter_created
+    ON content_reports (reporter_id, created_at DESC);
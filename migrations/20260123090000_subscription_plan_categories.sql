@@ -0,0 +1,2 @@
+-- Categorías de cursos que desbloquea cada plan; NULL = todo el catálogo
+ALTER TABLE subscription_plans ADD COLUMN IF NOT EXISTS allowed_categories TEXT[];
@@ -0,0 +1,46 @@
+-- Las fechas de creación/actualización siempre existen: se rellenan los NULL
+-- heredados y se marcan NOT NULL para que la API no tenga que devolver null.
+UPDATE users SET created_at = NOW() WHERE created_at IS NULL;
+UPDATE users SET updated_at = created_at WHERE updated_at IS NULL;
+ALTER TABLE users ALTER COLUMN created_at SET NOT NULL, ALTER COLUMN updated_at SET NOT NULL;
+
+UPDATE user_settings SET created_at = NOW() WHERE created_at IS NULL;
+UPDATE user_settings SET updated_at = created_at WHERE updated_at IS NULL;
+ALTER TABLE user_settings ALTER COLUMN created_at SET NOT NULL, ALTER COLUMN updated_at SET NOT NULL;
+
+UPDATE courses SET created_at = NOW() WHERE created_at IS NULL;
+UPDATE courses SET updated_at = created_at WHERE updated_at IS NULL;
+ALTER TABLE courses ALTER COLUMN created_at SET NOT NULL, ALTER COLUMN updated_at SET NOT NULL;
+
+UPDATE user_courses SET created_at = NOW() WHERE created_at IS NULL;
+UPDATE user_courses SET updated_at = created_at WHERE updated_at IS NULL;
+UPDATE user_courses SET purchased_at = created_at WHERE purchased_at IS NULL;
+ALTER TABLE user_courses
+    ALTER COLUMN created_at SET NOT NULL,
+    ALTER COLUMN updated_at SET NOT NULL,
+    ALTER COLUMN purchased_at SET NOT NULL;
+
+UPDATE course_progress SET created_at = NOW() WHERE created_at IS NULL;
+UPDATE course_progress SET updated_at = created_at WHERE updated_at IS NULL;
+ALTER TABLE course_progress ALTER COLUMN created_at SET NOT NULL, ALTER COLUMN updated_at SET NOT NULL;
+
+UPDATE achievement SET created_at = NOW() WHERE created_at IS NULL;
+ALTER TABLE achievement ALTER COLUMN created_at SET NOT NULL;
+
+UPDATE notification SET sent_at = NOW() WHERE sent_at IS NULL;
+ALTER TABLE notification ALTER COLUMN sent_at SET NOT NULL;
+
+UPDATE subscription_plans SET created_at = NOW() WHERE created_at IS NULL;
+UPDATE subscription_plans SET updated_at = created_at WHERE updated_at IS NULL;
+ALTER TABLE subscription_plans ALTER COLUMN created_at SET NOT NULL, ALTER COLUMN updated_at SET NOT NULL;
+
+UPDATE subscription SET created_at = NOW() WHERE created_at IS NULL;
+UPDATE subscription SET updated_at = created_at WHERE updated_at IS NULL;
+ALTER TABLE subscription ALTER COLUMN created_at SET NOT NULL, ALTER COLUMN updated_at SET NOT NULL;
+
+UPDATE payments SET created_at = NOW() WHERE created_at IS NULL;
+UPDATE payments SET updated_at = created_at WHERE updated_at IS NULL;
+ALTER TABLE payments ALTER COLUMN created_at SET NOT NULL, ALTER COLUMN updated_at SET NOT NULL;
+
+UPDATE password_reset_tokens SET created_at = NOW() WHERE created_at IS NULL;
+ALTER TABLE password_reset_tokens ALTER COLUMN created_at SET NOT NULL;
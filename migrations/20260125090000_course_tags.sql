@@ -0,0 +1,16 @@
+-- Etiquetas libres de los cursos (además de la categoría única)
+CREATE TABLE IF NOT EXISTS tags (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    -- Normalizada en kebab-case ("rust", "nivel-basico")
+    name VARCHAR(40) NOT NULL UNIQUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS course_tags (
+    course_id UUID NOT NULL REFERENCES courses(id) ON DELETE CASCADE,
+    tag_id UUID NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+    PRIMARY KEY (course_id, tag_id)
+);
+
+-- Filtro del catálogo por etiqueta
+CREATE INDEX IF NOT EXISTS idx_course_tags_tag ON course_tags (tag_id);
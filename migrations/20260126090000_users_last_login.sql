@@ -0,0 +1,5 @@
+-- Último inicio de sesión (contraseña o Google); NULL si nunca ha iniciado sesión
+ALTER TABLE users ADD COLUMN IF NOT EXISTS last_login_at TIMESTAMPTZ;
+
+-- Reporte de usuarios inactivos
+CREATE INDEX IF NOT EXISTS idx_users_last_login_at ON users (last_login_at);
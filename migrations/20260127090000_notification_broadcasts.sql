@@ -0,0 +1,25 @@
+-- Avisos masivos de los administradores a un segmento de usuarios
+CREATE TYPE broadcast_segment AS ENUM ('all', 'course_students', 'premium');
+CREATE TYPE broadcast_status AS ENUM ('running', 'completed', 'failed');
+
+CREATE TABLE IF NOT EXISTS notification_broadcasts (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    created_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    segment broadcast_segment NOT NULL,
+    course_id UUID REFERENCES courses(id) ON DELETE SET NULL,
+    title VARCHAR(255) NOT NULL,
+    message TEXT NOT NULL,
+    send_email BOOLEAN NOT NULL DEFAULT FALSE,
+    status broadcast_status NOT NULL DEFAULT 'running',
+    -- Notificaciones in-app creadas
+    recipients INTEGER NOT NULL DEFAULT 0,
+    -- Progreso del envío de correos (solo a quienes aceptan notificaciones por correo)
+    emails_total INTEGER NOT NULL DEFAULT 0,
+    emails_sent INTEGER NOT NULL DEFAULT 0,
+    emails_failed INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_notification_broadcasts_created
+    ON notification_broadcasts (created_at DESC);
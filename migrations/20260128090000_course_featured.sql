@@ -0,0 +1,8 @@
+-- Cursos destacados en la portada, en el orden que elija marketing
+ALTER TABLE courses
+    ADD COLUMN IF NOT EXISTS featured BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN IF NOT EXISTS featured_order INTEGER;
+
+CREATE INDEX IF NOT EXISTS idx_courses_featured_order
+    ON courses (featured_order)
+    WHERE featured;
@@ -0,0 +1,3 @@
+-- Cupo opcional para cursos por cohortes; NULL = sin límite
+ALTER TABLE courses
+    ADD COLUMN IF NOT EXISTS max_students INTEGER CHECK (max_students > 0);
@@ -0,0 +1,5 @@
+-- `amount` se guarda en unidades menores (centavos) junto a su moneda ISO 4217
+ALTER TABLE payments
+    ADD COLUMN IF NOT EXISTS currency VARCHAR(3) NOT NULL DEFAULT 'USD';
+
+COMMENT ON COLUMN payments.amount IS 'Monto en unidades menores de `currency` (p. ej. centavos)';
@@ -0,0 +1,12 @@
+-- Historial de ediciones de cursos: copia del contenido previo a cada actualización
+CREATE TABLE IF NOT EXISTS course_revisions (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    course_id UUID NOT NULL REFERENCES courses(id) ON DELETE CASCADE,
+    -- Correlativo por curso
+    revision INTEGER NOT NULL,
+    -- Curso, módulos y lecciones con la forma de `UpdateCourseDTO` (sin `version`)
+    snapshot JSONB NOT NULL,
+    edited_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (course_id, revision)
+);
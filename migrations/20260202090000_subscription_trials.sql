@@ -0,0 +1,3 @@
+-- Días de prueba gratis por plan (0: sin prueba) y marca de prueba ya usada por usuario
+ALTER TABLE subscription_plans ADD COLUMN IF NOT EXISTS trial_days INTEGER NOT NULL DEFAULT 0 CHECK (trial_days >= 0);
+ALTER TABLE users ADD COLUMN IF NOT EXISTS trial_used_at TIMESTAMPTZ;
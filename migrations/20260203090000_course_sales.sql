@@ -0,0 +1,22 @@
+-- Ofertas con fecha: `sale_price` rige entre `sale_starts_at` (incluido) y `sale_ends_at` (excluido)
+ALTER TABLE courses
+    ADD COLUMN IF NOT EXISTS sale_price DOUBLE PRECISION CHECK (sale_price >= 0),
+    ADD COLUMN IF NOT EXISTS sale_starts_at TIMESTAMPTZ,
+    ADD COLUMN IF NOT EXISTS sale_ends_at TIMESTAMPTZ;
+
+-- Precio a cobrar ahora; debe coincidir con `Course::effective_price`
+CREATE OR REPLACE FUNCTION course_effective_price(
+    price DOUBLE PRECISION,
+    sale_price DOUBLE PRECISION,
+    sale_starts_at TIMESTAMPTZ,
+    sale_ends_at TIMESTAMPTZ
+) RETURNS DOUBLE PRECISION
+LANGUAGE SQL STABLE AS $$
+    SELECT CASE
+        WHEN sale_price IS NOT NULL
+            AND (sale_starts_at IS NULL OR sale_starts_at <= NOW())
+            AND (sale_ends_at IS NULL OR NOW() < sale_ends_at)
+        THEN sale_price
+        ELSE price
+    END
+$$;
@@ -0,0 +1,12 @@
+-- Textos del curso en otros idiomas; el curso base queda en el idioma por defecto (`DEFAULT_LOCALE`)
+CREATE TABLE IF NOT EXISTS course_translations (
+    course_id UUID NOT NULL REFERENCES courses(id) ON DELETE CASCADE,
+    -- Etiqueta BCP 47 en minúsculas, p. ej. "en" o "pt-br"
+    locale VARCHAR(35) NOT NULL,
+    title VARCHAR(255) NOT NULL,
+    description TEXT NOT NULL,
+    long_description TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (course_id, locale)
+);
@@ -0,0 +1,10 @@
+-- Archivos subidos por los usuarios (avatares, adjuntos) para contabilizar su cuota
+CREATE TABLE IF NOT EXISTS user_uploads (
+    storage_key TEXT PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    size_bytes BIGINT NOT NULL CHECK (size_bytes >= 0),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_user_uploads_user_id ON user_uploads (user_id);
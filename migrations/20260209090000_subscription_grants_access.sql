@@ -0,0 +1,14 @@
+-- Única definición de "suscripción vigente": activa y pagada, o en el periodo de gracia
+-- tras un pago fallido. Canceladas y caducadas no dan acceso aunque `end_time` no haya pasado.
+CREATE OR REPLACE FUNCTION subscription_grants_access(
+    status subscription_status,
+    end_time TIMESTAMPTZ,
+    grace_until TIMESTAMPTZ
+) RETURNS BOOLEAN
+LANGUAGE SQL STABLE AS $$
+    SELECT COALESCE(
+        (status = 'active' AND end_time > NOW())
+        OR (status IN ('active', 'suspended') AND grace_until > NOW()),
+        false
+    )
+$$;
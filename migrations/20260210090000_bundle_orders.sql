@@ -0,0 +1,9 @@
+-- Cursos que se cobran en cada orden de PayPal de un paquete, fijados al crearla: la
+-- captura inscribe exactamente estos aunque el comprador haya comprado otro entre medias
+CREATE TABLE IF NOT EXISTS bundle_orders (
+    order_id VARCHAR(255) PRIMARY KEY,
+    bundle_id UUID NOT NULL REFERENCES bundles(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    course_ids UUID[] NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
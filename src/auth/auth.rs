@@ -1,6 +1,5 @@
 use jsonwebtoken::{decode, Validation, DecodingKey};
 use serde::{Serialize, Deserialize};
-use std::fs;
 use jsonwebtoken::Algorithm::RS256;
 use chrono::Utc;
 
@@ -21,12 +20,12 @@ pub fn is_premium(claims: &TokenClaims) -> bool {
     }
 }
 
-/// Verificar y decodificar Token JWT
-pub fn verify_jwt(token: &str) -> Option<String> {
-    let public_key_pem = fs::read("public.pem").expect("Error leyendo public_key.pem");
+/// Verificar y decodificar Token JWT con la clave pública ya cargada por `Config::init()`
+/// (`AppState.env.decoding_key`), sin leer `public.pem` en cada petición.
+pub fn verify_jwt(token: &str, decoding_key: &DecodingKey) -> Option<String> {
     match decode::<Claims>(
         token,
-        &DecodingKey::from_rsa_pem(&public_key_pem).expect("Clave pública inválida"),
+        decoding_key,
         &Validation::new(RS256),
     ) {
         Ok(data) => Some(data.claims.sub),
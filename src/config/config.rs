@@ -1,23 +1,145 @@
 use std::{env, fs};
+use actix_web::cookie::SameSite;
 use jsonwebtoken::{EncodingKey, DecodingKey};
 
+use crate::utils::redact::DEFAULT_REDACT_FIELDS;
+use crate::utils::redirect::url_origin;
+
+/// Prefijo de la versión actual de la API. Una `v2` se monta en su propio prefijo
+/// junto a este, reutilizando los scopes que no cambian, y `v1` sigue sirviendo a
+/// los clientes existentes hasta que se retire.
+pub const API_PREFIX: &str = "/api/v1";
+/// Autenticación, versionada junto al resto de la API.
+pub const AUTH_PREFIX: &str = "/api/v1/auth";
+/// Prefijos anteriores, sin versión: alias de `v1` mientras migran los clientes.
+pub const LEGACY_API_PREFIX: &str = "/api";
+pub const LEGACY_AUTH_PREFIX: &str = "/auth";
+
+/// Ruta absoluta dentro de la versión actual, p. ej. `api_path("/courses")`.
+pub fn api_path(path: &str) -> String {
+    format!("{}{}", API_PREFIX, path)
+}
+
+/// Ruta absoluta de autenticación, p. ej. `auth_path("/verify")`.
+pub fn auth_path(path: &str) -> String {
+    format!("{}{}", AUTH_PREFIX, path)
+}
+
 // FIXME: construir config
 #[derive(Debug, Clone)]
 pub struct Config {
     pub database_url: String,
     pub paypal_api_mode: String,
+    // Vida del JWT y de la cookie de sesión, en segundos
     pub jwt_maxage: i64,
+    // Vida del refresh token y de su cookie, en segundos
+    pub refresh_token_maxage: i64,
     pub private_key: Vec<u8>,
     pub public_key: Vec<u8>,
     pub encoding_key: EncodingKey,
     pub decoding_key: DecodingKey,
     pub paypal_client_id: String,
     pub paypal_secret: String,
+    // Origen público del sitio (URLs de retorno de PayPal, `home_url` de productos), siempre con esquema
     pub host: String,
     pub port: u16,
     pub paypal_webhook_id: String,
+    // Moneda ISO 4217 de las órdenes de PayPal de cursos y paquetes
+    pub paypal_currency: String,
+    pub request_timeout_secs: u64,
+    pub app_env: String,
+    // Base pública del frontend (enlaces de restablecimiento de contraseña)
+    pub frontend_base_url: String,
+    // Base pública de esta API (enlaces de verificación de correo)
+    pub api_base_url: String,
+    // Página del frontend a la que se redirige tras verificar el correo
+    pub verify_redirect_url: String,
+    // Orígenes a los que se puede redirigir cuando el destino viene en la petición
+    // (`?redirect=`); siempre incluye el del frontend y el de `verify_redirect_url`
+    pub allowed_redirect_origins: Vec<String>,
+    // Permite bajar a un plan más barato (PayPal puede generar reembolsos prorrateados)
+    pub allow_subscription_downgrades: bool,
+    // Días que se mantiene el acceso tras un pago fallido de la suscripción
+    pub subscription_grace_days: i64,
+    // Conexiones HTTP: keep-alive en segundos (0 = desactivado, por defecto 75),
+    // tiempo para recibir las cabeceras (por defecto 5000 ms) y para cerrar la conexión (por defecto 1000 ms)
+    pub keep_alive_secs: u64,
+    pub client_request_timeout_ms: u64,
+    pub client_disconnect_timeout_ms: u64,
+    // Sirve con TLS (key.pem/cert.pem) y habilita HTTP/2 vía ALPN
+    pub tls_enabled: bool,
+    // Recordatorios de curso: días sin actividad para avisar y días mínimos entre avisos
+    pub reminder_inactive_days: i64,
+    pub reminder_interval_days: i64,
+    // Secreto compartido con el proveedor de correo para firmar los webhooks de rebotes
+    pub bounce_webhook_secret: String,
+    // Credenciales OAuth de Google para el inicio de sesión con Google
+    pub google_client_id: String,
+    pub google_client_secret: String,
+    pub google_redirect_url: String,
+    // Horas de validez de las invitaciones de administradores
+    pub invite_expiry_hours: i64,
+    // Minutos de validez de los enlaces para restablecer la contraseña
+    pub password_reset_expiry_minutes: i64,
+    // Campos que se enmascaran en los logs (lista separada por comas)
+    pub log_redact_fields: Vec<String>,
+    // Cabeceras de seguridad; una cadena vacía desactiva la cabecera
+    pub content_security_policy: String,
+    pub frame_options: String,
+    pub referrer_policy: String,
+    // Solo se envía `Strict-Transport-Security` con TLS activo; 0 la desactiva
+    pub hsts_max_age_secs: u64,
+    // Una suscripción activa da acceso a todos los cursos, no solo a los comprados
+    pub premium_grants_all_courses: bool,
+    // Valoración que se muestra mientras un curso no tiene ninguna (0-5)
+    pub default_course_rating: i32,
+    pub default_course_students: i32,
+    // Idioma del contenido base de los cursos; las traducciones se sirven encima
+    pub default_locale: String,
+    // Reportes de contenido que un usuario puede enviar por hora
+    pub report_rate_limit_per_hour: i64,
+    // Restablecimientos de contraseña que los administradores pueden pedir por usuario y hora
+    pub admin_password_reset_limit_per_hour: i64,
+    // Revisiones que se conservan por curso (0 = todas)
+    pub course_revision_limit: i64,
+    // Segundos que se reutilizan los interruptores de funcionalidades antes de releerlos
+    pub feature_flag_cache_secs: u64,
+    // Atributos de las cookies; en desarrollo sin TLS: `Secure=false` y `SameSite=Lax`
+    pub cookie_secure: bool,
+    pub cookie_same_site: SameSite,
+    // CORS: listas separadas por comas y caché del preflight en segundos
+    pub cors_allowed_methods: String,
+    pub cors_allowed_headers: String,
+    pub cors_max_age_secs: usize,
+    // Archivos subidos: disco local (servido bajo `media_path`) u objeto en S3
+    pub storage_backend: StorageBackend,
+    pub media_path: String,
+    pub media_dir: String,
+    pub media_cache_max_age_secs: u64,
+    // Espacio máximo que pueden ocupar los archivos de cada usuario (0 = sin límite)
+    pub user_storage_quota_bytes: i64,
+    // S3 o compatible; sin `s3_endpoint` se usa el de AWS de la región y sin
+    // `s3_public_url` las URLs públicas apuntan al propio bucket
+    pub s3_bucket: String,
+    pub s3_region: String,
+    pub s3_endpoint: String,
+    pub s3_access_key_id: String,
+    pub s3_secret_access_key: String,
+    pub s3_public_url: String,
+}
+
+/// Dónde se guardan los archivos subidos (avatares, imágenes de cursos, íconos de logros).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StorageBackend {
+    Local,
+    S3,
 }
 
+/// Métodos que usan las rutas de la API (el preflight `OPTIONS` siempre se acepta).
+pub const DEFAULT_CORS_METHODS: &str = "GET,POST,PUT,DELETE";
+/// Cabeceras que envía el frontend: token, cuerpo JSON e id de request para trazas.
+pub const DEFAULT_CORS_HEADERS: &str = "authorization,content-type,accept,x-request-id";
+
 // FIXME: usar init
 impl Config {
 
@@ -25,6 +147,7 @@ impl Config {
         let database_url = env::var("DATABASE_URL").expect("DATABASE_URL no está seteada");
         let paypal_api_mode = env::var("PAYPAL_API_MODE").unwrap_or("https://api-m.sandbox.paypal.com".to_string());
         let jwt_maxage = env::var("JWT_MAXAGE").unwrap_or("3600".to_string()).parse().unwrap_or(3600);
+        let refresh_token_maxage = env::var("REFRESH_TOKEN_MAXAGE").unwrap_or("2592000".to_string()).parse().unwrap_or(2592000).max(1);
         let private_key = fs::read("private.pem").expect("No se pudo leer private.pem");
         let public_key = fs::read("public.pem").expect("No se pudo leer public.pem");
         let encoding_key = EncodingKey::from_rsa_pem(&private_key).expect("Error al construir Encodingkey");
@@ -32,12 +155,98 @@ impl Config {
         let paypal_client_id = env::var("PAYPAL_API_CLIENT_ID").expect("PAYPAL_API_CLIENT_ID no definido");
         let paypal_secret = env::var("PAYPAL_API_SECRET").expect("PAYPAL_API_SECRET no definido");
         let paypal_webhook_id = env::var("PAYPAL_WEBHOOK_ID").expect("PAYPAL_WEBHOOK_ID no definido");
-        let host = env::var("HOST").unwrap_or("localhost".to_string());
+        let paypal_currency = env::var("PAYPAL_CURRENCY").unwrap_or("USD".to_string()).trim().to_uppercase();
+        let request_timeout_secs = env::var("REQUEST_TIMEOUT_SECS").unwrap_or("30".to_string()).parse().unwrap_or(30);
+        let app_env = env::var("APP_ENV").unwrap_or("development".to_string());
+        let is_development = app_env == "development";
+        let host = normalize_host(&env::var("HOST").unwrap_or("localhost".to_string()), is_development)
+            .expect("HOST inválido");
+        let frontend_base_url = validate_base_url(
+            "FRONTEND_BASE_URL",
+            &env::var("FRONTEND_BASE_URL").unwrap_or("http://localhost:8080".to_string()),
+            is_development,
+        ).expect("FRONTEND_BASE_URL inválida");
+        let api_base_url = validate_base_url(
+            "API_BASE_URL",
+            &env::var("API_BASE_URL").unwrap_or("http://localhost:8000".to_string()),
+            is_development,
+        ).expect("API_BASE_URL inválida");
+        let verify_redirect_url = validate_base_url(
+            "VERIFY_REDIRECT_URL",
+            &env::var("VERIFY_REDIRECT_URL").unwrap_or(format!("{}/login", frontend_base_url)),
+            is_development,
+        ).expect("VERIFY_REDIRECT_URL inválida");
+        let allowed_redirect_origins = parse_redirect_origins(
+            &env::var("ALLOWED_REDIRECT_ORIGINS").unwrap_or_default(),
+            &[&frontend_base_url, &verify_redirect_url],
+            is_development,
+        ).expect("ALLOWED_REDIRECT_ORIGINS inválida");
+        let allow_subscription_downgrades = env::var("ALLOW_SUBSCRIPTION_DOWNGRADES").unwrap_or("false".to_string()).parse().unwrap_or(false);
+        let subscription_grace_days = env::var("SUBSCRIPTION_GRACE_DAYS").unwrap_or("7".to_string()).parse().unwrap_or(7);
+        let keep_alive_secs = env::var("KEEP_ALIVE_SECS").unwrap_or("75".to_string()).parse().expect("KEEP_ALIVE_SECS inválido");
+        let client_request_timeout_ms = env::var("CLIENT_REQUEST_TIMEOUT_MS").unwrap_or("5000".to_string()).parse().expect("CLIENT_REQUEST_TIMEOUT_MS inválido");
+        let client_disconnect_timeout_ms = env::var("CLIENT_DISCONNECT_TIMEOUT_MS").unwrap_or("1000".to_string()).parse().expect("CLIENT_DISCONNECT_TIMEOUT_MS inválido");
+        validate_connection_timeouts(keep_alive_secs, client_request_timeout_ms, client_disconnect_timeout_ms)
+            .expect("Configuración de conexiones inválida");
+        let tls_enabled = env::var("TLS_ENABLED").unwrap_or("false".to_string()).parse().unwrap_or(false);
+        let reminder_inactive_days = env::var("REMINDER_INACTIVE_DAYS").unwrap_or("7".to_string()).parse().unwrap_or(7);
+        let reminder_interval_days = env::var("REMINDER_INTERVAL_DAYS").unwrap_or("7".to_string()).parse().unwrap_or(7);
+        let bounce_webhook_secret = env::var("BOUNCE_WEBHOOK_SECRET").unwrap_or_default();
+        let google_client_id = env::var("GOOGLE_CLIENT_ID").unwrap_or_default();
+        let google_client_secret = env::var("GOOGLE_CLIENT_SECRET").unwrap_or_default();
+        let google_redirect_url = validate_base_url(
+            "GOOGLE_REDIRECT_URL",
+            &env::var("GOOGLE_REDIRECT_URL").unwrap_or(format!("{}{}", api_base_url, auth_path("/google/callback"))),
+            is_development,
+        ).expect("GOOGLE_REDIRECT_URL inválida");
+        let invite_expiry_hours = env::var("INVITE_EXPIRY_HOURS").unwrap_or("72".to_string()).parse().unwrap_or(72);
+        let password_reset_expiry_minutes = env::var("PASSWORD_RESET_EXPIRY_MINUTES").unwrap_or("30".to_string()).parse().unwrap_or(30).max(1);
+        let log_redact_fields = env::var("LOG_REDACT_FIELDS")
+            .map(|v| v.split(',').map(|f| f.trim().to_string()).filter(|f| !f.is_empty()).collect())
+            .unwrap_or_else(|_| DEFAULT_REDACT_FIELDS.iter().map(|f| f.to_string()).collect());
+        let content_security_policy = env::var("CONTENT_SECURITY_POLICY").unwrap_or("default-src 'none'; frame-ancestors 'none'".to_string());
+        let frame_options = env::var("X_FRAME_OPTIONS").unwrap_or("DENY".to_string());
+        let referrer_policy = env::var("REFERRER_POLICY").unwrap_or("no-referrer".to_string());
+        let hsts_max_age_secs = env::var("HSTS_MAX_AGE_SECS").unwrap_or("31536000".to_string()).parse().unwrap_or(31536000);
+        let premium_grants_all_courses = env::var("PREMIUM_GRANTS_ALL_COURSES").unwrap_or("true".to_string()).parse().unwrap_or(true);
+        let default_course_rating = env::var("DEFAULT_COURSE_RATING").unwrap_or("0".to_string()).parse().unwrap_or(0).clamp(0, 5);
+        let default_course_students = env::var("DEFAULT_COURSE_STUDENTS").unwrap_or("0".to_string()).parse().unwrap_or(0).max(0);
+        let default_locale = env::var("DEFAULT_LOCALE").ok()
+            .and_then(|l| crate::utils::locale::normalize_locale(&l))
+            .unwrap_or("es".to_string());
+        let report_rate_limit_per_hour = env::var("REPORT_RATE_LIMIT_PER_HOUR").unwrap_or("5".to_string()).parse().unwrap_or(5);
+        let admin_password_reset_limit_per_hour = env::var("ADMIN_PASSWORD_RESET_LIMIT_PER_HOUR").unwrap_or("3".to_string()).parse().unwrap_or(3);
+        let feature_flag_cache_secs = env::var("FEATURE_FLAG_CACHE_SECS").unwrap_or("30".to_string()).parse().unwrap_or(30);
+        let course_revision_limit = env::var("COURSE_REVISION_LIMIT").unwrap_or("20".to_string()).parse().unwrap_or(20).max(0);
+        let (cookie_secure, cookie_same_site) = parse_cookie_policy(
+            &env::var("COOKIE_SECURE").unwrap_or("true".to_string()),
+            &env::var("COOKIE_SAMESITE").unwrap_or("none".to_string()),
+        ).expect("Configuración de cookies inválida");
+        let cors_allowed_methods = env::var("CORS_ALLOWED_METHODS").unwrap_or(DEFAULT_CORS_METHODS.to_string());
+        let cors_allowed_headers = env::var("CORS_ALLOWED_HEADERS").unwrap_or(DEFAULT_CORS_HEADERS.to_string());
+        let cors_max_age_secs = env::var("CORS_MAX_AGE_SECS").unwrap_or("3600".to_string()).parse().unwrap_or(3600);
+        let storage_backend = parse_storage_backend(&env::var("STORAGE_BACKEND").unwrap_or("local".to_string()))
+            .expect("STORAGE_BACKEND inválido");
+        let media_path = validate_media_path(&env::var("MEDIA_PATH").unwrap_or("/media".to_string()))
+            .expect("MEDIA_PATH inválido");
+        let media_dir = env::var("MEDIA_DIR").unwrap_or("media".to_string());
+        let media_cache_max_age_secs = env::var("MEDIA_CACHE_MAX_AGE_SECS").unwrap_or("86400".to_string()).parse().unwrap_or(86400);
+        let user_storage_quota_bytes = env::var("USER_STORAGE_QUOTA_MB").unwrap_or("100".to_string()).parse::<i64>().unwrap_or(100).max(0) * 1024 * 1024;
+        let s3_bucket = env::var("S3_BUCKET").unwrap_or_default();
+        let s3_region = env::var("S3_REGION").unwrap_or("us-east-1".to_string());
+        let s3_endpoint = env::var("S3_ENDPOINT").unwrap_or_default();
+        let s3_access_key_id = env::var("S3_ACCESS_KEY_ID").unwrap_or_default();
+        let s3_secret_access_key = env::var("S3_SECRET_ACCESS_KEY").unwrap_or_default();
+        let s3_public_url = env::var("S3_PUBLIC_URL").unwrap_or_default();
+        if storage_backend == StorageBackend::S3 && (s3_bucket.is_empty() || s3_access_key_id.is_empty() || s3_secret_access_key.is_empty()) {
+            panic!("STORAGE_BACKEND=s3 requiere S3_BUCKET, S3_ACCESS_KEY_ID y S3_SECRET_ACCESS_KEY");
+        }
 
         Config {
             database_url,
             paypal_api_mode,
             jwt_maxage,
+            refresh_token_maxage,
             private_key,
             public_key,
             encoding_key,
@@ -47,6 +256,150 @@ impl Config {
             host,
             port: 8000,
             paypal_webhook_id,
+            paypal_currency,
+            request_timeout_secs,
+            app_env,
+            frontend_base_url,
+            api_base_url,
+            verify_redirect_url,
+            allowed_redirect_origins,
+            allow_subscription_downgrades,
+            subscription_grace_days,
+            keep_alive_secs,
+            client_request_timeout_ms,
+            client_disconnect_timeout_ms,
+            tls_enabled,
+            reminder_inactive_days,
+            reminder_interval_days,
+            bounce_webhook_secret,
+            google_client_id,
+            google_client_secret,
+            google_redirect_url,
+            invite_expiry_hours,
+            password_reset_expiry_minutes,
+            log_redact_fields,
+            content_security_policy,
+            frame_options,
+            referrer_policy,
+            hsts_max_age_secs,
+            premium_grants_all_courses,
+            default_course_rating,
+            default_course_students,
+            default_locale,
+            report_rate_limit_per_hour,
+            admin_password_reset_limit_per_hour,
+            course_revision_limit,
+            feature_flag_cache_secs,
+            cookie_secure,
+            cookie_same_site,
+            cors_allowed_methods,
+            cors_allowed_headers,
+            cors_max_age_secs,
+            storage_backend,
+            media_path,
+            media_dir,
+            media_cache_max_age_secs,
+            user_storage_quota_bytes,
+            s3_bucket,
+            s3_region,
+            s3_endpoint,
+            s3_access_key_id,
+            s3_secret_access_key,
+            s3_public_url,
+        }
+    }
+}
+
+/// Valida una URL base para enlaces públicos y la devuelve sin `/` final.
+/// Fuera de desarrollo debe ser una URL absoluta con https.
+pub fn validate_base_url(name: &str, url: &str, is_development: bool) -> Result<String, String> {
+    let parsed = reqwest::Url::parse(url)
+        .map_err(|e| format!("{} no es una URL absoluta ({}): {}", name, url, e))?;
+
+    if !is_development && parsed.scheme() != "https" {
+        return Err(format!("{} debe usar https fuera de desarrollo: {}", name, url));
+    }
+    if parsed.host_str().is_none() {
+        return Err(format!("{} no tiene host: {}", name, url));
+    }
+
+    Ok(url.trim_end_matches('/').to_string())
+}
+
+/// Lista de orígenes separados por comas para `ALLOWED_REDIRECT_ORIGINS`, más los de
+/// `always` (URLs ya validadas). Cada entrada se valida como una URL base y se reduce a
+/// su origen, así que `https://app.example.com/login` permite todo `https://app.example.com`.
+pub fn parse_redirect_origins(raw: &str, always: &[&str], is_development: bool) -> Result<Vec<String>, String> {
+    let mut origins: Vec<String> = Vec::new();
+    let entries = always.iter().copied().chain(raw.split(',').map(str::trim).filter(|e| !e.is_empty()));
+    for entry in entries {
+        let url = validate_base_url("ALLOWED_REDIRECT_ORIGINS", entry, is_development)?;
+        let origin = url_origin(&url)
+            .ok_or_else(|| format!("ALLOWED_REDIRECT_ORIGINS debe usar http o https: {}", entry))?;
+        if !origins.contains(&origin) {
+            origins.push(origin);
         }
     }
-}
\ No newline at end of file
+    Ok(origins)
+}
+
+/// Convierte `HOST` en una URL base: sin esquema se asume `http://` en desarrollo y
+/// `https://` fuera de él, y luego se valida como cualquier otra URL base.
+pub fn normalize_host(host: &str, is_development: bool) -> Result<String, String> {
+    let host = host.trim();
+    if host.contains("://") {
+        return validate_base_url("HOST", host, is_development);
+    }
+    let scheme = if is_development { "http" } else { "https" };
+    validate_base_url("HOST", &format!("{}://{}", scheme, host), is_development)
+}
+
+/// Lee `COOKIE_SECURE` y `COOKIE_SAMESITE` (`none`, `lax` o `strict`). Los navegadores
+/// rechazan `SameSite=None` sin `Secure`, así que esa combinación no se permite.
+pub fn parse_storage_backend(value: &str) -> Result<StorageBackend, String> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "local" => Ok(StorageBackend::Local),
+        "s3" => Ok(StorageBackend::S3),
+        _ => Err(format!("STORAGE_BACKEND debe ser local o s3: {}", value)),
+    }
+}
+
+/// Ruta pública de los archivos: empieza con `/`, sin `/` final y sin ser la raíz
+/// (no puede tapar las rutas de la API).
+pub fn validate_media_path(path: &str) -> Result<String, String> {
+    let path = path.trim();
+    if !path.starts_with('/') || path.ends_with('/') || path.contains("..") {
+        return Err(format!("MEDIA_PATH debe empezar con / y no terminar en /: {}", path));
+    }
+    Ok(path.to_string())
+}
+
+pub fn parse_cookie_policy(secure: &str, same_site: &str) -> Result<(bool, SameSite), String> {
+    let secure: bool = secure.trim().parse()
+        .map_err(|_| format!("COOKIE_SECURE debe ser true o false: {}", secure))?;
+    let same_site = match same_site.trim().to_ascii_lowercase().as_str() {
+        "none" => SameSite::None,
+        "lax" => SameSite::Lax,
+        "strict" => SameSite::Strict,
+        _ => return Err(format!("COOKIE_SAMESITE debe ser none, lax o strict: {}", same_site)),
+    };
+    if same_site == SameSite::None && !secure {
+        return Err("COOKIE_SAMESITE=none requiere COOKIE_SECURE=true".to_string());
+    }
+    Ok((secure, same_site))
+}
+
+/// Límites de los tiempos de conexión del servidor. Un `client_request_timeout` de 0
+/// desactivaría el límite para recibir cabeceras, así que no se permite.
+pub fn validate_connection_timeouts(keep_alive_secs: u64, client_request_timeout_ms: u64, client_disconnect_timeout_ms: u64) -> Result<(), String> {
+    if keep_alive_secs > 600 {
+        return Err(format!("KEEP_ALIVE_SECS debe estar entre 0 y 600: {}", keep_alive_secs));
+    }
+    if client_request_timeout_ms == 0 || client_request_timeout_ms > 60_000 {
+        return Err(format!("CLIENT_REQUEST_TIMEOUT_MS debe estar entre 1 y 60000: {}", client_request_timeout_ms));
+    }
+    if client_disconnect_timeout_ms > 60_000 {
+        return Err(format!("CLIENT_DISCONNECT_TIMEOUT_MS debe estar entre 0 y 60000: {}", client_disconnect_timeout_ms));
+    }
+    Ok(())
+}
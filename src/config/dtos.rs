@@ -1,10 +1,29 @@
 use core::str;
+use actix_web::{ http::StatusCode, HttpResponse };
 use chrono::{ DateTime, Utc, NaiveDate };
 use serde::{ Deserialize, Serialize };
 use uuid::Uuid;
 use validator::Validate; 
 
-use crate::models::models::{ Achievement, Course, User, UserRole};
+use crate::utils::{duration::parse_duration_seconds, money::{Money, apply_discount, format_minor_units, from_minor_units, parse_minor_units, to_minor_units}};
+use crate::models::models::{ Achievement, BroadcastSegment, Bundle, Course, CourseTranslation, Payment, ReportStatus, Subscription, SubscriptionPlan, SubscriptionStatus, User, UserRole};
+
+// Límites por petición al crear/editar un curso: acotan el tamaño de la transacción
+/// Categorías de curso admitidas por la base de datos (`courses_category_check`).
+pub const COURSE_CATEGORIES: [&str; 2] = ["básico", "premium"];
+
+/// Usuarios por petición de importación de progreso.
+pub const MAX_PROGRESS_IMPORT_USERS: u64 = 500;
+
+/// Etiquetas por curso y longitud máxima de cada una (`tags.name`).
+pub const MAX_COURSE_TAGS: usize = 10;
+pub const MAX_TAG_LENGTH: usize = 40;
+
+pub const MAX_COURSE_MODULES: u64 = 100;
+pub const MAX_MODULE_LESSONS: u64 = 200;
+
+// DTOs de salida: camelCase. Los DTOs que también se reciben como entrada
+// usan `rename_all(serialize = "camelCase")` para no romper los payloads actuales.
 
 #[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
 pub struct RegisterDTO {
@@ -48,7 +67,27 @@ pub struct RequestQueryDto {
     pub limit: Option<usize>,
 }
 
+/// `GET /api/v1/admin/users/inactive?days=`: sin iniciar sesión en los últimos `days` días.
+#[derive(Serialize, Deserialize, Validate)]
+pub struct InactiveUsersQueryDto {
+    #[validate(range(min = 1, max = 3650))]
+    pub days: Option<i64>,
+    #[validate(range(min = 1))]
+    pub page: Option<usize>,
+    #[validate(range(min = 1, max = 50))]
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Validate)]
+pub struct UserSearchQueryDto {
+    #[validate(length(min = 2, max = 100, message = "La búsqueda debe tener entre 2 y 100 caracteres"))]
+    pub q: String,
+    #[validate(range(min = 1, max = 50))]
+    pub limit: Option<usize>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct FilterUserDto {
     pub id: Option<String>,
     pub name: Option<String>,
@@ -56,14 +95,13 @@ pub struct FilterUserDto {
     pub phone: Option<String>,
     pub location: Option<String>,
     pub bio: Option<String>,
-    #[serde(rename = "birthDate")]
     pub birth_date: Option<NaiveDate>, 
     pub role: Option<UserRole>,
     pub verified: Option<bool>,
-    #[serde(rename = "createdAt")]
-    pub created_at: Option<DateTime<Utc>>,
-    #[serde(rename = "updatedAt")]
-    pub updated_at: Option<DateTime<Utc>>,
+    pub locked: Option<bool>,
+    pub last_login_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
 }
 
 impl FilterUserDto {
@@ -78,6 +116,8 @@ impl FilterUserDto {
             birth_date: user.birth_date,
             role: user.role.clone().into(),
             verified: Some(user.verified),
+            locked: Some(user.locked),
+            last_login_at: user.last_login_at,
             created_at: user.created_at,
             updated_at: user.updated_at,
         }
@@ -95,28 +135,22 @@ pub struct UserData {
     pub user: FilterUserDto,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct UserResponseDto {
-    pub status: String,
-    pub data: UserData,
-}
-
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UserListResponseDto {
-    pub status: String,
     pub users: Vec<FilterUserDto>,
     pub results: i64,
 }
 
+/// `data` de las respuestas que solo confirman una acción con un mensaje.
 #[derive(Debug, Serialize, Deserialize)]
-pub struct UserLoginResponseDto {
-    pub status: String,
+pub struct MessageDto {
+    pub message: String,
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct Response {
-    pub status: &'static str,
-    pub message: String,
+impl MessageDto {
+    pub fn new(message: impl Into<String>) -> Self {
+        MessageDto { message: message.into() }
+    }
 }
 
 #[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
@@ -131,6 +165,12 @@ pub struct UpdateLessonProgressDTO {
     pub progress: Option<f64>,
 }
 
+/// Estado deseado de la lección: repetir la misma petición no cambia nada.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SetLessonCompletedDTO {
+    pub completed: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
 pub struct RoleUpdateDTO {
     #[validate(custom(message = "Rol de usuario inválido", function = "validate_user_role"))]
@@ -143,6 +183,40 @@ fn validate_user_role(role: &UserRole) -> Result<(), validator::ValidationError>
     }
 }
 
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkUserAction {
+    Verify,
+    SetRole,
+    Lock,
+    Unlock,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkUserActionDTO {
+    #[validate(length(min = 1, max = 500, message = "Se requieren entre 1 y 500 usuarios"))]
+    pub user_ids: Vec<String>,
+    pub action: BulkUserAction,
+    // Solo para `set_role`
+    pub role: Option<UserRole>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkUserResultDto {
+    pub user_id: String,
+    pub status: String, // "updated" | "not_found" | "invalid_id"
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkUserActionResponseDto {
+    pub action: BulkUserAction,
+    pub updated: usize,
+    pub results: Vec<BulkUserResultDto>,
+}
+
 #[derive(Debug, Validate, Default, Clone, Serialize, Deserialize)]
 pub struct UserPasswordUpdateDTO {
     #[validate(
@@ -167,6 +241,187 @@ pub struct UserPasswordUpdateDTO {
 pub struct VerifyEmailQueryDTO {
     #[validate(length(min = 1, message = "El token es requerido"))]
     pub token: String,
+    // Página del frontend a la que volver; solo se usa si su origen está permitido
+    pub redirect: Option<String>,
+}
+
+#[derive(Validate, Debug, Clone, Serialize, Deserialize)]
+pub struct CreateInviteDTO {
+    #[validate(
+        length(min = 1, message = "El correo electrónico es requerido"),
+        email(message = "El correo electrónico no es válido")
+    )]
+    pub email: String,
+    pub role: UserRole,
+}
+
+#[derive(Validate, Debug, Clone, Serialize, Deserialize)]
+pub struct CreateReportDTO {
+    pub lesson_id: Option<Uuid>,
+    #[validate(length(min = 1, max = 100, message = "El motivo debe tener entre 1 y 100 caracteres"))]
+    pub reason: String,
+    #[validate(length(max = 2000, message = "El detalle no puede superar los 2000 caracteres"))]
+    pub details: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Validate)]
+pub struct ReportsQueryDto {
+    #[validate(range(min = 1))]
+    pub page: Option<usize>,
+    #[validate(range(min = 1, max = 50))]
+    pub limit: Option<usize>,
+    pub status: Option<ReportStatus>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdateReportDTO {
+    pub status: ReportStatus,
+}
+
+/// Aviso masivo de un administrador (`POST /api/v1/admin/notifications/broadcast`).
+#[derive(Validate, Debug, Clone, Serialize, Deserialize)]
+pub struct BroadcastNotificationDTO {
+    pub segment: BroadcastSegment,
+    /// Obligatorio con `course_students`; se ignora en los demás segmentos.
+    pub course_id: Option<Uuid>,
+    #[validate(length(min = 1, max = 255, message = "El título debe tener entre 1 y 255 caracteres"))]
+    pub title: String,
+    #[validate(length(min = 1, max = 5000, message = "El mensaje debe tener entre 1 y 5000 caracteres"))]
+    pub message: String,
+    /// Envía además un correo a quienes aceptan notificaciones por correo.
+    #[serde(default)]
+    pub send_email: bool,
+}
+
+/// Marca o desmarca un curso como destacado en la portada.
+#[derive(Validate, Debug, Clone, Serialize, Deserialize)]
+pub struct SetCourseFeaturedDTO {
+    pub featured: bool,
+    #[validate(range(min = 0, message = "El orden no puede ser negativo"))]
+    pub featured_order: Option<i32>,
+}
+
+/// Activa o desactiva una funcionalidad.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetFeatureFlagDTO {
+    pub enabled: bool,
+}
+
+/// Funcionalidad conocida con su estado efectivo (sin fila en la base queda activada).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeatureFlagDto {
+    pub key: String,
+    pub enabled: bool,
+    pub updated_by: Option<Uuid>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// Programa una oferta del curso. Las fechas aceptan cualquier zona horaria (RFC 3339)
+/// y se guardan en UTC; sin `sale_starts_at` la oferta empieza en el momento.
+#[derive(Validate, Debug, Clone, Serialize, Deserialize)]
+pub struct SetCourseSaleDTO {
+    #[validate(range(min = 0.0, message = "El precio de oferta no puede ser negativo"))]
+    pub sale_price: f64,
+    pub sale_starts_at: Option<DateTime<Utc>>,
+    pub sale_ends_at: DateTime<Utc>,
+}
+
+impl SetCourseSaleDTO {
+    /// La oferta termina después de empezar y no terminó ya.
+    pub fn has_valid_window(&self, now: DateTime<Utc>) -> bool {
+        let starts_at = self.sale_starts_at.unwrap_or(now);
+        self.sale_ends_at > starts_at && self.sale_ends_at > now
+    }
+}
+
+/// Traducción de un curso a un idioma; el idioma va en la ruta.
+#[derive(Validate, Debug, Clone, Serialize, Deserialize)]
+pub struct SetCourseTranslationDTO {
+    #[validate(length(min = 1, max = 255, message = "El título traducido es requerido"))]
+    pub title: String,
+
+    #[validate(length(min = 1, message = "La descripción traducida es requerida"))]
+    pub description: String,
+
+    #[serde(alias = "longDescription")]
+    pub long_description: Option<String>,
+}
+
+/// Respuesta de un endpoint público con el idioma realmente servido (`locale`), que
+/// puede ser el idioma por defecto si no había traducción para lo pedido.
+#[derive(Debug, Serialize)]
+pub struct LocalizedDto<T> {
+    #[serde(flatten)]
+    pub item: T,
+    pub locale: String,
+}
+
+/// Cupo del curso; `null` lo quita.
+#[derive(Validate, Debug, Clone, Serialize, Deserialize)]
+pub struct SetCourseMaxStudentsDTO {
+    #[validate(range(min = 1, message = "El cupo debe ser de al menos 1 alumno"))]
+    pub max_students: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Validate)]
+pub struct FeaturedCoursesQueryDto {
+    #[validate(range(min = 1, max = 50))]
+    pub limit: Option<i64>,
+}
+
+/// Reemplaza las etiquetas de un curso; se normalizan en kebab-case.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetCourseTagsDTO {
+    pub tags: Vec<String>,
+}
+
+/// Cómo combinar varias etiquetas en `GET /courses?tag=`: alguna (`any`) o todas (`all`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TagMatch {
+    #[default]
+    Any,
+    All,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct TagDto {
+    pub name: String,
+    pub course_count: i64,
+}
+
+#[derive(Validate, Debug, Clone, Serialize, Deserialize)]
+pub struct AcceptInviteDTO {
+    #[validate(length(min = 1, message = "El token es requerido"))]
+    pub token: String,
+    #[validate(length(min = 1, message = "El nombre de usuario es requerido"))]
+    pub name: String,
+    #[validate(
+        length(min = 6, message = "La contraseña debe tener al menos 6 caracteres"),
+    )]
+    pub password: String,
+    #[validate(
+        length(min = 1, message = "Confirmar contraseña es requerido"),
+        must_match(other = "password", message = "Las contraseñas no coinciden")
+    )]
+    #[serde(rename = "confirmPassword")]
+    pub confirm_password: String,
+}
+
+/// Parámetros con los que Google vuelve a `/api/v1/auth/google/callback`
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct GoogleLoginQueryDTO {
+    // A dónde volver tras iniciar sesión; solo se usa si su origen está permitido
+    pub redirect: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct GoogleCallbackQueryDTO {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
 }
 #[allow(dead_code)]
 #[derive(Deserialize, Serialize, Validate, Debug, Clone)]
@@ -195,8 +450,10 @@ pub struct ResetPasswordRequestDTO {
     pub confirm_new_password: String,
 }
 
+// Se serializa en camelCase; los alias aceptan ese mismo JSON (p. ej. una exportación) al importar
 #[allow(dead_code)]
 #[derive(Validate, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all(serialize = "camelCase"))]
 pub struct CreateCourseDTO {
     #[validate(length(min = 1, message = "El título del curso es requerido"))]
     pub title: String,
@@ -204,12 +461,14 @@ pub struct CreateCourseDTO {
     #[validate(length(min = 1, message = "La descripción corta es requerida"))]
     pub description: String,
 
+    #[serde(alias = "longDescription")]
     pub long_description: Option<String>,
 
     #[validate(length(min = 1, message = "El nivel es requerido"))]
     pub level: String, // "básico" | "intermedio" | "avanzado"
 
-    #[validate(range(min = 0.0, message = "El precio debe ser mayor a 0"))]
+    // 0 es válido: curso gratuito
+    #[validate(range(min = 0.0, message = "El precio no puede ser negativo"))]
     pub price: f64,
 
     pub duration: Option<String>, // ej: "4 semanas"
@@ -219,19 +478,26 @@ pub struct CreateCourseDTO {
     #[validate(url(message = "La URL de la imagen no es válida"))]
     pub image: Option<String>, // URL de imagen
 
+    #[validate(url(message = "La URL del tráiler no es válida"))]
+    #[serde(alias = "trailerUrl")]
+    pub trailer_url: Option<String>, // vídeo de presentación
+
     #[validate(length(min = 1, message = "La categoría es requerida"))]
     pub category: String, // "básico" | "premium"
 
     #[serde(default)]
     pub features: Option<Vec<String>>, // JSONB -> Vec<String>
 
+    #[serde(alias = "paypalProductId")]
     pub paypal_product_id: Option<String>,
 
     #[serde(default)]
+    #[validate(length(max = MAX_COURSE_MODULES, message = "Un curso admite como máximo 100 módulos"), nested)]
     pub modules: Vec<CreateModuleDTO>, // array de videos
 }
 
 #[derive(Validate, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all(serialize = "camelCase"))]
 pub struct CreateLessonDTO {
     #[validate(length(min = 1, message = "El título de la lección es requerido"))]
     pub title: String,
@@ -242,14 +508,19 @@ pub struct CreateLessonDTO {
     #[validate(length(min = 1, message = "El tipo de lección es requerido"))]
     pub r#type: String, // video | exercise | quiz
     
+    #[serde(alias = "contentUrl")]
     pub content_url: Option<String>,
     pub description: Option<String>,
     
     // El orden es opcional en la entrada, se puede calcular si no se proporciona
     pub order: Option<i32>, 
+
+    #[serde(default, alias = "isPreview")]
+    pub is_preview: bool,
 }
 
 #[derive(Validate, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all(serialize = "camelCase"))]
 pub struct CreateModuleDTO {
     #[validate(length(min = 1, message = "El título del módulo es requerido"))]
     pub title: String,
@@ -258,9 +529,45 @@ pub struct CreateModuleDTO {
     pub order: Option<i32>, 
     
     #[serde(default)]
+    #[validate(length(max = MAX_MODULE_LESSONS, message = "Un módulo admite como máximo 200 lecciones"))]
     pub lessons: Vec<CreateLessonDTO>,
 }
 
+/// Exportación del curso como documento importable con `POST /courses/edit`: sin
+/// alumnos, progreso ni producto de PayPal (la importación crea uno nuevo).
+impl From<CourseWithModulesDto> for CreateCourseDTO {
+    fn from(course: CourseWithModulesDto) -> Self {
+        CreateCourseDTO {
+            title: course.title,
+            description: course.description,
+            long_description: course.long_description,
+            level: course.level,
+            price: course.price,
+            duration: course.duration,
+            students: None,
+            image: course.image,
+            trailer_url: course.trailer_url,
+            category: course.category,
+            features: course.features,
+            paypal_product_id: None,
+            modules: course.modules.into_iter().map(|module| CreateModuleDTO {
+                title: module.title,
+                order: Some(module.order),
+                lessons: module.lessons.into_iter().map(|lesson| CreateLessonDTO {
+                    title: lesson.title,
+                    duration: lesson.duration,
+                    completed: false,
+                    r#type: lesson.r#type,
+                    content_url: lesson.content_url,
+                    description: lesson.description,
+                    order: Some(lesson.order),
+                    is_preview: lesson.is_preview,
+                }).collect(),
+            }).collect(),
+        }
+    }
+}
+
 #[allow(dead_code)]
 #[derive(Validate, Debug, Clone, Serialize, Deserialize,PartialEq)]
 pub struct UpdateCourseDTO {
@@ -275,7 +582,8 @@ pub struct UpdateCourseDTO {
     #[validate(length(min = 1, message = "El nivel es requerido"))]
     pub level: Option<String>, // "básico" | "intermedio" | "avanzado"
 
-    #[validate(range(min = 0.0, message = "El precio debe ser mayor a 0"))]
+    // 0 es válido: curso gratuito
+    #[validate(range(min = 0.0, message = "El precio no puede ser negativo"))]
     pub price: Option<f64>,
 
     pub duration: Option<String>, // ej: "4 semanas"
@@ -285,6 +593,9 @@ pub struct UpdateCourseDTO {
     #[validate(url(message = "La URL de la imagen no es válida"))]
     pub image: Option<String>, // URL de imagen
 
+    #[validate(url(message = "La URL del tráiler no es válida"))]
+    pub trailer_url: Option<String>, // vídeo de presentación
+
     #[validate(length(min = 1, message = "La categoría es requerida"))]
     pub category: Option<String>, // "básico" | "premium"
 
@@ -292,7 +603,14 @@ pub struct UpdateCourseDTO {
     pub features: Option<Vec<String>>, // JSONB -> Vec<String>
 
     #[serde(default)]
+    #[validate(length(max = MAX_COURSE_MODULES, message = "Un curso admite como máximo 100 módulos"), nested)]
     pub modules: Option<Vec<UpdateModuleDTO>>, // array de videos
+
+    // Versión del curso que leyó el cliente (control de concurrencia optimista)
+    pub version: i32,
+
+    // Sin `paypal_product_id` a propósito: si el cliente lo envía se ignora y el
+    // producto solo cambia con `POST /courses/edit/{id}/sync-paypal?force=true`.
 }
 
 impl PartialEq<Course> for UpdateCourseDTO {
@@ -306,6 +624,7 @@ impl PartialEq<Course> for UpdateCourseDTO {
             && self.duration == other.duration
             && self.students == Some(other.students)
             && self.image == other.image
+            && self.trailer_url == other.trailer_url
             && self.category == Some(other.category.clone())
     }
 }
@@ -325,9 +644,11 @@ pub struct UpdateLessonDTO {
     pub content_url: Option<String>,
     pub description: Option<String>,
     pub order: Option<i32>, 
+    // Si es None se conserva el valor actual
+    pub is_preview: Option<bool>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Validate, Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct UpdateModuleDTO {
     // Si 'id' está presente, se actualiza; si es None, se crea un nuevo módulo.
     pub id: Option<Uuid>, 
@@ -337,10 +658,12 @@ pub struct UpdateModuleDTO {
 
     #[serde(default)]
     // Aquí el Option<Vec> permite que se omita la lista de lecciones si no se van a actualizar
+    #[validate(length(max = MAX_MODULE_LESSONS, message = "Un módulo admite como máximo 200 lecciones"))]
     pub lessons: Option<Vec<UpdateLessonDTO>>, 
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
 pub struct LessonDto {
     pub id: Uuid,
     pub title: String,
@@ -350,9 +673,11 @@ pub struct LessonDto {
     pub content_url: Option<String>,
     pub description: Option<String>,
     pub order: i32,
+    pub is_preview: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
 pub struct ModuleWithLessonsDto {
     pub id: Uuid,
     pub title: String,
@@ -361,56 +686,206 @@ pub struct ModuleWithLessonsDto {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
 pub struct CourseWithModulesDto {
     pub id: Uuid,
     pub title: String,
     pub description: String,
     pub long_description: Option<String>,
     pub price: f64,
+    /// `price` o el precio de oferta si hay una vigente.
+    pub effective_price: f64,
     pub level: String,
     pub duration: Option<String>,
     pub students: i32,
     pub image: Option<String>,
+    pub trailer_url: Option<String>,
     pub category: String,
     pub features: Option<Vec<String>>,
+    pub tags: Vec<String>,
+    pub version: i32,
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub updated_at: chrono::DateTime<chrono::Utc>,
 
     pub total_lessons: i64,
     pub completed_lessons: i64,
+    /// Suma de las duraciones de las lecciones; `duration` queda como texto para mostrar.
+    pub computed_duration_seconds: u64,
 
     pub modules: Vec<ModuleWithLessonsDto>,
 }
 
+impl CourseWithModulesDto {
+    /// Suma las duraciones de las lecciones que se pueden interpretar; las demás no cuentan.
+    pub fn lesson_duration_seconds(&self) -> u64 {
+        self.modules.iter()
+            .flat_map(|m| &m.lessons)
+            .filter_map(|l| l.duration.as_deref().and_then(parse_duration_seconds))
+            .sum()
+    }
+}
+
+pub const MAX_BUNDLE_COURSES: u64 = 50;
+
+#[derive(Validate, Debug, Clone, Serialize, Deserialize)]
+pub struct CreateBundleDTO {
+    #[validate(length(min = 1, max = 255, message = "El título del paquete es requerido"))]
+    pub title: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    #[validate(range(min = 0, max = 100, message = "El descuento debe estar entre 0 y 100"))]
+    pub discount_percent: i32,
+    #[validate(length(min = 2, max = MAX_BUNDLE_COURSES, message = "Un paquete lleva entre 2 y 50 cursos"))]
+    pub course_ids: Vec<Uuid>,
+}
+
+/// Cambios de un paquete; lo que se omite se conserva. Con `active = false` sale del
+/// catálogo y ya no se pueden crear órdenes.
+#[derive(Validate, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpdateBundleDTO {
+    #[validate(length(min = 1, max = 255, message = "El título del paquete es requerido"))]
+    pub title: Option<String>,
+    pub description: Option<String>,
+    #[validate(range(min = 0, max = 100, message = "El descuento debe estar entre 0 y 100"))]
+    pub discount_percent: Option<i32>,
+    pub active: Option<bool>,
+}
+
+/// Curso de un paquete con su precio vigente. `owned` solo se calcula para un comprador.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleCourseDto {
+    pub id: Uuid,
+    pub title: String,
+    pub slug: String,
+    pub image: Option<String>,
+    pub price: f64,
+    #[serde(skip)]
+    pub paypal_product_id: Option<String>,
+    pub owned: bool,
+}
+
+/// Paquete para el catálogo. `price` es lo que se cobra: la suma de los cursos que
+/// aún no tiene el comprador con el descuento aplicado.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleDto {
+    pub id: Uuid,
+    pub title: String,
+    pub description: String,
+    pub discount_percent: i32,
+    pub courses: Vec<BundleCourseDto>,
+    pub currency: String,
+    pub total_price: f64,
+    pub price: f64,
+}
+
+impl BundleDto {
+    pub fn new(bundle: Bundle, courses: Vec<BundleCourseDto>, currency: &str) -> Self {
+        let to_pay: Vec<i64> = courses.iter()
+            .filter(|c| !c.owned)
+            .map(|c| to_minor_units(c.price, currency))
+            .collect();
+        let total: i64 = to_pay.iter().sum();
+        let price = apply_discount(total, bundle.discount_percent);
+        BundleDto {
+            id: bundle.id,
+            title: bundle.title,
+            description: bundle.description,
+            discount_percent: bundle.discount_percent,
+            courses,
+            currency: currency.to_string(),
+            total_price: from_minor_units(total, currency),
+            price: from_minor_units(price, currency),
+        }
+    }
+
+    /// Cursos que se cobran en la orden.
+    pub fn courses_to_buy(&self) -> impl Iterator<Item = &BundleCourseDto> {
+        self.courses.iter().filter(|c| !c.owned)
+    }
+}
+
+/// Lección en el índice del curso: sin `content_url` ni descripción.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OutlineLessonDto {
+    pub id: Uuid,
+    pub title: String,
+    pub duration: Option<String>,
+    pub r#type: String,
+    pub order: i32,
+    pub is_preview: bool,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OutlineModuleDto {
+    pub id: Uuid,
+    pub title: String,
+    pub order: i32,
+    pub lessons: Vec<OutlineLessonDto>,
+}
+
+/// Índice del curso para la página pública (`GET /courses/{id}/outline`): solo la
+/// estructura de módulos y lecciones, sin contenido.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CourseOutlineDto {
+    pub id: Uuid,
+    pub title: String,
+    pub total_lessons: i64,
+    pub computed_duration_seconds: u64,
+    pub modules: Vec<OutlineModuleDto>,
+}
+
 #[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct CourseResponseDTO {
     pub id: String,
     pub name: String,
     pub description: String,
     pub price: f64,
-    #[serde(rename = "createdAt")]
     pub created_at: DateTime<Utc>,
 }
 
-#[allow(dead_code)]
+/// Pago registrado a mano por un administrador (transferencia, efectivo...).
 #[derive(Validate, Debug, Clone, Serialize, Deserialize)]
 pub struct CreatePaymentDTO {
-    #[validate(length(min = 1, message = "El ID del curso es requerido"))]
-    pub course_id: String,
-    #[validate(length(min = 1, message = "El ID del usuario es requerido"))]
-    pub user_id: String,
-    #[validate(range(min = 0.0, message = "El monto debe ser mayor a 0"))]
+    pub course_id: Uuid,
+    pub user_id: Uuid,
+    // En la unidad mayor de la moneda: 10.5 son 1050 centavos
+    #[validate(range(min = 0.0, message = "El monto no puede ser negativo"))]
     pub amount: f64,
-    #[validate(length(min = 1, message = "El método de pago es requerido"))]
+    // Código ISO 4217; por defecto USD
+    #[validate(length(equal = 3, message = "La moneda debe ser un código ISO de 3 letras"))]
+    pub currency: Option<String>,
+    #[validate(length(min = 1, max = 50, message = "El método de pago debe tener entre 1 y 50 caracteres"))]
     pub payment_method: String,
-    #[validate(length(min = 1, message = "El ID de transacción es requerido"))]
+    #[validate(length(min = 1, max = 255, message = "El ID de transacción debe tener entre 1 y 255 caracteres"))]
     pub transaction_id: String,
 }
 
+impl CreatePaymentDTO {
+    /// Monto en unidades menores. `None` si trae más decimales de los que admite la moneda.
+    pub fn money(&self) -> Option<Money> {
+        if !self.amount.is_finite() {
+            return None;
+        }
+        let currency = self.currency.as_deref().unwrap_or("USD").to_uppercase();
+        parse_minor_units(&self.amount.to_string(), &currency)
+            .map(|amount_minor| Money { amount_minor, currency })
+    }
+}
+
 #[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize, Validate, Clone)]
 pub struct ProductDTO {
+    // Id propio del producto (6-50 caracteres); sin él PayPal genera uno
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
     #[validate(length(min = 1, message = "El nombre del producto es requerido"))]
     pub name: String,
     #[validate(length(min = 1, message = "La descripción del producto es requerida"))]
@@ -422,20 +897,41 @@ pub struct ProductDTO {
     pub home_url: Option<String>,
 }
 
-#[allow(dead_code)]
+/// Pago tal como lo ven los clientes: el monto exacto en unidades menores y un
+/// texto listo para mostrar, sin adivinar si son centavos o dólares.
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct PaymentResponseDTO {
-    pub id: String,
-    pub course_id: String,
-    pub user_id: String,
-    pub amount: f64,
+    pub id: Uuid,
+    pub course_id: Uuid,
+    pub user_id: Uuid,
+    pub amount_minor: i64,
+    pub currency: String,
+    /// p. ej. `"10.50 USD"`
+    pub amount_display: String,
     pub status: String, // "pending", "completed", "failed"
     pub payment_method: String,
-    pub transaction_id: Option<String>,
-    #[serde(rename = "createdAt")]
+    pub transaction_id: String,
     pub created_at: DateTime<Utc>,
-    #[serde(rename = "updatedAt")]
-    pub updated_at: Option<DateTime<Utc>>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<Payment> for PaymentResponseDTO {
+    fn from(payment: Payment) -> Self {
+        PaymentResponseDTO {
+            amount_display: format_minor_units(payment.amount, &payment.currency),
+            id: payment.id,
+            course_id: payment.course_id,
+            user_id: payment.user_id,
+            amount_minor: payment.amount,
+            currency: payment.currency,
+            status: payment.status,
+            payment_method: payment.payment_method,
+            transaction_id: payment.transaction_id,
+            created_at: payment.created_at,
+            updated_at: payment.updated_at,
+        }
+    }
 }
 
 #[allow(dead_code)]
@@ -449,6 +945,7 @@ pub struct VerifyPaymentDTO {
 
 #[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct UserPaymentStatusDTO {
     pub user_id: String,
     pub course_id: String,
@@ -456,13 +953,6 @@ pub struct UserPaymentStatusDTO {
     pub payment_date: Option<DateTime<Utc>>,
 }
 
-#[allow(dead_code)]
-#[derive(Debug, Serialize, Deserialize)]
-pub struct UserProfileResponse {
-    pub status: String,
-    pub data: UserProfileData,
-}
-
 #[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UserProfileData {
@@ -485,24 +975,26 @@ pub struct UpdateUserProfileDto {
 // Nuevos DTOs para courses y achievements (tipo "filter" como FilterUserDto)
 #[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
 pub struct FilterCourseDto {
     pub id: Uuid,
     pub title: Option<String>,
+    pub slug: Option<String>,
     pub description: Option<String>,
     pub long_description: Option<String>,
     pub price: Option<f64>,
+    pub effective_price: Option<f64>,
     pub level: Option<String>,
     pub duration: Option<String>,
     pub students: Option<i32>,
     pub image: Option<String>,
     pub category: Option<String>,
     pub rating: i32,
+    pub rating_count: i64,
     pub features: Option<Vec<String>>,
     pub paypal_product_id: Option<String>,
-    #[serde(rename = "createdAt")]
-    pub created_at: Option<DateTime<Utc>>,
-    #[serde(rename = "updatedAt")]
-    pub updated_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
 }
 
 impl FilterCourseDto {
@@ -513,19 +1005,22 @@ impl FilterCourseDto {
         FilterCourseDto {
             id: course.id,
             title: Some(course.title.to_owned()),
+            slug: Some(course.slug.to_owned()),
             description: Some(course.description.to_owned()),
             long_description: course.long_description.clone(),
             price: Some(course.price),
+            effective_price: Some(course.effective_price),
             level: Some(course.level.clone()),
             duration: course.duration.clone(),
             students: Some(course.students),
             image: course.image.clone(),
             category: Some(course.category.clone()),
-            rating: course.rating.clone(),
+            rating: course.rating,
+            rating_count: course.rating_count,
             paypal_product_id: course.paypal_product_id.clone(),
             features,
-            created_at: Some(course.created_at),
-            updated_at: Some(course.updated_at),
+            created_at: course.created_at,
+            updated_at: course.updated_at,
         }
     }
     
@@ -535,7 +1030,54 @@ impl FilterCourseDto {
 
 }
 
+/// Ids afectados por una operación y su número
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct ChangedIdsDto {
+    pub count: usize,
+    pub ids: Vec<Uuid>,
+}
+
+impl From<Vec<Uuid>> for ChangedIdsDto {
+    fn from(ids: Vec<Uuid>) -> Self {
+        ChangedIdsDto { count: ids.len(), ids }
+    }
+}
+
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct EntityChangesDto {
+    pub inserted: ChangedIdsDto,
+    pub updated: ChangedIdsDto,
+    pub deleted: ChangedIdsDto,
+}
+
+impl EntityChangesDto {
+    pub fn new(inserted: Vec<Uuid>, updated: Vec<Uuid>, deleted: Vec<Uuid>) -> Self {
+        EntityChangesDto {
+            inserted: inserted.into(),
+            updated: updated.into(),
+            deleted: deleted.into(),
+        }
+    }
+}
+
+/// Respuesta de `update_course` con `?dry_run=true`: lo que cambiaría, sin aplicarlo
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct CourseChangesetDto {
+    pub modules: EntityChangesDto,
+    pub lessons: EntityChangesDto,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CourseChangesResponseDto {
+    pub courses: Vec<UserCourseDto>,
+    pub deleted: Vec<Uuid>,
+    // Valor a enviar como `since` en la siguiente sincronización
+    pub cursor: DateTime<Utc>,
+}
+
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
 pub struct UserAchievementDto {
     pub id: Uuid,
     pub name: String,
@@ -552,12 +1094,12 @@ pub struct UserAchievementDto {
 
 #[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct FilterAchievementDto {
     pub id: Option<String>,
     pub title: Option<String>,
     pub description: Option<String>,
-    #[serde(rename = "createdAt")]
-    pub created_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
     // añade otros campos que tenga tu modelo Achievement si los necesitas (p.ej. points)
 }
 
@@ -568,7 +1110,7 @@ impl FilterAchievementDto {
             // adapta names según tu modelo Achievement
             title: Some(a.name.to_owned()),
             description: a.description.clone(),
-            created_at: Some(a.created_at),
+            created_at: a.created_at,
         }
     }
 
@@ -589,6 +1131,7 @@ pub struct CreatedRatingDto {
 }
 
 #[derive(Debug, Serialize, Deserialize, Validate, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
 pub struct CommentLessonDto {
     pub id: Uuid,
     pub user_id: Uuid,
@@ -599,6 +1142,7 @@ pub struct CommentLessonDto {
 }
 
 #[derive(Debug, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
 pub struct CourseRatingDto {
     pub average: f64,
     pub count: i64,
@@ -606,20 +1150,421 @@ pub struct CourseRatingDto {
 }
 
 #[derive(Debug, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
 pub struct UserCourseDto {
     pub id: Uuid,
     pub title: String,                       
+    pub slug: String,
     pub description: String,                  
     pub long_description: Option<String>,    
     pub level: String,                        
     pub price: f64,
+    // `price` o el precio de oferta si hay una vigente
+    pub effective_price: f64,
     pub duration: Option<String>,            
     pub students: i32,                                              
     pub image: Option<String>,                
     pub category: String,                     
     pub rating: i32,
+    // 0 = sin valoraciones; `rating` es entonces la valoración inicial configurada
+    pub rating_count: i64,
     pub features: Option<serde_json::Value>,
     pub paypal_product_id: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
-}
\ No newline at end of file
+}
+
+impl UserCourseDto {
+    /// Sustituye la media por `default_rating` en los cursos que aún no tienen valoraciones
+    pub fn apply_default_rating(&mut self, default_rating: i32) {
+        if self.rating_count == 0 {
+            self.rating = default_rating;
+        }
+    }
+
+    pub fn with_default_rating(mut courses: Vec<UserCourseDto>, default_rating: i32) -> Vec<UserCourseDto> {
+        courses.iter_mut().for_each(|c| c.apply_default_rating(default_rating));
+        courses
+    }
+
+    /// Sustituye los textos por los de la traducción; sin `long_description` traducida se
+    /// conserva la del curso base.
+    pub fn apply_translation(&mut self, translation: CourseTranslation) {
+        self.title = translation.title;
+        self.description = translation.description;
+        if translation.long_description.is_some() {
+            self.long_description = translation.long_description;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CourseProgressStatus {
+    Completed,
+    InProgress,
+    NotStarted,
+}
+
+impl CourseProgressStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CourseProgressStatus::Completed => "completed",
+            CourseProgressStatus::InProgress => "in_progress",
+            CourseProgressStatus::NotStarted => "not_started",
+        }
+    }
+}
+
+/// Motivo por el que un usuario puede ver un curso (`GET /api/v1/courses/{id}/access`).
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CourseAccessReason {
+    Owned,
+    Premium,
+    Admin,
+    None,
+}
+
+impl CourseAccessReason {
+    /// Si hay varios motivos gana la compra, que es permanente; luego la suscripción.
+    pub fn from_flags(owned: bool, premium: bool, is_admin: bool) -> Self {
+        if owned {
+            CourseAccessReason::Owned
+        } else if premium {
+            CourseAccessReason::Premium
+        } else if is_admin {
+            CourseAccessReason::Admin
+        } else {
+            CourseAccessReason::None
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CourseAccessDto {
+    pub has_access: bool,
+    pub reason: CourseAccessReason,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct UserCoursesQueryDto {
+    pub status: Option<CourseProgressStatus>,
+    #[validate(range(min = 1))]
+    pub page: Option<usize>,
+    #[validate(range(min = 1, max = 50))]
+    pub limit: Option<usize>,
+}
+
+/// Filtro de los logros de un usuario: `earned=false` deja solo los bloqueados.
+#[derive(Debug, Deserialize, Validate)]
+pub struct UserAchievementsQueryDto {
+    pub earned: Option<bool>,
+    #[validate(range(min = 1))]
+    pub page: Option<usize>,
+    #[validate(range(min = 1, max = 50))]
+    pub limit: Option<usize>,
+}
+
+/// Curso inscrito junto con el progreso del usuario (0 si aún no tiene registro)
+#[derive(Debug, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct EnrolledCourseDto {
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub course: UserCourseDto,
+    pub progress_percentage: f32,
+}
+
+/// Lecciones completadas de un alumno en la plataforma de origen
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProgressImportEntryDto {
+    pub user_id: Uuid,
+    pub completed_lesson_ids: Vec<Uuid>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ProgressImportResultDto {
+    pub user_id: Uuid,
+    pub status: String, // "imported" | "user_not_found" | "invalid_lessons"
+    pub completed_lessons: i64,
+    pub progress_percentage: f32,
+    // Lecciones que no pertenecen al curso (solo con `invalid_lessons`)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub invalid_lesson_ids: Vec<Uuid>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProgressImportResponseDto {
+    pub imported: usize,
+    pub results: Vec<ProgressImportResultDto>,
+}
+
+/// Progreso del curso recalculado tras registrar el avance de una lección
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CourseProgressEventDto {
+    pub course_id: Uuid,
+    pub progress_percentage: f32,
+    pub completed_lessons: i64,
+    pub total_lessons: i64,
+}
+
+/// Alumno inscrito en un curso, solo con los datos que necesita el administrador
+#[derive(Debug, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct CourseStudentDto {
+    pub name: String,
+    pub email: String,
+    pub enrolled_at: DateTime<Utc>,
+    pub progress_percentage: f32,
+    // `None` si aún no ha abierto ninguna lección
+    pub last_activity: Option<DateTime<Utc>>,
+}
+
+/// Envoltorio común para respuestas exitosas: `{ "status": "success", "data": ... }`.
+/// Los errores usan su contraparte `ApiError` (`{ "status": "fail", "message", "details"? }`).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiResponse<T> {
+    pub status: String,
+    pub data: T,
+}
+
+/// Marca las respuestas construidas con `ApiResponse` para que `ApiVersionCompat`
+/// pueda devolver solo `data` a los clientes que piden la versión anterior.
+#[derive(Debug, Clone, Copy)]
+pub struct ApiEnvelope;
+
+impl<T: Serialize> ApiResponse<T> {
+    pub fn new(data: T) -> Self {
+        ApiResponse { status: "success".to_string(), data }
+    }
+
+    /// `200 OK` con el envoltorio.
+    pub fn ok(data: T) -> HttpResponse {
+        Self::respond(StatusCode::OK, data)
+    }
+
+    /// `201 Created` con el envoltorio.
+    pub fn created(data: T) -> HttpResponse {
+        Self::respond(StatusCode::CREATED, data)
+    }
+
+    /// `202 Accepted` con el envoltorio, para trabajos que siguen en segundo plano.
+    pub fn accepted(data: T) -> HttpResponse {
+        Self::respond(StatusCode::ACCEPTED, data)
+    }
+
+    fn respond(status: StatusCode, data: T) -> HttpResponse {
+        let mut res = HttpResponse::build(status).json(Self::new(data));
+        res.extensions_mut().insert(ApiEnvelope);
+        res
+    }
+}
+
+impl<T: Serialize> ApiResponse<Vec<T>> {
+    /// `200 OK` con una página: `data` junto a `page`, `limit` y `total`. Ya tenía esta
+    /// forma antes del envoltorio, así que los clientes de la versión 1 la reciben igual.
+    pub fn paginated(data: Vec<T>, page: usize, limit: usize, total: i64) -> HttpResponse {
+        HttpResponse::Ok().json(PaginatedResponseDto {
+            status: "success".to_string(),
+            data,
+            page,
+            limit,
+            total,
+        })
+    }
+}
+
+/// Envoltorio común para respuestas paginadas
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaginatedResponseDto<T> {
+    pub status: String,
+    pub data: Vec<T>,
+    pub page: usize,
+    pub limit: usize,
+    pub total: i64,
+}
+
+/// Permisos efectivos del usuario autenticado (`GET /api/v1/me/entitlements`).
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EntitlementsDto {
+    pub role: UserRole,
+    pub is_premium: bool,
+    pub premium_until: Option<DateTime<Utc>>,
+    pub owned_course_ids: Vec<Uuid>,
+}
+
+/// Estado de la suscripción mostrado en el panel
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DashboardSubscriptionDto {
+    pub is_premium: bool,
+    pub premium_until: Option<DateTime<Utc>>,
+    // `None` si el usuario nunca se suscribió o ya no tiene una suscripción en vigor
+    pub status: Option<SubscriptionStatus>,
+}
+
+/// Cambio de plan de una suscripción (`subscription_plan_changes`).
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct SubscriptionPlanChangeDto {
+    pub from_plan_id: Option<String>,
+    pub to_plan_id: String,
+    // "applied" | "pending_approval"
+    pub status: String,
+    pub created_at: DateTime<Utc>,
+    pub applied_at: Option<DateTime<Utc>>,
+}
+
+/// Detalle de una suscripción del usuario (`GET /api/v1/subscriptions/{id}`).
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubscriptionDetailDto {
+    #[serde(flatten)]
+    pub subscription: Subscription,
+    // `None` si el plan se borró o el id guardado no es válido
+    pub plan: Option<SubscriptionPlan>,
+    // Da acceso premium ahora: periodo pagado o de gracia en curso
+    pub is_active: bool,
+    // Próximo cobro de PayPal; solo mientras la suscripción sigue activa
+    pub next_billing_at: Option<DateTime<Utc>>,
+    pub plan_changes: Vec<SubscriptionPlanChangeDto>,
+}
+
+impl SubscriptionDetailDto {
+    pub fn new(
+        subscription: Subscription,
+        plan: Option<SubscriptionPlan>,
+        plan_changes: Vec<SubscriptionPlanChangeDto>,
+        now: DateTime<Utc>,
+    ) -> Self {
+        let is_active = subscription.grants_access(now);
+        let next_billing_at = subscription.end_time
+            .filter(|_| subscription.status == SubscriptionStatus::Active);
+        SubscriptionDetailDto { subscription, plan, is_active, next_billing_at, plan_changes }
+    }
+}
+
+/// Todo lo que necesita la pantalla de inicio en una sola llamada (`GET /api/v1/dashboard`)
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DashboardDto {
+    pub user: FilterUserDto,
+    pub in_progress_courses: Vec<EnrolledCourseDto>,
+    pub unread_notifications: i64,
+    pub recent_achievements: Vec<UserAchievementDto>,
+    pub subscription: DashboardSubscriptionDto,
+}
+
+/// Cursos de una categoría en el resumen de administración
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CategoryCountDto {
+    pub category: String,
+    pub count: i64,
+}
+
+/// Ingresos en una moneda, con el mismo formato que `PaymentResponseDTO`
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RevenueDto {
+    pub amount_minor: i64,
+    pub currency: String,
+    pub amount_display: String,
+}
+
+impl From<Money> for RevenueDto {
+    fn from(money: Money) -> Self {
+        RevenueDto {
+            amount_display: format_minor_units(money.amount_minor, &money.currency),
+            amount_minor: money.amount_minor,
+            currency: money.currency,
+        }
+    }
+}
+
+/// Resumen de la plataforma para la portada de administración (`GET /api/v1/admin/stats`)
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlatformStatsDto {
+    pub total_users: i64,
+    pub verified_users: i64,
+    // Porcentaje con un decimal; 0 si aún no hay usuarios
+    pub verified_percent: f64,
+    pub total_courses: i64,
+    pub courses_by_category: Vec<CategoryCountDto>,
+    pub total_enrollments: i64,
+    pub active_subscriptions: i64,
+    // Pagos completados desde el día 1 del mes en curso (UTC), uno por moneda
+    pub revenue_this_month: Vec<RevenueDto>,
+    pub open_reports: i64,
+}
+
+/// Espacio ocupado por los archivos del usuario (`GET /api/v1/users/me/storage`)
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserStorageDto {
+    pub used_bytes: i64,
+    pub file_count: i64,
+    // `None` si no hay límite
+    pub quota_bytes: Option<i64>,
+    pub remaining_bytes: Option<i64>,
+}
+
+impl UserStorageDto {
+    pub fn new(used_bytes: i64, file_count: i64, quota_bytes: i64) -> Self {
+        let quota_bytes = (quota_bytes > 0).then_some(quota_bytes);
+        UserStorageDto {
+            used_bytes,
+            file_count,
+            quota_bytes,
+            remaining_bytes: quota_bytes.map(|quota| (quota - used_bytes).max(0)),
+        }
+    }
+}
+
+/// Siguiente lección a recordar a un usuario inactivo.
+#[derive(Debug, sqlx::FromRow)]
+pub struct CourseReminderDto {
+    pub user_id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub course_id: Uuid,
+    pub course_title: String,
+    pub lesson_id: Uuid,
+    pub lesson_title: String,
+}
+
+/// Destinatario del correo de un aviso masivo.
+#[derive(Debug, sqlx::FromRow)]
+pub struct BroadcastRecipientDto {
+    pub user_id: Uuid,
+    pub email: String,
+    pub name: String,
+}
+
+/// Evento de rebote/queja que envía el proveedor de correo.
+#[derive(Debug, Deserialize)]
+pub struct EmailBounceEventDto {
+    #[serde(rename = "type")]
+    pub event_type: String, // "bounce" | "complaint"
+    pub email: String,
+    pub reason: Option<String>,
+}
+
+/// Usuario con el correo rebotado (vista de administración).
+#[derive(Debug, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct BouncedUserDto {
+    pub id: Uuid,
+    pub name: String,
+    pub email: String,
+    pub email_bounced_at: Option<DateTime<Utc>>,
+    pub email_bounce_reason: Option<String>,
+}
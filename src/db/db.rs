@@ -1,12 +1,12 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 
-use sqlx::{Pool, Postgres, query_scalar, query_as, query, Error, Row};
+use sqlx::{PgConnection, Pool, Postgres, Transaction, query_scalar, query_as, query, Error, Row};
 use uuid::Uuid;
 
-use crate::{config::dtos::{CommentLessonDto, CourseRatingDto, CourseWithModulesDto, CreateCourseDTO, CreateLessonDTO, CreateModuleDTO, LessonDto, ModuleWithLessonsDto, UpdateCourseDTO, UserAchievementDto, UserCourseDto},  models::models::{Achievement, Course, CourseProgress, Lesson, Module, Notification, PasswordResetToken, Payment, Subscription, SubscriptionPlan, User, UserAchievement, UserCourse, UserRole}};
+use crate::{utils::{duration::parse_duration_seconds, money::{Money, split_minor_units, to_minor_units}, slug::{slugify, unique_slug}}, config::dtos::{BouncedUserDto, BundleCourseDto, BundleDto, CreateBundleDTO, UpdateBundleDTO, BroadcastRecipientDto, CommentLessonDto, CourseAccessReason, CourseChangesetDto, CourseProgressEventDto, CourseStudentDto, ProgressImportEntryDto, ProgressImportResultDto, EntityChangesDto, CourseProgressStatus, CourseRatingDto, CourseReminderDto, CourseOutlineDto, CourseWithModulesDto, CreateCourseDTO, CreateLessonDTO, CreateModuleDTO, EnrolledCourseDto, LessonDto, OutlineLessonDto, OutlineModuleDto, SubscriptionDetailDto, SubscriptionPlanChangeDto, TagDto, ModuleWithLessonsDto, UpdateCourseDTO, UserAchievementDto, UserCourseDto},  models::models::{Achievement, AuditLogEntry, BroadcastSegment, Bundle, BroadcastStatus, ContentReport, Course, CourseProgress, CourseRevision, CourseTranslation, FeatureFlag, Invite, Lesson, Module, Notification, NotificationBroadcast, PasswordResetToken, Payment, RefreshToken, ReportStatus, Subscription, SubscriptionPlan, SubscriptionStatus, User, UserAchievement, TriggerType, UserCourse, UserRole}};
 
 #[derive(Debug, Clone)]
 pub struct DBClient {
@@ -17,6 +17,58 @@ impl DBClient {
     pub fn new(pool: Pool<Postgres>) -> Self {
         DBClient { pool }
     }
+
+    /// Transacción para los handlers que encadenan varias escrituras (p. ej. con
+    /// `register_course_purchase_in`). Si se suelta sin `commit` se descarta todo.
+    pub async fn begin(&self) -> Result<Transaction<'static, Postgres>, Error> {
+        self.pool.begin().await
+    }
+
+    /// Logros que dependen de una compra; se comprueban después de confirmarla.
+    pub async fn award_purchase_achievements(&self, user_id: Uuid) {
+        let _ = self.check_and_award_achievements(user_id, TriggerType::CoursesEnrolled).await;
+        let _ = self.check_and_award_achievements(user_id, TriggerType::TotalSpend).await;
+    }
+}
+
+/// Genera un slug único a partir del título, ignorando los slugs (actuales o
+/// históricos) que ya pertenecen al propio curso.
+async fn next_course_slug(
+    tx: &mut Transaction<'_, Postgres>,
+    title: &str,
+    course_id: Uuid,
+) -> Result<String, Error> {
+    let base = slugify(title);
+    let taken = sqlx::query_scalar::<_, String>(
+        r#"
+        SELECT slug FROM courses
+        WHERE (slug = $1 OR slug LIKE $1 || '-%') AND id <> $2
+        UNION
+        SELECT slug FROM course_slug_history
+        WHERE (slug = $1 OR slug LIKE $1 || '-%') AND course_id <> $2
+        "#
+    )
+    .bind(&base)
+    .bind(course_id)
+    .fetch_all(&mut **tx)
+    .await?;
+
+    Ok(unique_slug(&base, &taken))
+}
+
+/// Indica si degradar o bloquear `user_ids` dejaría sin administradores activos.
+/// Bloquea las filas de los administradores hasta el final de la transacción.
+async fn leaves_no_active_admin(
+    tx: &mut Transaction<'_, Postgres>,
+    user_ids: &[Uuid],
+) -> Result<bool, Error> {
+    let admins = sqlx::query_scalar::<_, Uuid>(
+        "SELECT id FROM users WHERE role = 'admin' AND NOT locked FOR UPDATE"
+    )
+    .fetch_all(&mut **tx)
+    .await?;
+
+    Ok(!admins.is_empty() && admins.iter().all(|id| user_ids.contains(id)))
 }
 
 #[async_trait]
@@ -45,8 +97,37 @@ pub trait UserExt {
         role: Option<UserRole>,
     ) -> Result<User, Error>;
 
+    /// Devuelve el usuario con ese correo o lo crea si no existe (SSO, invitaciones).
+    /// `password = None` crea una cuenta sin contraseña. El booleano indica si se creó.
+    async fn save_or_get_user_by_email(
+        &self,
+        name: &str,
+        email: &str,
+        password: Option<&str>,
+        auth_provider: &str,
+        verified: bool,
+        role: Option<UserRole>,
+    ) -> Result<(User, bool), Error>;
+
+    /// Vincula la cuenta con el proveedor externo y la marca como verificada.
+    /// Las cuentas verificadas con contraseña conservan `auth_provider = 'local'` para
+    /// seguir pudiendo iniciar sesión con ella. Una cuenta local sin verificar la pudo
+    /// registrar cualquiera con ese correo: pierde la contraseña y sus sesiones
+    /// (refresh tokens y enlaces de restablecimiento). Devuelve `false` si el usuario ya
+    /// está vinculado a otra cuenta del proveedor.
+    async fn link_auth_provider(
+        &self,
+        user_id: Uuid,
+        auth_provider: &str,
+        provider_user_id: &str,
+    ) -> Result<bool, Error>;
+
     async fn get_user_count(&self) -> Result<i64, Error>;
 
+    /// Búsqueda difusa por nombre, correo o teléfono (índice trigram), mejores coincidencias primero.
+    /// `term` debe venir normalizado con `normalize_search_term`.
+    async fn search_users(&self, term: &str, limit: usize) -> Result<Vec<User>, Error>;
+
     async fn update_user_name<T: Into<String> + Send>(
         &self,
         user_id: Uuid,
@@ -100,6 +181,63 @@ pub trait UserExt {
         &self,
         user_id: Uuid,
     ) -> Result<HashMap<String, i32>, Error>;
+
+    /// Marca como verificados todos los usuarios del lote. Devuelve los ids actualizados.
+    async fn verify_users(&self, user_ids: &[Uuid]) -> Result<Vec<Uuid>, Error>;
+
+    /// Devuelve `Ok(None)` si el lote dejaría la plataforma sin administradores activos.
+    async fn set_users_role(
+        &self,
+        user_ids: &[Uuid],
+        role: UserRole,
+    ) -> Result<Option<Vec<Uuid>>, Error>;
+
+    /// Devuelve `Ok(None)` si el lote dejaría la plataforma sin administradores activos.
+    async fn set_users_locked(
+        &self,
+        user_ids: &[Uuid],
+        locked: bool,
+    ) -> Result<Option<Vec<Uuid>>, Error>;
+
+    /// Marca el correo como rebotado. Devuelve `false` si ningún usuario tiene ese correo.
+    async fn mark_email_bounced(&self, email: &str, reason: &str) -> Result<bool, Error>;
+
+    async fn is_email_bounced(&self, user_id: Uuid) -> Result<bool, Error>;
+
+    async fn get_bounced_users(
+        &self,
+        page: u32,
+        limit: usize,
+    ) -> Result<(Vec<BouncedUserDto>, i64), Error>;
+
+    /// Registra el inicio de sesión (contraseña o proveedor externo).
+    async fn touch_last_login(&self, user_id: Uuid) -> Result<(), Error>;
+
+    /// Cambia o quita (`None`) la imagen de perfil.
+    async fn set_profile_image_url(&self, user_id: Uuid, url: Option<&str>) -> Result<User, Error>;
+
+    /// Guarda un refresh token por su hash; el token en claro solo lo tiene la cookie.
+    async fn save_refresh_token(
+        &self,
+        user_id: Uuid,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<RefreshToken, Error>;
+
+    async fn get_refresh_token(&self, token_hash: &str) -> Result<Option<RefreshToken>, Error>;
+
+    /// Revoca el token. `false` si no existe o ya estaba revocado: de dos rotaciones
+    /// simultáneas del mismo token solo una lo consigue.
+    async fn revoke_refresh_token(&self, token_hash: &str) -> Result<bool, Error>;
+
+    /// Usuarios sin iniciar sesión desde `cutoff`; los que nunca lo hicieron cuentan
+    /// desde su registro. Primero los que llevan más tiempo inactivos.
+    async fn get_inactive_users(
+        &self,
+        cutoff: DateTime<Utc>,
+        page: u32,
+        limit: usize,
+    ) -> Result<(Vec<User>, i64), Error>;
 }
 
 #[async_trait]
@@ -133,7 +271,10 @@ impl UserExt for DBClient {
                     token_expiry, 
                     role as "role: UserRole",
                     profile_image_url,
-                    subscription_expires_at
+                    subscription_expires_at,
+                    locked,
+                    auth_provider,
+                    last_login_at
                 FROM users
                 WHERE id = $1
                 "#,
@@ -163,7 +304,10 @@ impl UserExt for DBClient {
                     token_expiry, 
                     role as "role: UserRole",
                     profile_image_url,
-                    subscription_expires_at
+                    subscription_expires_at,
+                    locked,
+                    auth_provider,
+                    last_login_at
                 FROM users
                 WHERE name = $1
                 "#,
@@ -193,7 +337,10 @@ impl UserExt for DBClient {
                     token_expiry, 
                     role as "role: UserRole",
                     profile_image_url,
-                    subscription_expires_at
+                    subscription_expires_at,
+                    locked,
+                    auth_provider,
+                    last_login_at
                 FROM users
                 WHERE email = $1
                 "#,
@@ -223,7 +370,10 @@ impl UserExt for DBClient {
                     token_expiry, 
                     role as "role: UserRole",
                     profile_image_url,
-                    subscription_expires_at
+                    subscription_expires_at,
+                    locked,
+                    auth_provider,
+                    last_login_at
                 FROM users
                 WHERE verification_token = $1
                 "#,
@@ -264,7 +414,10 @@ impl UserExt for DBClient {
                 token_expiry, 
                 role as "role: UserRole",
                 profile_image_url,
-                subscription_expires_at
+                subscription_expires_at,
+                locked,
+                auth_provider,
+                last_login_at
             FROM users
             ORDER BY created_at DESC LIMIT $1 OFFSET $2"#,
             limit as i64,
@@ -311,7 +464,10 @@ impl UserExt for DBClient {
                 token_expiry, 
                 role as "role: UserRole",
                 profile_image_url,
-                subscription_expires_at
+                subscription_expires_at,
+                locked,
+                auth_provider,
+                last_login_at
             "#,
             name.into(),
             email.into(),
@@ -330,6 +486,210 @@ impl UserExt for DBClient {
         Ok(user)
     }
 
+    async fn save_or_get_user_by_email(
+        &self,
+        name: &str,
+        email: &str,
+        password: Option<&str>,
+        auth_provider: &str,
+        verified: bool,
+        role: Option<UserRole>,
+    ) -> Result<(User, bool), Error> {
+        let mut tx = self.pool.begin().await?;
+        let role = role.unwrap_or(UserRole::User);
+
+        // ON CONFLICT evita la carrera entre dos altas simultáneas del mismo correo
+        let created = query_as!(
+            User,
+            r#"
+            INSERT INTO users (name, email, password, verified, role, auth_provider)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (email) DO NOTHING
+            RETURNING
+                id, 
+                name, 
+                email, 
+                phone,
+                location,
+                bio,
+                birth_date,
+                password, 
+                verified, 
+                created_at, 
+                updated_at, 
+                verification_token, 
+                token_expiry, 
+                role as "role: UserRole",
+                profile_image_url,
+                subscription_expires_at,
+                locked,
+                auth_provider,
+                last_login_at
+            "#,
+            name,
+            email,
+            password,
+            verified,
+            role as _,
+            auth_provider
+        )
+        .fetch_optional(&mut *tx)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+
+        let result = match created {
+            Some(user) => (user, true),
+            None => {
+                let user = query_as!(
+                    User,
+                    r#"
+                    SELECT
+                id, 
+                name, 
+                email, 
+                phone,
+                location,
+                bio,
+                birth_date,
+                password, 
+                verified, 
+                created_at, 
+                updated_at, 
+                verification_token, 
+                token_expiry, 
+                role as "role: UserRole",
+                profile_image_url,
+                subscription_expires_at,
+                locked,
+                auth_provider,
+                last_login_at
+                    FROM users
+                    WHERE email = $1
+                    "#,
+                    email
+                )
+                .fetch_one(&mut *tx)
+                .await.map_err(|e| {
+                    log::error!("ERROR: {}", e);
+                    e
+                })?;
+                (user, false)
+            }
+        };
+
+        tx.commit().await?;
+        Ok(result)
+    }
+
+    async fn link_auth_provider(
+        &self,
+        user_id: Uuid,
+        auth_provider: &str,
+        provider_user_id: &str,
+    ) -> Result<bool, Error> {
+        let mut tx = self.pool.begin().await?;
+
+        // Con la fila bloqueada: el estado leído es el que ve el UPDATE
+        let Some(was_verified) = sqlx::query_scalar::<_, bool>(
+            "SELECT verified FROM users WHERE id = $1 FOR UPDATE"
+        )
+        .bind(user_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        else {
+            return Ok(false);
+        };
+
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET provider_user_id = $3,
+                password = CASE WHEN verified THEN password ELSE NULL END,
+                auth_provider = CASE WHEN password IS NULL OR NOT verified THEN $2 ELSE auth_provider END,
+                verified = true,
+                verification_token = NULL,
+                token_expiry = NULL,
+                updated_at = NOW()
+            WHERE id = $1
+              AND (provider_user_id IS NULL OR provider_user_id = $3)
+            "#,
+            user_id,
+            auth_provider,
+            provider_user_id
+        )
+        .execute(&mut *tx)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        if !was_verified {
+            revoke_all_refresh_tokens(&mut tx, user_id).await?;
+            sqlx::query("UPDATE password_reset_tokens SET used = true WHERE user_id = $1 AND used = false")
+                .bind(user_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn search_users(&self, term: &str, limit: usize) -> Result<Vec<User>, Error> {
+        // La expresión coincide con la de `idx_users_search_trgm` para que el índice sirva
+        let users = query_as!(
+            User,
+            r#"
+            WITH candidates AS (
+                SELECT
+                    u.*,
+                    lower(u.name) || ' ' || lower(u.email) || ' ' || COALESCE(u.phone, '') AS doc
+                FROM users u
+                WHERE $1 <% (lower(u.name) || ' ' || lower(u.email) || ' ' || COALESCE(u.phone, ''))
+                   OR (lower(u.name) || ' ' || lower(u.email) || ' ' || COALESCE(u.phone, '')) LIKE '%' || $2 || '%'
+            )
+            SELECT
+                id,
+                name,
+                email,
+                phone,
+                location,
+                bio,
+                birth_date,
+                password,
+                verified,
+                created_at,
+                updated_at,
+                verification_token,
+                token_expiry,
+                role as "role: UserRole",
+                profile_image_url,
+                subscription_expires_at,
+                locked,
+                auth_provider,
+                last_login_at
+            FROM candidates
+            ORDER BY (doc LIKE '%' || $2 || '%') DESC, word_similarity($1, doc) DESC, created_at DESC
+            LIMIT $3
+            "#,
+            term,
+            escape_like(term),
+            limit as i64,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+        Ok(users)
+    }
+
     async fn get_user_count(&self) -> Result<i64, Error> {
         let mut tx = self.pool.begin().await?;
         let count = sqlx::query_scalar!(
@@ -373,7 +733,10 @@ impl UserExt for DBClient {
                 token_expiry, 
                 role as "role: UserRole",
                 profile_image_url,
-                subscription_expires_at
+                subscription_expires_at,
+                locked,
+                auth_provider,
+                last_login_at
             "#,
             new_name.into(),
             user_id
@@ -415,7 +778,10 @@ impl UserExt for DBClient {
                 token_expiry, 
                 role as "role: UserRole",
                 profile_image_url,
-                subscription_expires_at
+                subscription_expires_at,
+                locked,
+                auth_provider,
+                last_login_at
             "#,
             new_role as UserRole,
             user_id
@@ -469,7 +835,10 @@ impl UserExt for DBClient {
                 token_expiry,
                 role as "role: UserRole",
                 profile_image_url,
-                subscription_expires_at
+                subscription_expires_at,
+                locked,
+                auth_provider,
+                last_login_at
             "#,
             name,
             phone,
@@ -517,7 +886,10 @@ impl UserExt for DBClient {
                 token_expiry, 
                 role as "role: UserRole",
                 profile_image_url,
-                subscription_expires_at
+                subscription_expires_at,
+                locked,
+                auth_provider,
+                last_login_at
             "#,
             new_password,
             user_id
@@ -527,6 +899,8 @@ impl UserExt for DBClient {
             e
         })?
         ;
+        // Con la contraseña anterior pudieron robar una sesión: ninguna sobrevive al cambio
+        revoke_all_refresh_tokens(&mut tx, user_id).await?;
         tx.commit().await?;
         Ok(user)
     }
@@ -624,94 +998,530 @@ impl UserExt for DBClient {
             stats.insert(row.stat_type, row.value);
         }
 
-        // Cursos completados
-        let courses_completed = sqlx::query_scalar::<_, i32>(
+        // Estadísticas derivadas, una por disparador registrado
+        for trigger in TriggerType::ALL {
+            let value = resolve_trigger_stat(&self.pool, trigger, user_id).await?;
+            stats.insert(trigger.as_str().to_string(), value);
+        }
+
+        Ok(stats)
+    }
+
+    async fn verify_users(&self, user_ids: &[Uuid]) -> Result<Vec<Uuid>, Error> {
+        let updated = sqlx::query_scalar::<_, Uuid>(
             r#"
-            SELECT COUNT(DISTINCT uc.course_id)
-            FROM user_courses uc
-            WHERE uc.user_id = $1
-            AND EXISTS (
-                SELECT 1
-                FROM user_lesson_progress lp
-                JOIN lessons l ON lp.lesson_id = l.id
-                JOIN modules m ON l.module_id = m.id
-                WHERE lp.user_id = $1
-                AND lp.is_completed = true
-                AND m.course_id = uc.course_id
-                GROUP BY m.course_id
-                HAVING COUNT(*) = (
-                    SELECT COUNT(*)
-                    FROM lessons l2
-                    JOIN modules m2 ON l2.module_id = m2.id
-                    WHERE m2.course_id = uc.course_id
-                )
-            )
+            UPDATE users
+            SET verified = true, verification_token = NULL, token_expiry = NULL, updated_at = NOW()
+            WHERE id = ANY($1)
+            RETURNING id
             "#
         )
-        .bind(user_id)
-        .fetch_one(&self.pool)
-        .await
-        .unwrap_or(0);
+        .bind(user_ids)
+        .fetch_all(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+        Ok(updated)
+    }
+
+    async fn set_users_role(
+        &self,
+        user_ids: &[Uuid],
+        role: UserRole,
+    ) -> Result<Option<Vec<Uuid>>, Error> {
+        let mut tx = self.pool.begin().await?;
 
-        stats.insert("course_completed".to_string(), courses_completed);
+        if role != UserRole::Admin && leaves_no_active_admin(&mut tx, user_ids).await? {
+            return Ok(None);
+        }
 
-        // Lecciones completadas
-        let lessons_completed = sqlx::query_scalar::<_, i32>(
-            "SELECT COUNT(*) FROM user_lesson_progress WHERE user_id = $1 AND is_completed = true",
+        let updated = sqlx::query_scalar::<_, Uuid>(
+            "UPDATE users SET role = $1, updated_at = NOW() WHERE id = ANY($2) RETURNING id"
         )
-        .bind(user_id)
-        .fetch_one(&self.pool)
-        .await
-        .unwrap_or(0);
+        .bind(role)
+        .bind(user_ids)
+        .fetch_all(&mut *tx)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
 
-        stats.insert("lesson_completed".to_string(), lessons_completed);
+        tx.commit().await?;
+        Ok(Some(updated))
+    }
 
-        // Cursos inscritos
-        let courses_enrolled = sqlx::query_scalar::<_, i32>(
-            "SELECT COUNT(*) FROM user_courses WHERE user_id = $1",
-        )
-        .bind(user_id)
-        .fetch_one(&self.pool)
-        .await
-        .unwrap_or(0);
+    async fn set_users_locked(
+        &self,
+        user_ids: &[Uuid],
+        locked: bool,
+    ) -> Result<Option<Vec<Uuid>>, Error> {
+        let mut tx = self.pool.begin().await?;
 
-        stats.insert("courses_enrolled".to_string(), courses_enrolled);
+        if locked && leaves_no_active_admin(&mut tx, user_ids).await? {
+            return Ok(None);
+        }
 
-        // Comentarios
-        let comments_created = sqlx::query_scalar::<_, i32>(
-            "SELECT COUNT(*) FROM comments WHERE user_id = $1",
+        let updated = sqlx::query_scalar::<_, Uuid>(
+            "UPDATE users SET locked = $1, updated_at = NOW() WHERE id = ANY($2) RETURNING id"
         )
-        .bind(user_id)
-        .fetch_one(&self.pool)
-        .await
-        .unwrap_or(0);
-
-        stats.insert("comments_created".to_string(), comments_created);
+        .bind(locked)
+        .bind(user_ids)
+        .fetch_all(&mut *tx)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
 
+        tx.commit().await?;
+        Ok(Some(updated))
+    }
 
-        Ok(stats)
+    async fn mark_email_bounced(&self, email: &str, reason: &str) -> Result<bool, Error> {
+        let updated = sqlx::query(
+            r#"
+            UPDATE users
+            SET email_bounced_at = NOW(), email_bounce_reason = $2, updated_at = NOW()
+            WHERE LOWER(email) = LOWER($1)
+            "#
+        )
+        .bind(email)
+        .bind(reason)
+        .execute(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+        Ok(updated.rows_affected() > 0)
     }
-}
+
+    async fn is_email_bounced(&self, user_id: Uuid) -> Result<bool, Error> {
+        let bounced = sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS(SELECT 1 FROM users WHERE id = $1 AND email_bounced_at IS NOT NULL)"
+        )
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+        Ok(bounced)
+    }
+
+    async fn get_bounced_users(
+        &self,
+        page: u32,
+        limit: usize,
+    ) -> Result<(Vec<BouncedUserDto>, i64), Error> {
+        let offset = ((page - 1) * limit as u32) as i64;
+
+        let users = sqlx::query_as::<_, BouncedUserDto>(
+            r#"
+            SELECT id, name, email, email_bounced_at, email_bounce_reason
+            FROM users
+            WHERE email_bounced_at IS NOT NULL
+            ORDER BY email_bounced_at DESC
+            LIMIT $1 OFFSET $2
+            "#
+        )
+        .bind(limit as i64)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+
+        let total = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM users WHERE email_bounced_at IS NOT NULL"
+        )
+        .fetch_one(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+
+        Ok((users, total))
+    }
+
+    async fn set_profile_image_url(&self, user_id: Uuid, url: Option<&str>) -> Result<User, Error> {
+        sqlx::query_as::<_, User>(
+            "UPDATE users SET profile_image_url = $1, updated_at = NOW() WHERE id = $2 RETURNING *"
+        )
+        .bind(url)
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn touch_last_login(&self, user_id: Uuid) -> Result<(), Error> {
+        sqlx::query("UPDATE users SET last_login_at = NOW() WHERE id = $1")
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn save_refresh_token(
+        &self,
+        user_id: Uuid,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<RefreshToken, Error> {
+        sqlx::query_as::<_, RefreshToken>(
+            r#"
+            INSERT INTO refresh_tokens (user_id, token_hash, expires_at)
+            VALUES ($1, $2, $3)
+            RETURNING user_id, expires_at, revoked
+            "#
+        )
+        .bind(user_id)
+        .bind(token_hash)
+        .bind(expires_at)
+        .fetch_one(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })
+    }
+
+    async fn get_refresh_token(&self, token_hash: &str) -> Result<Option<RefreshToken>, Error> {
+        sqlx::query_as::<_, RefreshToken>("SELECT user_id, expires_at, revoked FROM refresh_tokens WHERE token_hash = $1")
+            .bind(token_hash)
+            .fetch_optional(&self.pool)
+            .await.map_err(|e| {
+                log::error!("ERROR: {}", e);
+                e
+            })
+    }
+
+    async fn revoke_refresh_token(&self, token_hash: &str) -> Result<bool, Error> {
+        let result = sqlx::query(
+            "UPDATE refresh_tokens SET revoked = true WHERE token_hash = $1 AND revoked = false"
+        )
+        .bind(token_hash)
+        .execute(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_inactive_users(
+        &self,
+        cutoff: DateTime<Utc>,
+        page: u32,
+        limit: usize,
+    ) -> Result<(Vec<User>, i64), Error> {
+        let offset = ((page - 1) * limit as u32) as i64;
+
+        let users = sqlx::query_as::<_, User>(
+            r#"
+            SELECT *
+            FROM users
+            WHERE COALESCE(last_login_at, created_at) < $1
+            ORDER BY COALESCE(last_login_at, created_at) ASC
+            LIMIT $2 OFFSET $3
+            "#
+        )
+        .bind(cutoff)
+        .bind(limit as i64)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+
+        let total = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM users WHERE COALESCE(last_login_at, created_at) < $1"
+        )
+        .bind(cutoff)
+        .fetch_one(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+
+        Ok((users, total))
+    }
+}
 
 // ===================== //
 //      COURSES EXT 
 // ===================== //
 
+/// Fila del catálogo: un curso con uno de sus módulos y una de sus lecciones.
+#[derive(Debug, Clone)]
+pub(crate) struct CatalogRow {
+    pub course_id: Uuid,
+    pub course_title: String,
+    pub description: String,
+    pub long_description: Option<String>,
+    pub level: Option<String>,
+    pub price: f64,
+    pub effective_price: f64,
+    pub duration: Option<String>,
+    pub students: Option<i32>,
+    pub image: Option<String>,
+    pub trailer_url: Option<String>,
+    pub category: Option<String>,
+    pub features: Option<serde_json::Value>,
+    pub tags: Vec<String>,
+    #[allow(dead_code)]
+    pub paypal_product_id: Option<String>,
+    pub version: i32,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub module_id: Option<Uuid>,
+    pub module_title: Option<String>,
+    pub module_order: Option<i32>,
+    pub lesson_id: Option<Uuid>,
+    pub lesson_title: Option<String>,
+    pub lesson_duration: Option<String>,
+    pub lesson_type: Option<String>,
+    pub content_url: Option<String>,
+    pub lesson_description: Option<String>,
+    pub lesson_order: Option<i32>,
+    pub lesson_is_preview: Option<bool>,
+}
+
+/// Fila de `get_course_outline`: un módulo con una de sus lecciones (o ninguna).
+#[derive(Debug, sqlx::FromRow)]
+pub(crate) struct OutlineRow {
+    pub module_id: Uuid,
+    pub module_title: String,
+    pub module_order: i32,
+    pub lesson_id: Option<Uuid>,
+    pub lesson_title: Option<String>,
+    pub lesson_duration: Option<String>,
+    pub lesson_type: Option<String>,
+    pub lesson_order: Option<i32>,
+    pub lesson_is_preview: Option<bool>,
+}
+
+/// Agrupa las filas ya ordenadas por módulo y lección en el índice del curso.
+pub(crate) fn group_outline_rows(course_id: Uuid, title: String, rows: Vec<OutlineRow>) -> CourseOutlineDto {
+    let mut modules: Vec<OutlineModuleDto> = Vec::new();
+    for row in rows {
+        if modules.last().is_none_or(|m| m.id != row.module_id) {
+            modules.push(OutlineModuleDto {
+                id: row.module_id,
+                title: row.module_title,
+                order: row.module_order,
+                lessons: vec![],
+            });
+        }
+        let module = modules.last_mut().expect("se acaba de insertar");
+
+        if let Some(lesson_id) = row.lesson_id {
+            module.lessons.push(OutlineLessonDto {
+                id: lesson_id,
+                title: row.lesson_title.unwrap_or_default(),
+                duration: row.lesson_duration,
+                r#type: row.lesson_type.unwrap_or_default(),
+                order: row.lesson_order.unwrap_or(1),
+                is_preview: row.lesson_is_preview.unwrap_or(false),
+            });
+        }
+    }
+
+    let lessons = || modules.iter().flat_map(|m| &m.lessons);
+    CourseOutlineDto {
+        id: course_id,
+        title,
+        total_lessons: lessons().count() as i64,
+        computed_duration_seconds: lessons()
+            .filter_map(|l| l.duration.as_deref().and_then(parse_duration_seconds))
+            .sum(),
+        modules,
+    }
+}
+
+/// Agrupa las filas del catálogo en cursos con sus módulos y lecciones. Un valor
+/// ausente en una fila toma un valor por defecto (y se registra) en lugar de hacer
+/// fallar todo el listado.
+pub(crate) fn group_catalog_rows(rows: Vec<CatalogRow>) -> Vec<CourseWithModulesDto> {
+    let mut courses_map: HashMap<Uuid, CourseWithModulesDto> = HashMap::new();
+    for row in rows {
+
+        // 1️⃣ Asegurar que el curso exista en el mapa
+        let course = courses_map
+            .entry(row.course_id)
+            .or_insert_with(|| {
+                if row.level.is_none() || row.category.is_none() {
+                    log::warn!("Curso {} sin nivel o categoría; se listan vacíos", row.course_id);
+                }
+                CourseWithModulesDto {
+                    id: row.course_id,
+                    title: row.course_title.clone(),
+                    description: row.description.clone(),
+                    long_description: row.long_description.clone(),
+                    price: row.price,
+                    effective_price: row.effective_price,
+                    level: row.level.clone().unwrap_or_default(),
+                    duration: row.duration.clone(),
+                    students: row.students.unwrap_or(0),
+                    image: row.image.clone(),
+                    trailer_url: row.trailer_url.clone(),
+                    category: row.category.clone().unwrap_or_default(),
+                    features: row.features
+                        .as_ref()
+                        .and_then(|v| serde_json::from_value(v.clone()).ok()),
+                    tags: row.tags.clone(),
+                    version: row.version,
+                    created_at: row.created_at,
+                    updated_at: row.updated_at,
+                    total_lessons: 0,
+                    completed_lessons: 0,
+                    computed_duration_seconds: 0,
+                    modules: vec![],
+                }
+            });
+
+        // 2️⃣ Si hay un módulo
+        if let Some(module_id) = row.module_id {
+            let module = course.modules
+                .iter_mut()
+                .find(|m| m.id == module_id);
+
+            let module_ref = match module {
+                Some(m) => m,
+                None => {
+                    course.modules.push(ModuleWithLessonsDto {
+                        id: module_id,
+                        title: row.module_title.unwrap_or("Title".to_string()),
+                        order: row.module_order.unwrap_or(1),
+                        lessons: vec![],
+                    });
+                    course.modules.last_mut().unwrap()
+                }
+            };
+
+            // 3️⃣ Si hay una lección
+            if let Some(lesson_id) = row.lesson_id {
+                module_ref.lessons.push(LessonDto {
+                    id: lesson_id,
+                    title: row.lesson_title.unwrap_or("Title".to_string()),
+                    duration: row.lesson_duration,
+                    completed: None,
+                    r#type: row.lesson_type.unwrap_or_else(|| "video".into()),
+                    content_url: row.content_url,
+                    description: row.lesson_description,
+                    order: row.lesson_order.unwrap_or(1),
+                    is_preview: row.lesson_is_preview.unwrap_or(false),
+                });
+            }
+        }
+    }
+    for course in courses_map.values_mut() {
+        course.computed_duration_seconds = course.lesson_duration_seconds();
+    }
+    courses_map.into_values().collect()
+}
+
 #[async_trait]
 pub trait CourseExt {
+    /// Devuelve el id del curso creado junto con lo guardado.
     async fn create_course(
         &self,
         dto: CreateCourseDTO,
-    ) -> Result<CreateCourseDTO, Error>;
+    ) -> Result<(Uuid, CreateCourseDTO), Error>;
 
     async fn get_course(&self, course_id: Uuid) -> Result<Option<Course>, Error>;
 
+    async fn get_course_by_slug(&self, slug: &str) -> Result<Option<Course>, Error>;
+
+    /// Módulos y lecciones del curso sin su contenido. `None` si el curso no existe.
+    async fn get_course_outline(&self, course_id: Uuid) -> Result<Option<CourseOutlineDto>, Error>;
+
+    /// Cursos modificados después de `since` y los ids de los eliminados desde entonces.
+    async fn get_courses_changed_since(
+        &self,
+        since: DateTime<Utc>,
+    ) -> Result<(Vec<UserCourseDto>, Vec<Uuid>), Error>;
+
     async fn get_user_courses(&self, user_id: Uuid) -> Result<Vec<UserCourseDto>, Error>;
 
+    /// Una página de los cursos con esos ids (sin repetidos), los más nuevos primero,
+    /// con el total para paginar.
+    async fn get_courses_by_ids(
+        &self,
+        course_ids: &[Uuid],
+        page: u32,
+        limit: usize,
+    ) -> Result<(Vec<UserCourseDto>, i64), Error>;
+
+    /// Catálogo paginado. Con `tags` solo los cursos con alguna de ellas, o con todas si `match_all`.
     async fn get_courses(
         &self,
         page: u32,
         limit: usize,
+        tags: &[String],
+        match_all: bool,
+    ) -> Result<Vec<UserCourseDto>, Error>;
+
+    /// Cursos destacados por `featured_order` (sin orden al final, los más nuevos primero).
+    async fn get_featured_courses(&self, limit: i64) -> Result<Vec<UserCourseDto>, Error>;
+
+    /// Ids de todos los cursos, del más antiguo al más nuevo.
+    async fn get_course_ids(&self) -> Result<Vec<Uuid>, Error>;
+
+    /// `None` si el curso no existe. Al desmarcarlo se descarta también su orden.
+    async fn set_course_featured(
+        &self,
+        course_id: Uuid,
+        featured: bool,
+        featured_order: Option<i32>,
+    ) -> Result<Option<Course>, Error>;
+
+    /// Programa la oferta del curso, o la quita con `sale_price = None`. `None` si el
+    /// curso no existe.
+    async fn set_course_sale(
+        &self,
+        course_id: Uuid,
+        sale_price: Option<f64>,
+        sale_starts_at: Option<DateTime<Utc>>,
+        sale_ends_at: Option<DateTime<Utc>>,
+    ) -> Result<Option<Course>, Error>;
+
+    /// Curso con los textos de la primera traducción disponible de `locales` (en orden de
+    /// preferencia) y el idioma de esa traducción; sin ninguna, el curso base y `None`.
+    async fn get_course_localized(
+        &self,
+        course_id: Uuid,
+        locales: &[String],
+    ) -> Result<Option<(Course, Option<String>)>, Error>;
+
+    /// La mejor traducción de `locales` para cada curso de `course_ids` que tenga alguna.
+    async fn get_course_translations_for(
+        &self,
+        course_ids: &[Uuid],
+        locales: &[String],
+    ) -> Result<Vec<CourseTranslation>, Error>;
+
+    async fn get_course_translations(&self, course_id: Uuid) -> Result<Vec<CourseTranslation>, Error>;
+
+    /// Crea o reemplaza la traducción. `None` si el curso no existe.
+    async fn upsert_course_translation(
+        &self,
+        course_id: Uuid,
+        locale: &str,
+        title: &str,
+        description: &str,
+        long_description: Option<&str>,
+    ) -> Result<Option<CourseTranslation>, Error>;
+
+    /// `false` si no había traducción en ese idioma.
+    async fn delete_course_translation(&self, course_id: Uuid, locale: &str) -> Result<bool, Error>;
+
+    /// Cursos de la misma categoría (primero los del mismo nivel) que el usuario aún no tiene.
+    async fn get_related_courses(
+        &self,
+        course_id: Uuid,
+        user_id: Uuid,
+        limit: i64,
     ) -> Result<Vec<UserCourseDto>, Error>;
 
     async fn get_all_courses_with_modules(
@@ -730,14 +1540,34 @@ pub trait CourseExt {
         user_id: Option<Uuid>,
     ) -> Result<Option<CourseWithModulesDto>, sqlx::Error>;
 
+    /// Devuelve `Ok(None)` si la versión enviada ya no es la vigente. El contenido previo
+    /// queda como revisión de `edited_by`; se conservan las últimas `keep_revisions` (0 = todas).
     async fn update_course(
         &self,
         course_id: Uuid,
         dto: UpdateCourseDTO,
-    ) -> Result<CourseWithModulesDto, Error>;
+        edited_by: Uuid,
+        keep_revisions: i64,
+    ) -> Result<Option<CourseWithModulesDto>, Error>;
+
+    /// Módulos y lecciones que `update_course` insertaría, actualizaría o borraría, sin
+    /// aplicar nada. Devuelve `Ok(None)` si la versión enviada ya no es la vigente.
+    async fn preview_course_update(
+        &self,
+        course_id: Uuid,
+        dto: UpdateCourseDTO,
+    ) -> Result<Option<CourseChangesetDto>, Error>;
 
     async fn delete_course(&self, course_id: Uuid) -> Result<(), Error>;
 
+    async fn get_courses_missing_paypal_product(&self) -> Result<Vec<Course>, Error>;
+
+    async fn set_course_paypal_product_id(
+        &self,
+        course_id: Uuid,
+        paypal_product_id: &str,
+    ) -> Result<Course, Error>;
+
     #[allow(dead_code)]
     async fn get_course_count(&self) -> Result<i64, Error>;
 
@@ -781,7 +1611,7 @@ impl CourseExt for DBClient {
     async fn create_course(
         &self,
         dto: CreateCourseDTO,
-    ) -> Result<CreateCourseDTO, Error> {
+    ) -> Result<(Uuid, CreateCourseDTO), Error> {
         let course_id = Uuid::new_v4();
         let now = Utc::now();
 
@@ -800,29 +1630,45 @@ impl CourseExt for DBClient {
             None => serde_json::Value::Array(vec![]),
         };
 
-        let course_insert_result = sqlx::query_as::<_, Course>(
+        let slug = match next_course_slug(&mut tx, &dto.title, course_id).await {
+            Ok(s) => s,
+            Err(e) => {
+                let _ = tx.rollback().await;
+                return Err(e);
+            }
+        };
+
+        // `level`, `students` y `category` tienen valor por defecto pero admiten NULL en el esquema
+        let course_insert_result = query_as!(
+            Course,
             r#"
             INSERT INTO courses
-                (id, title, description, long_description, level, price, duration, students, image, category, features, paypal_product_id, created_at, updated_at)
+                (id, title, slug, description, long_description, level, price, duration, students, image, trailer_url, category, features, paypal_product_id, created_at, updated_at)
             VALUES
-                ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
-            RETURNING *
-            "#
+                ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)
+            RETURNING
+                id, title, slug, description, long_description,
+                level AS "level!", price, duration, students AS "students!", image, trailer_url,
+                category AS "category!", features, paypal_product_id, version, created_at, updated_at,
+                featured, featured_order, max_students, sale_price, sale_starts_at, sale_ends_at
+            "#,
+            course_id,
+            &dto.title,
+            &slug,
+            &dto.description,
+            dto.long_description.as_deref(),
+            &dto.level,
+            dto.price,
+            dto.duration.as_deref(),
+            dto.students.unwrap_or(0),
+            dto.image.as_deref(),
+            dto.trailer_url.as_deref(),
+            &dto.category,
+            features_json,
+            dto.paypal_product_id.as_deref(),
+            now,
+            now,
         )
-        .bind(course_id)
-        .bind(&dto.title)
-        .bind(&dto.description)
-        .bind(&dto.long_description)
-        .bind(&dto.level)
-        .bind(dto.price) // Asegúrate que dto.price sea compatible con DECIMAL
-        .bind(&dto.duration)
-        .bind(dto.students.unwrap_or(0))
-        .bind(&dto.image)
-        .bind(&dto.category)
-        .bind(features_json)
-        .bind(&dto.paypal_product_id)
-        .bind(now)
-        .bind(now)
         .fetch_one(&mut *tx)
         .await;
 
@@ -841,16 +1687,17 @@ impl CourseExt for DBClient {
             // Forzamos el orden basado en el índice para evitar error de UNIQUE constraint
             let module_order = (module_idx + 1) as i32; 
 
-            let module_insert = sqlx::query_as::<_, Module>(
+            let module_insert = query_as!(
+                Module,
                 r#"
                 INSERT INTO modules (course_id, title, "order")
                 VALUES ($1, $2, $3)
-                RETURNING *
-                "#
+                RETURNING id, course_id, title, "order"
+                "#,
+                course_id,
+                &module_dto.title,
+                module_order,
             )
-            .bind(course_id)
-            .bind(&module_dto.title)
-            .bind(module_order)
             .fetch_one(&mut *tx)
             .await;
 
@@ -868,20 +1715,24 @@ impl CourseExt for DBClient {
                 // Forzamos el orden también aquí
                 let lesson_order = (lesson_idx + 1) as i32;
 
-                let lesson_insert = sqlx::query_as::<_, Lesson>(
+                let lesson_insert = query_as!(
+                    Lesson,
                     r#"
-                    INSERT INTO lessons (module_id, title, duration, "type", content_url, description, "order")
-                    VALUES ($1, $2, $3, $4, $5, $6, $7)
-                    RETURNING *
-                    "#
+                    INSERT INTO lessons (module_id, title, duration, "type", content_url, description, "order", is_preview)
+                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                    RETURNING
+                        id, module_id, title, duration, completed AS "completed?", "type",
+                        content_url, description, "order", is_preview
+                    "#,
+                    module_model.id,
+                    &lesson.title,
+                    lesson.duration.as_deref(),
+                    &lesson.r#type,
+                    lesson.content_url.as_deref(),
+                    lesson.description.as_deref(),
+                    lesson_order,
+                    lesson.is_preview,
                 )
-                .bind(module_model.id)
-                .bind(&lesson.title)
-                .bind(&lesson.duration)
-                .bind(&lesson.r#type)
-                .bind(&lesson.content_url)
-                .bind(&lesson.description)
-                .bind(lesson_order)
                 .fetch_one(&mut *tx)
                 .await;
 
@@ -901,6 +1752,7 @@ impl CourseExt for DBClient {
                     content_url: lesson_model.content_url,
                     description: lesson_model.description,
                     order: Some(lesson_order),
+                    is_preview: lesson_model.is_preview,
                 });
             }
 
@@ -916,7 +1768,7 @@ impl CourseExt for DBClient {
             return Err(e);
         }
 
-        Ok(CreateCourseDTO {
+        Ok((course_id, CreateCourseDTO {
             title: course.title,
             description: course.description,
             long_description: course.long_description,
@@ -925,11 +1777,12 @@ impl CourseExt for DBClient {
             duration: course.duration,
             students: Some(course.students),
             image: course.image,
+            trailer_url: course.trailer_url,
             category: course.category,
             features: course.features.and_then(|f| serde_json::from_value(f).ok()),
-            paypal_product_id: None,
+            paypal_product_id: course.paypal_product_id,
             modules: modules_dtos,
-        })
+        }))
     }
 
     async fn get_course(&self, course_id: Uuid) -> Result<Option<Course>, Error> {
@@ -948,16 +1801,77 @@ impl CourseExt for DBClient {
         Ok(course)
     }
 
-    async fn get_user_courses(
-        &self,
-        user_id: Uuid
-    ) -> Result<Vec<UserCourseDto>, Error> {
+    /// Busca por slug actual y, si no existe, por los slugs históricos del curso.
+    async fn get_course_outline(&self, course_id: Uuid) -> Result<Option<CourseOutlineDto>, Error> {
+        let Some(title) = sqlx::query_scalar::<_, String>("SELECT title FROM courses WHERE id = $1")
+            .bind(course_id)
+            .fetch_optional(&self.pool)
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        // Solo las columnas del índice: ni `content_url` ni `description`
+        let rows = sqlx::query_as::<_, OutlineRow>(
+            r#"
+            SELECT
+                m.id AS module_id,
+                m.title AS module_title,
+                m."order" AS module_order,
+                l.id AS lesson_id,
+                l.title AS lesson_title,
+                l.duration AS lesson_duration,
+                l."type" AS lesson_type,
+                l."order" AS lesson_order,
+                l.is_preview AS lesson_is_preview
+            FROM modules m
+            LEFT JOIN lessons l ON l.module_id = m.id
+            WHERE m.course_id = $1
+            ORDER BY m."order", m.id, l."order", l.id
+            "#
+        )
+        .bind(course_id)
+        .fetch_all(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+
+        Ok(Some(group_outline_rows(course_id, title, rows)))
+    }
+
+    async fn get_course_by_slug(&self, slug: &str) -> Result<Option<Course>, Error> {
+        let course = sqlx::query_as::<_, Course>(
+            r#"
+            SELECT c.* FROM courses c
+            WHERE c.slug = $1
+            UNION ALL
+            SELECT c.* FROM courses c
+            JOIN course_slug_history h ON h.course_id = c.id
+            WHERE h.slug = $1
+            LIMIT 1
+            "#,
+        )
+        .bind(slug)
+        .fetch_optional(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+        Ok(course)
+    }
 
+    async fn get_courses_changed_since(
+        &self,
+        since: DateTime<Utc>,
+    ) -> Result<(Vec<UserCourseDto>, Vec<Uuid>), Error> {
+        let mut tx = self.pool.begin().await?;
         let courses = sqlx::query_as::<_, UserCourseDto>(
             r#"
             SELECT
                 c.id,
                 c.title,
+                c.slug,
                 c.description,
                 c.long_description,
                 c.level,
@@ -965,6 +1879,7 @@ impl CourseExt for DBClient {
                 c.students,
                 c.paypal_product_id,
                 c.price,
+                course_effective_price(c.price, c.sale_price, c.sale_starts_at, c.sale_ends_at) AS effective_price,
                 c.image,
                 c.category,
                 COALESCE(AVG(cr.rating), 0)::int AS rating,
@@ -972,34 +1887,143 @@ impl CourseExt for DBClient {
                 c.created_at,
                 c.updated_at,
                 c.features
-                -- user course
-
             FROM courses c
-            INNER JOIN user_courses uc
-                ON uc.course_id = c.id
             LEFT JOIN course_ratings cr
                 ON cr.course_id = c.id
-            WHERE uc.user_id = $1
+            WHERE c.updated_at > $1
             GROUP BY c.id
-            ORDER BY c.created_at DESC
+            ORDER BY c.updated_at ASC
             "#
         )
-        .bind(user_id)
-        .fetch_all(&self.pool)
-        .await
-        .map_err(|e| {
-            log::error!("ERROR get_user_courses: {}", e);
+        .bind(since)
+        .fetch_all(&mut *tx)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+
+        let deleted = sqlx::query_scalar::<_, Uuid>(
+            "SELECT course_id FROM course_tombstones WHERE deleted_at > $1 ORDER BY deleted_at ASC"
+        )
+        .bind(since)
+        .fetch_all(&mut *tx)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+
+        tx.commit().await?;
+        Ok((courses, deleted))
+    }
+
+    async fn get_user_courses(
+        &self,
+        user_id: Uuid
+    ) -> Result<Vec<UserCourseDto>, Error> {
+
+        let courses = sqlx::query_as::<_, UserCourseDto>(
+            r#"
+            SELECT
+                c.id,
+                c.title,
+                c.slug,
+                c.description,
+                c.long_description,
+                c.level,
+                c.duration,
+                c.students,
+                c.paypal_product_id,
+                c.price,
+                course_effective_price(c.price, c.sale_price, c.sale_starts_at, c.sale_ends_at) AS effective_price,
+                c.image,
+                c.category,
+                COALESCE(AVG(cr.rating), 0)::int AS rating,
+                COUNT(cr.id) AS rating_count,
+                c.created_at,
+                c.updated_at,
+                c.features
+                -- user course
+
+            FROM courses c
+            INNER JOIN user_courses uc
+                ON uc.course_id = c.id
+            LEFT JOIN course_ratings cr
+                ON cr.course_id = c.id
+            WHERE uc.user_id = $1
+            GROUP BY c.id
+            ORDER BY c.created_at DESC
+            "#
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            log::error!("ERROR get_user_courses: {}", e);
             e
         })?;
 
         Ok(courses)
     }
 
+    async fn get_courses_by_ids(
+        &self,
+        course_ids: &[Uuid],
+        page: u32,
+        limit: usize,
+    ) -> Result<(Vec<UserCourseDto>, i64), Error> {
+        let offset = ((page - 1) * limit as u32) as i64;
+
+        let courses = sqlx::query_as::<_, UserCourseDto>(
+            r#"
+            SELECT
+                c.id,
+                c.title,
+                c.slug,
+                c.description,
+                c.long_description,
+                c.level,
+                c.duration,
+                c.students,
+                c.paypal_product_id,
+                c.price,
+                course_effective_price(c.price, c.sale_price, c.sale_starts_at, c.sale_ends_at) AS effective_price,
+                c.image,
+                c.category,
+                COALESCE(AVG(cr.rating), 0)::int AS rating,
+                COUNT(cr.id) AS rating_count,
+                c.created_at,
+                c.updated_at,
+                c.features
+            FROM courses c
+            LEFT JOIN course_ratings cr
+                ON cr.course_id = c.id
+            WHERE c.id = ANY($1)
+            GROUP BY c.id
+            ORDER BY c.created_at DESC
+            LIMIT $2 OFFSET $3
+            "#
+        )
+        .bind(course_ids)
+        .bind(limit as i64)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            log::error!("ERROR get_courses_by_ids: {}", e);
+            e
+        })?;
+
+        // Los ids ya vienen sin repetidos, así que el total es el número de ids recibidos
+        Ok((courses, course_ids.len() as i64))
+    }
+
 
     async fn get_courses(
         &self,
         page: u32,
         limit: usize,
+        tags: &[String],
+        match_all: bool,
     ) -> Result<Vec<UserCourseDto>, Error> {
         let mut tx = self.pool.begin().await?;
         let offset = ((page - 1) * limit as u32) as i64;
@@ -1008,6 +2032,7 @@ impl CourseExt for DBClient {
             SELECT
                 c.id,
                 c.title,
+                c.slug,
                 c.description,
                 c.long_description,
                 c.level,
@@ -1015,6 +2040,7 @@ impl CourseExt for DBClient {
                 c.students,
                 c.paypal_product_id,
                 c.price,
+                course_effective_price(c.price, c.sale_price, c.sale_starts_at, c.sale_ends_at) AS effective_price,
                 c.image,
                 c.category,
                 COALESCE(AVG(cr.rating), 0)::int AS rating,
@@ -1027,6 +2053,12 @@ impl CourseExt for DBClient {
             FROM courses c
             LEFT JOIN course_ratings cr
                 ON cr.course_id = c.id
+            -- `$3` llega sin repetidos: con `$4` el curso debe tenerlas todas
+            WHERE cardinality($3::text[]) = 0
+               OR (
+                    SELECT COUNT(*) FROM course_tags ct JOIN tags t ON t.id = ct.tag_id
+                    WHERE ct.course_id = c.id AND t.name = ANY($3)
+               ) >= CASE WHEN $4 THEN cardinality($3::text[]) ELSE 1 END
             GROUP BY c.id
             ORDER BY c.created_at DESC
             LIMIT $1 OFFSET $2
@@ -1034,6 +2066,8 @@ impl CourseExt for DBClient {
         )
         .bind(limit as i64)
         .bind(offset)
+        .bind(tags)
+        .bind(match_all)
         .fetch_all(&mut *tx)
         .await.map_err(|e| {
             log::error!("ERROR: {}", e);
@@ -1043,13 +2077,268 @@ impl CourseExt for DBClient {
         Ok(courses)
     }
 
+    async fn get_featured_courses(&self, limit: i64) -> Result<Vec<UserCourseDto>, Error> {
+        let courses = sqlx::query_as::<_, UserCourseDto>(
+            r#"
+            SELECT
+                c.id,
+                c.title,
+                c.slug,
+                c.description,
+                c.long_description,
+                c.level,
+                c.duration,
+                c.students,
+                c.paypal_product_id,
+                c.price,
+                course_effective_price(c.price, c.sale_price, c.sale_starts_at, c.sale_ends_at) AS effective_price,
+                c.image,
+                c.category,
+                COALESCE(AVG(cr.rating), 0)::int AS rating,
+                COUNT(cr.id) AS rating_count,
+                c.created_at,
+                c.updated_at,
+                c.features
+            FROM courses c
+            LEFT JOIN course_ratings cr
+                ON cr.course_id = c.id
+            WHERE c.featured
+            GROUP BY c.id
+            ORDER BY c.featured_order ASC NULLS LAST, c.created_at DESC
+            LIMIT $1
+            "#
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+        Ok(courses)
+    }
+
+    async fn get_course_ids(&self) -> Result<Vec<Uuid>, Error> {
+        sqlx::query_scalar::<_, Uuid>("SELECT id FROM courses ORDER BY created_at ASC, id ASC")
+            .fetch_all(&self.pool)
+            .await.map_err(|e| {
+                log::error!("ERROR: {}", e);
+                e
+            })
+    }
+
+    async fn set_course_featured(
+        &self,
+        course_id: Uuid,
+        featured: bool,
+        featured_order: Option<i32>,
+    ) -> Result<Option<Course>, Error> {
+        sqlx::query_as::<_, Course>(
+            r#"
+            UPDATE courses
+            SET featured = $2,
+                featured_order = CASE WHEN $2 THEN $3 ELSE NULL END,
+                updated_at = NOW()
+            WHERE id = $1
+            RETURNING *
+            "#
+        )
+        .bind(course_id)
+        .bind(featured)
+        .bind(featured_order)
+        .fetch_optional(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })
+    }
+
+    async fn set_course_sale(
+        &self,
+        course_id: Uuid,
+        sale_price: Option<f64>,
+        sale_starts_at: Option<DateTime<Utc>>,
+        sale_ends_at: Option<DateTime<Utc>>,
+    ) -> Result<Option<Course>, Error> {
+        sqlx::query_as::<_, Course>(
+            r#"
+            UPDATE courses
+            SET sale_price = $2,
+                sale_starts_at = $3,
+                sale_ends_at = $4,
+                updated_at = NOW()
+            WHERE id = $1
+            RETURNING *
+            "#
+        )
+        .bind(course_id)
+        .bind(sale_price)
+        .bind(sale_starts_at)
+        .bind(sale_ends_at)
+        .fetch_optional(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })
+    }
+
+    async fn get_course_localized(
+        &self,
+        course_id: Uuid,
+        locales: &[String],
+    ) -> Result<Option<(Course, Option<String>)>, Error> {
+        let Some(course) = self.get_course(course_id).await? else {
+            return Ok(None);
+        };
+
+        let translation = self.get_course_translations_for(&[course_id], locales).await?;
+        Ok(Some(match translation.into_iter().next() {
+            Some(t) => {
+                let course = Course {
+                    title: t.title,
+                    description: t.description,
+                    long_description: t.long_description.or(course.long_description),
+                    ..course
+                };
+                (course, Some(t.locale))
+            }
+            None => (course, None),
+        }))
+    }
+
+    async fn get_course_translations_for(
+        &self,
+        course_ids: &[Uuid],
+        locales: &[String],
+    ) -> Result<Vec<CourseTranslation>, Error> {
+        if course_ids.is_empty() || locales.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        sqlx::query_as::<_, CourseTranslation>(
+            r#"
+            SELECT DISTINCT ON (course_id) *
+            FROM course_translations
+            WHERE course_id = ANY($1) AND locale = ANY($2)
+            ORDER BY course_id, array_position($2, locale::text)
+            "#
+        )
+        .bind(course_ids)
+        .bind(locales)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn get_course_translations(&self, course_id: Uuid) -> Result<Vec<CourseTranslation>, Error> {
+        sqlx::query_as::<_, CourseTranslation>(
+            "SELECT * FROM course_translations WHERE course_id = $1 ORDER BY locale"
+        )
+        .bind(course_id)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn upsert_course_translation(
+        &self,
+        course_id: Uuid,
+        locale: &str,
+        title: &str,
+        description: &str,
+        long_description: Option<&str>,
+    ) -> Result<Option<CourseTranslation>, Error> {
+        sqlx::query_as::<_, CourseTranslation>(
+            r#"
+            INSERT INTO course_translations (course_id, locale, title, description, long_description)
+            SELECT id, $2, $3, $4, $5 FROM courses WHERE id = $1
+            ON CONFLICT (course_id, locale) DO UPDATE
+            SET title = EXCLUDED.title,
+                description = EXCLUDED.description,
+                long_description = EXCLUDED.long_description,
+                updated_at = NOW()
+            RETURNING *
+            "#
+        )
+        .bind(course_id)
+        .bind(locale)
+        .bind(title)
+        .bind(description)
+        .bind(long_description)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    async fn delete_course_translation(&self, course_id: Uuid, locale: &str) -> Result<bool, Error> {
+        let result = sqlx::query("DELETE FROM course_translations WHERE course_id = $1 AND locale = $2")
+            .bind(course_id)
+            .bind(locale)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_related_courses(
+        &self,
+        course_id: Uuid,
+        user_id: Uuid,
+        limit: i64,
+    ) -> Result<Vec<UserCourseDto>, Error> {
+        let courses = sqlx::query_as::<_, UserCourseDto>(
+            r#"
+            WITH base AS (
+                SELECT category, level FROM courses WHERE id = $1
+            )
+            SELECT
+                c.id,
+                c.title,
+                c.slug,
+                c.description,
+                c.long_description,
+                c.level,
+                c.duration,
+                c.students,
+                c.paypal_product_id,
+                c.price,
+                course_effective_price(c.price, c.sale_price, c.sale_starts_at, c.sale_ends_at) AS effective_price,
+                c.image,
+                c.category,
+                COALESCE(AVG(cr.rating), 0)::int AS rating,
+                COUNT(cr.id) AS rating_count,
+                c.created_at,
+                c.updated_at,
+                c.features
+            FROM courses c
+            JOIN base b
+                ON c.category = b.category
+            LEFT JOIN course_ratings cr
+                ON cr.course_id = c.id
+            WHERE c.id <> $1
+              AND NOT EXISTS (
+                  SELECT 1 FROM user_courses uc
+                  WHERE uc.course_id = c.id AND uc.user_id = $2
+              )
+            GROUP BY c.id, b.level
+            ORDER BY (c.level = b.level) DESC, rating DESC, c.students DESC
+            LIMIT $3
+            "#
+        )
+        .bind(course_id)
+        .bind(user_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+        Ok(courses)
+    }
+
     /// Mucho más eficiente: 3 queries en vez de un JOIN enorme.
     async fn get_all_courses_with_modules(
         &self,
     ) -> Result<Vec<CourseWithModulesDto>, Error> {
         let mut tx = self.pool.begin().await?;
         // 1️⃣ Traer cursos
-        let rows = sqlx::query!(
+        let rows = sqlx::query_as!(
+            CatalogRow,
             r#"
             SELECT 
                 c.id AS course_id,
@@ -1058,12 +2347,19 @@ impl CourseExt for DBClient {
                 c.long_description,
                 c.level,
                 c.price,
+                course_effective_price(c.price, c.sale_price, c.sale_starts_at, c.sale_ends_at) AS "effective_price!",
                 c.duration,
                 c.students,
                 c.image,
+                c.trailer_url,
                 c.category,
                 c.features,
+                ARRAY(
+                    SELECT t.name FROM course_tags ct JOIN tags t ON t.id = ct.tag_id
+                    WHERE ct.course_id = c.id ORDER BY t.name
+                ) AS "tags!",
                 c.paypal_product_id,
+                c.version,
                 c.created_at,
                 c.updated_at,
 
@@ -1077,7 +2373,8 @@ impl CourseExt for DBClient {
                 l."type" AS "lesson_type?",
                 l.content_url AS "content_url?",
                 l.description AS "lesson_description?",
-                l."order" AS "lesson_order?"
+                l."order" AS "lesson_order?",
+                l.is_preview AS "lesson_is_preview?"
 
             FROM courses c
             LEFT JOIN modules m ON m.course_id = c.id
@@ -1090,73 +2387,9 @@ impl CourseExt for DBClient {
             log::error!("ERROR: {}", e);
             e
         })?;
-        // 2️⃣ Procesar filas en estructuras separadas
-        // -------------- AGRUPACIÓN EFICIENTE ---------------
-        
-        let mut courses_map: HashMap<Uuid, CourseWithModulesDto> = HashMap::new();
-        for row in rows {
-
-            // 1️⃣ Asegurar que el curso exista en el mapa
-            let course = courses_map
-                .entry(row.course_id)
-                .or_insert_with(|| CourseWithModulesDto {
-                    id: row.course_id,
-                    title: row.course_title.clone(),
-                    description: row.description.clone(),
-                    long_description: row.long_description.clone(),
-                    price: row.price,
-                    level: row.level.unwrap(),
-                    duration: row.duration,
-                    students: row.students.unwrap_or(0),
-                    image: row.image.clone(),
-                    category: row.category.unwrap(),
-                    features: row.features
-                        .as_ref()
-                        .and_then(|v| serde_json::from_value(v.clone()).ok()),
-                    created_at: row.created_at.unwrap(),
-                    updated_at: row.updated_at.unwrap(),
-                    total_lessons: 0,
-                    completed_lessons: 0,
-                    modules: vec![],
-                });
-
-            // 2️⃣ Si hay un módulo
-            if let Some(module_id) = row.module_id {
-                let module = course.modules
-                    .iter_mut()
-                    .find(|m| m.id == module_id);
-
-                let module_ref = match module {
-                    Some(m) => m,
-                    None => {
-                        course.modules.push(ModuleWithLessonsDto {
-                            id: module_id,
-                            title: row.module_title.unwrap_or("Title".to_string()),
-                            order: row.module_order.unwrap_or(1),
-                            lessons: vec![],
-                        });
-                        course.modules.last_mut().unwrap()
-                    }
-                };
-
-                // 3️⃣ Si hay una lección
-                if let Some(lesson_id) = row.lesson_id {
-                    module_ref.lessons.push(LessonDto {
-                        id: lesson_id,
-                        title: row.lesson_title.clone().unwrap_or("Title".to_string()),
-                        duration: row.lesson_duration,
-                        completed: None,
-                        r#type: row.lesson_type.clone().unwrap(),
-                        content_url: row.content_url.clone(),
-                        description: row.lesson_description.clone(),
-                        order: row.lesson_order.unwrap(),
-                    });
-                }
-            }
-        }
-        tx.commit().await?;
-        Ok(courses_map.into_values().collect())
-    }
+        tx.commit().await?;
+        Ok(group_catalog_rows(rows))
+    }
 
     async fn get_course_with_videos(
         &self,
@@ -1174,11 +2407,18 @@ impl CourseExt for DBClient {
                 c.long_description,
                 c.level,
                 c.price,
+                course_effective_price(c.price, c.sale_price, c.sale_starts_at, c.sale_ends_at) AS "effective_price!",
                 c.duration,
                 c.students,
                 c.image,
+                c.trailer_url,
                 c.category,
                 c.features,
+                ARRAY(
+                    SELECT t.name FROM course_tags ct JOIN tags t ON t.id = ct.tag_id
+                    WHERE ct.course_id = c.id ORDER BY t.name
+                ) AS "tags!",
+                c.version,
                 c.created_at,
                 c.updated_at,
 
@@ -1193,6 +2433,7 @@ impl CourseExt for DBClient {
                 l.content_url AS "content_url?",
                 l.description AS "lesson_description?",
                 l."order" AS "lesson_order?",
+                l.is_preview AS "lesson_is_preview?",
 
                 ulp.is_completed AS "lesson_completed?"
 
@@ -1226,18 +2467,23 @@ impl CourseExt for DBClient {
                 description: row.description.clone(),
                 long_description: row.long_description.clone(),
                 price: row.price,
+                effective_price: row.effective_price,
                 level: row.level.clone().unwrap_or_default(),
                 duration: row.duration,
                 students: row.students.unwrap_or(0),
                 image: row.image.clone(),
+                trailer_url: row.trailer_url.clone(),
                 category: row.category.clone().unwrap_or_default(),
                 features: row.features
                     .as_ref()
                     .and_then(|v| serde_json::from_value(v.clone()).ok()),
-                created_at: row.created_at.unwrap(),
-                updated_at: row.updated_at.unwrap(),
+                tags: row.tags.clone(),
+                version: row.version,
+                created_at: row.created_at,
+                updated_at: row.updated_at,
                 total_lessons: 0,
                 completed_lessons: 0,
+                computed_duration_seconds: 0,
                 modules: vec![],
             });
 
@@ -1273,6 +2519,7 @@ impl CourseExt for DBClient {
                         content_url: row.content_url.clone(),
                         description: row.lesson_description.clone(),
                         order: row.lesson_order.unwrap_or(1),
+                        is_preview: row.lesson_is_preview.unwrap_or(false),
                     });
                 }
             }
@@ -1280,6 +2527,7 @@ impl CourseExt for DBClient {
         if let Some(course) = &mut course_opt {
             course.total_lessons = total_lessons;
             course.completed_lessons = completed_lessons;
+            course.computed_duration_seconds = course.lesson_duration_seconds();
         }
 
         tx.commit().await?;
@@ -1293,8 +2541,8 @@ impl CourseExt for DBClient {
     ) -> Result<Option<CourseWithModulesDto>, sqlx::Error> {
         let mut tx = self.pool.begin().await?;
 
-        // Usamos una CTE para calcular lesson_index y luego en la selección final
-        // exponemos content_url y description solo cuando lesson_index = 1.
+        // En la selección final exponemos content_url y description solo
+        // para las lecciones marcadas con is_preview.
         let rows = sqlx::query!(
             r#"
             WITH course_data AS (
@@ -1305,11 +2553,18 @@ impl CourseExt for DBClient {
                     c.long_description,
                     c.level,
                     c.price,
+                    course_effective_price(c.price, c.sale_price, c.sale_starts_at, c.sale_ends_at) AS effective_price,
                     c.duration,
                     c.students,
                     c.image,
+                    c.trailer_url,
                     c.category,
                     c.features,
+                    ARRAY(
+                        SELECT t.name FROM course_tags ct JOIN tags t ON t.id = ct.tag_id
+                        WHERE ct.course_id = c.id ORDER BY t.name
+                    ) AS tags,
+                    c.version,
                     c.created_at,
                     c.updated_at,
 
@@ -1325,9 +2580,9 @@ impl CourseExt for DBClient {
                     l.description AS lesson_description,
                     l."order" AS lesson_order,
 
-                    ulp.is_completed AS lesson_completed,
+                    l.is_preview AS lesson_is_preview,
 
-                    ROW_NUMBER() OVER (ORDER BY m."order" ASC NULLS LAST, l."order" ASC NULLS LAST) AS lesson_index
+                    ulp.is_completed AS lesson_completed
                 FROM courses c
                 LEFT JOIN modules m ON m.course_id = c.id
                 LEFT JOIN lessons l ON l.module_id = m.id
@@ -1343,11 +2598,15 @@ impl CourseExt for DBClient {
                 long_description,
                 level,
                 price,
+                effective_price AS "effective_price!",
                 duration,
                 students,
                 image,
+                trailer_url,
                 category,
                 features,
+                tags AS "tags!",
+                version,
                 created_at,
                 updated_at,
 
@@ -1359,14 +2618,14 @@ impl CourseExt for DBClient {
                 lesson_title AS "lesson_title?",
                 lesson_duration AS "lesson_duration?",
                 lesson_type AS "lesson_type?",
-                -- Exponer content_url solo para la primera lección del curso
-                CASE WHEN lesson_index = 1 THEN lesson_content_url ELSE NULL END AS "content_url?: String",
-                -- Exponer description solo para la primera lección del curso
-                CASE WHEN lesson_index = 1 THEN lesson_description ELSE NULL END AS "lesson_description?: String",
+                -- Exponer content_url solo para las lecciones de muestra
+                CASE WHEN lesson_is_preview THEN lesson_content_url ELSE NULL END AS "content_url?: String",
+                -- Exponer description solo para las lecciones de muestra
+                CASE WHEN lesson_is_preview THEN lesson_description ELSE NULL END AS "lesson_description?: String",
                 lesson_order AS "lesson_order?",
+                lesson_is_preview AS "lesson_is_preview?",
 
-                lesson_completed AS "lesson_completed?",
-                lesson_index AS "lesson_index?"
+                lesson_completed AS "lesson_completed?"
             FROM course_data
             ORDER BY module_order ASC NULLS LAST, lesson_order ASC NULLS LAST
             "#,
@@ -1393,18 +2652,23 @@ impl CourseExt for DBClient {
                 description: row.description.clone(),
                 long_description: row.long_description.clone(),
                 price: row.price,
+                effective_price: row.effective_price,
                 level: row.level.clone().unwrap_or_default(),
                 duration: row.duration.clone(),
                 students: row.students.unwrap_or(0),
                 image: row.image.clone(),
+                trailer_url: row.trailer_url.clone(),
                 category: row.category.clone().unwrap_or_default(),
                 features: row.features
                     .as_ref()
                     .and_then(|v| serde_json::from_value(v.clone()).ok()),
-                created_at: row.created_at.unwrap_or_else(|| chrono::Utc::now()),
-                updated_at: row.updated_at.unwrap_or_else(|| chrono::Utc::now()),
+                tags: row.tags.clone(),
+                version: row.version,
+                created_at: row.created_at,
+                updated_at: row.updated_at,
                 total_lessons: 0,
                 completed_lessons: 0,
+                computed_duration_seconds: 0,
                 modules: vec![],
             });
 
@@ -1434,16 +2698,17 @@ impl CourseExt for DBClient {
                     }
 
                     // Nota: content_url y lesson_description ya vienen nulos para todas
-                    // las lecciones excepto la primera (por la CASE en SQL).
+                    // las lecciones que no son de muestra (por la CASE en SQL).
                     module_ref.lessons.push(LessonDto {
                         id: lesson_id,
                         title: row.lesson_title.clone().unwrap_or_else(|| "Lección".into()),
                         duration: row.lesson_duration.clone(),
                         completed: row.lesson_completed,
                         r#type: row.lesson_type.clone().unwrap_or_else(|| "video".into()),
-                        content_url: row.content_url.clone(),         // solo Some para la primera lección
-                        description: row.lesson_description.clone(),  // solo Some para la primera lección
+                        content_url: row.content_url.clone(),         // solo Some para lecciones de muestra
+                        description: row.lesson_description.clone(),  // solo Some para lecciones de muestra
                         order: row.lesson_order.unwrap_or(1),
+                        is_preview: row.lesson_is_preview.unwrap_or(false),
                     });
                 }
             }
@@ -1452,6 +2717,7 @@ impl CourseExt for DBClient {
         if let Some(course) = &mut course_opt {
             course.total_lessons = total_lessons;
             course.completed_lessons = completed_lessons;
+            course.computed_duration_seconds = course.lesson_duration_seconds();
         }
 
         tx.commit().await?;
@@ -1463,188 +2729,123 @@ impl CourseExt for DBClient {
         &self,
         course_id: Uuid,
         mut dto: UpdateCourseDTO,
-    ) -> Result<CourseWithModulesDto, Error> {
+        edited_by: Uuid,
+        keep_revisions: i64,
+    ) -> Result<Option<CourseWithModulesDto>, Error> {
         let mut tx = self.pool.begin().await?;
         let now = Utc::now();
 
-        // Asegurar que cada módulo tenga un UUID
-        if let Some(mods) = dto.modules.as_mut() {
-            for m in mods.iter_mut() {
-                if m.id.is_none() {
-                    m.id = Some(Uuid::new_v4());
-                }
-                if let Some(lessons) = m.lessons.as_mut() {
-                    for l in lessons.iter_mut() {
-                        if l.id.is_none() {
-                            l.id = Some(Uuid::new_v4());
-                        }
-                        // Propagar el module_id correcto
-                        l.module_id = m.id;
-                    }
-                }
-            }
+        if !bump_course_version(&mut tx, course_id, dto.version).await? {
+            return Ok(None);
         }
+        // Con la fila ya bloqueada por el cambio de versión
+        save_course_revision(&mut tx, course_id, edited_by, keep_revisions).await?;
 
-        // Serializar módulos y lecciones a JSON
-        let modules_json = serde_json::to_value(&dto.modules).unwrap_or(serde_json::json!([]));
-        let lessons_json = {
-            let lessons_vec: Vec<_> = dto.modules
-                .as_ref()
-                .map(|mods| {
-                    mods.iter().flat_map(|m| {
-                        m.lessons.as_ref()
-                            .map(|lessons| lessons.iter().map(|l| {
-                                serde_json::json!({
-                                    "id": l.id,
-                                    "module_id": l.module_id,
-                                    "title": l.title.clone(),
-                                    "duration": l.duration.clone(),
-                                    "type": l.r#type.clone(),
-                                    "content_url": l.content_url.clone(),
-                                    "description": l.description.clone(),
-                                    "order": l.order
-                                })
-                            }).collect::<Vec<_>>())
-                            .unwrap_or_default()
-                    }).collect::<Vec<_>>()
-                })
-                .unwrap_or_default();
-            serde_json::to_value(lessons_vec).unwrap_or(serde_json::json!([]))
-        };
-
-        let sql = r#"
-            WITH
-            course_update AS (
-                UPDATE courses SET
-                    title = COALESCE($2, title),
-                    description = COALESCE($3, description),
-                    long_description = COALESCE($4, long_description),
-                    level = COALESCE($5, level),
-                    price = COALESCE($6, price),
-                    duration = COALESCE($7, duration),
-                    students = COALESCE($8, students),
-                    image = COALESCE($9, image),
-                    category = COALESCE($10, category),
-                    features = COALESCE($11::jsonb, features),
-                    updated_at = $12
-                WHERE id = $1
-                RETURNING *
-            ),
-
-            module_input AS (
-                SELECT
-                    (m->>'id')::uuid AS id,
-                    m->>'title' AS title,
-                    (m->>'order')::int AS module_order,
-                    $1 AS course_id
-                FROM jsonb_array_elements($13::jsonb) AS m
-            ),
-            module_upsert AS (
-                INSERT INTO modules (id, course_id, title, "order")
-                SELECT
-                    id,
-                    course_id,
-                    title,
-                    module_order
-                FROM module_input
-                ON CONFLICT (id) DO UPDATE SET
-                    title = EXCLUDED.title,
-                    "order" = EXCLUDED."order"
-                RETURNING id, title
-            ),
-
-            module_ids AS (
-                SELECT id, title FROM module_upsert
-            ),
+        // Si cambia el título se regenera el slug y el anterior queda como redirección
+        if let Some(title) = dto.title.as_deref() {
+            let current = query!(
+                "SELECT title, slug FROM courses WHERE id = $1",
+                course_id
+            )
+            .fetch_optional(&mut *tx)
+            .await?;
 
-            module_deleted AS (
-                DELETE FROM modules
-                WHERE course_id = $1
-                AND id NOT IN (SELECT id FROM module_input)
-                RETURNING id
-            ),
+            if let Some(current) = current
+                && current.title != title
+            {
+                let new_slug = next_course_slug(&mut tx, title, course_id).await?;
+                if new_slug != current.slug {
+                    query!(
+                        "INSERT INTO course_slug_history (slug, course_id) VALUES ($1, $2) ON CONFLICT (slug) DO NOTHING",
+                        &current.slug,
+                        course_id
+                    )
+                    .execute(&mut *tx)
+                    .await?;
 
-            lesson_input AS (
-                SELECT
-                    (l->>'id')::uuid AS id,
-                    (l->>'module_id')::uuid AS module_id,
-                    l->>'title' AS title,
-                    l->>'duration' AS duration,
-                    l->>'type' AS type,
-                    l->>'content_url' AS content_url,
-                    l->>'description' AS description,
-                    (l->>'order')::int AS lesson_order
-                FROM jsonb_array_elements($14::jsonb) AS l
-            ),
-            lesson_upsert AS (
-                INSERT INTO lessons (id, module_id, title, duration, "type", content_url, description, "order")
-                SELECT
-                    lesson_input.id,
-                    lesson_input.module_id,
-                    lesson_input.title,
-                    lesson_input.duration,
-                    lesson_input.type,
-                    lesson_input.content_url,
-                    lesson_input.description,
-                    lesson_input.lesson_order
-                FROM lesson_input
-                JOIN module_ids ON lesson_input.module_id = module_ids.id
-                ON CONFLICT (id) DO UPDATE SET
-                    module_id = EXCLUDED.module_id,
-                    title = EXCLUDED.title,
-                    duration = EXCLUDED.duration,
-                    "type" = EXCLUDED."type",
-                    content_url = EXCLUDED.content_url,
-                    description = EXCLUDED.description,
-                    "order" = EXCLUDED."order"
-                RETURNING lessons.id
-            ),
+                    // El curso puede volver a un slug que ya tuvo
+                    query!("DELETE FROM course_slug_history WHERE slug = $1", &new_slug)
+                        .execute(&mut *tx)
+                        .await?;
 
+                    query!("UPDATE courses SET slug = $1 WHERE id = $2", &new_slug, course_id)
+                        .execute(&mut *tx)
+                        .await?;
+                }
+            }
+        }
 
-            lesson_deleted AS (
-                DELETE FROM lessons
-                WHERE module_id IN (SELECT id FROM module_upsert)
-                AND id NOT IN (SELECT id FROM lesson_input)
-                RETURNING id
-            )
+        let plan = prepare_course_update(&mut tx, course_id, &mut dto).await?;
 
-            SELECT * FROM course_update;
-        "#;
+        // La fila del curso ya está bloqueada por `bump_course_version`.
+        // `paypal_product_id` no se toca: solo lo cambia `set_course_paypal_product_id`
+        query!(
+            r#"
+            UPDATE courses SET
+                title = COALESCE($2, title),
+                description = COALESCE($3, description),
+                long_description = COALESCE($4, long_description),
+                level = COALESCE($5, level),
+                price = COALESCE($6, price),
+                duration = COALESCE($7, duration),
+                students = COALESCE($8, students),
+                image = COALESCE($9, image),
+                trailer_url = COALESCE($13, trailer_url),
+                category = COALESCE($10, category),
+                features = COALESCE($11::jsonb, features),
+                updated_at = $12
+            WHERE id = $1
+            "#,
+            course_id,
+            dto.title,
+            dto.description,
+            dto.long_description,
+            dto.level,
+            dto.price,
+            dto.duration,
+            dto.students,
+            dto.image,
+            dto.category,
+            dto.features.as_ref().map(|f| serde_json::to_value(f).unwrap()),
+            now,
+            dto.trailer_url,
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
 
-        let _ = sqlx::query(sql)
-            .bind(course_id)
-            .bind(dto.title)
-            .bind(dto.description)
-            .bind(dto.long_description)
-            .bind(dto.level)
-            .bind(dto.price)
-            .bind(dto.duration)
-            .bind(dto.students)
-            .bind(dto.image)
-            .bind(dto.category)
-            .bind(dto.features.map(|f| serde_json::to_value(f).unwrap()))
-            .bind(now)
-            .bind(modules_json)
-            .bind(lessons_json)
-            .execute(&mut *tx)
-            .await
-            .map_err(|e| {
-                log::error!("ERROR: {}", e);
-                e
-            });
+        apply_course_update_plan(&mut tx, course_id, &dto, &plan).await?;
 
         tx.commit().await?;
-        Ok(
+        Ok(Some(
             self.get_all_courses_with_modules()
                 .await
                 .map_err(|e| { log::error!("ERROR: {}", e); e })?
                 .into_iter()
                 .find(|c| c.id == course_id)
                 .expect("Curso debería existir después de la actualización")
-        )
+        ))
     }
 
+    async fn preview_course_update(
+        &self,
+        course_id: Uuid,
+        mut dto: UpdateCourseDTO,
+    ) -> Result<Option<CourseChangesetDto>, Error> {
+        // El mismo plan que ejecutaría `update_course`; la transacción se descarta
+        let mut tx = self.pool.begin().await?;
+
+        if !bump_course_version(&mut tx, course_id, dto.version).await? {
+            return Ok(None);
+        }
+        let changeset = prepare_course_update(&mut tx, course_id, &mut dto).await?;
 
+        tx.rollback().await?;
+        Ok(Some(changeset))
+    }
 
     async fn delete_course(&self, course_id: Uuid) -> Result<(), Error> {
         let mut tx = self.pool.begin().await?;
@@ -1656,10 +2857,52 @@ impl CourseExt for DBClient {
             e
         })?
         ;
+        // Tombstone para que los clientes sincronizados eliminen el curso de su caché
+        sqlx::query(
+            "INSERT INTO course_tombstones (course_id) VALUES ($1) ON CONFLICT (course_id) DO UPDATE SET deleted_at = NOW()"
+        )
+            .bind(course_id)
+            .execute(&mut *tx)
+            .await?;
         tx.commit().await?;
         Ok(())
     }
 
+    async fn get_courses_missing_paypal_product(&self) -> Result<Vec<Course>, Error> {
+        let courses = sqlx::query_as::<_, Course>(
+            "SELECT * FROM courses WHERE paypal_product_id IS NULL ORDER BY created_at ASC"
+        )
+        .fetch_all(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+        Ok(courses)
+    }
+
+    async fn set_course_paypal_product_id(
+        &self,
+        course_id: Uuid,
+        paypal_product_id: &str,
+    ) -> Result<Course, Error> {
+        let course = sqlx::query_as::<_, Course>(
+            r#"
+            UPDATE courses
+            SET paypal_product_id = $1, updated_at = NOW()
+            WHERE id = $2
+            RETURNING *
+            "#
+        )
+        .bind(paypal_product_id)
+        .bind(course_id)
+        .fetch_one(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+        Ok(course)
+    }
+
     async fn get_course_count(&self) -> Result<i64, Error> {
         let mut tx = self.pool.begin().await?;
         let result = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM courses")
@@ -1793,8 +3036,8 @@ impl CourseExt for DBClient {
         let summary = sqlx::query!(
             r#"
             SELECT
-                COALESCE(AVG(rating), 0)::float AS average,
-                COUNT(*)::bigint AS count
+                COALESCE(AVG(rating), 0)::float AS "average!",
+                COUNT(*)::bigint AS "count!"
             FROM course_ratings
             WHERE course_id = $1
             "#,
@@ -1821,8 +3064,8 @@ impl CourseExt for DBClient {
         };
 
         Ok(CourseRatingDto {
-            average: summary.average.unwrap_or(0.0),
-            count: summary.count.unwrap_or(0),
+            average: summary.average,
+            count: summary.count,
             user_rating,
         })
     }
@@ -1839,7 +3082,7 @@ pub trait AchievementExt {
         name: T,
         description: Option<T>,
         icon: Option<T>,
-        trigger_type: &str,
+        trigger_type: TriggerType,
         trigger_value: i32,
         active: bool,
     ) -> Result<Achievement, Error>;
@@ -1851,7 +3094,7 @@ pub trait AchievementExt {
         name: Option<T>,
         description: Option<T>,
         icon: Option<T>,
-        trigger_type: Option<&str>,
+        trigger_type: Option<TriggerType>,
         trigger_value: Option<i32>,
         active: Option<bool>,
     ) -> Result<Achievement, Error>;
@@ -1896,6 +3139,16 @@ pub trait UserAchievementExt {
         user_id: Uuid,
     ) -> Result<Vec<UserAchievementDto>, Error>;
 
+    /// Página de logros activos del usuario, opcionalmente solo ganados o solo bloqueados.
+    #[allow(dead_code)]
+    async fn get_user_achievements_paginated(
+        &self,
+        user_id: Uuid,
+        earned: Option<bool>,
+        page: usize,
+        limit: usize,
+    ) -> Result<(Vec<UserAchievementDto>, i64), Error>;
+
     /// Verifica si un usuario ya ha ganado un logro específico.
     #[allow(dead_code)]
     async fn has_user_earned(
@@ -1904,18 +3157,24 @@ pub trait UserAchievementExt {
         achievement_id: Uuid,
     ) -> Result<bool, Error>;
 
-    /// Obtiene logros de usuario con detalles completos.
+    /// Ids de todos los logros que el usuario ya ganó, en una sola consulta.
+    async fn get_earned_achievement_ids(&self, user_id: Uuid) -> Result<HashSet<Uuid>, Error>;
+
+    /// Obtiene logros de usuario con detalles completos (también los inactivos),
+    /// paginados y opcionalmente filtrados por ganados/bloqueados.
     async fn get_user_achievements_with_details(
         &self,
         user_id: Uuid,
-    ) -> Result<Vec<serde_json::Value>, Error>;
+        earned: Option<bool>,
+        page: usize,
+        limit: usize,
+    ) -> Result<(Vec<serde_json::Value>, i64), Error>;
 
     /// Verifica y otorga logros automáticamente basados en acciones.
     async fn check_and_award_achievements(
         &self,
         user_id: Uuid,
-        action: &str,
-        value: Option<i32>,
+        trigger: TriggerType,
     ) -> Result<Vec<Achievement>, Error>;
 }
 
@@ -1924,6 +3183,9 @@ pub trait NotificationExt {
     async fn get_user_notifications(&self, user_id: Uuid) -> Result<Vec<Notification>, Error>;
     async fn mark_notification_read(&self, notification_id: Uuid) -> Result<(), Error>;
     async fn create_notification(&self, user_id: Uuid, title: &str, message: &str, sent_via: &str) -> Result<Notification, Error>;
+    async fn count_unread_notifications(&self, user_id: Uuid) -> Result<i64, Error>;
+    /// Notificación in-app para todos los administradores activos. Devuelve cuántas se crearon.
+    async fn notify_admins(&self, title: &str, message: &str) -> Result<u64, Error>;
 }
 
 /// Implementación para la conexión principal del sistema (`DBClient`).
@@ -1934,7 +3196,7 @@ impl AchievementExt for DBClient {
         name: T,
         description: Option<T>,
         icon: Option<T>,
-        trigger_type: &str,
+        trigger_type: TriggerType,
         trigger_value: i32,
         active: bool,
     ) -> Result<Achievement, Error> {
@@ -1953,7 +3215,7 @@ impl AchievementExt for DBClient {
         .bind(name.into())
         .bind(description.map(|d| d.into()))
         .bind(icon.map(|i| i.into()))
-        .bind(trigger_type)
+        .bind(trigger_type.as_str())
         .bind(trigger_value)
         .bind(active)
         .bind(now)
@@ -1973,7 +3235,7 @@ impl AchievementExt for DBClient {
         name: Option<T>,
         description: Option<T>,
         icon: Option<T>,
-        trigger_type: Option<&str>,
+        trigger_type: Option<TriggerType>,
         trigger_value: Option<i32>,
         active: Option<bool>,
     ) -> Result<Achievement, Error> {
@@ -1996,7 +3258,7 @@ impl AchievementExt for DBClient {
         .bind(name.map(|n| n.into()))
         .bind(description.map(|d| d.into()))
         .bind(icon.map(|i| i.into()))
-        .bind(trigger_type)
+        .bind(trigger_type.map(|t| t.as_str()))
         .bind(trigger_value)
         .bind(active)
         .fetch_one(&mut *tx)
@@ -2165,6 +3427,72 @@ impl UserAchievementExt for DBClient {
         Ok(achievements)
     }
 
+    async fn get_user_achievements_paginated(
+        &self,
+        user_id: Uuid,
+        earned: Option<bool>,
+        page: usize,
+        limit: usize,
+    ) -> Result<(Vec<UserAchievementDto>, i64), Error> {
+        let offset = ((page - 1) * limit) as i64;
+
+        let achievements = sqlx::query_as::<_, UserAchievementDto>(
+            r#"
+            SELECT
+                a.id,
+                a.name,
+                a.description,
+                a.icon,
+                a.trigger_type,
+                a.trigger_value,
+                a.active,
+                COALESCE(ua.earned, false) AS earned,
+                ua.earned_at,
+                a.created_at
+            FROM achievement a
+            LEFT JOIN user_achievement ua
+                ON ua.achievement_id = a.id
+                AND ua.user_id = $1
+            WHERE a.active = true
+              AND ($2::bool IS NULL OR COALESCE(ua.earned, false) = $2)
+            ORDER BY a.created_at ASC
+            LIMIT $3 OFFSET $4
+            "#
+        )
+        .bind(user_id)
+        .bind(earned)
+        .bind(limit as i64)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            log::error!("Error: {}", e);
+            e
+        })?;
+
+        let total = sqlx::query_scalar::<_, i64>(
+            r#"
+            SELECT COUNT(*)
+            FROM achievement a
+            LEFT JOIN user_achievement ua
+                ON ua.achievement_id = a.id
+                AND ua.user_id = $1
+            WHERE a.active = true
+              AND ($2::bool IS NULL OR COALESCE(ua.earned, false) = $2)
+            "#
+        )
+        .bind(user_id)
+        .bind(earned)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| {
+            log::error!("Error: {}", e);
+            e
+        })?;
+
+        Ok((achievements, total))
+    }
+
     async fn has_user_earned(
         &self,
         user_id: Uuid,
@@ -2182,10 +3510,18 @@ impl UserAchievementExt for DBClient {
         Ok(exists)
     }
 
+    async fn get_earned_achievement_ids(&self, user_id: Uuid) -> Result<HashSet<Uuid>, Error> {
+        earned_achievement_ids(&self.pool, user_id).await
+    }
+
     async fn get_user_achievements_with_details(
         &self,
         user_id: Uuid,
-    ) -> Result<Vec<serde_json::Value>, Error> {
+        earned: Option<bool>,
+        page: usize,
+        limit: usize,
+    ) -> Result<(Vec<serde_json::Value>, i64), Error> {
+        let offset = ((page - 1) * limit) as i64;
         let mut tx = self.pool.begin().await?;
         let rows = sqlx::query(
             r#"
@@ -2202,16 +3538,37 @@ impl UserAchievementExt for DBClient {
                 ua.earned_at
             FROM achievement a
             LEFT JOIN user_achievement ua ON ua.achievement_id = a.id AND ua.user_id = $1
+            WHERE $2::bool IS NULL OR COALESCE(ua.earned, false) = $2
             ORDER BY a.created_at DESC
+            LIMIT $3 OFFSET $4
             "#,
         )
         .bind(user_id)
+        .bind(earned)
+        .bind(limit as i64)
+        .bind(offset)
         .fetch_all(&mut *tx)
         .await.map_err(|e| {
             log::error!("ERROR: {}", e);
             e
         })?;
 
+        let total = sqlx::query_scalar::<_, i64>(
+            r#"
+            SELECT COUNT(*)
+            FROM achievement a
+            LEFT JOIN user_achievement ua ON ua.achievement_id = a.id AND ua.user_id = $1
+            WHERE $2::bool IS NULL OR COALESCE(ua.earned, false) = $2
+            "#,
+        )
+        .bind(user_id)
+        .bind(earned)
+        .fetch_one(&mut *tx)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+
         let result: Vec<serde_json::Value> = rows
             .into_iter()
             .map(|row| {
@@ -2231,140 +3588,44 @@ impl UserAchievementExt for DBClient {
             .collect();
 
         tx.commit().await?;
-        Ok(result)
+        Ok((result, total))
     }
 
     async fn check_and_award_achievements(
         &self,
         user_id: Uuid,
-        action: &str,
-        value: Option<i32>,
+        trigger: TriggerType,
     ) -> Result<Vec<Achievement>, Error> {
         let mut tx = self.pool.begin().await?;
-        let fallback_value = value.unwrap_or(1);
 
         // 1️⃣ Calcular el valor actual del usuario
-        let current_value: i32 = match action {
-            "course_completed" => {
-                sqlx::query_scalar::<_, i64>(
-                    r#"
-                    SELECT COUNT(DISTINCT uc.course_id)
-                    FROM user_courses uc
-                    WHERE uc.user_id = $1
-                    AND EXISTS (
-                        SELECT 1
-                        FROM user_lesson_progress lp
-                        JOIN lessons l ON lp.lesson_id = l.id
-                        JOIN modules m ON l.module_id = m.id
-                        WHERE lp.user_id = $1
-                        AND lp.is_completed = true
-                        AND m.course_id = uc.course_id
-                        GROUP BY m.course_id
-                        HAVING COUNT(*) = (
-                            SELECT COUNT(*)
-                            FROM lessons l2
-                            JOIN modules m2 ON l2.module_id = m2.id
-                            WHERE m2.course_id = uc.course_id
-                        )
-                    )
-                    "#
-                )
-                .bind(user_id)
-                .fetch_one(&mut *tx)
-                .await
-                .map_err(|e| {
-                    log::error!("Error: {}", e);
-                    e
-                })? as i32
-            }
+        let current_value = resolve_trigger_stat(&mut *tx, trigger, user_id).await?;
 
-            "lesson_completed" => {
-                sqlx::query_scalar::<_, i64>(
-                    "SELECT COUNT(*) FROM user_lesson_progress WHERE user_id = $1 AND is_completed = true"
-                )
-                .bind(user_id)
-                .fetch_one(&mut *tx)
-                .await
-                .map_err(|e| {
+        // 2️⃣ Obtener logros alcanzables
+        let achievements = sqlx::query_as::<_, Achievement>(
+            r#"
+            SELECT id, name, description, icon, trigger_type, trigger_value, active, created_at
+            FROM achievement
+            WHERE trigger_type = $1
+              AND trigger_value <= $2
+              AND active = true
+            "#
+        )
+        .bind(trigger.as_str())
+        .bind(current_value)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| {
                     log::error!("Error: {}", e);
                     e
-                })? as i32
-            }
+                })?;
 
-            "courses_enrolled" => {
-                sqlx::query_scalar::<_, i64>(
-                    "SELECT COUNT(*) FROM user_courses WHERE user_id = $1"
-                )
-                .bind(user_id)
-                .fetch_one(&mut *tx)
-                .await
-                .map_err(|e| {
-                    log::error!("Error: {}", e);
-                    e
-                })? as i32
-            }
-            
-            "comments_created" => {
-                sqlx::query_scalar::<_, i64>(
-                    "SELECT COUNT(*) FROM lesson_comments WHERE user_id = $1"
-                )
-                .bind(user_id)
-                .fetch_one(&mut *tx)
-                .await
-                .map_err(|e| {
-                    log::error!("Error: {}", e);
-                    e
-                })? as i32
-            }
-
-            "login_streak" => {
-                let count: i32 = sqlx::query_scalar(
-                    r#"
-                    SELECT COALESCE(
-                        (SELECT value
-                        FROM user_stats
-                        WHERE user_id = $1 AND stat_type = 'login_streak'),
-                        0
-                    )
-                    "#
-                )
-                .bind(user_id)
-                .fetch_one(&mut *tx)
-                .await
-                .map_err(|e| {
-                    log::error!("Error: {}", e);
-                    e
-                })?;
-
-                count as i32
-            }
-
-            _ => fallback_value,
-        };
-
-        // 2️⃣ Obtener logros alcanzables
-        let achievements = sqlx::query_as::<_, Achievement>(
-            r#"
-            SELECT id, name, description, icon, trigger_type, trigger_value, active, created_at
-            FROM achievement
-            WHERE trigger_type = $1
-              AND trigger_value <= $2
-              AND active = true
-            "#
-        )
-        .bind(action)
-        .bind(current_value)
-        .fetch_all(&mut *tx)
-        .await
-        .map_err(|e| {
-                    log::error!("Error: {}", e);
-                    e
-                })?;
-
-        let mut awarded = Vec::new();
+        // Los ya ganados no necesitan ir a la base: el INSERT solo se intenta con los pendientes
+        let earned = earned_achievement_ids(&mut *tx, user_id).await?;
+        let mut awarded = Vec::new();
 
         // 3️⃣ Insertar / actualizar logros de forma atómica
-        for achievement in achievements {
+        for achievement in achievements.into_iter().filter(|a| !earned.contains(&a.id)) {
             let was_awarded = sqlx::query_scalar::<_, bool>(
                 r#"
                 INSERT INTO user_achievement (user_id, achievement_id, earned, earned_at)
@@ -2397,17 +3658,662 @@ impl UserAchievementExt for DBClient {
     }
 }
 
+/// Fin del periodo pagado: `duration_months` meses de calendario desde `start` (el 31
+/// de enero más un mes es el último día de febrero).
+pub(crate) fn subscription_period_end(start: DateTime<Utc>, duration_months: i32) -> DateTime<Utc> {
+    start
+        .checked_add_months(chrono::Months::new(duration_months.max(0) as u32))
+        .unwrap_or(start)
+}
+
+/// Normaliza el texto de búsqueda de usuarios: minúsculas y espacios colapsados.
+/// `None` si queda demasiado corto para que la búsqueda trigram sea útil.
+pub(crate) fn normalize_search_term(q: &str) -> Option<String> {
+    let term = q.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+    (term.chars().count() >= 2).then_some(term)
+}
+
+/// Escapa los comodines de `LIKE` para buscar el texto literal
+pub(crate) fn escape_like(term: &str) -> String {
+    term.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+/// Control optimista: sube la versión solo si nadie cambió el curso desde que el cliente
+/// lo leyó. El UPDATE bloquea la fila hasta el final de la transacción. Devuelve `false`
+/// si la versión quedó obsoleta y `RowNotFound` si el curso no existe.
+async fn bump_course_version(
+    tx: &mut Transaction<'_, Postgres>,
+    course_id: Uuid,
+    version: i32,
+) -> Result<bool, Error> {
+    let bumped = sqlx::query_scalar::<_, i32>(
+        "UPDATE courses SET version = version + 1 WHERE id = $1 AND version = $2 RETURNING version"
+    )
+    .bind(course_id)
+    .bind(version)
+    .fetch_optional(&mut **tx)
+    .await?;
+
+    if bumped.is_some() {
+        return Ok(true);
+    }
+
+    let exists = sqlx::query_scalar::<_, bool>("SELECT EXISTS(SELECT 1 FROM courses WHERE id = $1)")
+        .bind(course_id)
+        .fetch_one(&mut **tx)
+        .await?;
+    if exists { Ok(false) } else { Err(Error::RowNotFound) }
+}
+
+/// Guarda el contenido actual del curso (con la forma de `UpdateCourseDTO`, sin `version`
+/// ni `students`) como la siguiente revisión y descarta las que excedan `keep_revisions`.
+async fn save_course_revision(
+    tx: &mut Transaction<'_, Postgres>,
+    course_id: Uuid,
+    edited_by: Uuid,
+    keep_revisions: i64,
+) -> Result<(), Error> {
+    let revision = sqlx::query_scalar::<_, i32>(
+        r#"
+        INSERT INTO course_revisions (course_id, revision, snapshot, edited_by)
+        SELECT
+            c.id,
+            COALESCE((SELECT MAX(revision) FROM course_revisions WHERE course_id = c.id), 0) + 1,
+            jsonb_build_object(
+                'title', c.title,
+                'description', c.description,
+                'long_description', c.long_description,
+                'level', c.level,
+                'price', c.price,
+                'duration', c.duration,
+                'image', c.image,
+                'trailer_url', c.trailer_url,
+                'category', c.category,
+                'features', c.features,
+                'modules', COALESCE((
+                    SELECT jsonb_agg(jsonb_build_object(
+                        'id', m.id,
+                        'title', m.title,
+                        'order', m."order",
+                        'lessons', COALESCE((
+                            SELECT jsonb_agg(jsonb_build_object(
+                                'id', l.id,
+                                'module_id', l.module_id,
+                                'title', l.title,
+                                'duration', l.duration,
+                                'type', l."type",
+                                'content_url', l.content_url,
+                                'description', l.description,
+                                'order', l."order",
+                                'is_preview', l.is_preview
+                            ) ORDER BY l."order")
+                            FROM lessons l WHERE l.module_id = m.id
+                        ), '[]'::jsonb)
+                    ) ORDER BY m."order")
+                    FROM modules m WHERE m.course_id = c.id
+                ), '[]'::jsonb)
+            ),
+            $2
+        FROM courses c
+        WHERE c.id = $1
+        RETURNING revision
+        "#
+    )
+    .bind(course_id)
+    .bind(edited_by)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    if keep_revisions > 0 {
+        sqlx::query("DELETE FROM course_revisions WHERE course_id = $1 AND revision <= $2 - $3")
+            .bind(course_id)
+            .bind(revision)
+            .bind(keep_revisions)
+            .execute(&mut **tx)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Asegura que cada módulo y lección de la edición tenga un UUID y propaga el `module_id`.
+fn assign_course_update_ids(dto: &mut UpdateCourseDTO) {
+    if let Some(mods) = dto.modules.as_mut() {
+        for m in mods.iter_mut() {
+            if m.id.is_none() {
+                m.id = Some(Uuid::new_v4());
+            }
+            if let Some(lessons) = m.lessons.as_mut() {
+                for l in lessons.iter_mut() {
+                    if l.id.is_none() {
+                        l.id = Some(Uuid::new_v4());
+                    }
+                    l.module_id = m.id;
+                }
+            }
+        }
+    }
+}
+
+/// Renumera módulos y lecciones de la edición como 1..N dentro de su padre, respetando el
+/// `order` recibido. Si dos coinciden gana el que llega antes; los que no traen `order`
+/// van al final en el orden en que llegan.
+pub(crate) fn normalize_course_update_order(dto: &mut UpdateCourseDTO) {
+    if let Some(mods) = dto.modules.as_mut() {
+        mods.sort_by_key(|m| m.order.unwrap_or(i32::MAX));
+        for (i, m) in mods.iter_mut().enumerate() {
+            m.order = Some(i as i32 + 1);
+            if let Some(lessons) = m.lessons.as_mut() {
+                lessons.sort_by_key(|l| l.order.unwrap_or(i32::MAX));
+                for (j, l) in lessons.iter_mut().enumerate() {
+                    l.order = Some(j as i32 + 1);
+                }
+            }
+        }
+    }
+}
+
+/// Ids de los módulos del curso y de sus lecciones (`(lesson_id, module_id)`).
+async fn load_course_structure(
+    tx: &mut Transaction<'_, Postgres>,
+    course_id: Uuid,
+) -> Result<(Vec<Uuid>, Vec<(Uuid, Uuid)>), Error> {
+    let modules = sqlx::query_scalar::<_, Uuid>("SELECT id FROM modules WHERE course_id = $1")
+        .bind(course_id)
+        .fetch_all(&mut **tx)
+        .await?;
+
+    let lessons = sqlx::query_as::<_, (Uuid, Uuid)>(
+        "SELECT l.id, l.module_id FROM lessons l JOIN modules m ON m.id = l.module_id WHERE m.course_id = $1"
+    )
+    .bind(course_id)
+    .fetch_all(&mut **tx)
+    .await?;
+
+    Ok((modules, lessons))
+}
+
+/// Plan de `update_course` (con los ids ya asignados): lo que vaya en la edición se
+/// inserta o actualiza según exista ya en el curso, y lo que no venga se borra. Las
+/// lecciones de los módulos borrados caen en cascada y se cuentan como borradas.
+/// Sin `modules` en la edición no se toca la estructura.
+pub(crate) fn plan_course_update(
+    dto: &UpdateCourseDTO,
+    existing_modules: &[Uuid],
+    existing_lessons: &[(Uuid, Uuid)],
+) -> CourseChangesetDto {
+    let Some(modules) = dto.modules.as_ref() else {
+        return CourseChangesetDto::default();
+    };
+
+    let input_modules: Vec<Uuid> = modules.iter().filter_map(|m| m.id).collect();
+    let input_lessons: Vec<Uuid> = modules
+        .iter()
+        .flat_map(|m| m.lessons.iter().flatten())
+        .filter_map(|l| l.id)
+        .collect();
+
+    let (updated_modules, inserted_modules): (Vec<Uuid>, Vec<Uuid>) =
+        input_modules.iter().partition(|id| existing_modules.contains(id));
+    let deleted_modules = existing_modules
+        .iter()
+        .filter(|id| !input_modules.contains(id))
+        .copied()
+        .collect();
+
+    let (updated_lessons, inserted_lessons): (Vec<Uuid>, Vec<Uuid>) =
+        input_lessons.iter().partition(|id| existing_lessons.iter().any(|(l, _)| l == *id));
+    let deleted_lessons = existing_lessons
+        .iter()
+        .filter(|(id, _)| !input_lessons.contains(id))
+        .map(|(id, _)| *id)
+        .collect();
+
+    CourseChangesetDto {
+        modules: EntityChangesDto::new(inserted_modules, updated_modules, deleted_modules),
+        lessons: EntityChangesDto::new(inserted_lessons, updated_lessons, deleted_lessons),
+    }
+}
+
+/// Asigna ids, normaliza el orden y calcula el plan de la edición contra la estructura
+/// actual del curso. `update_course` ejecuta este plan y `preview_course_update` lo
+/// devuelve, así que la vista previa coincide siempre con lo aplicado.
+async fn prepare_course_update(
+    tx: &mut Transaction<'_, Postgres>,
+    course_id: Uuid,
+    dto: &mut UpdateCourseDTO,
+) -> Result<CourseChangesetDto, Error> {
+    assign_course_update_ids(dto);
+    normalize_course_update_order(dto);
+    let (modules, lessons) = load_course_structure(tx, course_id).await?;
+    Ok(plan_course_update(dto, &modules, &lessons))
+}
+
+/// Aplica el plan de `plan_course_update`: inserta y actualiza exactamente los módulos y
+/// lecciones que indica y borra los que marca como borrados. Sin `modules` en la edición
+/// el plan está vacío y la estructura no se toca.
+async fn apply_course_update_plan(
+    tx: &mut Transaction<'_, Postgres>,
+    course_id: Uuid,
+    dto: &UpdateCourseDTO,
+    plan: &CourseChangesetDto,
+) -> Result<(), Error> {
+    let Some(modules) = dto.modules.as_ref() else {
+        return Ok(());
+    };
+
+    // Las claves coinciden con las columnas de `jsonb_to_recordset`
+    let modules_json = serde_json::Value::from_iter(modules.iter().map(|m| serde_json::json!({
+        "id": m.id,
+        "title": m.title,
+        "module_order": m.order
+    })));
+    let lessons_json = serde_json::Value::from_iter(modules.iter().flat_map(|m| m.lessons.iter().flatten()).map(|l| serde_json::json!({
+        "id": l.id,
+        "module_id": l.module_id,
+        "title": l.title,
+        "duration": l.duration,
+        "type": l.r#type,
+        "content_url": l.content_url,
+        "description": l.description,
+        "lesson_order": l.order,
+        "is_preview": l.is_preview
+    })));
+    let log_error = |e: Error| {
+        log::error!("ERROR: {}", e);
+        e
+    };
+
+    // Los `order` son únicos por padre y se comprueban fila a fila: se aparcan en negativo
+    // para que reordenar o mover lecciones no choque con el orden que tenían
+    sqlx::query(r#"UPDATE modules SET "order" = -"order" WHERE course_id = $1"#)
+        .bind(course_id)
+        .execute(&mut **tx)
+        .await.map_err(log_error)?;
+    sqlx::query(r#"UPDATE lessons SET "order" = -"order" WHERE module_id IN (SELECT id FROM modules WHERE course_id = $1)"#)
+        .bind(course_id)
+        .execute(&mut **tx)
+        .await.map_err(log_error)?;
+
+    sqlx::query("DELETE FROM lessons WHERE id = ANY($1)")
+        .bind(&plan.lessons.deleted.ids)
+        .execute(&mut **tx)
+        .await.map_err(log_error)?;
+
+    sqlx::query(
+        r#"
+        UPDATE modules SET title = m.title, "order" = m.module_order
+        FROM jsonb_to_recordset($1) AS m(id uuid, title text, module_order int)
+        WHERE modules.id = m.id AND modules.id = ANY($2)
+        "#
+    )
+    .bind(&modules_json)
+    .bind(&plan.modules.updated.ids)
+    .execute(&mut **tx)
+    .await.map_err(log_error)?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO modules (id, course_id, title, "order")
+        SELECT m.id, $2, m.title, m.module_order
+        FROM jsonb_to_recordset($1) AS m(id uuid, title text, module_order int)
+        WHERE m.id = ANY($3)
+        "#
+    )
+    .bind(&modules_json)
+    .bind(course_id)
+    .bind(&plan.modules.inserted.ids)
+    .execute(&mut **tx)
+    .await.map_err(log_error)?;
+
+    sqlx::query(
+        r#"
+        UPDATE lessons SET
+            module_id = l.module_id,
+            title = l.title,
+            duration = l.duration,
+            "type" = l.type,
+            content_url = l.content_url,
+            description = l.description,
+            "order" = l.lesson_order,
+            -- Sin is_preview en la entrada se conserva el valor actual
+            is_preview = COALESCE(l.is_preview, lessons.is_preview)
+        FROM jsonb_to_recordset($1) AS l(id uuid, module_id uuid, title text, duration text, type text,
+                                        content_url text, description text, lesson_order int, is_preview boolean)
+        WHERE lessons.id = l.id AND lessons.id = ANY($2)
+        "#
+    )
+    .bind(&lessons_json)
+    .bind(&plan.lessons.updated.ids)
+    .execute(&mut **tx)
+    .await.map_err(log_error)?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO lessons (id, module_id, title, duration, "type", content_url, description, "order", is_preview)
+        SELECT l.id, l.module_id, l.title, l.duration, l.type, l.content_url, l.description, l.lesson_order,
+               COALESCE(l.is_preview, false)
+        FROM jsonb_to_recordset($1) AS l(id uuid, module_id uuid, title text, duration text, type text,
+                                        content_url text, description text, lesson_order int, is_preview boolean)
+        WHERE l.id = ANY($2)
+        "#
+    )
+    .bind(&lessons_json)
+    .bind(&plan.lessons.inserted.ids)
+    .execute(&mut **tx)
+    .await.map_err(log_error)?;
+
+    // Al final, cuando las lecciones que cambian de módulo ya salieron de los que se borran
+    sqlx::query("DELETE FROM modules WHERE id = ANY($1)")
+        .bind(&plan.modules.deleted.ids)
+        .execute(&mut **tx)
+        .await.map_err(log_error)?;
+
+    Ok(())
+}
+
+/// Registro de disparadores de logros: consulta que calcula, para `$1 = user_id`,
+/// el valor que se compara con `achievement.trigger_value`. El `match` es
+/// exhaustivo, así que un `TriggerType` nuevo no compila sin su consulta.
+pub(crate) fn trigger_stat_query(trigger: TriggerType) -> &'static str {
+    match trigger {
+        TriggerType::CourseCompleted => r#"
+            SELECT COUNT(DISTINCT uc.course_id)
+            FROM user_courses uc
+            WHERE uc.user_id = $1
+            AND EXISTS (
+                SELECT 1
+                FROM user_lesson_progress lp
+                JOIN lessons l ON lp.lesson_id = l.id
+                JOIN modules m ON l.module_id = m.id
+                WHERE lp.user_id = $1
+                AND lp.is_completed = true
+                AND m.course_id = uc.course_id
+                GROUP BY m.course_id
+                HAVING COUNT(*) = (
+                    SELECT COUNT(*)
+                    FROM lessons l2
+                    JOIN modules m2 ON l2.module_id = m2.id
+                    WHERE m2.course_id = uc.course_id
+                )
+            )
+        "#,
+        TriggerType::LessonCompleted =>
+            "SELECT COUNT(*) FROM user_lesson_progress WHERE user_id = $1 AND is_completed = true",
+        TriggerType::CoursesEnrolled =>
+            "SELECT COUNT(*) FROM user_courses WHERE user_id = $1",
+        TriggerType::CommentsCreated =>
+            "SELECT COUNT(*) FROM lesson_comments WHERE user_id = $1",
+        TriggerType::LoginStreak =>
+            "SELECT COALESCE((SELECT value FROM user_stats WHERE user_id = $1 AND stat_type = 'login_streak'), 0)::BIGINT",
+        // Los pagos están en centavos; el umbral del logro, en unidades de la moneda
+        TriggerType::TotalSpend =>
+            "SELECT (COALESCE(SUM(amount), 0) / 100)::BIGINT FROM payments WHERE user_id = $1 AND status = 'COMPLETED'",
+    }
+}
+
+async fn earned_achievement_ids<'e, E>(executor: E, user_id: Uuid) -> Result<HashSet<Uuid>, Error>
+where
+    E: sqlx::PgExecutor<'e>,
+{
+    let ids = query_scalar::<_, Uuid>(
+        "SELECT achievement_id FROM user_achievement WHERE user_id = $1 AND earned = true"
+    )
+    .bind(user_id)
+    .fetch_all(executor)
+    .await?;
+    Ok(ids.into_iter().collect())
+}
+
+async fn resolve_trigger_stat<'e, E>(executor: E, trigger: TriggerType, user_id: Uuid) -> Result<i32, Error>
+where
+    E: sqlx::PgExecutor<'e>,
+{
+    let value = query_scalar::<_, i64>(trigger_stat_query(trigger))
+        .bind(user_id)
+        .fetch_one(executor)
+        .await
+        .map_err(|e| {
+            log::error!("Error calculando '{}': {}", trigger.as_str(), e);
+            e
+        })?;
+    Ok(value.clamp(0, i32::MAX as i64) as i32)
+}
+
+/// Revoca todos los refresh tokens del usuario dentro de la transacción de quien llama:
+/// la contraseña nueva y el cierre de las sesiones se confirman juntos.
+pub async fn revoke_all_refresh_tokens(conn: &mut PgConnection, user_id: Uuid) -> Result<u64, Error> {
+    let result = sqlx::query("UPDATE refresh_tokens SET revoked = true WHERE user_id = $1 AND revoked = false")
+        .bind(user_id)
+        .execute(&mut *conn)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+    Ok(result.rows_affected())
+}
+
+/// Pasos de `register_course_purchase` sobre una conexión o transacción ajena, para
+/// componerlos con otras escrituras del handler: confirmar (o descartar) es cosa de
+/// quien llama, y los logros se otorgan después con `award_purchase_achievements`.
+/// `false` si el curso está lleno; `RowNotFound` si no existe.
+pub async fn register_course_purchase_in(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    course_id: Uuid,
+    transaction_id: String,
+    amount: &Money,
+    payment_method: String,
+    status: String,
+) -> Result<bool, Error> {
+    // Bloquea el curso (y de paso verifica que existe): las compras simultáneas
+    // esperan aquí y cuentan los alumnos ya con las inscripciones anteriores
+    let max_students = sqlx::query_scalar::<_, Option<i32>>(
+        "SELECT max_students FROM courses WHERE id = $1 FOR UPDATE"
+    )
+    .bind(course_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(Error::RowNotFound)?;
+
+    // En una sentencia aparte para que vea las inscripciones confirmadas mientras esperaba
+    let (enrolled, already_enrolled) = sqlx::query_as::<_, (i64, bool)>(
+        r#"
+        SELECT COUNT(*), COALESCE(BOOL_OR(user_id = $2), false)
+        FROM user_courses
+        WHERE course_id = $1
+        "#
+    )
+    .bind(course_id)
+    .bind(user_id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    if !has_free_seat(max_students, enrolled, already_enrolled) {
+        return Ok(false);
+    }
+
+    // Registrar la compra en la tabla payments y user_courses
+    query_as::<_, Payment>(
+        r#"
+        INSERT INTO payments
+        (id, user_id, course_id, amount, currency, payment_method, transaction_id, status, created_at, updated_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+        RETURNING id, user_id, course_id, amount, currency, payment_method, transaction_id, status, created_at, updated_at
+        "#
+    )
+    .bind(Uuid::new_v4())
+    .bind(user_id)
+    .bind(course_id)
+    .bind(amount.amount_minor)
+    .bind(&amount.currency)
+    .bind(payment_method)
+    .bind(transaction_id)
+    .bind(status)
+    .bind(Utc::now())
+    .bind(Utc::now())
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|e| {
+        log::error!("ERROR: {}", e);
+        e
+    })?;
+
+    // Registrar en user_courses si no existe
+    let user_course_exists = query_scalar!(
+        "SELECT EXISTS(SELECT 1 FROM user_courses WHERE user_id = $1 AND course_id = $2)",
+        user_id,
+        course_id
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    if !user_course_exists.unwrap_or(false) {
+        query!(
+            r#"
+            INSERT INTO user_courses (id, user_id, course_id, purchased_at)
+            VALUES ($1, $2, $3, $4)
+            "#,
+            Uuid::new_v4(),
+            user_id,
+            course_id,
+            Utc::now(),
+        )
+        .execute(&mut *conn)
+        .await?;
+        query!(
+            r#"
+            UPDATE courses
+            SET students = students + 1
+            WHERE id = $1
+            "#,
+            course_id
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    // Inicializar progreso del curso si no existe
+    let progress_exists = query_scalar!(
+        "SELECT EXISTS(SELECT 1 FROM course_progress WHERE user_id = $1 AND course_id = $2)",
+        user_id,
+        course_id
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    if !progress_exists.unwrap_or(false) {
+        // Obtener el número total de lecciones del curso
+        let total_lessons = query_scalar!(
+            r#"
+            SELECT COUNT(l.*)
+            FROM courses c
+            JOIN modules m ON m.course_id = c.id
+            JOIN lessons l ON l.module_id = m.id
+            WHERE c.id = $1
+            "#,
+            course_id
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+
+        let total_lessons_i32 = total_lessons.map(|v| v as i32);
+
+        query!(
+            r#"
+            INSERT INTO course_progress
+            (id, user_id, course_id, progress_percentage, total_lessons, completed_lessons, last_accessed, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            "#,
+            Uuid::new_v4(),
+            user_id,
+            course_id,
+            0.0,  // progreso inicial 0%
+            total_lessons_i32,
+            Some(0),  // 0 lecciones completadas inicialmente
+            Utc::now(),
+            Utc::now(),
+            Utc::now()
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(true)
+}
+
+/// Une los cursos comprados con el catálogo completo (`None` si el usuario no
+/// tiene acceso a todo), sin duplicados y conservando el orden de compra.
+pub(crate) fn merge_accessible_course_ids(purchased: &[Uuid], all_courses: Option<&[Uuid]>) -> Vec<Uuid> {
+    let mut ids: Vec<Uuid> = Vec::with_capacity(purchased.len());
+    for id in purchased.iter().chain(all_courses.unwrap_or_default()) {
+        if !ids.contains(id) {
+            ids.push(*id);
+        }
+    }
+    ids
+}
+
+/// Categorías que desbloquean las suscripciones vigentes del usuario. `None` si
+/// alguna no tiene restricción (plan ilimitado); si no, la unión de las permitidas.
+pub(crate) fn merge_plan_category_scopes(scopes: &[Option<Vec<String>>]) -> Option<Vec<String>> {
+    let mut categories: Vec<String> = Vec::new();
+    for scope in scopes {
+        let allowed = scope.as_ref()?;
+        for category in allowed {
+            if !categories.contains(category) {
+                categories.push(category.clone());
+            }
+        }
+    }
+    Some(categories)
+}
+
+/// Porcentaje de avance de un curso; 0 si el curso no tiene lecciones.
+pub(crate) fn course_progress_percentage(completed: i64, total: i64) -> f32 {
+    if total > 0 {
+        (completed as f32 / total as f32) * 100.0
+    } else {
+        0.0
+    }
+}
+
+/// Lecciones pedidas que no pertenecen al curso, sin repetir y en el orden recibido.
+pub(crate) fn lessons_outside_course(requested: &[Uuid], course_lessons: &[Uuid]) -> Vec<Uuid> {
+    let mut invalid: Vec<Uuid> = Vec::new();
+    for id in requested {
+        if !course_lessons.contains(id) && !invalid.contains(id) {
+            invalid.push(*id);
+        }
+    }
+    invalid
+}
+
+/// Hay plaza si el curso no tiene cupo, si aún no se llenó o si el usuario ya está
+/// inscrito (volver a comprar no ocupa otra plaza).
+pub(crate) fn has_free_seat(max_students: Option<i32>, enrolled: i64, already_enrolled: bool) -> bool {
+    already_enrolled || max_students.is_none_or(|max| enrolled < i64::from(max))
+}
+
 #[async_trait]
 pub trait CoursePurchaseExt {
+    /// Devuelve `false` sin registrar nada si el curso ya llenó su cupo. La fila del
+    /// curso queda bloqueada durante la transacción, así que las compras simultáneas
+    /// no pueden superar `max_students`.
     async fn register_course_purchase(
         &self,
         user_id: Uuid,
         course_id: Uuid,
         transaction_id: String,
-        amount: i64,
+        amount: &Money,
         payment_method: String,
         status: String,
-    ) -> Result<(), Error>;
+    ) -> Result<bool, Error>;
+
+    /// Comprobación previa (sin bloqueo) para no crear órdenes de cursos llenos.
+    /// `RowNotFound` si el curso no existe.
+    async fn course_has_seat(&self, course_id: Uuid, user_id: Uuid) -> Result<bool, Error>;
+
+    /// Fija o quita (`None`) el cupo del curso. `None` si el curso no existe.
+    async fn set_course_max_students(&self, course_id: Uuid, max_students: Option<i32>) -> Result<Option<Course>, Error>;
 
     async fn check_user_course_access (
         &self,
@@ -2415,11 +4321,48 @@ pub trait CoursePurchaseExt {
         course_id: Uuid,
     ) -> Result<Option<bool>, Error>;
 
+    /// Por qué el usuario tiene acceso al curso. `None` si el curso no existe.
+    async fn course_access_reason(
+        &self,
+        user_id: Uuid,
+        course_id: Uuid,
+    ) -> Result<Option<CourseAccessReason>, Error>;
+
     async fn get_user_purchased_courses(
         &self,
         user_id: Uuid,
     ) -> Result<Vec<Uuid>, Error>;
-    #[allow(dead_code)]
+
+    /// Cursos a los que el usuario tiene acceso: los comprados y, si es admin, todos.
+    /// Si es premium (y `premium_grants_all` está activo), los de las categorías
+    /// que cubre su plan, o todos si el plan no tiene restricción.
+    async fn get_accessible_course_ids(
+        &self,
+        user_id: Uuid,
+        premium_grants_all: bool,
+    ) -> Result<Vec<Uuid>, Error>;
+
+    /// Cursos comprados filtrados por estado de progreso, con el total para paginar.
+    async fn get_user_courses_by_status(
+        &self,
+        user_id: Uuid,
+        status: Option<CourseProgressStatus>,
+        page: usize,
+        limit: usize,
+    ) -> Result<(Vec<EnrolledCourseDto>, i64), Error>;
+
+    /// Alumnos inscritos en el curso con su progreso, de la inscripción más reciente
+    /// a la más antigua. `None` si el curso no existe.
+    async fn get_course_students(
+        &self,
+        course_id: Uuid,
+        page: usize,
+        limit: usize,
+    ) -> Result<Option<(Vec<CourseStudentDto>, i64)>, Error>;
+
+    /// Lecciones del curso en el orden de módulos y lecciones. `None` si el curso no existe.
+    async fn get_course_lesson_ids(&self, course_id: Uuid) -> Result<Option<Vec<Uuid>>, Error>;
+
     async fn get_user_course_progress(
         &self,
         user_id: Uuid,
@@ -2434,13 +4377,34 @@ pub trait CoursePurchaseExt {
         progress_percentage: f32,
     ) -> Result<(), Error>;
 
+    /// Guarda el avance de la lección y devuelve el progreso recalculado del curso.
     async fn update_lesson_progress(
         &self,
         user_id: Uuid,
         lesson_id: Uuid,
         is_completed: bool,
         progress: Option<f64>,
-    ) -> Result<(), Error>;
+    ) -> Result<CourseProgressEventDto, Error>;
+
+    /// Marca como completadas las lecciones importadas y recalcula el progreso de cada
+    /// alumno en una sola transacción. No otorga logros ni envía notificaciones.
+    /// `None` si el curso no existe.
+    async fn import_lesson_progress(
+        &self,
+        course_id: Uuid,
+        entries: &[ProgressImportEntryDto],
+    ) -> Result<Option<Vec<ProgressImportResultDto>>, Error>;
+
+    /// Marca o desmarca una lección como completada y recalcula el progreso del curso,
+    /// también hacia abajo. Repetir el mismo estado no cambia fechas ni otorga logros de
+    /// nuevo. `None` si la lección no pertenece al curso.
+    async fn set_lesson_completed(
+        &self,
+        user_id: Uuid,
+        course_id: Uuid,
+        lesson_id: Uuid,
+        completed: bool,
+    ) -> Result<Option<CourseProgressEventDto>, Error>;
 }
 
 #[async_trait]
@@ -2451,184 +4415,104 @@ impl CoursePurchaseExt for DBClient {
         user_id: Uuid,
         course_id: Uuid,
         transaction_id: String,
-        amount: i64,
+        amount: &Money,
         payment_method: String,
         status: String,
-    ) -> Result<(), Error> {
-        let mut tx = self.pool.begin().await?;
-        // Verificar que el curso existe
-        let course_exists = query_scalar!(
-            "SELECT EXISTS(SELECT 1 FROM courses WHERE id = $1)",
-            course_id
-        )
-        .fetch_one(&mut *tx)
-        .await?;
-
-        if !course_exists.unwrap_or(false) {
-            return Err(Error::RowNotFound);
+    ) -> Result<bool, Error> {
+        let mut tx = self.begin().await?;
+        if !register_course_purchase_in(&mut tx, user_id, course_id, transaction_id, amount, payment_method, status).await? {
+            return Ok(false);
         }
+        tx.commit().await?;
+
+        self.award_purchase_achievements(user_id).await;
+        Ok(true)
+    }
 
-        // Registrar la compra en la tabla payments y user_courses
-        query_as::<_, Payment>(
+    async fn course_has_seat(&self, course_id: Uuid, user_id: Uuid) -> Result<bool, Error> {
+        let (max_students, enrolled, already_enrolled) = sqlx::query_as::<_, (Option<i32>, i64, bool)>(
             r#"
-            INSERT INTO payments
-            (id, user_id, course_id, amount, payment_method, transaction_id, status, created_at, updated_at)
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
-            RETURNING id, user_id, course_id, amount, payment_method, transaction_id, status, created_at, updated_at
+            SELECT
+                c.max_students,
+                (SELECT COUNT(*) FROM user_courses uc WHERE uc.course_id = c.id),
+                EXISTS(SELECT 1 FROM user_courses uc WHERE uc.course_id = c.id AND uc.user_id = $2)
+            FROM courses c
+            WHERE c.id = $1
             "#
         )
-        .bind(Uuid::new_v4())
-        .bind(user_id)
         .bind(course_id)
-        .bind(amount)
-        .bind(payment_method)
-        .bind(transaction_id)
-        .bind(status)
-        .bind(Utc::now())
-        .bind(Utc::now())
-        .fetch_one(&mut *tx)
-        .await
-        .map_err(|e| {
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await.map_err(|e| {
             log::error!("ERROR: {}", e);
             e
-        })?;
+        })?
+        .ok_or(Error::RowNotFound)?;
 
-        // Registrar en user_courses si no existe
-        let user_course_exists = query_scalar!(
-            "SELECT EXISTS(SELECT 1 FROM user_courses WHERE user_id = $1 AND course_id = $2)",
-            user_id,
-            course_id
+        Ok(has_free_seat(max_students, enrolled, already_enrolled))
+    }
+
+    async fn set_course_max_students(&self, course_id: Uuid, max_students: Option<i32>) -> Result<Option<Course>, Error> {
+        sqlx::query_as::<_, Course>(
+            "UPDATE courses SET max_students = $2, updated_at = NOW() WHERE id = $1 RETURNING *"
         )
-        .fetch_one(&mut *tx)
-        .await?;
-
-        if !user_course_exists.unwrap_or(false) {
-            query!(
-                r#"
-                INSERT INTO user_courses (id, user_id, course_id, purchased_at)
-                VALUES ($1, $2, $3, $4)
-                "#,
-                Uuid::new_v4(),
-                user_id,
-                course_id,
-                Utc::now(),
-            )
-            .execute(&mut *tx)
-            .await?;
-            query!(
-                r#"
-                UPDATE courses
-                SET students = students + 1
-                WHERE id = $1
-                "#,
-                course_id
-            )
-            .execute(&mut *tx)
-            .await?;
-        }
-
-        // Inicializar progreso del curso si no existe
-        let progress_exists = query_scalar!(
-            "SELECT EXISTS(SELECT 1 FROM course_progress WHERE user_id = $1 AND course_id = $2)",
-            user_id,
-            course_id
-        )
-        .fetch_one(&mut *tx)
-        .await?;
-
-        if !progress_exists.unwrap_or(false) {
-            // Obtener el número total de lecciones del curso
-            let total_lessons = query_scalar!(
-                r#"
-                SELECT COUNT(l.*)
-                FROM courses c
-                JOIN modules m ON m.course_id = c.id
-                JOIN lessons l ON l.module_id = m.id
-                WHERE c.id = $1
-                "#,
-                course_id
-            )
-            .fetch_one(&mut *tx)
-            .await?;
-
-            let total_lessons_i32 = total_lessons.map(|v| v as i32);
-
-            query!(
-                r#"
-                INSERT INTO course_progress
-                (id, user_id, course_id, progress_percentage, total_lessons, completed_lessons, last_accessed, created_at, updated_at)
-                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
-                "#,
-                Uuid::new_v4(),
-                user_id,
-                course_id,
-                0.0,  // progreso inicial 0%
-                total_lessons_i32,
-                Some(0),  // 0 lecciones completadas inicialmente
-                Utc::now(),
-                Utc::now(),
-                Utc::now()
-            )
-            .execute(&mut *tx)
-            .await?;
-        }
-        tx.commit().await?;
-
-        // Verificar logros de cursos inscritos
-        let _ = self.check_and_award_achievements(user_id, "courses_enrolled", None).await;
-
-        Ok(())
-    }
+        .bind(course_id)
+        .bind(max_students)
+        .fetch_optional(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })
+    }
 
     async fn check_user_course_access(
         &self,
         user_id: Uuid,
         course_id: Uuid
     ) -> Result<Option<bool>, Error> {
-        let mut tx = self.pool.begin().await?;
-        // 1. Verificar si el usuario es admin
-        let is_admin = query_scalar!(
-            "SELECT EXISTS(SELECT 1 FROM users WHERE id = $1 AND role = 'admin')",
-            user_id
-        )
-        .fetch_one(&mut *tx)
-        .await?;
-
-        if is_admin.unwrap_or(false) {
-            return Ok(Some(true));
-        }
-
-        // 2. Verificar si el usuario tiene una suscripción activa
-        let has_active_subscription = query_scalar!(
-            "SELECT EXISTS(SELECT 1 FROM subscription WHERE user_id = $1 AND status = true AND end_time > NOW())",
-            user_id
-        )
-        .fetch_one(&mut *tx)
-        .await?;
+        let reason = self.course_access_reason(user_id, course_id).await?;
+        Ok(Some(reason.is_some_and(|r| r != CourseAccessReason::None)))
+    }
 
-        if has_active_subscription.unwrap_or(false) {
-            return Ok(Some(true));
-        }
-        
-        // 3. Verificar si el usuario ha comprado este curso específico
-        let check = query_scalar!(
+    async fn course_access_reason(
+        &self,
+        user_id: Uuid,
+        course_id: Uuid,
+    ) -> Result<Option<CourseAccessReason>, Error> {
+        // La suscripción cuenta si está vigente (`subscription_grants_access`) y su plan cubre la categoría del curso.
+        // `subscription.plan_id` guarda el id del plan en PayPal; sin plan conocido no hay restricción
+        let row = query!(
             r#"
-            SELECT EXISTS(
-                SELECT 1 FROM user_courses
-                WHERE user_id = $1 AND course_id = $2
-            )
+            SELECT
+                EXISTS(SELECT 1 FROM courses WHERE id = $2) AS "course_exists!",
+                EXISTS(SELECT 1 FROM users WHERE id = $1 AND role = 'admin') AS "is_admin!",
+                EXISTS(
+                    SELECT 1 FROM user_courses
+                    WHERE user_id = $1 AND course_id = $2
+                ) AS "owned!",
+                EXISTS(
+                    SELECT 1
+                    FROM subscription s
+                    JOIN courses c ON c.id = $2
+                    LEFT JOIN subscription_plans p ON p.paypal_plan_id = s.plan_id
+                    WHERE s.user_id = $1 AND subscription_grants_access(s.status, s.end_time, s.grace_until)
+                      AND (p.allowed_categories IS NULL OR c.category = ANY(p.allowed_categories))
+                ) AS "premium!"
             "#,
             user_id,
             course_id
         )
-        .fetch_one(&mut *tx)
+        .fetch_one(&self.pool)
         .await
         .map_err(|e| {
             log::error!("Error: {}", e);
             e
-        });
-        tx.commit().await?;
-        return check
+        })?;
+
+        if !row.course_exists {
+            return Ok(None);
+        }
+        Ok(Some(CourseAccessReason::from_flags(row.owned, row.premium, row.is_admin)))
     }
 
     async fn get_user_purchased_courses(
@@ -2657,6 +4541,230 @@ impl CoursePurchaseExt for DBClient {
         return purcha
     }
 
+    async fn get_accessible_course_ids(
+        &self,
+        user_id: Uuid,
+        premium_grants_all: bool,
+    ) -> Result<Vec<Uuid>, Error> {
+        let purchased = self.get_user_purchased_courses(user_id).await?;
+
+        let is_admin = sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS(SELECT 1 FROM users WHERE id = $1 AND role = 'admin')"
+        )
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+
+        // Alcance de cada suscripción vigente (una fila por suscripción)
+        let scopes = if is_admin || !premium_grants_all {
+            Vec::new()
+        } else {
+            sqlx::query_scalar::<_, Option<Vec<String>>>(
+                r#"
+                SELECT p.allowed_categories
+                FROM subscription s
+                LEFT JOIN subscription_plans p ON p.paypal_plan_id = s.plan_id
+                WHERE s.user_id = $1 AND subscription_grants_access(s.status, s.end_time, s.grace_until)
+                "#
+            )
+            .bind(user_id)
+            .fetch_all(&self.pool)
+            .await.map_err(|e| {
+                log::error!("ERROR: {}", e);
+                e
+            })?
+        };
+
+        if !is_admin && scopes.is_empty() {
+            return Ok(merge_accessible_course_ids(&purchased, None));
+        }
+
+        let categories = if is_admin { None } else { merge_plan_category_scopes(&scopes) };
+        let all_courses = sqlx::query_scalar::<_, Uuid>(
+            "SELECT id FROM courses WHERE $1::text[] IS NULL OR category = ANY($1) ORDER BY created_at"
+        )
+        .bind(categories)
+        .fetch_all(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+
+        Ok(merge_accessible_course_ids(&purchased, Some(&all_courses)))
+    }
+
+    async fn get_course_students(
+        &self,
+        course_id: Uuid,
+        page: usize,
+        limit: usize,
+    ) -> Result<Option<(Vec<CourseStudentDto>, i64)>, Error> {
+        let offset = ((page - 1) * limit) as i64;
+
+        let course_exists = query_scalar!(
+            "SELECT EXISTS(SELECT 1 FROM courses WHERE id = $1)",
+            course_id
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        if !course_exists.unwrap_or(false) {
+            return Ok(None);
+        }
+
+        let students = sqlx::query_as::<_, CourseStudentDto>(
+            r#"
+            SELECT
+                u.name,
+                u.email,
+                uc.purchased_at AS enrolled_at,
+                COALESCE(cp.progress_percentage, 0) AS progress_percentage,
+                cp.last_accessed AS last_activity
+            FROM user_courses uc
+            JOIN users u ON u.id = uc.user_id
+            LEFT JOIN course_progress cp ON cp.user_id = uc.user_id AND cp.course_id = uc.course_id
+            WHERE uc.course_id = $1
+            ORDER BY uc.purchased_at DESC
+            LIMIT $2 OFFSET $3
+            "#
+        )
+        .bind(course_id)
+        .bind(limit as i64)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+
+        let total = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM user_courses WHERE course_id = $1"
+        )
+        .bind(course_id)
+        .fetch_one(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+
+        Ok(Some((students, total)))
+    }
+
+    async fn get_user_courses_by_status(
+        &self,
+        user_id: Uuid,
+        status: Option<CourseProgressStatus>,
+        page: usize,
+        limit: usize,
+    ) -> Result<(Vec<EnrolledCourseDto>, i64), Error> {
+        let status = status.map(|s| s.as_str());
+        let offset = ((page - 1) * limit) as i64;
+        // Sin fila en course_progress el curso cuenta como no iniciado
+        let status_filter = r#"
+            (
+                $2::text IS NULL
+                OR ($2 = 'completed' AND COALESCE(cp.progress_percentage, 0) >= 100)
+                OR ($2 = 'in_progress' AND COALESCE(cp.progress_percentage, 0) > 0 AND COALESCE(cp.progress_percentage, 0) < 100)
+                OR ($2 = 'not_started' AND COALESCE(cp.progress_percentage, 0) <= 0)
+            )
+        "#;
+
+        let mut tx = self.pool.begin().await?;
+        let courses = sqlx::query_as::<_, EnrolledCourseDto>(&format!(
+            r#"
+            SELECT
+                c.id,
+                c.title,
+                c.slug,
+                c.description,
+                c.long_description,
+                c.level,
+                c.duration,
+                c.students,
+                c.paypal_product_id,
+                c.price,
+                course_effective_price(c.price, c.sale_price, c.sale_starts_at, c.sale_ends_at) AS effective_price,
+                c.image,
+                c.category,
+                COALESCE(AVG(cr.rating), 0)::int AS rating,
+                COUNT(cr.id) AS rating_count,
+                c.created_at,
+                c.updated_at,
+                c.features,
+                COALESCE(cp.progress_percentage, 0)::real AS progress_percentage
+            FROM user_courses uc
+            INNER JOIN courses c
+                ON c.id = uc.course_id
+            LEFT JOIN course_progress cp
+                ON cp.course_id = uc.course_id AND cp.user_id = uc.user_id
+            LEFT JOIN course_ratings cr
+                ON cr.course_id = c.id
+            WHERE uc.user_id = $1 AND {status_filter}
+            GROUP BY c.id, cp.progress_percentage, uc.purchased_at
+            ORDER BY uc.purchased_at DESC
+            LIMIT $3 OFFSET $4
+            "#
+        ))
+        .bind(user_id)
+        .bind(status)
+        .bind(limit as i64)
+        .bind(offset)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| {
+            log::error!("ERROR get_user_courses_by_status: {}", e);
+            e
+        })?;
+
+        let total = sqlx::query_scalar::<_, i64>(&format!(
+            r#"
+            SELECT COUNT(*)
+            FROM user_courses uc
+            LEFT JOIN course_progress cp
+                ON cp.course_id = uc.course_id AND cp.user_id = uc.user_id
+            WHERE uc.user_id = $1 AND {status_filter}
+            "#
+        ))
+        .bind(user_id)
+        .bind(status)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok((courses, total))
+    }
+
+    async fn get_course_lesson_ids(&self, course_id: Uuid) -> Result<Option<Vec<Uuid>>, Error> {
+        let course_exists = query_scalar!(
+            "SELECT EXISTS(SELECT 1 FROM courses WHERE id = $1)",
+            course_id
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        if !course_exists.unwrap_or(false) {
+            return Ok(None);
+        }
+
+        let lesson_ids = query_scalar!(
+            r#"
+            SELECT l.id
+            FROM lessons l
+            JOIN modules m ON m.id = l.module_id
+            WHERE m.course_id = $1
+            ORDER BY m."order", l."order"
+            "#,
+            course_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(Some(lesson_ids))
+    }
+
     async fn get_user_course_progress(
         &self,
         user_id: Uuid,
@@ -2719,7 +4827,7 @@ impl CoursePurchaseExt for DBClient {
         lesson_id: Uuid,
         is_completed: bool,
         progress: Option<f64>,
-    ) -> Result<(), Error> {
+    ) -> Result<CourseProgressEventDto, Error> {
         let mut tx = self.pool.begin().await?;
 
         // Actualizar o crear el progreso de la lección
@@ -2796,11 +4904,7 @@ impl CoursePurchaseExt for DBClient {
         let total_lessons_value = total_lessons.unwrap_or(1);
 
         // Calcular el porcentaje de progreso
-        let progress_percentage = if total_lessons_value > 0 {
-            (completed_lessons_value as f32 / total_lessons_value as f32) * 100.0
-        } else {
-            0.0
-        };
+        let progress_percentage = course_progress_percentage(completed_lessons_value, total_lessons_value);
 
 
         // Actualizar el progreso del curso
@@ -2829,54 +4933,319 @@ impl CoursePurchaseExt for DBClient {
         // Otorgar logros después del commit
         if is_completed {
             let _ = self
-                .check_and_award_achievements(user_id, "lesson_completed", None)
+                .check_and_award_achievements(user_id, TriggerType::LessonCompleted)
                 .await;
         }
 
         if progress_percentage >= 100.0 {
             let _ = self
-                .check_and_award_achievements(user_id, "course_completed", None)
+                .check_and_award_achievements(user_id, TriggerType::CourseCompleted)
                 .await;
         }
     
-        Ok(())
+        Ok(CourseProgressEventDto {
+            course_id,
+            progress_percentage,
+            completed_lessons: completed_lessons_value,
+            total_lessons: total_lessons_value,
+        })
     }
 
-}
-
-#[async_trait]
-pub trait SubscriptionPlanExt {
-    async fn create_subscription_plan(
+    async fn import_lesson_progress(
         &self,
-        name: &str,
-        description: Option<&String>,
-        price: f64,
-        duration_months: i32,
-        features: Option<&serde_json::Value>,
-        paypal_plan_id: Option<&str>,
-    ) -> Result<SubscriptionPlan, Error>;
+        course_id: Uuid,
+        entries: &[ProgressImportEntryDto],
+    ) -> Result<Option<Vec<ProgressImportResultDto>>, Error> {
+        let mut tx = self.pool.begin().await?;
 
-    async fn update_subscription_plan(
-        &self,
-        plan_id: Uuid,
-        name: Option<&str>,
-        description: Option<&String>,
-        price: Option<f64>,
-        duration_months: Option<i32>,
-        features: Option<&serde_json::Value>,
-        paypal_plan_id: Option<&str>,
-        active: Option<bool>,
-    ) -> Result<SubscriptionPlan, Error>;
+        let course_exists = query_scalar!(
+            "SELECT EXISTS(SELECT 1 FROM courses WHERE id = $1)",
+            course_id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
 
-    async fn delete_subscription_plan(&self, plan_id: Uuid) -> Result<(), Error>;
+        if !course_exists.unwrap_or(false) {
+            return Ok(None);
+        }
 
-    async fn get_subscription_plans(&self) -> Result<Vec<SubscriptionPlan>, Error>;
-}
+        let course_lessons = sqlx::query_scalar::<_, Uuid>(
+            r#"
+            SELECT l.id
+            FROM lessons l
+            JOIN modules m ON m.id = l.module_id
+            WHERE m.course_id = $1
+            "#
+        )
+        .bind(course_id)
+        .fetch_all(&mut *tx)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+        let total_lessons = course_lessons.len() as i64;
 
-#[async_trait]
-pub trait SubscriptionExt {
-    async fn create_subscription(
-        &self,
+        let user_ids: Vec<Uuid> = entries.iter().map(|e| e.user_id).collect();
+        let existing_users = sqlx::query_scalar::<_, Uuid>(
+            "SELECT id FROM users WHERE id = ANY($1)"
+        )
+        .bind(&user_ids)
+        .fetch_all(&mut *tx)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+
+        let mut results = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let mut result = ProgressImportResultDto {
+                user_id: entry.user_id,
+                status: "imported".to_string(),
+                completed_lessons: 0,
+                progress_percentage: 0.0,
+                invalid_lesson_ids: Vec::new(),
+            };
+
+            if !existing_users.contains(&entry.user_id) {
+                result.status = "user_not_found".to_string();
+                results.push(result);
+                continue;
+            }
+
+            let invalid = lessons_outside_course(&entry.completed_lesson_ids, &course_lessons);
+            if !invalid.is_empty() {
+                result.status = "invalid_lessons".to_string();
+                result.invalid_lesson_ids = invalid;
+                results.push(result);
+                continue;
+            }
+
+            // Conserva la fecha de finalización si la lección ya estaba completada
+            sqlx::query(
+                r#"
+                INSERT INTO user_lesson_progress (user_id, lesson_id, is_completed, completed_at)
+                SELECT $1, lesson_id, true, NOW()
+                FROM UNNEST($2::uuid[]) AS lesson_id
+                ON CONFLICT (user_id, lesson_id)
+                DO UPDATE SET
+                    is_completed = true,
+                    updated_at = NOW(),
+                    completed_at = COALESCE(user_lesson_progress.completed_at, NOW())
+                "#
+            )
+            .bind(entry.user_id)
+            .bind(&entry.completed_lesson_ids)
+            .execute(&mut *tx)
+            .await.map_err(|e| {
+                log::error!("ERROR: {}", e);
+                e
+            })?;
+
+            let completed_lessons = sqlx::query_scalar::<_, i64>(
+                r#"
+                SELECT COUNT(*) FROM user_lesson_progress
+                WHERE user_id = $1 AND is_completed = true AND lesson_id = ANY($2)
+                "#
+            )
+            .bind(entry.user_id)
+            .bind(&course_lessons)
+            .fetch_one(&mut *tx)
+            .await.map_err(|e| {
+                log::error!("ERROR: {}", e);
+                e
+            })?;
+            let progress_percentage = course_progress_percentage(completed_lessons, total_lessons);
+
+            sqlx::query(
+                r#"
+                INSERT INTO course_progress (user_id, course_id, progress_percentage, total_lessons, completed_lessons)
+                VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT (user_id, course_id)
+                DO UPDATE SET
+                    progress_percentage = $3,
+                    total_lessons = $4,
+                    completed_lessons = $5,
+                    updated_at = NOW(),
+                    completed_at = CASE
+                        WHEN $3 >= 100 THEN COALESCE(course_progress.completed_at, NOW())
+                        ELSE course_progress.completed_at
+                    END
+                "#
+            )
+            .bind(entry.user_id)
+            .bind(course_id)
+            .bind(progress_percentage)
+            .bind(total_lessons as i32)
+            .bind(completed_lessons as i32)
+            .execute(&mut *tx)
+            .await.map_err(|e| {
+                log::error!("ERROR: {}", e);
+                e
+            })?;
+
+            result.completed_lessons = completed_lessons;
+            result.progress_percentage = progress_percentage;
+            results.push(result);
+        }
+
+        tx.commit().await?;
+        Ok(Some(results))
+    }
+
+    async fn set_lesson_completed(
+        &self,
+        user_id: Uuid,
+        course_id: Uuid,
+        lesson_id: Uuid,
+        completed: bool,
+    ) -> Result<Option<CourseProgressEventDto>, Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let course_lessons = sqlx::query_scalar::<_, Uuid>(
+            r#"
+            SELECT l.id
+            FROM lessons l
+            JOIN modules m ON m.id = l.module_id
+            WHERE m.course_id = $1
+            "#
+        )
+        .bind(course_id)
+        .fetch_all(&mut *tx)
+        .await?;
+        if !course_lessons.contains(&lesson_id) {
+            return Ok(None);
+        }
+
+        let was_completed = sqlx::query_scalar::<_, bool>(
+            "SELECT is_completed FROM user_lesson_progress WHERE user_id = $1 AND lesson_id = $2 FOR UPDATE"
+        )
+        .bind(user_id)
+        .bind(lesson_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .unwrap_or(false);
+
+        // Conserva la fecha de finalización si la lección ya estaba completada
+        sqlx::query(
+            r#"
+            INSERT INTO user_lesson_progress (user_id, lesson_id, is_completed, completed_at, last_accessed)
+            VALUES ($1, $2, $3, CASE WHEN $3 THEN NOW() END, NOW())
+            ON CONFLICT (user_id, lesson_id)
+            DO UPDATE SET
+                is_completed = $3,
+                last_accessed = NOW(),
+                updated_at = NOW(),
+                completed_at = CASE WHEN $3 THEN COALESCE(user_lesson_progress.completed_at, NOW()) END
+            "#
+        )
+        .bind(user_id)
+        .bind(lesson_id)
+        .bind(completed)
+        .execute(&mut *tx)
+        .await?;
+
+        let completed_lessons = sqlx::query_scalar::<_, i64>(
+            r#"
+            SELECT COUNT(*) FROM user_lesson_progress
+            WHERE user_id = $1 AND is_completed = true AND lesson_id = ANY($2)
+            "#
+        )
+        .bind(user_id)
+        .bind(&course_lessons)
+        .fetch_one(&mut *tx)
+        .await?;
+        let total_lessons = course_lessons.len() as i64;
+        let progress_percentage = course_progress_percentage(completed_lessons, total_lessons);
+
+        sqlx::query(
+            r#"
+            INSERT INTO course_progress (user_id, course_id, progress_percentage, total_lessons, completed_lessons, last_accessed)
+            VALUES ($1, $2, $3, $4, $5, NOW())
+            ON CONFLICT (user_id, course_id)
+            DO UPDATE SET
+                progress_percentage = $3,
+                total_lessons = $4,
+                completed_lessons = $5,
+                last_accessed = NOW(),
+                updated_at = NOW(),
+                completed_at = CASE
+                    WHEN $3 >= 100 THEN COALESCE(course_progress.completed_at, NOW())
+                END
+            "#
+        )
+        .bind(user_id)
+        .bind(course_id)
+        .bind(progress_percentage)
+        .bind(total_lessons as i32)
+        .bind(completed_lessons as i32)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        // Logros solo al pasar a completada, no al repetir la misma petición
+        if completed && !was_completed {
+            let _ = self
+                .check_and_award_achievements(user_id, TriggerType::LessonCompleted)
+                .await;
+            if progress_percentage >= 100.0 {
+                let _ = self
+                    .check_and_award_achievements(user_id, TriggerType::CourseCompleted)
+                    .await;
+            }
+        }
+
+        Ok(Some(CourseProgressEventDto {
+            course_id,
+            progress_percentage,
+            completed_lessons,
+            total_lessons,
+        }))
+    }
+
+}
+
+/// Datos de `create_subscription_plan`.
+#[derive(Debug)]
+pub struct NewSubscriptionPlan<'a> {
+    pub name: &'a str,
+    pub description: Option<&'a str>,
+    pub price: f64,
+    pub duration_months: i32,
+    pub features: Option<&'a serde_json::Value>,
+    pub paypal_plan_id: Option<&'a str>,
+    pub allowed_categories: Option<&'a [String]>,
+    pub trial_days: i32,
+}
+
+/// Cambios de `update_subscription_plan`: `None` deja el valor actual.
+#[derive(Debug, Default)]
+pub struct SubscriptionPlanChanges<'a> {
+    pub name: Option<&'a str>,
+    pub description: Option<&'a str>,
+    pub price: Option<f64>,
+    pub duration_months: Option<i32>,
+    pub features: Option<&'a serde_json::Value>,
+    pub paypal_plan_id: Option<&'a str>,
+    pub active: Option<bool>,
+    // Lista vacía: quita la restricción y el plan vuelve a cubrir todo el catálogo.
+    pub allowed_categories: Option<&'a [String]>,
+}
+
+#[async_trait]
+pub trait SubscriptionPlanExt {
+    async fn create_subscription_plan(&self, plan: NewSubscriptionPlan<'_>) -> Result<SubscriptionPlan, Error>;
+
+    async fn update_subscription_plan(&self, plan_id: Uuid, changes: SubscriptionPlanChanges<'_>) -> Result<SubscriptionPlan, Error>;
+
+    async fn delete_subscription_plan(&self, plan_id: Uuid) -> Result<(), Error>;
+
+    async fn get_subscription_plans(&self) -> Result<Vec<SubscriptionPlan>, Error>;
+}
+
+#[async_trait]
+pub trait SubscriptionExt {
+    async fn create_subscription(
+        &self,
         user_id: Uuid,
         plan_id: &String,
         paypal_id: &String,
@@ -2900,9 +5269,25 @@ pub trait SubscriptionExt {
     async fn update_subscription_status(
         &self,
         paypal_subscription_id: &str,
-        status: bool,
+        status: SubscriptionStatus,
     ) -> Result<(), Error>;
 
+    /// Suscripción del usuario con su plan e historial de cambios de plan. `None` si no
+    /// existe o pertenece a otro usuario.
+    async fn get_subscription_detail(
+        &self,
+        user_id: Uuid,
+        subscription_id: Uuid,
+    ) -> Result<Option<SubscriptionDetailDto>, Error>;
+
+    /// Activa la suscripción por el periodo de su plan y extiende `users.subscription_expires_at`.
+    /// Idempotente: una suscripción ya activa y vigente no cambia y devuelve `false`.
+    /// `RowNotFound` si la suscripción no existe o su plan no es válido.
+    async fn activate_subscription(
+        &self,
+        paypal_subscription_id: &str,
+    ) -> Result<bool, Error>;
+
     async fn expire_subscription(
         &self,
         paypal_subscription_id: &str,
@@ -2912,39 +5297,63 @@ pub trait SubscriptionExt {
         &self,
         user_id: Uuid,
     ) -> Result<bool, Error>;
+
+    /// Abre el periodo de gracia tras un pago fallido. Devuelve la suscripción solo
+    /// si el periodo se abre ahora (para avisar al usuario una única vez).
+    async fn start_subscription_grace_period(
+        &self,
+        paypal_subscription_id: &str,
+        grace_until: DateTime<Utc>,
+    ) -> Result<Option<Subscription>, Error>;
+
+    /// Desactiva las suscripciones cuyo periodo pagado y de gracia ya terminaron.
+    async fn expire_lapsed_subscriptions(&self) -> Result<u64, Error>;
+
+    /// Registra un cambio de plan. Si no hay `approval_url` el cambio queda aplicado
+    /// y se actualiza `subscription.plan_id`; si la hay, queda pendiente de aprobación.
+    async fn record_subscription_plan_change(
+        &self,
+        subscription_id: Uuid,
+        user_id: Uuid,
+        from_plan_id: Option<&str>,
+        to_plan_id: &str,
+        approval_url: Option<&str>,
+    ) -> Result<(), Error>;
+
+    /// Aplica el plan que PayPal reporta en BILLING.SUBSCRIPTION.UPDATED y cierra
+    /// los cambios pendientes hacia ese plan.
+    async fn apply_subscription_plan_change(
+        &self,
+        paypal_subscription_id: &str,
+        plan_id: &str,
+    ) -> Result<(), Error>;
 }
 
 #[async_trait]
 impl SubscriptionPlanExt for DBClient {
-    async fn create_subscription_plan(
-        &self,
-        name: &str,
-        description: Option<&String>,
-        price: f64,
-        duration_months: i32,
-        features: Option<&serde_json::Value>,
-        paypal_plan_id: Option<&str>,
-    ) -> Result<SubscriptionPlan, Error> {
+    async fn create_subscription_plan(&self, new_plan: NewSubscriptionPlan<'_>) -> Result<SubscriptionPlan, Error> {
         let mut tx = self.pool.begin().await?;
         let id = Uuid::new_v4();
         let now = Utc::now();
 
         let plan = sqlx::query_as::<_, SubscriptionPlan>(
             r#"
-            INSERT INTO subscription_plans (id, name, description, price, duration_months, features, paypal_plan_id, active, created_at, updated_at)
-            VALUES ($1, $2, $3, $4, $5, $6, $7, true, $8, $9)
-            RETURNING id, name, description, price, duration_months, features, paypal_plan_id, active, created_at, updated_at
+            INSERT INTO subscription_plans (id, name, description, price, duration_months, features, paypal_plan_id, active, created_at, updated_at, allowed_categories, trial_days)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, true, $8, $9, NULLIF($10, '{}'::text[]), $11)
+            RETURNING id, name, description, price, duration_months, features, paypal_plan_id, active, created_at, updated_at, allowed_categories, trial_days
             "#,
         )
         .bind(id)
-        .bind(name)
-        .bind(description)
-        .bind(price)
-        .bind(duration_months)
-        .bind(features)
-        .bind(paypal_plan_id)
+        .bind(new_plan.name)
+        .bind(new_plan.description)
+        .bind(new_plan.price)
+        .bind(new_plan.duration_months)
+        .bind(new_plan.features)
+        .bind(new_plan.paypal_plan_id)
         .bind(now)
         .bind(now)
+        .bind(new_plan.allowed_categories)
+        .bind(new_plan.trial_days)
         .fetch_one(&mut *tx)
         .await.map_err(|e| {
             log::error!("ERROR: {}", e);
@@ -2954,17 +5363,7 @@ impl SubscriptionPlanExt for DBClient {
         Ok(plan)
     }
 
-    async fn update_subscription_plan(
-        &self,
-        plan_id: Uuid,
-        name: Option<&str>,
-        description: Option<&String>,
-        price: Option<f64>,
-        duration_months: Option<i32>,
-        features: Option<&serde_json::Value>,
-        paypal_plan_id: Option<&str>,
-        active: Option<bool>,
-    ) -> Result<SubscriptionPlan, Error> {
+    async fn update_subscription_plan(&self, plan_id: Uuid, changes: SubscriptionPlanChanges<'_>) -> Result<SubscriptionPlan, Error> {
         let mut tx = self.pool.begin().await?;
         let now = Utc::now();
 
@@ -2978,20 +5377,25 @@ impl SubscriptionPlanExt for DBClient {
                 features = COALESCE($6, features),
                 paypal_plan_id = COALESCE($7, paypal_plan_id),
                 active = COALESCE($8, active),
-                updated_at = $9
+                updated_at = $9,
+                allowed_categories = CASE
+                    WHEN $10::text[] IS NULL THEN allowed_categories
+                    ELSE NULLIF($10, '{}'::text[])
+                END
             WHERE id = $1
-            RETURNING id, name, description, price, duration_months, features, paypal_plan_id, active, created_at, updated_at
+            RETURNING id, name, description, price, duration_months, features, paypal_plan_id, active, created_at, updated_at, allowed_categories, trial_days
             "#,
         )
         .bind(plan_id)
-        .bind(name)
-        .bind(description)
-        .bind(price)
-        .bind(duration_months)
-        .bind(features)
-        .bind(paypal_plan_id)
-        .bind(active)
+        .bind(changes.name)
+        .bind(changes.description)
+        .bind(changes.price)
+        .bind(changes.duration_months)
+        .bind(changes.features)
+        .bind(changes.paypal_plan_id)
+        .bind(changes.active)
         .bind(now)
+        .bind(changes.allowed_categories)
         .fetch_one(&mut *tx)
         .await.map_err(|e| {
             log::error!("ERROR: {}", e);
@@ -3018,7 +5422,7 @@ impl SubscriptionPlanExt for DBClient {
         let mut tx = self.pool.begin().await?;
         let plans = sqlx::query_as::<_, SubscriptionPlan>(
             r#"
-            SELECT id, name, description, price, duration_months, features, paypal_plan_id, active, created_at, updated_at
+            SELECT id, name, description, price, duration_months, features, paypal_plan_id, active, created_at, updated_at, allowed_categories, trial_days
             FROM subscription_plans
             WHERE active = true
             ORDER BY created_at DESC
@@ -3050,8 +5454,8 @@ impl SubscriptionExt for DBClient {
         sqlx::query(
             r#"
             UPDATE subscription
-            SET status = false, updated_at = $2
-            WHERE user_id = $1 AND status = true
+            SET status = 'cancelled', updated_at = $2
+            WHERE user_id = $1 AND status IN ('active', 'suspended')
             "#,
         )
         .bind(user_id)
@@ -3062,6 +5466,41 @@ impl SubscriptionExt for DBClient {
             e
         })?;
 
+        // Prueba gratis: solo para quien nunca tuvo una suscripción ni usó otra prueba.
+        // El cobro lo difiere PayPal con el ciclo TRIAL del plan; aquí solo se da acceso.
+        let trial_days = sqlx::query_scalar::<_, i32>(
+            "SELECT trial_days FROM subscription_plans WHERE paypal_plan_id = $1 OR id::text = $1 LIMIT 1"
+        )
+        .bind(plan_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .unwrap_or(0);
+
+        let mut trial_end = None;
+        if trial_days > 0 {
+            let end = now + chrono::Duration::days(trial_days as i64);
+            let claimed = sqlx::query(
+                r#"
+                UPDATE users
+                SET trial_used_at = $2,
+                    subscription_expires_at = GREATEST(subscription_expires_at, $3),
+                    updated_at = $2
+                WHERE id = $1
+                  AND trial_used_at IS NULL
+                  AND NOT EXISTS (SELECT 1 FROM subscription WHERE user_id = $1)
+                "#,
+            )
+            .bind(user_id)
+            .bind(now)
+            .bind(end)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected() == 1;
+            if claimed {
+                trial_end = Some(end);
+            }
+        }
+
         let subscription = sqlx::query_as::<_, Subscription>(
             r#"
             INSERT INTO subscription (
@@ -3075,8 +5514,8 @@ impl SubscriptionExt for DBClient {
                 created_at, 
                 updated_at
             )
-            VALUES ($1, $2, $3, true, $4, $5, NULL, $6, $7)
-            RETURNING id, user_id, paypal_subscription_id, status, plan_id, start_time, end_time, created_at, updated_at
+            VALUES ($1, $2, $3, 'active', $4, $5, $8, $6, $7)
+            RETURNING id, user_id, paypal_subscription_id, status, plan_id, start_time, end_time, grace_until, created_at, updated_at
             "#,
         )
         .bind(id)        // $1
@@ -3086,6 +5525,7 @@ impl SubscriptionExt for DBClient {
         .bind(now)       // $5 (start_time)
         .bind(now)       // $6 (created_at)
         .bind(now)       // $7 (updated_at)
+        .bind(trial_end) // $8 (end_time: fin de la prueba, o NULL hasta la activación)
         .fetch_one(&mut *tx)
         .await
         .map_err(|e| {
@@ -3104,7 +5544,7 @@ impl SubscriptionExt for DBClient {
         let mut tx = self.pool.begin().await?;
         let subscriptions = sqlx::query_as::<_, Subscription>(
             r#"
-            SELECT id, user_id, paypal_subscription_id, status, plan_id, start_time, end_time, created_at, updated_at
+            SELECT id, user_id, paypal_subscription_id, status, plan_id, start_time, end_time, grace_until, created_at, updated_at
             FROM subscription
             WHERE user_id = $1
             ORDER BY created_at DESC
@@ -3130,7 +5570,7 @@ impl SubscriptionExt for DBClient {
         sqlx::query(
             r#"
             UPDATE subscription
-            SET status = false, updated_at = $2
+            SET status = 'cancelled', updated_at = $2
             WHERE id = $1
             "#,
         )
@@ -3169,10 +5609,10 @@ impl SubscriptionExt for DBClient {
             // Obtener duration_months del plan
             let duration_months: Option<i32> = sqlx::query_scalar(
                 r#"
-                SELECT duration_months FROM subscription_plans WHERE id = $1
+                SELECT duration_months FROM subscription_plans WHERE paypal_plan_id = $1 OR id::text = $1 LIMIT 1
                 "#,
             )
-            .bind(Uuid::parse_str(&plan_id).map_err(|_| Error::RowNotFound)?)
+            .bind(&plan_id)
             .fetch_optional(&mut *tx)
             .await.map_err(|e| {
                 log::error!("ERROR: {}", e);
@@ -3185,7 +5625,7 @@ impl SubscriptionExt for DBClient {
                 sqlx::query(
                     r#"
                     UPDATE subscription
-                    SET end_time = $2, updated_at = $3
+                    SET end_time = $2, grace_until = NULL, updated_at = $3
                     WHERE paypal_subscription_id = $1
                     "#,
                 )
@@ -3207,7 +5647,7 @@ impl SubscriptionExt for DBClient {
     async fn update_subscription_status(
         &self,
         paypal_subscription_id: &str,
-        status: bool,
+        status: SubscriptionStatus,
     ) -> Result<(), Error> {
         let mut tx = self.pool.begin().await?;
         let now = Utc::now();
@@ -3215,7 +5655,10 @@ impl SubscriptionExt for DBClient {
         sqlx::query(
             r#"
             UPDATE subscription
-            SET status = $2, updated_at = $3
+            SET status = $2,
+                -- la suspensión conserva el periodo de gracia abierto por el pago fallido
+                grace_until = CASE WHEN $2 = 'suspended'::subscription_status THEN grace_until ELSE NULL END,
+                updated_at = $3
             WHERE paypal_subscription_id = $1
             "#,
         )
@@ -3231,6 +5674,106 @@ impl SubscriptionExt for DBClient {
         Ok(())
     }
 
+    async fn get_subscription_detail(
+        &self,
+        user_id: Uuid,
+        subscription_id: Uuid,
+    ) -> Result<Option<SubscriptionDetailDto>, Error> {
+        let subscription = sqlx::query_as::<_, Subscription>(
+            r#"
+            SELECT id, user_id, paypal_subscription_id, status, plan_id, start_time, end_time, grace_until, created_at, updated_at
+            FROM subscription
+            WHERE id = $1 AND user_id = $2
+            "#,
+        )
+        .bind(subscription_id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        let Some(subscription) = subscription else {
+            return Ok(None);
+        };
+
+        // `plan_id` guarda el id del plan en PayPal (o el local en filas antiguas)
+        let plan = match subscription.plan_id.as_deref() {
+            Some(plan_id) => sqlx::query_as::<_, SubscriptionPlan>("SELECT * FROM subscription_plans WHERE paypal_plan_id = $1 OR id::text = $1 LIMIT 1")
+                .bind(plan_id)
+                .fetch_optional(&self.pool)
+                .await?,
+            None => None,
+        };
+
+        let plan_changes = sqlx::query_as::<_, SubscriptionPlanChangeDto>(
+            r#"
+            SELECT from_plan_id, to_plan_id, status, created_at, applied_at
+            FROM subscription_plan_changes
+            WHERE subscription_id = $1
+            ORDER BY created_at DESC
+            "#,
+        )
+        .bind(subscription_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(Some(SubscriptionDetailDto::new(subscription, plan, plan_changes, Utc::now())))
+    }
+
+    async fn activate_subscription(
+        &self,
+        paypal_subscription_id: &str,
+    ) -> Result<bool, Error> {
+        let mut tx = self.pool.begin().await?;
+        let now = Utc::now();
+
+        // Bloquea la fila: eventos duplicados simultáneos se procesan de a uno
+        let current = sqlx::query_as::<_, (Uuid, SubscriptionStatus, Option<DateTime<Utc>>, Option<String>)>(
+            "SELECT user_id, status, end_time, plan_id FROM subscription WHERE paypal_subscription_id = $1 FOR UPDATE"
+        )
+        .bind(paypal_subscription_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(Error::RowNotFound)?;
+        let (user_id, status, end_time, plan_id) = current;
+
+        if status == SubscriptionStatus::Active && end_time.is_some_and(|end| end > now) {
+            return Ok(false);
+        }
+
+        let plan_id = plan_id.ok_or(Error::RowNotFound)?;
+        let duration_months = sqlx::query_scalar::<_, i32>(
+            "SELECT duration_months FROM subscription_plans WHERE paypal_plan_id = $1 OR id::text = $1 LIMIT 1"
+        )
+            .bind(plan_id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or(Error::RowNotFound)?;
+        let end_time = subscription_period_end(now, duration_months);
+
+        sqlx::query(
+            r#"
+            UPDATE subscription
+            SET status = 'active', end_time = $2, grace_until = NULL, updated_at = $3
+            WHERE paypal_subscription_id = $1
+            "#
+        )
+        .bind(paypal_subscription_id)
+        .bind(end_time)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+
+        // Con otra suscripción vigente más larga se conserva su vencimiento
+        sqlx::query("UPDATE users SET subscription_expires_at = GREATEST(subscription_expires_at, $2), updated_at = $3 WHERE id = $1")
+            .bind(user_id)
+            .bind(end_time)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
     async fn expire_subscription(
         &self,
         paypal_subscription_id: &str,
@@ -3241,8 +5784,8 @@ impl SubscriptionExt for DBClient {
         sqlx::query(
             r#"
             UPDATE subscription
-            SET status = false, end_time = $2, updated_at = $2
-            WHERE paypal_subscription_id = $1 AND end_time IS NULL
+            SET status = 'expired', end_time = LEAST(COALESCE(end_time, $2), $2), grace_until = NULL, updated_at = $2
+            WHERE paypal_subscription_id = $1
             "#,
         )
         .bind(paypal_subscription_id)
@@ -3264,8 +5807,8 @@ impl SubscriptionExt for DBClient {
         let has_active = sqlx::query_scalar::<_, bool>(
             r#"
             SELECT EXISTS(
-                SELECT 1 FROM subscription 
-                WHERE user_id = $1 AND end_time > NOW()
+                SELECT 1 FROM subscription
+                WHERE user_id = $1 AND subscription_grants_access(status, end_time, grace_until)
             )
             "#,
         )
@@ -3278,31 +5821,208 @@ impl SubscriptionExt for DBClient {
         tx.commit().await?;
         Ok(has_active)
     }
-}
-
-#[async_trait]
-pub trait PasswordResetTokenExt {
-    async fn create_password_reset_token(
-        &self,
-        user_id: Uuid,
-        token_hash: &str,
-        expires_at: DateTime<Utc>,
-    ) -> Result<PasswordResetToken, Error>;
 
-    async fn get_password_reset_token(
+    async fn start_subscription_grace_period(
         &self,
-        token_hash: &str,
-    ) -> Result<Option<PasswordResetToken>, Error>;
+        paypal_subscription_id: &str,
+        grace_until: DateTime<Utc>,
+    ) -> Result<Option<Subscription>, Error> {
+        let mut tx = self.pool.begin().await?;
+        let now = Utc::now();
 
-    async fn mark_token_used(
-        &self,
+        let subscription = sqlx::query_as::<_, Subscription>(
+            r#"
+            UPDATE subscription
+            SET grace_until = $2, updated_at = $3
+            WHERE paypal_subscription_id = $1 AND status IN ('active', 'suspended') AND grace_until IS NULL
+            RETURNING id, user_id, paypal_subscription_id, status, plan_id, start_time, end_time, grace_until, created_at, updated_at
+            "#,
+        )
+        .bind(paypal_subscription_id)
+        .bind(grace_until)
+        .bind(now)
+        .fetch_optional(&mut *tx)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+        tx.commit().await?;
+        Ok(subscription)
+    }
+
+    async fn expire_lapsed_subscriptions(&self) -> Result<u64, Error> {
+        let now = Utc::now();
+
+        let result = sqlx::query(
+            r#"
+            UPDATE subscription
+            SET status = 'expired', grace_until = NULL, updated_at = $1
+            WHERE status IN ('active', 'suspended')
+              AND grace_until IS NOT NULL AND grace_until <= $1
+              AND (end_time IS NULL OR end_time <= $1)
+            "#,
+        )
+        .bind(now)
+        .execute(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+        Ok(result.rows_affected())
+    }
+
+    async fn record_subscription_plan_change(
+        &self,
+        subscription_id: Uuid,
+        user_id: Uuid,
+        from_plan_id: Option<&str>,
+        to_plan_id: &str,
+        approval_url: Option<&str>,
+    ) -> Result<(), Error> {
+        let mut tx = self.pool.begin().await?;
+        let now = Utc::now();
+        let status = if approval_url.is_some() { "pending_approval" } else { "applied" };
+
+        sqlx::query(
+            r#"
+            INSERT INTO subscription_plan_changes (subscription_id, user_id, from_plan_id, to_plan_id, status, approval_url, created_at, applied_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, CASE WHEN $6::TEXT IS NULL THEN $7 END)
+            "#,
+        )
+        .bind(subscription_id)
+        .bind(user_id)
+        .bind(from_plan_id)
+        .bind(to_plan_id)
+        .bind(status)
+        .bind(approval_url)
+        .bind(now)
+        .execute(&mut *tx)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+
+        if approval_url.is_none() {
+            sqlx::query(
+                r#"
+                UPDATE subscription
+                SET plan_id = $2, updated_at = $3
+                WHERE id = $1
+                "#,
+            )
+            .bind(subscription_id)
+            .bind(to_plan_id)
+            .bind(now)
+            .execute(&mut *tx)
+            .await.map_err(|e| {
+                log::error!("ERROR: {}", e);
+                e
+            })?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn apply_subscription_plan_change(
+        &self,
+        paypal_subscription_id: &str,
+        plan_id: &str,
+    ) -> Result<(), Error> {
+        let mut tx = self.pool.begin().await?;
+        let now = Utc::now();
+
+        let subscription_id: Option<Uuid> = sqlx::query_scalar(
+            r#"
+            UPDATE subscription
+            SET plan_id = $2, updated_at = $3
+            WHERE paypal_subscription_id = $1
+            RETURNING id
+            "#,
+        )
+        .bind(paypal_subscription_id)
+        .bind(plan_id)
+        .bind(now)
+        .fetch_optional(&mut *tx)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+
+        if let Some(subscription_id) = subscription_id {
+            sqlx::query(
+                r#"
+                UPDATE subscription_plan_changes
+                SET status = 'applied', applied_at = $3
+                WHERE subscription_id = $1 AND to_plan_id = $2 AND status = 'pending_approval'
+                "#,
+            )
+            .bind(subscription_id)
+            .bind(plan_id)
+            .bind(now)
+            .execute(&mut *tx)
+            .await.map_err(|e| {
+                log::error!("ERROR: {}", e);
+                e
+            })?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+/// Resultado de `reset_password_with_token`.
+#[derive(Debug, PartialEq)]
+pub enum PasswordReset {
+    Reset(Uuid),
+    InvalidToken,
+    Expired,
+    AlreadyUsed,
+}
+
+/// Resultado de `create_admin_password_reset`.
+#[derive(Debug)]
+pub enum AdminPasswordReset {
+    Created(Box<User>),
+    UserNotFound,
+    RateLimited,
+}
+
+#[async_trait]
+pub trait PasswordResetTokenExt {
+    async fn create_password_reset_token(
+        &self,
+        user_id: Uuid,
         token_hash: &str,
-    ) -> Result<(), Error>;
+        expires_at: DateTime<Utc>,
+    ) -> Result<PasswordResetToken, Error>;
+
+    /// Cambia la contraseña con un token de restablecimiento. El token se marca como usado
+    /// en la misma transacción, así que dos peticiones con el mismo token no pueden ganar ambas.
+    async fn reset_password_with_token(
+        &self,
+        token_hash: &str,
+        password_hash: &str,
+    ) -> Result<PasswordReset, Error>;
 
     async fn invalidate_user_tokens(
         &self,
         user_id: Uuid,
     ) -> Result<(), Error>;
+
+    /// Restablecimiento pedido por un administrador: anula los enlaces anteriores, crea
+    /// uno nuevo, desbloquea la cuenta y lo anota en `audit_log`, todo o nada. Con
+    /// `limit_per_hour > 0` el límite se comprueba en la misma transacción, con la fila
+    /// del usuario bloqueada, para que dos peticiones simultáneas no lo superen.
+    async fn create_admin_password_reset(
+        &self,
+        actor_id: Uuid,
+        user_id: Uuid,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+        limit_per_hour: i64,
+    ) -> Result<AdminPasswordReset, Error>;
 }
 
 #[async_trait]
@@ -3349,37 +6069,65 @@ impl PasswordResetTokenExt for DBClient {
         Ok(token)
     }
 
-    async fn get_password_reset_token(
+    async fn reset_password_with_token(
         &self,
         token_hash: &str,
-    ) -> Result<Option<PasswordResetToken>, Error> {
+        password_hash: &str,
+    ) -> Result<PasswordReset, Error> {
         let mut tx = self.pool.begin().await?;
-        let token = sqlx::query_as::<_, PasswordResetToken>(
+
+        // Reclamar el token y cambiar la contraseña van juntos: si otra petición lo
+        // reclamó antes, este UPDATE espera a su commit y ya no encuentra la fila
+        let claimed = sqlx::query_scalar::<_, Uuid>(
             r#"
-            SELECT id, user_id, token_hash, version, expires_at, used, created_at
-            FROM password_reset_tokens
-            WHERE token_hash = $1 AND used = false AND expires_at > $2
-            "#,
+            UPDATE password_reset_tokens SET used = true
+            WHERE token_hash = $1 AND used = false AND expires_at > NOW()
+            RETURNING user_id
+            "#
         )
         .bind(token_hash)
-        .bind(Utc::now())
         .fetch_optional(&mut *tx)
         .await.map_err(|e| {
             log::error!("ERROR: {}", e);
             e
         })?;
+
+        let Some(user_id) = claimed else {
+            let state = sqlx::query_as::<_, (bool, DateTime<Utc>)>(
+                "SELECT used, expires_at FROM password_reset_tokens WHERE token_hash = $1"
+            )
+            .bind(token_hash)
+            .fetch_optional(&mut *tx)
+            .await?;
+            return Ok(match state {
+                Some((true, _)) => PasswordReset::AlreadyUsed,
+                Some((false, _)) => PasswordReset::Expired,
+                None => PasswordReset::InvalidToken,
+            });
+        };
+
+        sqlx::query("UPDATE users SET password = $1, updated_at = NOW() WHERE id = $2")
+            .bind(password_hash)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await.map_err(|e| {
+                log::error!("ERROR: {}", e);
+                e
+            })?;
+        revoke_all_refresh_tokens(&mut tx, user_id).await?;
+
         tx.commit().await?;
-        Ok(token)
+        Ok(PasswordReset::Reset(user_id))
     }
 
-    async fn mark_token_used(
+    async fn invalidate_user_tokens(
         &self,
-        token_hash: &str,
+        user_id: Uuid,
     ) -> Result<(), Error> {
         let mut tx = self.pool.begin().await?;
         sqlx::query!(
-            "UPDATE password_reset_tokens SET used = true WHERE token_hash = $1",
-            token_hash
+            "UPDATE password_reset_tokens SET used = true WHERE user_id = $1",
+            user_id
         )
         .execute(&mut *tx)
         .await.map_err(|e| {
@@ -3390,22 +6138,69 @@ impl PasswordResetTokenExt for DBClient {
         Ok(())
     }
 
-    async fn invalidate_user_tokens(
+    async fn create_admin_password_reset(
         &self,
+        actor_id: Uuid,
         user_id: Uuid,
-    ) -> Result<(), Error> {
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+        limit_per_hour: i64,
+    ) -> Result<AdminPasswordReset, Error> {
         let mut tx = self.pool.begin().await?;
-        sqlx::query!(
-            "UPDATE password_reset_tokens SET used = true WHERE user_id = $1",
-            user_id
+
+        let Some(user) = sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = $1 FOR UPDATE")
+            .bind(user_id)
+            .fetch_optional(&mut *tx)
+            .await?
+        else {
+            return Ok(AdminPasswordReset::UserNotFound);
+        };
+
+        // Con la fila bloqueada, los restablecimientos del mismo usuario se cuentan de uno en uno
+        if limit_per_hour > 0 {
+            let recent = count_recent_audit_entries(&mut *tx, AUDIT_ADMIN_PASSWORD_RESET, user_id, Utc::now() - chrono::Duration::hours(1)).await?;
+            if recent >= limit_per_hour {
+                return Ok(AdminPasswordReset::RateLimited);
+            }
+        }
+
+        sqlx::query("UPDATE password_reset_tokens SET used = true WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        // El administrador restablece porque la cuenta pudo estar comprometida
+        revoke_all_refresh_tokens(&mut tx, user_id).await?;
+        sqlx::query(
+            r#"
+            INSERT INTO password_reset_tokens (id, user_id, token_hash, version, expires_at, used, created_at)
+            SELECT $1, $2, $3, COALESCE(MAX(version), 0) + 1, $4, false, NOW()
+            FROM password_reset_tokens WHERE user_id = $2
+            "#
         )
+        .bind(Uuid::new_v4())
+        .bind(user_id)
+        .bind(token_hash)
+        .bind(expires_at)
         .execute(&mut *tx)
-        .await.map_err(|e| {
-            log::error!("ERROR: {}", e);
-            e
-        })?;
+        .await?;
+
+        if user.locked {
+            sqlx::query("UPDATE users SET locked = false, updated_at = NOW() WHERE id = $1")
+                .bind(user_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        record_audit(
+            &mut *tx,
+            actor_id,
+            AUDIT_ADMIN_PASSWORD_RESET,
+            Some(user_id),
+            serde_json::json!({ "unlocked": user.locked }),
+        ).await?;
+
         tx.commit().await?;
-        Ok(())
+        Ok(AdminPasswordReset::Created(Box::new(User { locked: false, ..user })))
     }
 }
 
@@ -3431,6 +6226,39 @@ impl NotificationExt for DBClient {
         Ok(notifications)
     }
 
+    async fn count_unread_notifications(&self, user_id: Uuid) -> Result<i64, Error> {
+        let count = sqlx::query_scalar!(
+            "SELECT COUNT(*) FROM notification WHERE user_id = $1 AND read = false",
+            user_id
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+        Ok(count.unwrap_or(0))
+    }
+
+    async fn notify_admins(&self, title: &str, message: &str) -> Result<u64, Error> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO notification (user_id, title, message, sent_via)
+            SELECT id, $1, $2, 'in_app'
+            FROM users
+            WHERE role = 'admin' AND locked = false
+            "#,
+        )
+        .bind(title)
+        .bind(message)
+        .execute(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+        Ok(result.rows_affected())
+    }
+
     async fn mark_notification_read(&self, notification_id: Uuid) -> Result<(), Error> {
         let mut tx = self.pool.begin().await?;
         sqlx::query!(
@@ -3472,4 +6300,1510 @@ impl NotificationExt for DBClient {
         tx.commit().await?;
         Ok(notification)
     }
-}
\ No newline at end of file
+}
+
+/// Seguimiento de aperturas/clics de correos de campaña.
+#[async_trait]
+pub trait EmailEventExt {
+    /// Registra el envío y devuelve su token, o `None` si el usuario desactivó el seguimiento.
+    async fn create_email_tracking(&self, user_id: Uuid, campaign: &str) -> Result<Option<Uuid>, Error>;
+
+    /// Registra una apertura o clic. Devuelve `false` si el token no corresponde a ningún envío.
+    async fn record_email_event(&self, token: Uuid, event_type: &str, url: Option<&str>) -> Result<bool, Error>;
+}
+
+#[async_trait]
+impl EmailEventExt for DBClient {
+    async fn create_email_tracking(&self, user_id: Uuid, campaign: &str) -> Result<Option<Uuid>, Error> {
+        let token = Uuid::new_v4();
+
+        let inserted = sqlx::query(
+            r#"
+            INSERT INTO email_events (token, user_id, campaign, event_type)
+            SELECT $1, $2, $3, 'sent'
+            WHERE NOT EXISTS (
+                SELECT 1 FROM user_settings WHERE user_id = $2 AND no_tracking = true
+            )
+            "#,
+        )
+        .bind(token)
+        .bind(user_id)
+        .bind(campaign)
+        .execute(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+
+        Ok((inserted.rows_affected() > 0).then_some(token))
+    }
+
+    async fn record_email_event(&self, token: Uuid, event_type: &str, url: Option<&str>) -> Result<bool, Error> {
+        let inserted = sqlx::query(
+            r#"
+            INSERT INTO email_events (token, user_id, campaign, event_type, url)
+            SELECT token, user_id, campaign, $2, $3
+            FROM email_events
+            WHERE token = $1 AND event_type = 'sent'
+            "#,
+        )
+        .bind(token)
+        .bind(event_type)
+        .bind(url)
+        .execute(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+
+        Ok(inserted.rows_affected() > 0)
+    }
+}
+
+/// Recordatorios por email para retomar cursos.
+#[async_trait]
+pub trait CourseReminderExt {
+    /// Usuarios con recordatorios activos, sin actividad desde `inactive_since`, sin aviso
+    /// desde `reminded_since` y con un curso incompleto; uno por usuario con su siguiente lección.
+    async fn get_pending_course_reminders(
+        &self,
+        inactive_since: DateTime<Utc>,
+        reminded_since: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<CourseReminderDto>, Error>;
+
+    async fn record_course_reminder(
+        &self,
+        user_id: Uuid,
+        course_id: Uuid,
+        lesson_id: Uuid,
+    ) -> Result<(), Error>;
+}
+
+#[async_trait]
+impl CourseReminderExt for DBClient {
+    async fn get_pending_course_reminders(
+        &self,
+        inactive_since: DateTime<Utc>,
+        reminded_since: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<CourseReminderDto>, Error> {
+        let reminders = sqlx::query_as::<_, CourseReminderDto>(
+            r#"
+            WITH last_activity AS (
+                SELECT user_id, MAX(last_accessed) AS last_at
+                FROM user_lesson_progress
+                GROUP BY user_id
+            ),
+            next_lessons AS (
+                -- Primera lección sin completar de cada curso comprado
+                SELECT DISTINCT ON (uc.user_id, uc.course_id)
+                    uc.user_id,
+                    uc.course_id,
+                    uc.purchased_at,
+                    c.title AS course_title,
+                    l.id AS lesson_id,
+                    l.title AS lesson_title
+                FROM user_courses uc
+                JOIN courses c ON c.id = uc.course_id
+                JOIN modules m ON m.course_id = c.id
+                JOIN lessons l ON l.module_id = m.id
+                LEFT JOIN user_lesson_progress ulp
+                    ON ulp.lesson_id = l.id AND ulp.user_id = uc.user_id
+                WHERE COALESCE(ulp.is_completed, false) = false
+                ORDER BY uc.user_id, uc.course_id, m."order", l."order"
+            )
+            SELECT DISTINCT ON (u.id)
+                u.id AS user_id,
+                u.email,
+                u.name,
+                n.course_id,
+                n.course_title,
+                n.lesson_id,
+                n.lesson_title
+            FROM users u
+            JOIN next_lessons n ON n.user_id = u.id
+            LEFT JOIN last_activity a ON a.user_id = u.id
+            LEFT JOIN user_settings us ON us.user_id = u.id
+            WHERE u.verified = true
+              AND u.locked = false
+              AND u.email_bounced_at IS NULL
+              AND COALESCE(us.course_reminders, true) = true
+              AND COALESCE(a.last_at, n.purchased_at) < $1
+              AND NOT EXISTS (
+                  SELECT 1 FROM course_reminders_sent r
+                  WHERE r.user_id = u.id AND r.sent_at > $2
+              )
+            ORDER BY u.id, n.purchased_at DESC
+            LIMIT $3
+            "#,
+        )
+        .bind(inactive_since)
+        .bind(reminded_since)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+        Ok(reminders)
+    }
+
+    async fn record_course_reminder(
+        &self,
+        user_id: Uuid,
+        course_id: Uuid,
+        lesson_id: Uuid,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO course_reminders_sent (user_id, course_id, lesson_id)
+            VALUES ($1, $2, $3)
+            "#,
+        )
+        .bind(user_id)
+        .bind(course_id)
+        .bind(lesson_id)
+        .execute(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+        Ok(())
+    }
+}
+
+/// Invitaciones de administradores para dar de alta usuarios con un rol asignado.
+#[async_trait]
+pub trait InviteExt {
+    /// Crea la invitación y revoca las pendientes para el mismo correo.
+    async fn create_invite(
+        &self,
+        email: &str,
+        role: UserRole,
+        token_hash: &str,
+        invited_by: Uuid,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Invite, Error>;
+
+    async fn get_pending_invites(&self, page: u32, limit: usize) -> Result<(Vec<Invite>, i64), Error>;
+
+    /// Invitación vigente (no usada, no revocada y sin caducar) para ese token.
+    async fn get_valid_invite(&self, token_hash: &str) -> Result<Option<Invite>, Error>;
+
+    /// Devuelve `false` si la invitación no existe o ya no está pendiente.
+    async fn revoke_invite(&self, invite_id: Uuid) -> Result<bool, Error>;
+
+    /// Consume la invitación y crea la cuenta verificada con el rol asignado en una
+    /// sola transacción. `None` si la invitación dejó de ser válida o el correo ya
+    /// tiene cuenta.
+    async fn accept_invite(
+        &self,
+        invite_id: Uuid,
+        name: &str,
+        password_hash: &str,
+    ) -> Result<Option<User>, Error>;
+}
+
+#[async_trait]
+impl InviteExt for DBClient {
+    async fn create_invite(
+        &self,
+        email: &str,
+        role: UserRole,
+        token_hash: &str,
+        invited_by: Uuid,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Invite, Error> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            r#"
+            UPDATE invites SET revoked_at = NOW()
+            WHERE email = $1 AND used_at IS NULL AND revoked_at IS NULL
+            "#
+        )
+        .bind(email)
+        .execute(&mut *tx)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+
+        let invite = sqlx::query_as::<_, Invite>(
+            r#"
+            INSERT INTO invites (email, role, token_hash, invited_by, expires_at)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id, email, role, invited_by, expires_at, used_at, revoked_at, created_at
+            "#
+        )
+        .bind(email)
+        .bind(role)
+        .bind(token_hash)
+        .bind(invited_by)
+        .bind(expires_at)
+        .fetch_one(&mut *tx)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+
+        tx.commit().await?;
+        Ok(invite)
+    }
+
+    async fn get_pending_invites(&self, page: u32, limit: usize) -> Result<(Vec<Invite>, i64), Error> {
+        let offset = ((page - 1) * limit as u32) as i64;
+
+        let invites = sqlx::query_as::<_, Invite>(
+            r#"
+            SELECT id, email, role, invited_by, expires_at, used_at, revoked_at, created_at
+            FROM invites
+            WHERE used_at IS NULL AND revoked_at IS NULL AND expires_at > NOW()
+            ORDER BY created_at DESC
+            LIMIT $1 OFFSET $2
+            "#
+        )
+        .bind(limit as i64)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+
+        let total = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM invites WHERE used_at IS NULL AND revoked_at IS NULL AND expires_at > NOW()"
+        )
+        .fetch_one(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+
+        Ok((invites, total))
+    }
+
+    async fn get_valid_invite(&self, token_hash: &str) -> Result<Option<Invite>, Error> {
+        sqlx::query_as::<_, Invite>(
+            r#"
+            SELECT id, email, role, invited_by, expires_at, used_at, revoked_at, created_at
+            FROM invites
+            WHERE token_hash = $1 AND used_at IS NULL AND revoked_at IS NULL AND expires_at > NOW()
+            "#
+        )
+        .bind(token_hash)
+        .fetch_optional(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })
+    }
+
+    async fn revoke_invite(&self, invite_id: Uuid) -> Result<bool, Error> {
+        let result = sqlx::query(
+            r#"
+            UPDATE invites SET revoked_at = NOW()
+            WHERE id = $1 AND used_at IS NULL AND revoked_at IS NULL
+            "#
+        )
+        .bind(invite_id)
+        .execute(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn accept_invite(
+        &self,
+        invite_id: Uuid,
+        name: &str,
+        password_hash: &str,
+    ) -> Result<Option<User>, Error> {
+        let mut tx = self.pool.begin().await?;
+
+        // Marcarla como usada primero evita que dos peticiones consuman la misma invitación
+        let invite = sqlx::query_as::<_, (String, UserRole)>(
+            r#"
+            UPDATE invites SET used_at = NOW()
+            WHERE id = $1 AND used_at IS NULL AND revoked_at IS NULL AND expires_at > NOW()
+            RETURNING email, role
+            "#
+        )
+        .bind(invite_id)
+        .fetch_optional(&mut *tx)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+
+        let Some((email, role)) = invite else {
+            return Ok(None);
+        };
+
+        let user = query_as!(
+            User,
+            r#"
+            INSERT INTO users (name, email, password, verified, role)
+            VALUES ($1, $2, $3, true, $4)
+            ON CONFLICT (email) DO NOTHING
+            RETURNING
+                id, 
+                name, 
+                email, 
+                phone,
+                location,
+                bio,
+                birth_date,
+                password, 
+                verified, 
+                created_at, 
+                updated_at, 
+                verification_token, 
+                token_expiry, 
+                role as "role: UserRole",
+                profile_image_url,
+                subscription_expires_at,
+                locked,
+                auth_provider,
+                last_login_at
+            "#,
+            name,
+            email,
+            password_hash,
+            role as _
+        )
+        .fetch_optional(&mut *tx)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+
+        // Si el correo ya tenía cuenta la invitación sigue pendiente
+        if user.is_none() {
+            tx.rollback().await?;
+            return Ok(None);
+        }
+
+        tx.commit().await?;
+        Ok(user)
+    }
+}
+
+/// Reportes de contenido enviados por los usuarios y su revisión por los administradores.
+#[async_trait]
+pub trait ReportExt {
+    /// Registra el reporte. `RowNotFound` si el curso no existe o la lección no le pertenece.
+    async fn create_report(
+        &self,
+        reporter_id: Uuid,
+        course_id: Uuid,
+        lesson_id: Option<Uuid>,
+        reason: &str,
+        details: Option<&str>,
+    ) -> Result<ContentReport, Error>;
+
+    async fn count_recent_reports(&self, reporter_id: Uuid, since: DateTime<Utc>) -> Result<i64, Error>;
+
+    async fn get_reports(
+        &self,
+        status: Option<ReportStatus>,
+        page: u32,
+        limit: usize,
+    ) -> Result<(Vec<ContentReport>, i64), Error>;
+
+    /// `None` si el reporte no existe. Al cerrarlo guarda quién y cuándo lo revisó.
+    async fn update_report_status(
+        &self,
+        report_id: Uuid,
+        status: ReportStatus,
+        admin_id: Uuid,
+    ) -> Result<Option<ContentReport>, Error>;
+}
+
+#[async_trait]
+impl ReportExt for DBClient {
+    async fn create_report(
+        &self,
+        reporter_id: Uuid,
+        course_id: Uuid,
+        lesson_id: Option<Uuid>,
+        reason: &str,
+        details: Option<&str>,
+    ) -> Result<ContentReport, Error> {
+        sqlx::query_as::<_, ContentReport>(
+            r#"
+            INSERT INTO content_reports (reporter_id, course_id, lesson_id, reason, details)
+            SELECT $1, c.id, $3, $4, $5
+            FROM courses c
+            WHERE c.id = $2
+              AND (
+                $3::uuid IS NULL
+                OR EXISTS (
+                    SELECT 1 FROM lessons l
+                    JOIN modules m ON m.id = l.module_id
+                    WHERE l.id = $3 AND m.course_id = c.id
+                )
+              )
+            RETURNING id, reporter_id, course_id, lesson_id, reason, details, status,
+                      resolved_by, resolved_at, created_at
+            "#
+        )
+        .bind(reporter_id)
+        .bind(course_id)
+        .bind(lesson_id)
+        .bind(reason)
+        .bind(details)
+        .fetch_optional(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?
+        .ok_or(Error::RowNotFound)
+    }
+
+    async fn count_recent_reports(&self, reporter_id: Uuid, since: DateTime<Utc>) -> Result<i64, Error> {
+        sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM content_reports WHERE reporter_id = $1 AND created_at >= $2"
+        )
+        .bind(reporter_id)
+        .bind(since)
+        .fetch_one(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })
+    }
+
+    async fn get_reports(
+        &self,
+        status: Option<ReportStatus>,
+        page: u32,
+        limit: usize,
+    ) -> Result<(Vec<ContentReport>, i64), Error> {
+        let offset = ((page - 1) * limit as u32) as i64;
+
+        let reports = sqlx::query_as::<_, ContentReport>(
+            r#"
+            SELECT id, reporter_id, course_id, lesson_id, reason, details, status,
+                   resolved_by, resolved_at, created_at
+            FROM content_reports
+            WHERE ($1::report_status IS NULL OR status = $1)
+            ORDER BY created_at DESC
+            LIMIT $2 OFFSET $3
+            "#
+        )
+        .bind(status)
+        .bind(limit as i64)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+
+        let total = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM content_reports WHERE ($1::report_status IS NULL OR status = $1)"
+        )
+        .bind(status)
+        .fetch_one(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+
+        Ok((reports, total))
+    }
+
+    async fn update_report_status(
+        &self,
+        report_id: Uuid,
+        status: ReportStatus,
+        admin_id: Uuid,
+    ) -> Result<Option<ContentReport>, Error> {
+        sqlx::query_as::<_, ContentReport>(
+            r#"
+            UPDATE content_reports
+            SET status = $2,
+                resolved_by = CASE WHEN $2 = 'open'::report_status THEN NULL ELSE $3 END,
+                resolved_at = CASE WHEN $2 = 'open'::report_status THEN NULL ELSE NOW() END
+            WHERE id = $1
+            RETURNING id, reporter_id, course_id, lesson_id, reason, details, status,
+                      resolved_by, resolved_at, created_at
+            "#
+        )
+        .bind(report_id)
+        .bind(status)
+        .bind(admin_id)
+        .fetch_optional(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })
+    }
+}
+
+#[async_trait]
+pub trait TagExt {
+    /// Etiquetas en uso con cuántos cursos tiene cada una.
+    async fn get_tags(&self) -> Result<Vec<TagDto>, Error>;
+
+    /// Reemplaza las etiquetas del curso (ya normalizadas) y devuelve las vigentes.
+    /// `None` si el curso no existe.
+    async fn set_course_tags(&self, course_id: Uuid, tags: &[String]) -> Result<Option<Vec<String>>, Error>;
+}
+
+#[async_trait]
+impl TagExt for DBClient {
+    async fn get_tags(&self) -> Result<Vec<TagDto>, Error> {
+        sqlx::query_as::<_, TagDto>(
+            r#"
+            SELECT t.name, COUNT(*) AS course_count
+            FROM tags t
+            JOIN course_tags ct ON ct.tag_id = t.id
+            GROUP BY t.name
+            ORDER BY course_count DESC, t.name
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })
+    }
+
+    async fn set_course_tags(&self, course_id: Uuid, tags: &[String]) -> Result<Option<Vec<String>>, Error> {
+        let mut tx = self.pool.begin().await?;
+
+        // Tocar `updated_at` hace que `/courses/changes` reenvíe el curso con sus etiquetas
+        let exists = sqlx::query_scalar::<_, Uuid>(
+            "UPDATE courses SET updated_at = NOW() WHERE id = $1 RETURNING id"
+        )
+        .bind(course_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+        if exists.is_none() {
+            return Ok(None);
+        }
+
+        sqlx::query("INSERT INTO tags (name) SELECT unnest($1::text[]) ON CONFLICT (name) DO NOTHING")
+            .bind(tags)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query("DELETE FROM course_tags WHERE course_id = $1")
+            .bind(course_id)
+            .execute(&mut *tx)
+            .await?;
+
+        let tags = sqlx::query_scalar::<_, String>(
+            r#"
+            WITH inserted AS (
+                INSERT INTO course_tags (course_id, tag_id)
+                SELECT $1, id FROM tags WHERE name = ANY($2)
+                RETURNING tag_id
+            )
+            SELECT t.name FROM inserted i JOIN tags t ON t.id = i.tag_id ORDER BY t.name
+            "#
+        )
+        .bind(course_id)
+        .bind(tags)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(Some(tags))
+    }
+}
+
+/// Usuarios activos del segmento del aviso `$1` (alias `u`).
+const BROADCAST_AUDIENCE: &str = r#"
+    FROM users u
+    JOIN notification_broadcasts b ON b.id = $1
+    WHERE u.locked = false
+      AND (
+        b.segment = 'all'
+        OR (b.segment = 'course_students' AND EXISTS (
+            SELECT 1 FROM user_courses uc WHERE uc.user_id = u.id AND uc.course_id = b.course_id
+        ))
+        OR (b.segment = 'premium' AND EXISTS (
+            SELECT 1 FROM subscription s
+            WHERE s.user_id = u.id AND subscription_grants_access(s.status, s.end_time, s.grace_until)
+        ))
+      )
+"#;
+
+/// Quienes además reciben el correo: verificados, sin rebotes y sin haber desactivado
+/// las notificaciones por correo.
+const BROADCAST_EMAIL_FILTER: &str = r#"
+      AND u.verified = true
+      AND u.email_bounced_at IS NULL
+      AND NOT EXISTS (
+        SELECT 1 FROM user_settings us WHERE us.user_id = u.id AND us.email_notifications = false
+      )
+"#;
+
+const BROADCAST_COLUMNS: &str = r#"id, created_by, segment, course_id, title, message, send_email, status,
+    recipients, emails_total, emails_sent, emails_failed, created_at, completed_at"#;
+
+#[async_trait]
+pub trait BroadcastExt {
+    /// Registra el aviso y crea las notificaciones in-app del segmento en una sola
+    /// transacción. Si hay correos por enviar queda `running`; si no, `completed`.
+    /// `RowNotFound` si el segmento apunta a un curso que no existe.
+    async fn create_broadcast(
+        &self,
+        created_by: Uuid,
+        segment: BroadcastSegment,
+        course_id: Option<Uuid>,
+        title: &str,
+        message: &str,
+        send_email: bool,
+    ) -> Result<NotificationBroadcast, Error>;
+
+    async fn get_broadcast(&self, broadcast_id: Uuid) -> Result<Option<NotificationBroadcast>, Error>;
+
+    async fn get_broadcast_email_recipients(&self, broadcast_id: Uuid) -> Result<Vec<BroadcastRecipientDto>, Error>;
+
+    /// Suma correos enviados y fallidos al progreso del aviso.
+    async fn record_broadcast_progress(&self, broadcast_id: Uuid, sent: i32, failed: i32) -> Result<(), Error>;
+
+    async fn finish_broadcast(&self, broadcast_id: Uuid, status: BroadcastStatus) -> Result<(), Error>;
+}
+
+#[async_trait]
+impl BroadcastExt for DBClient {
+    async fn create_broadcast(
+        &self,
+        created_by: Uuid,
+        segment: BroadcastSegment,
+        course_id: Option<Uuid>,
+        title: &str,
+        message: &str,
+        send_email: bool,
+    ) -> Result<NotificationBroadcast, Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let broadcast_id = sqlx::query_scalar::<_, Uuid>(
+            r#"
+            INSERT INTO notification_broadcasts (created_by, segment, course_id, title, message, send_email)
+            SELECT $1, $2, $3, $4, $5, $6
+            WHERE $3::uuid IS NULL OR EXISTS (SELECT 1 FROM courses WHERE id = $3)
+            RETURNING id
+            "#
+        )
+        .bind(created_by)
+        .bind(segment)
+        .bind(course_id)
+        .bind(title)
+        .bind(message)
+        .bind(send_email)
+        .fetch_optional(&mut *tx)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?
+        .ok_or(Error::RowNotFound)?;
+
+        // Un único `INSERT ... SELECT`: el tamaño del segmento no multiplica las consultas
+        let recipients = sqlx::query(&format!(
+            "INSERT INTO notification (user_id, title, message, sent_via) SELECT u.id, b.title, b.message, 'in_app' {}",
+            BROADCAST_AUDIENCE
+        ))
+        .bind(broadcast_id)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+        let emails_total = if send_email {
+            sqlx::query_scalar::<_, i64>(&format!(
+                "SELECT COUNT(*) {} {}",
+                BROADCAST_AUDIENCE, BROADCAST_EMAIL_FILTER
+            ))
+            .bind(broadcast_id)
+            .fetch_one(&mut *tx)
+            .await?
+        } else {
+            0
+        };
+
+        let broadcast = sqlx::query_as::<_, NotificationBroadcast>(&format!(
+            r#"
+            UPDATE notification_broadcasts
+            SET recipients = $2,
+                emails_total = $3,
+                status = CASE WHEN $3 > 0 THEN 'running' ELSE 'completed' END::broadcast_status,
+                completed_at = CASE WHEN $3 > 0 THEN NULL ELSE NOW() END
+            WHERE id = $1
+            RETURNING {}
+            "#,
+            BROADCAST_COLUMNS
+        ))
+        .bind(broadcast_id)
+        .bind(recipients as i32)
+        .bind(emails_total as i32)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(broadcast)
+    }
+
+    async fn get_broadcast(&self, broadcast_id: Uuid) -> Result<Option<NotificationBroadcast>, Error> {
+        sqlx::query_as::<_, NotificationBroadcast>(&format!(
+            "SELECT {} FROM notification_broadcasts WHERE id = $1",
+            BROADCAST_COLUMNS
+        ))
+        .bind(broadcast_id)
+        .fetch_optional(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })
+    }
+
+    async fn get_broadcast_email_recipients(&self, broadcast_id: Uuid) -> Result<Vec<BroadcastRecipientDto>, Error> {
+        sqlx::query_as::<_, BroadcastRecipientDto>(&format!(
+            "SELECT u.id AS user_id, u.email, u.name {} {} ORDER BY u.created_at",
+            BROADCAST_AUDIENCE, BROADCAST_EMAIL_FILTER
+        ))
+        .bind(broadcast_id)
+        .fetch_all(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })
+    }
+
+    async fn record_broadcast_progress(&self, broadcast_id: Uuid, sent: i32, failed: i32) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            UPDATE notification_broadcasts
+            SET emails_sent = emails_sent + $2, emails_failed = emails_failed + $3
+            WHERE id = $1
+            "#
+        )
+        .bind(broadcast_id)
+        .bind(sent)
+        .bind(failed)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn finish_broadcast(&self, broadcast_id: Uuid, status: BroadcastStatus) -> Result<(), Error> {
+        sqlx::query("UPDATE notification_broadcasts SET status = $2, completed_at = NOW() WHERE id = $1")
+            .bind(broadcast_id)
+            .bind(status)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Resultado de `restore_course_revision`.
+#[derive(Debug)]
+pub enum RevisionRestore {
+    Restored(Box<CourseWithModulesDto>),
+    RevisionNotFound,
+    // Otro administrador editó el curso mientras se restauraba
+    VersionConflict,
+}
+
+#[async_trait]
+pub trait CourseRevisionExt {
+    /// Revisiones del curso, de la más reciente a la más antigua.
+    async fn get_course_revisions(&self, course_id: Uuid) -> Result<Vec<CourseRevision>, Error>;
+
+    /// Vuelve el curso al contenido de la revisión `revision` usando `update_course`, por lo
+    /// que el contenido reemplazado queda a su vez como revisión nueva. `RowNotFound` si el
+    /// curso no existe.
+    async fn restore_course_revision(
+        &self,
+        course_id: Uuid,
+        revision: i32,
+        edited_by: Uuid,
+        keep_revisions: i64,
+    ) -> Result<RevisionRestore, Error>;
+}
+
+#[async_trait]
+impl CourseRevisionExt for DBClient {
+    async fn get_course_revisions(&self, course_id: Uuid) -> Result<Vec<CourseRevision>, Error> {
+        sqlx::query_as::<_, CourseRevision>(
+            "SELECT * FROM course_revisions WHERE course_id = $1 ORDER BY revision DESC"
+        )
+        .bind(course_id)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn restore_course_revision(
+        &self,
+        course_id: Uuid,
+        revision: i32,
+        edited_by: Uuid,
+        keep_revisions: i64,
+    ) -> Result<RevisionRestore, Error> {
+        let current = sqlx::query_as::<_, (i32, Option<serde_json::Value>)>(
+            r#"
+            SELECT c.version, r.snapshot
+            FROM courses c
+            LEFT JOIN course_revisions r ON r.course_id = c.id AND r.revision = $2
+            WHERE c.id = $1
+            "#
+        )
+        .bind(course_id)
+        .bind(revision)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(Error::RowNotFound)?;
+
+        let (version, Some(mut snapshot)) = current else {
+            return Ok(RevisionRestore::RevisionNotFound);
+        };
+        // Si otro administrador edita entre medio, `update_course` detecta el conflicto de versión
+        snapshot["version"] = serde_json::json!(version);
+        let dto: UpdateCourseDTO = serde_json::from_value(snapshot)
+            .map_err(|e| Error::Decode(Box::new(e)))?;
+
+        Ok(match self.update_course(course_id, dto, edited_by, keep_revisions).await? {
+            Some(course) => RevisionRestore::Restored(Box::new(course)),
+            None => RevisionRestore::VersionConflict,
+        })
+    }
+}
+
+#[async_trait]
+pub trait FeatureFlagExt {
+    async fn get_feature_flags(&self) -> Result<Vec<FeatureFlag>, Error>;
+
+    async fn set_feature_flag(&self, key: &str, enabled: bool, updated_by: Uuid) -> Result<FeatureFlag, Error>;
+}
+
+#[async_trait]
+impl FeatureFlagExt for DBClient {
+    async fn get_feature_flags(&self) -> Result<Vec<FeatureFlag>, Error> {
+        sqlx::query_as::<_, FeatureFlag>("SELECT * FROM feature_flags ORDER BY key")
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn set_feature_flag(&self, key: &str, enabled: bool, updated_by: Uuid) -> Result<FeatureFlag, Error> {
+        sqlx::query_as::<_, FeatureFlag>(
+            r#"
+            INSERT INTO feature_flags (key, enabled, updated_by)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (key) DO UPDATE
+            SET enabled = EXCLUDED.enabled, updated_by = EXCLUDED.updated_by, updated_at = NOW()
+            RETURNING *
+            "#
+        )
+        .bind(key)
+        .bind(enabled)
+        .bind(updated_by)
+        .fetch_one(&self.pool)
+        .await
+    }
+}
+
+/// Acción de `audit_log` para los restablecimientos de contraseña hechos por un administrador.
+pub const AUDIT_ADMIN_PASSWORD_RESET: &str = "admin_password_reset";
+
+/// Acción de `audit_log` para los pagos registrados a mano por un administrador.
+pub const AUDIT_MANUAL_PAYMENT: &str = "manual_payment";
+
+/// Anota una acción administrativa dentro de la transacción que la ejecuta.
+pub async fn record_audit<'e, E>(
+    executor: E,
+    actor_id: Uuid,
+    action: &str,
+    target_user_id: Option<Uuid>,
+    details: serde_json::Value,
+) -> Result<(), Error>
+where
+    E: sqlx::Executor<'e, Database = sqlx::Postgres>,
+{
+    sqlx::query("INSERT INTO audit_log (actor_id, action, target_user_id, details) VALUES ($1, $2, $3, $4)")
+        .bind(actor_id)
+        .bind(action)
+        .bind(target_user_id)
+        .bind(details)
+        .execute(executor)
+        .await?;
+    Ok(())
+}
+
+/// Veces que se registró `action` sobre el usuario desde `since` (para limitar su frecuencia).
+pub async fn count_recent_audit_entries<'e, E>(
+    executor: E,
+    action: &str,
+    target_user_id: Uuid,
+    since: DateTime<Utc>,
+) -> Result<i64, Error>
+where
+    E: sqlx::Executor<'e, Database = sqlx::Postgres>,
+{
+    sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM audit_log WHERE action = $1 AND target_user_id = $2 AND created_at > $3"
+    )
+    .bind(action)
+    .bind(target_user_id)
+    .bind(since)
+    .fetch_one(executor)
+    .await
+}
+
+#[async_trait]
+pub trait AuditLogExt {
+    /// Historial de acciones sobre el usuario, de la más reciente a la más antigua.
+    async fn get_user_audit_log(&self, target_user_id: Uuid) -> Result<Vec<AuditLogEntry>, Error>;
+}
+
+#[async_trait]
+impl AuditLogExt for DBClient {
+    async fn get_user_audit_log(&self, target_user_id: Uuid) -> Result<Vec<AuditLogEntry>, Error> {
+        sqlx::query_as::<_, AuditLogEntry>(
+            "SELECT * FROM audit_log WHERE target_user_id = $1 ORDER BY created_at DESC"
+        )
+        .bind(target_user_id)
+        .fetch_all(&self.pool)
+        .await
+    }
+}
+
+/// Cifras agregadas de la plataforma para la portada de administración.
+#[async_trait]
+pub trait PlatformStatsExt {
+    /// Usuarios registrados y cuántos de ellos verificaron el correo (`(total, verified)`).
+    async fn count_users(&self) -> Result<(i64, i64), Error>;
+
+    /// Cursos por categoría, de la más poblada a la menos.
+    async fn count_courses_by_category(&self) -> Result<Vec<(String, i64)>, Error>;
+
+    async fn count_enrollments(&self) -> Result<i64, Error>;
+
+    /// Suscripciones en vigor con el mismo criterio que `check_user_has_active_subscription`.
+    async fn count_active_subscriptions(&self) -> Result<i64, Error>;
+
+    /// Ingresos de los pagos completados desde `since`, uno por moneda.
+    async fn get_revenue_since(&self, since: DateTime<Utc>) -> Result<Vec<Money>, Error>;
+
+    /// Reportes de contenido aún sin revisar.
+    async fn count_open_reports(&self) -> Result<i64, Error>;
+}
+
+#[async_trait]
+impl PlatformStatsExt for DBClient {
+    async fn count_users(&self) -> Result<(i64, i64), Error> {
+        sqlx::query_as::<_, (i64, i64)>(
+            "SELECT COUNT(*), COUNT(*) FILTER (WHERE verified) FROM users"
+        )
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn count_courses_by_category(&self) -> Result<Vec<(String, i64)>, Error> {
+        sqlx::query_as::<_, (String, i64)>(
+            r#"
+            SELECT COALESCE(category, 'básico'), COUNT(*)
+            FROM courses
+            GROUP BY 1
+            ORDER BY 2 DESC, 1
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn count_enrollments(&self) -> Result<i64, Error> {
+        sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM user_courses")
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    async fn count_active_subscriptions(&self) -> Result<i64, Error> {
+        sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM subscription WHERE subscription_grants_access(status, end_time, grace_until)"
+        )
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn get_revenue_since(&self, since: DateTime<Utc>) -> Result<Vec<Money>, Error> {
+        let rows = sqlx::query_as::<_, (String, i64)>(
+            r#"
+            SELECT currency, SUM(amount)::BIGINT
+            FROM payments
+            WHERE status = 'COMPLETED' AND created_at >= $1
+            GROUP BY currency
+            ORDER BY currency
+            "#
+        )
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(currency, amount_minor)| Money { amount_minor, currency })
+            .collect())
+    }
+
+    async fn count_open_reports(&self) -> Result<i64, Error> {
+        sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM content_reports WHERE status = $1"
+        )
+        .bind(ReportStatus::Open)
+        .fetch_one(&self.pool)
+        .await
+    }
+}
+
+/// Resultado de `reserve_user_upload`.
+#[derive(Debug, PartialEq)]
+pub enum UploadReservation {
+    Reserved,
+    // Con el archivo se superaría la cuota; `used_bytes` es el uso actual
+    QuotaExceeded { used_bytes: i64 },
+    // La clave pertenece a un archivo de otro usuario
+    KeyTaken,
+}
+
+/// Contabilidad de los archivos que suben los usuarios, para su cuota de almacenamiento.
+#[async_trait]
+pub trait UserUploadExt {
+    /// Anota `size_bytes` en la clave (reemplazando lo que ocupaba antes) si cabe en
+    /// `quota_bytes` (0 = sin límite). Las subidas del mismo usuario se serializan con el
+    /// bloqueo de su fila, así que dos a la vez no pueden pasarse juntas de la cuota.
+    async fn reserve_user_upload(
+        &self,
+        user_id: Uuid,
+        storage_key: &str,
+        size_bytes: i64,
+        quota_bytes: i64,
+    ) -> Result<UploadReservation, Error>;
+
+    /// Libera la clave. `false` si no es un archivo del usuario.
+    async fn release_user_upload(&self, user_id: Uuid, storage_key: &str) -> Result<bool, Error>;
+
+    /// Bytes ocupados y número de archivos del usuario.
+    async fn get_user_storage_usage(&self, user_id: Uuid) -> Result<(i64, i64), Error>;
+}
+
+#[async_trait]
+impl UserUploadExt for DBClient {
+    async fn reserve_user_upload(
+        &self,
+        user_id: Uuid,
+        storage_key: &str,
+        size_bytes: i64,
+        quota_bytes: i64,
+    ) -> Result<UploadReservation, Error> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("SELECT id FROM users WHERE id = $1 FOR UPDATE")
+            .bind(user_id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or(Error::RowNotFound)?;
+
+        let owner = sqlx::query_scalar::<_, Uuid>(
+            "SELECT user_id FROM user_uploads WHERE storage_key = $1"
+        )
+        .bind(storage_key)
+        .fetch_optional(&mut *tx)
+        .await?;
+        if owner.is_some_and(|owner| owner != user_id) {
+            return Ok(UploadReservation::KeyTaken);
+        }
+
+        // Al reemplazar un archivo su tamaño anterior deja de contar
+        let (used_bytes, replaced_bytes) = sqlx::query_as::<_, (i64, i64)>(
+            r#"
+            SELECT
+                COALESCE(SUM(size_bytes), 0)::BIGINT,
+                COALESCE(SUM(size_bytes) FILTER (WHERE storage_key = $2), 0)::BIGINT
+            FROM user_uploads
+            WHERE user_id = $1
+            "#
+        )
+        .bind(user_id)
+        .bind(storage_key)
+        .fetch_one(&mut *tx)
+        .await?;
+        if !fits_storage_quota(used_bytes - replaced_bytes, size_bytes, quota_bytes) {
+            return Ok(UploadReservation::QuotaExceeded { used_bytes });
+        }
+
+        sqlx::query(
+            r#"
+            INSERT INTO user_uploads (storage_key, user_id, size_bytes)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (storage_key) DO UPDATE SET size_bytes = EXCLUDED.size_bytes, updated_at = NOW()
+            "#
+        )
+        .bind(storage_key)
+        .bind(user_id)
+        .bind(size_bytes)
+        .execute(&mut *tx)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+
+        tx.commit().await?;
+        Ok(UploadReservation::Reserved)
+    }
+
+    async fn release_user_upload(&self, user_id: Uuid, storage_key: &str) -> Result<bool, Error> {
+        let deleted = sqlx::query("DELETE FROM user_uploads WHERE storage_key = $1 AND user_id = $2")
+            .bind(storage_key)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(deleted.rows_affected() > 0)
+    }
+
+    async fn get_user_storage_usage(&self, user_id: Uuid) -> Result<(i64, i64), Error> {
+        sqlx::query_as::<_, (i64, i64)>(
+            "SELECT COALESCE(SUM(size_bytes), 0)::BIGINT, COUNT(*) FROM user_uploads WHERE user_id = $1"
+        )
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await
+    }
+}
+
+/// `true` si `size_bytes` cabe junto a lo ya usado; una cuota de 0 no tiene límite.
+pub(crate) fn fits_storage_quota(used_bytes: i64, size_bytes: i64, quota_bytes: i64) -> bool {
+    quota_bytes <= 0 || used_bytes.saturating_add(size_bytes) <= quota_bytes
+}
+
+/// Resultado de `register_bundle_purchase`.
+#[derive(Debug, PartialEq)]
+pub enum BundlePurchase {
+    /// Cursos de la orden registrados con esta captura y los del paquete que no se
+    /// registran en ella (no se cobraron o ya los registró una captura anterior).
+    Registered { granted: Vec<Uuid>, skipped: Vec<Uuid> },
+    /// Ese curso llenó su cupo: no se registró nada del paquete.
+    CourseFull(Uuid),
+}
+
+/// Fila de los cursos de uno o varios paquetes.
+#[derive(Debug, sqlx::FromRow)]
+struct BundleCourseRow {
+    bundle_id: Uuid,
+    id: Uuid,
+    title: String,
+    slug: String,
+    image: Option<String>,
+    paypal_product_id: Option<String>,
+    price: f64,
+    owned: bool,
+}
+
+impl From<BundleCourseRow> for BundleCourseDto {
+    fn from(row: BundleCourseRow) -> Self {
+        BundleCourseDto {
+            id: row.id,
+            title: row.title,
+            slug: row.slug,
+            image: row.image,
+            price: row.price,
+            paypal_product_id: row.paypal_product_id,
+            owned: row.owned,
+        }
+    }
+}
+
+/// Cursos de los paquetes con su precio vigente; `owned` indica si `user_id` ya los tiene.
+async fn bundle_course_rows(
+    conn: &mut PgConnection,
+    bundle_ids: &[Uuid],
+    user_id: Uuid,
+) -> Result<Vec<BundleCourseRow>, Error> {
+    sqlx::query_as::<_, BundleCourseRow>(
+        r#"
+        SELECT
+            bc.bundle_id,
+            c.id,
+            c.title,
+            c.slug,
+            c.image,
+            c.paypal_product_id,
+            course_effective_price(c.price, c.sale_price, c.sale_starts_at, c.sale_ends_at) AS price,
+            EXISTS(
+                SELECT 1 FROM user_courses uc WHERE uc.course_id = c.id AND uc.user_id = $2
+            ) AS owned
+        FROM bundle_courses bc
+        JOIN courses c ON c.id = bc.course_id
+        WHERE bc.bundle_id = ANY($1)
+        ORDER BY c.id
+        "#
+    )
+    .bind(bundle_ids)
+    .bind(user_id)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| {
+        log::error!("ERROR: {}", e);
+        e
+    })
+}
+
+#[async_trait]
+pub trait BundleExt {
+    /// Crea el paquete con sus cursos. `RowNotFound` si alguno de los cursos no existe.
+    async fn create_bundle(&self, dto: &CreateBundleDTO) -> Result<Bundle, Error>;
+
+    /// Cambia solo los campos presentes en `dto`. `None` si el paquete no existe.
+    async fn update_bundle(&self, bundle_id: Uuid, dto: &UpdateBundleDTO) -> Result<Option<Bundle>, Error>;
+
+    /// `false` si el paquete no existe.
+    async fn delete_bundle(&self, bundle_id: Uuid) -> Result<bool, Error>;
+
+    /// Paquetes activos con sus cursos, para el catálogo, con precios en `currency`.
+    async fn get_bundles(&self, currency: &str) -> Result<Vec<BundleDto>, Error>;
+
+    /// Paquete activo con los cursos que ya tiene `user_id` marcados (no se cobran).
+    /// `None` si no existe o está desactivado.
+    async fn get_bundle_for_user(&self, bundle_id: Uuid, user_id: Uuid, currency: &str) -> Result<Option<BundleDto>, Error>;
+
+    /// Guarda los cursos que se cobran en la orden `order_id`, al crearla en PayPal.
+    async fn record_bundle_order(
+        &self,
+        order_id: &str,
+        bundle_id: Uuid,
+        user_id: Uuid,
+        course_ids: &[Uuid],
+    ) -> Result<(), Error>;
+
+    /// Inscribe al usuario en los cursos que se cobraron en la orden (los de
+    /// `record_bundle_order`), en una sola transacción, aunque haya comprado alguno por
+    /// separado después de crearla. El monto cobrado se reparte entre ellos en proporción
+    /// a su precio vigente y cada uno queda como un pago `{order_id}:{course_id}`. Volver a
+    /// llamarla con la misma orden no registra nada nuevo. `RowNotFound` si la orden no es
+    /// de ese paquete y usuario o el paquete ya no existe.
+    async fn register_bundle_purchase(
+        &self,
+        user_id: Uuid,
+        bundle_id: Uuid,
+        order_id: &str,
+        amount: &Money,
+        payment_method: String,
+        status: String,
+    ) -> Result<BundlePurchase, Error>;
+}
+
+#[async_trait]
+impl BundleExt for DBClient {
+    async fn create_bundle(&self, dto: &CreateBundleDTO) -> Result<Bundle, Error> {
+        let mut course_ids = dto.course_ids.clone();
+        course_ids.sort();
+        course_ids.dedup();
+
+        let mut tx = self.pool.begin().await?;
+        let bundle = sqlx::query_as::<_, Bundle>(
+            r#"
+            INSERT INTO bundles (title, description, discount_percent)
+            VALUES ($1, $2, $3)
+            RETURNING *
+            "#
+        )
+        .bind(dto.title.trim())
+        .bind(dto.description.trim())
+        .bind(dto.discount_percent)
+        .fetch_one(&mut *tx)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+
+        let inserted = sqlx::query(
+            "INSERT INTO bundle_courses (bundle_id, course_id) SELECT $1, id FROM courses WHERE id = ANY($2)"
+        )
+        .bind(bundle.id)
+        .bind(&course_ids)
+        .execute(&mut *tx)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?
+        .rows_affected();
+        if inserted != course_ids.len() as u64 {
+            return Err(Error::RowNotFound);
+        }
+
+        tx.commit().await?;
+        Ok(bundle)
+    }
+
+    async fn update_bundle(&self, bundle_id: Uuid, dto: &UpdateBundleDTO) -> Result<Option<Bundle>, Error> {
+        sqlx::query_as::<_, Bundle>(
+            r#"
+            UPDATE bundles
+            SET title = COALESCE($2, title),
+                description = COALESCE($3, description),
+                discount_percent = COALESCE($4, discount_percent),
+                active = COALESCE($5, active),
+                updated_at = NOW()
+            WHERE id = $1
+            RETURNING *
+            "#
+        )
+        .bind(bundle_id)
+        .bind(dto.title.as_deref().map(str::trim))
+        .bind(dto.description.as_deref().map(str::trim))
+        .bind(dto.discount_percent)
+        .bind(dto.active)
+        .fetch_optional(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })
+    }
+
+    async fn delete_bundle(&self, bundle_id: Uuid) -> Result<bool, Error> {
+        let result = sqlx::query("DELETE FROM bundles WHERE id = $1")
+            .bind(bundle_id)
+            .execute(&self.pool)
+            .await.map_err(|e| {
+                log::error!("ERROR: {}", e);
+                e
+            })?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_bundles(&self, currency: &str) -> Result<Vec<BundleDto>, Error> {
+        let bundles = sqlx::query_as::<_, Bundle>(
+            "SELECT * FROM bundles WHERE active = true ORDER BY created_at DESC"
+        )
+        .fetch_all(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+
+        let bundle_ids: Vec<Uuid> = bundles.iter().map(|b| b.id).collect();
+        let mut conn = self.pool.acquire().await?;
+        let mut rows = bundle_course_rows(&mut conn, &bundle_ids, Uuid::nil()).await?;
+
+        Ok(bundles.into_iter().map(|bundle| {
+            let (courses, rest): (Vec<_>, Vec<_>) = rows.drain(..).partition(|r| r.bundle_id == bundle.id);
+            rows = rest;
+            BundleDto::new(bundle, courses.into_iter().map(BundleCourseDto::from).collect(), currency)
+        }).collect())
+    }
+
+    async fn get_bundle_for_user(&self, bundle_id: Uuid, user_id: Uuid, currency: &str) -> Result<Option<BundleDto>, Error> {
+        let Some(bundle) = sqlx::query_as::<_, Bundle>(
+            "SELECT * FROM bundles WHERE id = $1 AND active = true"
+        )
+        .bind(bundle_id)
+        .fetch_optional(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?
+        else {
+            return Ok(None);
+        };
+
+        let mut conn = self.pool.acquire().await?;
+        let rows = bundle_course_rows(&mut conn, &[bundle_id], user_id).await?;
+        Ok(Some(BundleDto::new(bundle, rows.into_iter().map(BundleCourseDto::from).collect(), currency)))
+    }
+
+    async fn record_bundle_order(
+        &self,
+        order_id: &str,
+        bundle_id: Uuid,
+        user_id: Uuid,
+        course_ids: &[Uuid],
+    ) -> Result<(), Error> {
+        sqlx::query(
+            "INSERT INTO bundle_orders (order_id, bundle_id, user_id, course_ids) VALUES ($1, $2, $3, $4)"
+        )
+        .bind(order_id)
+        .bind(bundle_id)
+        .bind(user_id)
+        .bind(course_ids)
+        .execute(&self.pool)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+        Ok(())
+    }
+
+    async fn register_bundle_purchase(
+        &self,
+        user_id: Uuid,
+        bundle_id: Uuid,
+        order_id: &str,
+        amount: &Money,
+        payment_method: String,
+        status: String,
+    ) -> Result<BundlePurchase, Error> {
+        let mut tx = self.begin().await?;
+        let ordered = sqlx::query_scalar::<_, Vec<Uuid>>(
+            "SELECT course_ids FROM bundle_orders WHERE order_id = $1 AND bundle_id = $2 AND user_id = $3"
+        )
+        .bind(order_id)
+        .bind(bundle_id)
+        .bind(user_id)
+        .fetch_optional(&mut *tx)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?
+        .ok_or(Error::RowNotFound)?;
+
+        // Cursos de esta orden que ya registró una captura anterior
+        let registered = sqlx::query_scalar::<_, Uuid>(
+            "SELECT course_id FROM payments WHERE transaction_id = ANY($1)"
+        )
+        .bind(ordered.iter().map(|id| format!("{}:{}", order_id, id)).collect::<Vec<_>>())
+        .fetch_all(&mut *tx)
+        .await.map_err(|e| {
+            log::error!("ERROR: {}", e);
+            e
+        })?;
+
+        // Ordenados por id: las compras simultáneas bloquean los cursos en el mismo orden
+        let (to_grant, skipped): (Vec<_>, Vec<_>) = bundle_course_rows(&mut tx, &[bundle_id], user_id)
+            .await?
+            .into_iter()
+            .partition(|row| ordered.contains(&row.id) && !registered.contains(&row.id));
+
+        let weights: Vec<i64> = to_grant.iter().map(|row| to_minor_units(row.price, &amount.currency)).collect();
+        let shares = split_minor_units(amount.amount_minor, &weights);
+        for (row, share) in to_grant.iter().zip(shares) {
+            let share = Money { amount_minor: share, currency: amount.currency.clone() };
+            if !register_course_purchase_in(
+                &mut tx,
+                user_id,
+                row.id,
+                format!("{}:{}", order_id, row.id),
+                &share,
+                payment_method.clone(),
+                status.clone(),
+            ).await? {
+                return Ok(BundlePurchase::CourseFull(row.id));
+            }
+        }
+        tx.commit().await?;
+
+        if !to_grant.is_empty() {
+            self.award_purchase_achievements(user_id).await;
+        }
+        Ok(BundlePurchase::Registered {
+            granted: to_grant.into_iter().map(|row| row.id).collect(),
+            skipped: skipped.into_iter().map(|row| row.id).collect(),
+        })
+    }
+}
@@ -7,12 +7,21 @@ use actix_web::{
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
+use crate::config::dtos::{COURSE_CATEGORIES, MAX_COURSE_TAGS, MAX_TAG_LENGTH};
+
+/// Cuerpo JSON de todos los errores de la API.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ErrorResponse {
     pub status: String,
     pub message: String,
+    // Contexto legible por máquina (p. ej. el campo en conflicto)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
 }
 
+/// Contraparte de `ApiResponse` para los errores.
+pub type ApiError = ErrorResponse;
+
 impl fmt::Display for ErrorResponse {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", serde_json::to_string(&self).unwrap())
@@ -30,20 +39,88 @@ pub enum ErrorMessage {
     WrongCredentials,
     EmailExist,
     UserNoLongerExist,
+    UserLocked,
+    PasswordlessAccount,
+    // Inicio de sesión con proveedores externos
+    InvalidOAuthState,
+    OAuthProviderError,
+    OAuthEmailNotVerified,
+    OAuthAccountConflict,
+    UserNotFound,
+    EmailNotFound,
+    LastActiveAdmin,
+    OldPasswordIncorrect,
+    RoleRequired,
+    InvalidVerificationToken,
+    VerificationTokenExpired,
+    InvalidResetToken,
+    ResetTokenExpired,
+    ResetTokenAlreadyUsed,
+    InvalidInvite,
+    InviteNotFound,
+    SearchTermTooShort,
+    ReportNotFound,
+    BroadcastNotFound,
+    BroadcastCourseRequired,
+    InvalidCourseCategory(String),
+    InvalidCourseTags,
+    ProgressImportSize(u64),
+    TooManyReports,
+    TooManyPasswordResets,
+    StorageQuotaExceeded,
+    InvalidAvatar,
+    InvalidTrialDays(i32),
     TokenNotProvided,
+    InvalidRefreshToken,
     PermissionDenied,
     UserNotAuthenticated,
+    RequestTimeout,
+    RouteNotFound,
+    FeatureDisabled,
+    FeatureFlagNotFound,
+    MethodNotAllowed,
+    PayPalUnavailable,
+    // Webhooks
+    MissingWebhookHeader(String),
+    InvalidPayPalWebhook,
+    InvalidBounceWebhook,
     // Errores de cursos
     CourseNotFound,
+    CourseRevisionNotFound,
     CourseAlreadyExists,
     InvalidCourseData,
+    CourseNotSyncedWithPayPal,
+    CourseVersionConflict,
+    LessonNotFound,
+    CourseHasNoLessons,
+    CourseFull,
+    InvalidSaleWindow,
+    SalePriceNotLower,
+    InvalidLocale(String),
+    CourseTranslationNotFound,
+    InvalidId,
+    AchievementNotFound,
+    // Errores de suscripciones
+    SubscriptionNotFound,
+    SubscriptionNotActive,
+    SubscriptionRejectedByPayPal,
+    SubscriptionPlanNotFound,
+    PlanNotLinkedToPayPal,
+    SubscriptionAlreadyOnPlan,
+    SubscriptionDowngradeNotAllowed,
     // Errores de pagos
     PaymentNotFound,
     PaymentFailed,
     PaymentAlreadyProcessed,
     InsufficientFunds,
     CourseAlreadyPurchased,
+    BundleNotFound,
+    BundleAlreadyOwned,
+    InvalidBundle,
     InvalidPaymentMethod,
+    PaymentNotCompleted,
+    InvalidPaymentAmount,
+    DuplicateTransaction,
 }
 
 impl ToString for ErrorMessage {
@@ -59,6 +136,40 @@ impl ErrorMessage {
             ErrorMessage::WrongCredentials => "Email or password is wrong".to_string(),
             ErrorMessage::EmailExist => "A user with this email already exists".to_string(),
             ErrorMessage::UserNoLongerExist => "User belonging to this token no longer exists".to_string(),
+            ErrorMessage::UserLocked => "This account has been locked by an administrator".to_string(),
+            ErrorMessage::PasswordlessAccount => "This account signs in with an external provider and has no password".to_string(),
+            // Inicio de sesión con proveedores externos
+            ErrorMessage::InvalidOAuthState => "Invalid or expired sign-in state, please try again".to_string(),
+            ErrorMessage::OAuthProviderError => "The sign-in provider could not be reached. Please try again later".to_string(),
+            ErrorMessage::OAuthEmailNotVerified => "The email of the external account is not verified".to_string(),
+            ErrorMessage::OAuthAccountConflict => "This email is already linked to a different external account".to_string(),
+            ErrorMessage::UserNotFound => "User not found".to_string(),
+            ErrorMessage::EmailNotFound => "No account is registered with this email".to_string(),
+            ErrorMessage::OldPasswordIncorrect => "Old password is incorrect".to_string(),
+            ErrorMessage::RoleRequired => "A role is required for this action".to_string(),
+            ErrorMessage::InvalidVerificationToken => "Verification token is invalid".to_string(),
+            ErrorMessage::VerificationTokenExpired => "Verification token has expired".to_string(),
+            ErrorMessage::InvalidResetToken => "Password reset token is invalid".to_string(),
+            ErrorMessage::ResetTokenExpired => "Password reset link has expired, please request a new one".to_string(),
+            ErrorMessage::ResetTokenAlreadyUsed => "Password reset link has already been used".to_string(),
+            ErrorMessage::InvalidInvite => "Invitation is invalid, expired or already used".to_string(),
+            ErrorMessage::InviteNotFound => "Invitation not found or no longer pending".to_string(),
+            ErrorMessage::SearchTermTooShort => "Search term must have at least 2 characters".to_string(),
+            ErrorMessage::ReportNotFound => "Report not found".to_string(),
+            ErrorMessage::BroadcastNotFound => "Broadcast not found".to_string(),
+            ErrorMessage::BroadcastCourseRequired => "The course_students segment requires a course_id".to_string(),
+            ErrorMessage::ProgressImportSize(max) =>
+                format!("Progress import must contain between 1 and {} users", max),
+            ErrorMessage::InvalidCourseCategory(category) =>
+                format!("Unknown course category '{}', expected one of: {}", category, COURSE_CATEGORIES.join(", ")),
+            ErrorMessage::InvalidCourseTags =>
+                format!("A course accepts up to {} tags of at most {} characters", MAX_COURSE_TAGS, MAX_TAG_LENGTH),
+            ErrorMessage::TooManyReports => "Too many reports sent, try again later".to_string(),
+            ErrorMessage::TooManyPasswordResets => "Too many password resets for this user, try again later".to_string(),
+            ErrorMessage::StorageQuotaExceeded => "This upload would exceed your storage quota".to_string(),
+            ErrorMessage::InvalidAvatar => "Avatar must be a non-empty PNG, JPEG or WebP image".to_string(),
+            ErrorMessage::InvalidTrialDays(max) => format!("Trial days must be between 0 and {}", max),
+            ErrorMessage::LastActiveAdmin => "The operation would leave the platform without an active administrator".to_string(),
             ErrorMessage::EmptyPassword => "Password cannot be empty".to_string(),
             ErrorMessage::HashingError => "Error while hashing password".to_string(),
             ErrorMessage::InvalidHashFormat => "Invalid password hash format".to_string(),
@@ -66,19 +177,56 @@ impl ErrorMessage {
                 format!("Password must not be more than {} characters", max_length),
             ErrorMessage::InvalidToken => "Authentication token is invalid or expired".to_string(),
             ErrorMessage::TokenNotProvided => "You are not logged in, please provide a token".to_string(),
+            ErrorMessage::InvalidRefreshToken => "Refresh token is invalid, expired or revoked".to_string(),
             ErrorMessage::PermissionDenied => "You are not allowed to perform this action".to_string(),
             ErrorMessage::UserNotAuthenticated => "Authentication required. Please log in.".to_string(),
+            ErrorMessage::RequestTimeout => "The request took too long to complete. Please try again later".to_string(),
+            ErrorMessage::RouteNotFound => "Not Found".to_string(),
+            ErrorMessage::FeatureDisabled => "This feature is currently disabled".to_string(),
+            ErrorMessage::FeatureFlagNotFound => "Feature flag not found".to_string(),
+            ErrorMessage::MethodNotAllowed => "Method Not Allowed".to_string(),
+            ErrorMessage::PayPalUnavailable => "The payment provider is temporarily unavailable. Please try again later".to_string(),
+            // Webhooks
+            ErrorMessage::MissingWebhookHeader(header) => format!("Missing {} header", header),
+            ErrorMessage::InvalidPayPalWebhook => "Invalid PayPal webhook signature".to_string(),
+            ErrorMessage::InvalidBounceWebhook => "Invalid bounce webhook signature".to_string(),
             // Errores de cursos
             ErrorMessage::CourseNotFound => "The requested course was not found".to_string(),
+            ErrorMessage::CourseRevisionNotFound => "Course revision not found".to_string(),
             ErrorMessage::CourseAlreadyExists => "A course with this name already exists".to_string(),
             ErrorMessage::InvalidCourseData => "Invalid course data provided".to_string(),
+            ErrorMessage::CourseNotSyncedWithPayPal => "This course is not available for purchase yet".to_string(),
+            ErrorMessage::CourseVersionConflict => "The course was modified by someone else, reload it and try again".to_string(),
+            ErrorMessage::LessonNotFound => "Lesson not found in this course".to_string(),
+            ErrorMessage::CourseHasNoLessons => "Course has no lessons to complete".to_string(),
+            ErrorMessage::CourseFull => "Course full".to_string(),
+            ErrorMessage::InvalidSaleWindow => "The sale must end after it starts and not be over already".to_string(),
+            ErrorMessage::SalePriceNotLower => "The sale price must be lower than the course price".to_string(),
+            ErrorMessage::InvalidLocale(locale) => format!("Invalid locale: {}", locale),
+            ErrorMessage::CourseTranslationNotFound => "Course translation not found".to_string(),
+            ErrorMessage::InvalidId => "Invalid id".to_string(),
+            ErrorMessage::AchievementNotFound => "The requested achievement was not found".to_string(),
+            // Errores de suscripciones
+            ErrorMessage::SubscriptionNotFound => "Subscription not found or does not belong to user".to_string(),
+            ErrorMessage::SubscriptionNotActive => "Subscription is not active".to_string(),
+            ErrorMessage::SubscriptionRejectedByPayPal => "PayPal rejected the subscription".to_string(),
+            ErrorMessage::SubscriptionPlanNotFound => "Plan not found".to_string(),
+            ErrorMessage::PlanNotLinkedToPayPal => "Plan is not linked to PayPal".to_string(),
+            ErrorMessage::SubscriptionAlreadyOnPlan => "Subscription is already on this plan".to_string(),
+            ErrorMessage::SubscriptionDowngradeNotAllowed => "Downgrading to a cheaper plan is not allowed".to_string(),
             // Errores de pagos
             ErrorMessage::PaymentNotFound => "The requested payment was not found".to_string(),
             ErrorMessage::PaymentFailed => "Payment processing failed".to_string(),
             ErrorMessage::PaymentAlreadyProcessed => "This payment has already been processed".to_string(),
             ErrorMessage::InsufficientFunds => "Insufficient funds for this transaction".to_string(),
             ErrorMessage::CourseAlreadyPurchased => "You have already purchased this course".to_string(),
+            ErrorMessage::BundleNotFound => "Bundle not found".to_string(),
+            ErrorMessage::BundleAlreadyOwned => "You already own every course in this bundle".to_string(),
+            ErrorMessage::InvalidBundle => "A bundle needs at least two existing courses".to_string(),
             ErrorMessage::InvalidPaymentMethod => "Invalid payment method".to_string(),
+            ErrorMessage::PaymentNotCompleted => "The payment was not completed".to_string(),
+            ErrorMessage::InvalidPaymentAmount => "The amount has more decimals than the currency allows".to_string(),
+            ErrorMessage::DuplicateTransaction => "A payment with this transaction id already exists".to_string(),
         }
     }
 }
@@ -87,6 +235,7 @@ impl ErrorMessage {
 pub struct HttpError {
     pub message: String,
     pub status: StatusCode,
+    pub details: Option<serde_json::Value>,
 }
 
 impl HttpError {
@@ -95,6 +244,7 @@ impl HttpError {
         HttpError {
             message: message.into(),
             status,
+            details: None,
         }
     }
 
@@ -102,6 +252,7 @@ impl HttpError {
         HttpError {
             message: message.into(),
             status: StatusCode::INTERNAL_SERVER_ERROR,
+            details: None,
         }
     }
 
@@ -109,6 +260,7 @@ impl HttpError {
         HttpError {
             message: message.into(),
             status: StatusCode::BAD_REQUEST,
+            details: None,
         }
     }
 
@@ -117,13 +269,15 @@ impl HttpError {
         HttpError {
             message: message.into(),
             status: StatusCode::NOT_FOUND,
+            details: None,
         }
     }
 
     pub fn unique_constraint_violation(message: impl Into<String>) -> Self {
         HttpError { 
             message: message.into(), 
-            status: StatusCode::CONFLICT 
+            status: StatusCode::CONFLICT,
+            details: None,
         }
     }
 
@@ -131,6 +285,7 @@ impl HttpError {
         HttpError {
             message: message.into(),
             status: StatusCode::UNAUTHORIZED,
+            details: None,
         }
     }
 
@@ -139,13 +294,53 @@ impl HttpError {
         HttpError {
             message: message.into(),
             status: StatusCode::FORBIDDEN,
+            details: None,
+        }
+    }
+
+    pub fn bad_gateway(message: impl Into<String>) -> Self {
+        HttpError {
+            message: message.into(),
+            status: StatusCode::BAD_GATEWAY,
+            details: None,
+        }
+    }
+
+    pub fn too_many_requests(message: impl Into<String>) -> Self {
+        HttpError {
+            message: message.into(),
+            status: StatusCode::TOO_MANY_REQUESTS,
+            details: None,
+        }
+    }
+
+    pub fn payload_too_large(message: impl Into<String>) -> Self {
+        HttpError {
+            message: message.into(),
+            status: StatusCode::PAYLOAD_TOO_LARGE,
+            details: None,
+        }
+    }
+
+    pub fn gateway_timeout(message: impl Into<String>) -> Self {
+        HttpError {
+            message: message.into(),
+            status: StatusCode::GATEWAY_TIMEOUT,
+            details: None,
         }
     }
 
+    /// Adjunta contexto legible por máquina a la respuesta.
+    pub fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
     pub fn into_http_response(self) -> HttpResponse {
-        HttpResponse::build(self.status).json(ErrorResponse {
+        HttpResponse::build(self.status).json(ApiError {
             status: "fail".to_string(),
-            message: self.message.clone(),
+            message: self.message,
+            details: self.details,
         })
     }
 }
@@ -175,9 +370,14 @@ impl ResponseError for HttpError {
         let status = match self.status {
             StatusCode::BAD_REQUEST => StatusCode::BAD_REQUEST,
             StatusCode::CONFLICT => StatusCode::CONFLICT,
+            StatusCode::BAD_GATEWAY => StatusCode::BAD_GATEWAY,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
         };
 
-        HttpResponse::build(status).body(self.message.clone())
+        HttpResponse::build(status).json(ApiError {
+            status: "fail".to_string(),
+            message: self.message.clone(),
+            details: self.details.clone(),
+        })
     }
 }
@@ -1,8 +1,8 @@
 use actix_web::{web, HttpResponse, Result};
 use serde::{Deserialize};
 use uuid::Uuid;
-use crate::{AppState, db::db::{AchievementExt, UserAchievementExt, UserExt}, errors::error::HttpError};
-use std::sync::Arc;
+use validator::Validate;
+use crate::{AppState, config::dtos::{ApiResponse, UserAchievementsQueryDto}, db::db::{AchievementExt, UserAchievementExt, UserExt}, errors::error::{ErrorMessage, HttpError}, models::models::TriggerType, utils::path_id::{AchievementId, UserId}};
 
 // DTOs para logros
 #[derive(Deserialize)]
@@ -39,20 +39,23 @@ pub struct EarnAchievementRequest {
 
 // Crear un nuevo logro (solo admin)
 pub async fn create_achievement(
-    app_state: web::Data<Arc<AppState>>,
+    app_state: web::Data<AppState>,
     req: web::Json<CreateAchievementRequest>,
 ) -> Result<HttpResponse, HttpError> {
+    // Solo se aceptan disparadores con estadística registrada
+    let trigger_type: TriggerType = req.trigger_type.parse().map_err(HttpError::bad_request)?;
+
     let achievement = app_state.db_client
-        .create_achievement(&req.name, req.description.as_ref(), req.icon.as_ref(), &req.trigger_type, req.trigger_value, req.active.unwrap_or(true))
+        .create_achievement(&req.name, req.description.as_ref(), req.icon.as_ref(), trigger_type, req.trigger_value, req.active.unwrap_or(true))
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
-    Ok(HttpResponse::Created().json(achievement))
+    Ok(ApiResponse::created(achievement))
 }
 
 // Obtener todos los logros
 pub async fn get_achievements(
-    app_state: web::Data<Arc<AppState>>,
+    app_state: web::Data<AppState>,
     query: web::Query<std::collections::HashMap<String, String>>,
 ) -> Result<HttpResponse, HttpError> {
     let page = query.get("page").and_then(|p| p.parse().ok()).unwrap_or(1);
@@ -63,12 +66,12 @@ pub async fn get_achievements(
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
-    Ok(HttpResponse::Ok().json(achievements))
+    Ok(ApiResponse::ok(achievements))
 }
 
 // Asignar logro a usuario
 pub async fn assign_achievement_to_user(
-    app_state: web::Data<Arc<AppState>>,
+    app_state: web::Data<AppState>,
     req: web::Json<AssignAchievementRequest>,
 ) -> Result<HttpResponse, HttpError> {
     let user_achievement = app_state.db_client
@@ -76,12 +79,12 @@ pub async fn assign_achievement_to_user(
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
-    Ok(HttpResponse::Ok().json(user_achievement))
+    Ok(ApiResponse::ok(user_achievement))
 }
 
 // Marcar logro como ganado
 pub async fn earn_achievement(
-    app_state: web::Data<Arc<AppState>>,
+    app_state: web::Data<AppState>,
     req: web::Json<EarnAchievementRequest>,
 ) -> Result<HttpResponse, HttpError> {
     let user_achievement = app_state.db_client
@@ -89,98 +92,117 @@ pub async fn earn_achievement(
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
-    Ok(HttpResponse::Ok().json(user_achievement))
+    Ok(ApiResponse::ok(user_achievement))
 }
 
-// Obtener logros de un usuario
+// Obtener logros de un usuario (`?page=&limit=&earned=`)
 pub async fn get_user_achievements(
-    app_state: web::Data<Arc<AppState>>,
-    user_id: web::Path<Uuid>,
+    app_state: web::Data<AppState>,
+    UserId(user_id): UserId,
+    query: web::Query<UserAchievementsQueryDto>,
 ) -> Result<HttpResponse, HttpError> {
-    let achievements = app_state.db_client
-        .get_user_achievements(*user_id)
+    query.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let page = query.page.unwrap_or(1);
+    let limit = query.limit.unwrap_or(10);
+
+    let (achievements, total) = app_state.db_client
+        .get_user_achievements_paginated(user_id, query.earned, page, limit)
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
-    Ok(HttpResponse::Ok().json(achievements))
+    Ok(ApiResponse::paginated(achievements, page, limit, total))
 }
 
 // Obtener un logro específico
 pub async fn get_achievement(
-    app_state: web::Data<Arc<AppState>>,
-    achievement_id: web::Path<Uuid>,
+    app_state: web::Data<AppState>,
+    AchievementId(achievement_id): AchievementId,
 ) -> Result<HttpResponse, HttpError> {
     let achievement = app_state.db_client
-        .get_achievement(*achievement_id)
+        .get_achievement(achievement_id)
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
     match achievement {
-        Some(a) => Ok(HttpResponse::Ok().json(a)),
-        None => Err(HttpError::not_found("Logro no encontrado".to_string())),
+        Some(a) => Ok(ApiResponse::ok(a)),
+        None => Err(HttpError::not_found(ErrorMessage::AchievementNotFound.to_string())),
     }
 }
 
 // Actualizar un logro
 pub async fn update_achievement(
-    app_state: web::Data<Arc<AppState>>,
-    achievement_id: web::Path<Uuid>,
+    app_state: web::Data<AppState>,
+    AchievementId(achievement_id): AchievementId,
     req: web::Json<UpdateAchievementRequest>,
 ) -> Result<HttpResponse, HttpError> {
+    let trigger_type = req.trigger_type.as_deref()
+        .map(str::parse::<TriggerType>)
+        .transpose()
+        .map_err(HttpError::bad_request)?;
+
     let achievement = app_state.db_client
-        .update_achievement(*achievement_id, req.name.as_ref(), req.description.as_ref(), req.icon.as_ref(), req.trigger_type.as_ref().map(|s| s.as_str()), req.trigger_value, req.active)
+        .update_achievement(achievement_id, req.name.as_ref(), req.description.as_ref(), req.icon.as_ref(), trigger_type, req.trigger_value, req.active)
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
-    Ok(HttpResponse::Ok().json(achievement))
+    Ok(ApiResponse::ok(achievement))
 }
 
 // Eliminar un logro
 pub async fn delete_achievement(
-    app_state: web::Data<Arc<AppState>>,
-    achievement_id: web::Path<Uuid>,
+    app_state: web::Data<AppState>,
+    AchievementId(achievement_id): AchievementId,
 ) -> Result<HttpResponse, HttpError> {
     app_state.db_client
-        .delete_achievement(*achievement_id)
+        .delete_achievement(achievement_id)
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
     Ok(HttpResponse::NoContent().finish())
 }
 
-// Obtener logros de usuario con detalles completos
+// Obtener logros de usuario con detalles completos (`?page=&limit=&earned=`)
 pub async fn get_user_achievements_with_details(
-    app_state: web::Data<Arc<AppState>>,
-    user_id: web::Path<Uuid>,
+    app_state: web::Data<AppState>,
+    UserId(user_id): UserId,
+    query: web::Query<UserAchievementsQueryDto>,
 ) -> Result<HttpResponse, HttpError> {
-    let user_achievements = app_state.db_client
-        .get_user_achievements_with_details(*user_id)
+    query.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let page = query.page.unwrap_or(1);
+    let limit = query.limit.unwrap_or(10);
+
+    let (user_achievements, total) = app_state.db_client
+        .get_user_achievements_with_details(user_id, query.earned, page, limit)
         .await
         .map_err(|e| {
             log::error!("Error al obtener los logros del usuario: {}", e);
             HttpError::server_error(e.to_string())
         })?;
-    Ok(HttpResponse::Ok().json(user_achievements))
+
+    Ok(ApiResponse::paginated(user_achievements, page, limit, total))
 }
 
 // Verificar y otorgar logros automáticamente
 #[derive(Deserialize)]
 pub struct CheckAchievementsRequest {
-    pub action: String,
-    pub value: Option<i32>,
+    pub action: TriggerType,
 }
 
 pub async fn check_and_award_achievements(
-    app_state: web::Data<Arc<AppState>>,
-    user_id: web::Path<Uuid>,
+    app_state: web::Data<AppState>,
+    UserId(user_id): UserId,
     req: web::Json<CheckAchievementsRequest>,
 ) -> Result<HttpResponse, HttpError> {
     let awarded = app_state.db_client
-        .check_and_award_achievements(*user_id, &req.action, req.value)
+        .check_and_award_achievements(user_id, req.action)
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
-    Ok(HttpResponse::Ok().json(awarded))
+    Ok(ApiResponse::ok(awarded))
 }
 
 // Función de debug para verificar estado de logros
@@ -190,7 +212,7 @@ pub struct DebugAchievementsRequest {
 }
 
 pub async fn debug_user_achievements(
-    app_state: web::Data<Arc<AppState>>,
+    app_state: web::Data<AppState>,
     req: web::Json<DebugAchievementsRequest>,
 ) -> Result<HttpResponse, HttpError> {
     // Obtener estadísticas del usuario usando la nueva función
@@ -206,16 +228,23 @@ pub async fn debug_user_achievements(
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
     // Obtener logros del usuario
-    let user_achievements = app_state.db_client
-        .get_user_achievements_with_details(req.user_id)
+    let (user_achievements, _) = app_state.db_client
+        .get_user_achievements_with_details(req.user_id, None, 1, 100)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    // Ganados entre todos los logros, no solo los de la primera página
+    let earned_ids = app_state.db_client
+        .get_earned_achievement_ids(req.user_id)
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
     let debug_info = serde_json::json!({
-        "user_stats": user_stats,
-        "available_achievements": achievements,
-        "user_achievements": user_achievements
+        "userStats": user_stats,
+        "availableAchievements": achievements,
+        "userAchievements": user_achievements,
+        "earnedAchievementIds": earned_ids
     });
 
-    Ok(HttpResponse::Ok().json(debug_info))
+    Ok(ApiResponse::ok(debug_info))
 }
\ No newline at end of file
@@ -0,0 +1,174 @@
+use actix_web::{HttpResponse, http::StatusCode, web::{Data, Json, ReqData}};
+use serde_json::{Value, json};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    AppState,
+    config::dtos::{ApiResponse, BundleDto, CreateBundleDTO, UpdateBundleDTO},
+    db::db::{BundleExt, CoursePurchaseExt},
+    errors::error::{ErrorMessage, HttpError},
+    func::payments::{BUNDLE_CUSTOM_ID_PREFIX, create_paypal_order},
+    middleware::middleware::JWTAuthMiddleware,
+    config::config::api_path,
+    utils::{money::{minor_units_value, to_minor_units}, path_id::BundleId},
+};
+
+/// Paquetes activos para el catálogo, con el precio sin descontar cursos ya comprados.
+pub async fn get_bundles(app_state: Data<AppState>) -> Result<HttpResponse, HttpError> {
+    let bundles = app_state.db_client
+        .get_bundles(&app_state.env.paypal_currency).await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+    Ok(ApiResponse::ok(bundles))
+}
+
+pub async fn create_bundle(
+    app_state: Data<AppState>,
+    Json(body): Json<CreateBundleDTO>,
+) -> Result<HttpResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let bundle = app_state.db_client
+        .create_bundle(&body).await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => HttpError::bad_request(ErrorMessage::InvalidBundle.to_string()),
+            e => HttpError::server_error(e.to_string()),
+        })?;
+    Ok(ApiResponse::created(bundle))
+}
+
+pub async fn update_bundle(
+    BundleId(bundle_id): BundleId,
+    app_state: Data<AppState>,
+    Json(body): Json<UpdateBundleDTO>,
+) -> Result<HttpResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let bundle = app_state.db_client
+        .update_bundle(bundle_id, &body).await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+    let Some(bundle) = bundle else {
+        return Ok(HttpError::not_found(ErrorMessage::BundleNotFound.to_string()).into_http_response());
+    };
+    Ok(ApiResponse::ok(bundle))
+}
+
+pub async fn delete_bundle(
+    BundleId(bundle_id): BundleId,
+    app_state: Data<AppState>,
+) -> Result<HttpResponse, HttpError> {
+    let deleted = app_state.db_client
+        .delete_bundle(bundle_id).await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if !deleted {
+        return Ok(HttpError::not_found(ErrorMessage::BundleNotFound.to_string()).into_http_response());
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Cuerpo de la orden de PayPal de un paquete: un ítem por curso que aún no tiene el
+/// comprador y el descuento del paquete en `breakdown`. `None` si algún curso no está
+/// sincronizado con PayPal (la orden saldría con `sku: null`).
+pub(crate) fn bundle_order_body(bundle: &BundleDto, host: &str) -> Option<Value> {
+    let currency = bundle.currency.as_str();
+    let items = bundle.courses_to_buy()
+        .map(|course| Some(json!({
+            "name": course.title,
+            "description": format!("Paquete: {}", bundle.title),
+            "unit_amount": {
+                "currency_code": currency,
+                "value": minor_units_value(to_minor_units(course.price, currency), currency)
+            },
+            "quantity": "1",
+            "category": "DIGITAL_GOODS",
+            "sku": course.paypal_product_id.clone()?
+        })))
+        .collect::<Option<Vec<_>>>()?;
+
+    let item_total = to_minor_units(bundle.total_price, currency);
+    let price = to_minor_units(bundle.price, currency);
+
+    Some(json!({
+        "intent": "CAPTURE",
+        "payment_source": {
+            "paypal": {
+                "experience_context": {
+                    "payment_method_preference": "IMMEDIATE_PAYMENT_REQUIRED",
+                    "landing_page": "LOGIN",
+                    "user_action": "PAY_NOW",
+                    "return_url": format!("{}/paypal/capture?bundle_id={}", host, bundle.id),
+                    "cancel_url": format!("{}/paypal/cancel?bundle_id={}", host, bundle.id)
+                }
+            }
+        },
+        "purchase_units": [{
+            "invoice_id": Uuid::new_v4().to_string(),
+            "custom_id": format!("{}{}", BUNDLE_CUSTOM_ID_PREFIX, bundle.id),
+            "amount": {
+                "currency_code": currency,
+                "value": minor_units_value(price, currency),
+                "breakdown": {
+                    "item_total": { "currency_code": currency, "value": minor_units_value(item_total, currency) },
+                    "discount": { "currency_code": currency, "value": minor_units_value(item_total - price, currency) }
+                }
+            },
+            "items": items
+        }]
+    }))
+}
+
+/// Crea la orden de PayPal de un paquete. Los cursos que el usuario ya tiene no se cobran;
+/// si ya los tiene todos no hay nada que comprar. Los que se cobran quedan guardados con
+/// la orden y son los que se inscriben al capturarla.
+pub async fn created_bundle_order(
+    BundleId(bundle_id): BundleId,
+    app_state: Data<AppState>,
+    user: ReqData<JWTAuthMiddleware>,
+) -> Result<HttpResponse, HttpError> {
+    let user_id = user.user.id;
+    let bundle = app_state.db_client
+        .get_bundle_for_user(bundle_id, user_id, &app_state.env.paypal_currency).await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+    let Some(bundle) = bundle else {
+        return Ok(HttpError::not_found(ErrorMessage::BundleNotFound.to_string()).into_http_response());
+    };
+
+    if bundle.courses_to_buy().next().is_none() {
+        return Err(HttpError::unique_constraint_violation(ErrorMessage::BundleAlreadyOwned.to_string()));
+    }
+    for course in bundle.courses_to_buy() {
+        let has_seat = app_state.db_client
+            .course_has_seat(course.id, user_id).await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+        if !has_seat {
+            return Err(HttpError::new(ErrorMessage::CourseFull.to_string(), StatusCode::CONFLICT));
+        }
+    }
+
+    let Some(body) = bundle_order_body(&bundle, &app_state.env.host) else {
+        let unsynced: Vec<Uuid> = bundle.courses_to_buy()
+            .filter(|c| c.paypal_product_id.is_none())
+            .map(|c| c.id)
+            .collect();
+        log::error!(
+            "El paquete {} tiene cursos sin paypal_product_id {:?}; sincronízalos con POST {}",
+            bundle_id, unsynced, api_path("/courses/edit/{id}/sync-paypal")
+        );
+        return Ok(HttpError::new(ErrorMessage::CourseNotSyncedWithPayPal.to_string(), StatusCode::UNPROCESSABLE_ENTITY)
+            .into_http_response());
+    };
+
+    let order_id = match create_paypal_order(&app_state, &body).await {
+        Ok(order_id) => order_id,
+        Err(res) => return Ok(res),
+    };
+    let course_ids: Vec<Uuid> = bundle.courses_to_buy().map(|c| c.id).collect();
+    app_state.db_client
+        .record_bundle_order(&order_id, bundle_id, user_id, &course_ids).await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    Ok(ApiResponse::ok(json!({ "id": order_id })))
+}
@@ -1,32 +1,36 @@
-use std::sync::Arc;
-use actix_web::{  HttpResponse, web::{ self, Data, Json, Path, Query, ReqData } };
+use actix_web::{  HttpRequest, HttpResponse, http::{header, StatusCode}, web::{ self, Data, Json, Path, Query, ReqData } };
 use validator::Validate;
 use uuid::Uuid;
 use serde::Deserialize;
 use sqlx::Error as SqlxError;
 use serde_json::json;
+use actix_web::rt::time::{interval_at, Instant};
+use futures::{Stream, StreamExt, stream};
+use tokio::sync::broadcast::{self, error::RecvError};
 
 use crate::{
     AppState, 
-    config::dtos::{ CreateCourseDTO, CreatedCommentDto, CreatedRatingDto, ProductDTO, UpdateCourseDTO, UpdateLessonProgressDTO }, 
-    db::db::{CourseExt, CoursePurchaseExt, UserAchievementExt}, 
+    config::dtos::{ ApiResponse, CourseAccessDto, CourseAccessReason, CourseChangesResponseDto, CourseProgressEventDto, CourseWithModulesDto, CreateCourseDTO, CreatedCommentDto, CreatedRatingDto, FeaturedCoursesQueryDto, FilterCourseDto, LocalizedDto, ProductDTO, ProgressImportEntryDto, ProgressImportResponseDto, MAX_COURSE_TAGS, MAX_PROGRESS_IMPORT_USERS, MAX_TAG_LENGTH, RequestQueryDto, SetCourseFeaturedDTO, SetCourseMaxStudentsDTO, SetCourseSaleDTO, SetCourseTagsDTO, SetCourseTranslationDTO, SetLessonCompletedDTO, TagMatch, UpdateCourseDTO, UpdateLessonProgressDTO, UserCourseDto }, 
+    db::db::{CourseExt, CoursePurchaseExt, DBClient, CourseRevisionExt, RevisionRestore, TagExt, UserAchievementExt, UserExt}, 
     errors::error::{ ErrorMessage, HttpError }, 
     func::payments::{create_product }, 
     middleware::middleware::{ JWTAuthMiddleware },
+    models::models::TriggerType,
+    config::config::api_path,
+    services::{events::DomainEvent, feature_flags::{Feature, feature_gate}},
+    utils::{locale::{normalize_locale, preferred_locales}, path_id::{CourseId, LessonId, UserId}, slug::normalize_tags},
 };
 
 //===================COMMENTS===================//
 
 pub async fn create_lesson_comment(
-    path: Path<String>,
-    app_state: Data<Arc<AppState>>,
+    CourseId(course_id): CourseId,
+    app_state: Data<AppState>,
     auth: web::ReqData<JWTAuthMiddleware>,
     Json(body): Json<CreatedCommentDto>,
 ) -> Result<HttpResponse, HttpError> {
 
     // 1️⃣ Parsear el ID del curso
-    let course_id = Uuid::parse_str(&path.into_inner())
-        .map_err(|e| HttpError::bad_request(e.to_string()))?;
 
     // 2️⃣ Crear el comentario (esto debe COMMITTEAR internamente)
     let comment = app_state.db_client
@@ -37,7 +41,7 @@ pub async fn create_lesson_comment(
     // 3️⃣ Verificar y otorgar logros por comentarios
     // Usar EXACTAMENTE el trigger definido en la tabla achievement
     if let Err(err) = app_state.db_client
-        .check_and_award_achievements(auth.user.id, "comments_created", None)
+        .check_and_award_achievements(auth.user.id, TriggerType::CommentsCreated)
         .await
     {
         // No rompemos la request, pero sí registramos el error
@@ -45,17 +49,15 @@ pub async fn create_lesson_comment(
     }
 
     // 4️⃣ Responder
-    Ok(HttpResponse::Ok().json(comment))
+    Ok(ApiResponse::ok(comment))
 }
 
 pub async fn get_lesson_comments(
-    path: Path<String>,
-    app_state: Data<Arc<AppState>>
+    CourseId(course_id): CourseId,
+    app_state: Data<AppState>
 ) -> Result<HttpResponse, HttpError> {
-    let course_id = Uuid::parse_str(&path.into_inner())
-        .map_err(|e| HttpError::bad_request(e.to_string()))?;
 
-    Ok(HttpResponse::Ok().json(
+    Ok(ApiResponse::ok(
         app_state.db_client
         .get_lesson_comments(course_id).await
         .map_err(|e| HttpError::server_error(e.to_string()))?
@@ -64,43 +66,37 @@ pub async fn get_lesson_comments(
 
 pub async fn delete_comment(
     path: Path<(Uuid,Uuid)>,
-    app_state: Data<Arc<AppState>>,
+    app_state: Data<AppState>,
     _auth: web::ReqData<JWTAuthMiddleware>  // requiere autenticación
 ) -> Result<HttpResponse, HttpError> {
-    let (_, commentid) = path.into_inner();
-    let comment_id = Uuid::parse_str(&commentid.to_string())
-        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+    let (_, comment_id) = path.into_inner();
 
     app_state.db_client
         .delete_lesson_comment(comment_id).await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
-    Ok(HttpResponse::Ok().json(()))
+    Ok(ApiResponse::ok(()))
 }
 
 pub async fn create_or_update_rating(
-    path: Path<String>,
-    app_state: Data<Arc<AppState>>,
+    CourseId(course_id): CourseId,
+    app_state: Data<AppState>,
     _auth: web::ReqData<JWTAuthMiddleware>, // requiere autenticación
     Json(body): Json<CreatedRatingDto>
 ) -> Result<HttpResponse, HttpError> {
-    let course_id = Uuid::parse_str(&path.into_inner())
-        .map_err(|e| HttpError::bad_request(e.to_string()))?;
 
     app_state.db_client
         .create_or_update_rating(course_id, _auth.user.id, body.rating).await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
-    Ok(HttpResponse::Ok().json(()))
+    Ok(ApiResponse::ok(()))
 }
 
 pub async fn get_rating(
-    path: Path<String>,
-    app_state: Data<Arc<AppState>>,
+    CourseId(course_id): CourseId,
+    app_state: Data<AppState>,
     _auth: web::ReqData<JWTAuthMiddleware>
 ) -> Result<HttpResponse, HttpError> {
-    let course_id = Uuid::parse_str(&path.into_inner())
-        .map_err(|e| HttpError::bad_request(e.to_string()))?;
 
     let rating = app_state.db_client
         .get_rating(course_id, Some(_auth.user.id)).await
@@ -112,51 +108,421 @@ pub async fn get_rating(
             }
         )?;
 
-    Ok(HttpResponse::Ok().json(rating))
+    Ok(ApiResponse::ok(rating))
 }
 
 
+/// Idiomas pedidos en `Accept-Language` que hay que buscar entre las traducciones.
+fn request_locales(req: &HttpRequest, default_locale: &str) -> Vec<String> {
+    req.headers()
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| preferred_locales(v, default_locale))
+        .unwrap_or_default()
+}
+
+/// Aplica a cada curso su mejor traducción e indica el idioma servido en cada uno.
+async fn localize_courses(
+    db: &DBClient,
+    req: &HttpRequest,
+    default_locale: &str,
+    courses: Vec<UserCourseDto>,
+) -> Result<Vec<LocalizedDto<UserCourseDto>>, HttpError> {
+    let locales = request_locales(req, default_locale);
+    let ids: Vec<Uuid> = courses.iter().map(|c| c.id).collect();
+    let mut translations = db
+        .get_course_translations_for(&ids, &locales)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    Ok(courses
+        .into_iter()
+        .map(|mut course| {
+            let locale = match translations.iter().position(|t| t.course_id == course.id) {
+                Some(i) => {
+                    let translation = translations.swap_remove(i);
+                    let locale = translation.locale.clone();
+                    course.apply_translation(translation);
+                    locale
+                }
+                None => default_locale.to_string(),
+            };
+            LocalizedDto { item: course, locale }
+        })
+        .collect())
+}
+
+/// Las respuestas localizadas dependen de `Accept-Language`; con un único idioma servido
+/// se indica además en `Content-Language`.
+fn with_language_headers(mut res: HttpResponse, locale: Option<&str>) -> HttpResponse {
+    res.headers_mut().insert(header::VARY, header::HeaderValue::from_static("Accept-Language"));
+    if let Some(value) = locale.and_then(|l| header::HeaderValue::from_str(l).ok()) {
+        res.headers_mut().insert(header::CONTENT_LANGUAGE, value);
+    }
+    res
+}
+
 #[derive(Deserialize)]
 pub struct ListQuery {
     page: Option<u32>,
     limit: Option<usize>,
+    // Etiquetas separadas por comas: `?tag=rust,web&tag_match=all`
+    tag: Option<String>,
+    tag_match: Option<TagMatch>,
 }
 
 pub async fn get_courses(
+    req: HttpRequest,
     Query(q): Query<ListQuery>,
-    app_state: Data<Arc<AppState>>
+    app_state: Data<AppState>
 ) -> Result<HttpResponse, HttpError> {
     let page = q.page.unwrap_or(1);
     let limit = q.limit.unwrap_or(10);
 
+    let tags = normalize_tags(q.tag.as_deref().unwrap_or_default().split(','));
+    let match_all = q.tag_match.unwrap_or_default() == TagMatch::All;
+
     let courses = app_state.db_client
-        .get_courses(page, limit).await
+        .get_courses(page, limit, &tags, match_all).await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
+    let courses = UserCourseDto::with_default_rating(courses, app_state.env.default_course_rating);
+    let courses = localize_courses(&app_state.db_client, &req, &app_state.env.default_locale, courses).await?;
 
-    Ok(HttpResponse::Ok().json(courses))
+    Ok(with_language_headers(ApiResponse::ok(courses), None))
 }
 
-pub async fn get_course(
-    path: Path<String>,
-    app_state: Data<Arc<AppState>>
+#[derive(Deserialize)]
+pub struct ChangesQuery {
+    since: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Sincronización incremental: cursos cambiados y eliminados desde `since`.
+/// Sin `since` devuelve el catálogo completo.
+pub async fn get_course_changes(
+    Query(q): Query<ChangesQuery>,
+    app_state: Data<AppState>
+) -> Result<HttpResponse, HttpError> {
+    // El cursor se toma antes de consultar para no perder cambios concurrentes
+    let cursor = chrono::Utc::now();
+    let since = q.since.unwrap_or(chrono::DateTime::<chrono::Utc>::UNIX_EPOCH);
+
+    let (courses, deleted) = app_state.db_client
+        .get_courses_changed_since(since).await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+    let courses = UserCourseDto::with_default_rating(courses, app_state.env.default_course_rating);
+
+    Ok(ApiResponse::ok(CourseChangesResponseDto {
+        courses,
+        deleted,
+        cursor,
+    }))
+}
+
+/// Etiquetas en uso, para el filtro del catálogo.
+pub async fn get_tags(
+    app_state: Data<AppState>
+) -> Result<HttpResponse, HttpError> {
+    let tags = app_state.db_client
+        .get_tags().await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    Ok(ApiResponse::ok(tags))
+}
+
+/// Normaliza las etiquetas recibidas y comprueba los límites por curso.
+pub(crate) fn validate_course_tags(raw: &[String]) -> Result<Vec<String>, HttpError> {
+    let tags = normalize_tags(raw.iter().map(String::as_str));
+    if tags.len() > MAX_COURSE_TAGS || tags.iter().any(|t| t.chars().count() > MAX_TAG_LENGTH) {
+        return Err(HttpError::bad_request(ErrorMessage::InvalidCourseTags.to_string()));
+    }
+    Ok(tags)
+}
+
+/// Reemplaza las etiquetas del curso (solo administradores). Una lista vacía las quita todas.
+pub async fn set_course_tags(
+    CourseId(course_id): CourseId,
+    app_state: Data<AppState>,
+    Json(body): Json<SetCourseTagsDTO>,
+) -> Result<HttpResponse, HttpError> {
+    let tags = validate_course_tags(&body.tags)?;
+
+    let tags = app_state.db_client
+        .set_course_tags(course_id, &tags).await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let Some(tags) = tags else {
+        return Ok(HttpError::not_found(ErrorMessage::CourseNotFound.to_string()).into_http_response());
+    };
+
+    Ok(ApiResponse::ok(tags))
+}
+
+/// Cursos destacados para la portada, en el orden elegido por los administradores.
+pub async fn get_featured_courses(
+    req: HttpRequest,
+    Query(q): Query<FeaturedCoursesQueryDto>,
+    app_state: Data<AppState>,
 ) -> Result<HttpResponse, HttpError> {
-    let id_str = path.into_inner();
-    let course_id = Uuid::parse_str(&id_str).map_err(|e| HttpError::bad_request(e.to_string()))?;
+    q.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let courses = app_state.db_client
+        .get_featured_courses(q.limit.unwrap_or(6)).await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+    let courses = UserCourseDto::with_default_rating(courses, app_state.env.default_course_rating);
+
+    let courses = localize_courses(&app_state.db_client, &req, &app_state.env.default_locale, courses).await?;
+
+    let courses: Vec<LocalizedDto<FilterCourseDto>> = courses
+        .into_iter()
+        .map(|c| LocalizedDto { item: FilterCourseDto::filter_course(&c.item), locale: c.locale })
+        .collect();
+    Ok(with_language_headers(ApiResponse::ok(courses), None))
+}
+
+pub async fn set_course_featured(
+    CourseId(course_id): CourseId,
+    app_state: Data<AppState>,
+    Json(body): Json<SetCourseFeaturedDTO>,
+) -> Result<HttpResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
 
     let course = app_state.db_client
-        .get_course(course_id).await
+        .set_course_featured(course_id, body.featured, body.featured_order).await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
     match course {
-        Some(c) => Ok(HttpResponse::Ok().json(c)),
-        None => Err(HttpError::not_found(ErrorMessage::CourseNotFound.to_string())),
+        Some(course) => Ok(ApiResponse::ok(course)),
+        None => Ok(HttpError::not_found(ErrorMessage::CourseNotFound.to_string()).into_http_response()),
+    }
+}
+
+/// Programa una oferta con fecha. El precio de oferta debe ser menor que el precio
+/// del curso; mientras dure, las órdenes de PayPal se crean con él.
+pub async fn set_course_sale(
+    CourseId(course_id): CourseId,
+    app_state: Data<AppState>,
+    Json(body): Json<SetCourseSaleDTO>,
+) -> Result<HttpResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+    let now = chrono::Utc::now();
+    if !body.has_valid_window(now) {
+        return Err(HttpError::bad_request(ErrorMessage::InvalidSaleWindow.to_string()));
+    }
+
+    let Some(course) = app_state.db_client.get_course(course_id).await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+    else {
+        return Ok(HttpError::not_found(ErrorMessage::CourseNotFound.to_string()).into_http_response());
+    };
+    if body.sale_price >= course.price {
+        return Err(HttpError::bad_request(ErrorMessage::SalePriceNotLower.to_string()));
+    }
+
+    let course = app_state.db_client
+        .set_course_sale(course_id, Some(body.sale_price), Some(body.sale_starts_at.unwrap_or(now)), Some(body.sale_ends_at)).await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    match course {
+        Some(course) => Ok(ApiResponse::ok(course)),
+        None => Ok(HttpError::not_found(ErrorMessage::CourseNotFound.to_string()).into_http_response()),
+    }
+}
+
+/// Quita la oferta (programada o vigente) del curso.
+pub async fn clear_course_sale(
+    CourseId(course_id): CourseId,
+    app_state: Data<AppState>,
+) -> Result<HttpResponse, HttpError> {
+    let course = app_state.db_client
+        .set_course_sale(course_id, None, None, None).await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    match course {
+        Some(course) => Ok(ApiResponse::ok(course)),
+        None => Ok(HttpError::not_found(ErrorMessage::CourseNotFound.to_string()).into_http_response()),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct TranslationPath {
+    locale: String,
+}
+
+/// Etiqueta de la ruta normalizada, o `400` si no es un idioma válido.
+fn translation_locale(path: Path<TranslationPath>) -> Result<String, HttpError> {
+    let raw = path.into_inner().locale;
+    normalize_locale(&raw).ok_or_else(|| HttpError::bad_request(ErrorMessage::InvalidLocale(raw).to_string()))
+}
+
+pub async fn get_course_translations(
+    CourseId(course_id): CourseId,
+    app_state: Data<AppState>,
+) -> Result<HttpResponse, HttpError> {
+    let translations = app_state.db_client
+        .get_course_translations(course_id).await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    Ok(ApiResponse::ok(translations))
+}
+
+/// Crea o reemplaza la traducción del curso a `{locale}`.
+pub async fn set_course_translation(
+    CourseId(course_id): CourseId,
+    path: Path<TranslationPath>,
+    app_state: Data<AppState>,
+    Json(body): Json<SetCourseTranslationDTO>,
+) -> Result<HttpResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+    let locale = translation_locale(path)?;
+
+    let translation = app_state.db_client
+        .upsert_course_translation(
+            course_id,
+            &locale,
+            body.title.trim(),
+            body.description.trim(),
+            body.long_description.as_deref().map(str::trim).filter(|d| !d.is_empty()),
+        ).await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    match translation {
+        Some(translation) => Ok(ApiResponse::ok(translation)),
+        None => Ok(HttpError::not_found(ErrorMessage::CourseNotFound.to_string()).into_http_response()),
+    }
+}
+
+pub async fn delete_course_translation(
+    CourseId(course_id): CourseId,
+    path: Path<TranslationPath>,
+    app_state: Data<AppState>,
+) -> Result<HttpResponse, HttpError> {
+    let locale = translation_locale(path)?;
+
+    let deleted = app_state.db_client
+        .delete_course_translation(course_id, &locale).await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if !deleted {
+        return Ok(HttpError::not_found(ErrorMessage::CourseTranslationNotFound.to_string()).into_http_response());
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Fija el cupo de un curso por cohortes. Bajarlo por debajo de los inscritos no
+/// expulsa a nadie: solo impide nuevas compras.
+pub async fn set_course_max_students(
+    CourseId(course_id): CourseId,
+    app_state: Data<AppState>,
+    Json(body): Json<SetCourseMaxStudentsDTO>,
+) -> Result<HttpResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let course = app_state.db_client
+        .set_course_max_students(course_id, body.max_students).await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    match course {
+        Some(course) => Ok(ApiResponse::ok(course)),
+        None => Ok(HttpError::not_found(ErrorMessage::CourseNotFound.to_string()).into_http_response()),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct RelatedQuery {
+    limit: Option<i64>,
+}
+
+/// Recomendaciones para la ficha del curso: misma categoría, sin los cursos que ya tiene el usuario.
+pub async fn get_related_courses(
+    CourseId(course_id): CourseId,
+    Query(q): Query<RelatedQuery>,
+    app_state: Data<AppState>,
+    auth: web::ReqData<JWTAuthMiddleware>
+) -> Result<HttpResponse, HttpError> {
+    let limit = q.limit.unwrap_or(4).clamp(1, 20);
+
+    let courses = app_state.db_client
+        .get_related_courses(course_id, auth.user.id, limit).await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+    let courses = UserCourseDto::with_default_rating(courses, app_state.env.default_course_rating);
+
+    let courses: Vec<FilterCourseDto> = courses.iter().map(FilterCourseDto::filter_course).collect();
+    Ok(ApiResponse::ok(courses))
+}
+
+/// Curso en el idioma preferido de `Accept-Language` que tenga traducción, o en el idioma base.
+pub async fn get_course(
+    req: HttpRequest,
+    CourseId(course_id): CourseId,
+    app_state: Data<AppState>
+) -> Result<HttpResponse, HttpError> {
+    localized_course_response(&req, &app_state, course_id).await
+}
+
+async fn localized_course_response(
+    req: &HttpRequest,
+    app_state: &AppState,
+    course_id: Uuid,
+) -> Result<HttpResponse, HttpError> {
+    let locales = request_locales(req, &app_state.env.default_locale);
+    let course = app_state.db_client
+        .get_course_localized(course_id, &locales).await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let Some((course, locale)) = course else {
+        return Ok(HttpError::not_found(ErrorMessage::CourseNotFound.to_string()).into_http_response());
+    };
+    let locale = locale.unwrap_or_else(|| app_state.env.default_locale.clone());
+    let res = ApiResponse::ok(LocalizedDto { item: course, locale: locale.clone() });
+    Ok(with_language_headers(res, Some(&locale)))
+}
+
+/// Índice público del curso (módulos y lecciones sin contenido) para la página del curso.
+pub async fn get_course_outline(
+    CourseId(course_id): CourseId,
+    app_state: Data<AppState>
+) -> Result<HttpResponse, HttpError> {
+    let outline = app_state.db_client
+        .get_course_outline(course_id).await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let Some(outline) = outline else {
+        return Ok(HttpError::not_found(ErrorMessage::CourseNotFound.to_string()).into_http_response());
+    };
+    Ok(ApiResponse::ok(outline))
+}
+
+pub async fn get_course_by_slug(
+    req: HttpRequest,
+    path: Path<String>,
+    app_state: Data<AppState>
+) -> Result<HttpResponse, HttpError> {
+    let slug = path.into_inner();
+
+    let course = app_state.db_client
+        .get_course_by_slug(&slug).await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or_else(|| HttpError::not_found(ErrorMessage::CourseNotFound.to_string()))?;
+
+    // Slug antiguo (el curso cambió de título): redirigir a la URL canónica
+    if course.slug != slug {
+        return Ok(HttpResponse::MovedPermanently()
+            .insert_header((header::LOCATION, api_path(&format!("/courses/slug/{}", course.slug))))
+            .finish());
     }
+
+    localized_course_response(&req, &app_state, course.id).await
 }
 
 
 pub async fn get_courses_with_modules(
     // Query(q): Query<ListQuery>,
-    app_state: Data<Arc<AppState>>
+    app_state: Data<AppState>
 ) -> Result<HttpResponse, HttpError> {
 
     // // Valores por defecto
@@ -170,17 +536,14 @@ pub async fn get_courses_with_modules(
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
     // Respuesta HTTP 200 OK
-    Ok(HttpResponse::Ok().json(courses))
+    Ok(ApiResponse::ok(courses))
 }
 
 pub async fn get_course_with_modules(
-    path: Path<String>,
-    app_state: Data<Arc<AppState>>,
+    CourseId(course_id): CourseId,
+    app_state: Data<AppState>,
     _auth: web::ReqData<JWTAuthMiddleware>  // requiere autenticación
 ) -> Result<HttpResponse, HttpError> {
-    let id_str = path.into_inner();
-    let course_id = Uuid::parse_str(&id_str)
-        .map_err(|e| HttpError::bad_request(e.to_string()))?;
 
     let course = app_state.db_client
         .get_course_with_videos(course_id,Some( _auth.user.id))
@@ -188,19 +551,16 @@ pub async fn get_course_with_modules(
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
     match course {
-        Some(c) => Ok(HttpResponse::Ok().json(c)),
+        Some(c) => Ok(ApiResponse::ok(c)),
         None => Err(HttpError::not_found(ErrorMessage::CourseNotFound.to_string())),
     }
 }
 
 pub async fn get_course_with_modules_preview(
-    path: Path<String>,
-    app_state: Data<Arc<AppState>>,
+    CourseId(course_id): CourseId,
+    app_state: Data<AppState>,
     _auth: web::ReqData<JWTAuthMiddleware>
 ) -> Result<HttpResponse, HttpError> {
-    let id_str = path.into_inner();
-    let course_id = Uuid::parse_str(&id_str)
-        .map_err(|e| HttpError::bad_request(e.to_string()))?;
 
     let course = app_state.db_client
         .get_course_with_videos_preview(course_id,Some( _auth.user.id))
@@ -214,40 +574,111 @@ pub async fn get_course_with_modules_preview(
         )?;
 
     match course {
-        Some(c) => Ok(HttpResponse::Ok().json(c)),
+        Some(c) => Ok(ApiResponse::ok(c)),
         None => Err(HttpError::not_found(ErrorMessage::CourseNotFound.to_string())),
     }
 }
 
+/// Respaldo de un curso (contenido completo, sin alumnos ni progreso) en el formato
+/// que acepta `POST /courses/edit`.
+pub async fn export_course(
+    CourseId(course_id): CourseId,
+    app_state: Data<AppState>,
+) -> Result<HttpResponse, HttpError> {
+    let course = app_state.db_client
+        .get_course_with_videos(course_id, None)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let Some(course) = course else {
+        return Ok(HttpError::not_found(ErrorMessage::CourseNotFound.to_string()).into_http_response());
+    };
+
+    Ok(HttpResponse::Ok()
+        .insert_header((header::CONTENT_DISPOSITION, format!("attachment; filename=\"course-{}.json\"", course_id)))
+        .json(CreateCourseDTO::from(course)))
+}
+
+/// Arreglo JSON con la exportación de cada curso, leídos de a uno para no cargar
+/// todo el catálogo en memoria. Un curso borrado mientras tanto se omite.
+pub(crate) fn course_export_stream(
+    db: DBClient,
+    course_ids: Vec<Uuid>,
+) -> impl Stream<Item = Result<web::Bytes, actix_web::Error>> {
+    let courses = stream::unfold((db, course_ids.into_iter(), true), |(db, mut ids, first)| async move {
+        loop {
+            let course_id = ids.next()?;
+            let course = match db.get_course_with_videos(course_id, None).await {
+                Ok(Some(course)) => course,
+                Ok(None) => continue,
+                Err(e) => {
+                    log::error!("Exportación interrumpida en el curso {}: {}", course_id, e);
+                    let error = actix_web::error::ErrorInternalServerError(e);
+                    return Some((Err(error), (db, Vec::new().into_iter(), first)));
+                }
+            };
+            let mut chunk = if first { Vec::new() } else { b",".to_vec() };
+            if let Err(e) = serde_json::to_writer(&mut chunk, &CreateCourseDTO::from(course)) {
+                return Some((Err(actix_web::error::ErrorInternalServerError(e)), (db, Vec::new().into_iter(), first)));
+            }
+            return Some((Ok(web::Bytes::from(chunk)), (db, ids, false)));
+        }
+    });
+
+    stream::once(async { Ok(web::Bytes::from_static(b"[")) })
+        .chain(courses)
+        .chain(stream::once(async { Ok(web::Bytes::from_static(b"]")) }))
+}
+
+/// Respaldo de todo el catálogo; cada elemento se importa con `POST /courses/edit`.
+pub async fn export_courses(
+    app_state: Data<AppState>,
+) -> Result<HttpResponse, HttpError> {
+    let course_ids = app_state.db_client
+        .get_course_ids()
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header((header::CONTENT_TYPE, "application/json"))
+        .insert_header((header::CONTENT_DISPOSITION, "attachment; filename=\"courses.json\""))
+        .streaming(course_export_stream(app_state.db_client.clone(), course_ids)))
+}
+
+/// Id del producto de PayPal de un curso, derivado de su id: si la creación se reintenta
+/// (p. ej. falló guardar el id en la base de datos) PayPal lo rechaza como duplicado y se
+/// reutiliza el mismo producto en lugar de crear otro.
+pub(crate) fn course_paypal_product_id(course_id: Uuid) -> String {
+    format!("COURSE-{}", course_id.simple())
+}
+
+/// Producto de PayPal que representa un curso
+fn course_product(app_state: &AppState, course_id: Uuid, title: &str, description: &str, image: Option<String>) -> ProductDTO {
+    ProductDTO {
+        id: Some(course_paypal_product_id(course_id)),
+        name: title.to_string(),
+        description: description.to_string(),
+        type_: "SERVICE".to_string(),
+        category: "EDUCATIONAL_AND_TEXTBOOKS".to_string(),
+        image_url: image,
+        home_url: Some(format!("{}/courses/", app_state.env.host))
+    }
+}
+
 pub async fn create_course(
-    app_state: Data<Arc<AppState>>,
+    app_state: Data<AppState>,
     Json(body): Json<CreateCourseDTO>,
     _auth: web::ReqData<JWTAuthMiddleware> // ya validado por middleware/RoleCheck o AuthMiddlewareFactory
 ) -> Result<HttpResponse, HttpError> {
     body.validate().map_err(|e| HttpError::bad_request(e.to_string()))?;
-    let host = app_state.env.host.trim_end_matches('/');
-    let product_body = ProductDTO {
-        name: body.title.clone(),
-        description: body.description.clone(),
-        type_: "SERVICE".to_string(),
-        category: "EDUCATIONAL_AND_TEXTBOOKS".to_string(),
-        image_url: body.image.clone(),
-        home_url: Some(if host.starts_with("https://") {
-            format!("{}/courses/", host)
-        } else {
-            format!("https://{}/courses/", host)
-        })
-    };
-    log::debug!("PayPal request body: {:?}", product_body);
-    let product_id = create_product(app_state.clone(), product_body).await.map_err(|e| {
-        HttpError::server_error(format!("Failed to create product: {}", e.to_string()))
-    })?;
     let new_body = CreateCourseDTO {
-        paypal_product_id: Some(product_id.clone()),
-        ..body.clone()
+        paypal_product_id: None,
+        students: body.students.or(Some(app_state.env.default_course_students)),
+        ..body
     };
 
-    let course = app_state.db_client.create_course(new_body).await.map_err(|e| {
+    // Primero el curso y después el producto: un fallo nunca deja un producto huérfano
+    let (course_id, mut course) = app_state.db_client.create_course(new_body).await.map_err(|e| {
         let s = e.to_string();
         if s.contains("duplicate") || s.contains("unique") {
             HttpError::unique_constraint_violation(ErrorMessage::CourseAlreadyExists.to_string())
@@ -256,40 +687,205 @@ pub async fn create_course(
         }
     })?;
 
-    Ok(HttpResponse::Created().json(course))
+    let product_body = course_product(&app_state, course_id, &course.title, &course.description, course.image.clone());
+    log::debug!("PayPal request body: {:?}", product_body);
+    let product_id = create_product(app_state.clone(), product_body).await;
+    let saved = match product_id {
+        Ok(product_id) => app_state.db_client
+            .set_course_paypal_product_id(course_id, &product_id).await
+            .map(|c| c.paypal_product_id)
+            .map_err(|e| HttpError::server_error(e.to_string())),
+        Err(e) => Err(e),
+    };
+
+    // El curso queda guardado sin producto; `sync-paypal` lo reintenta con el mismo id
+    match saved {
+        Ok(paypal_product_id) => course.paypal_product_id = paypal_product_id,
+        Err(e) => {
+            log::error!("Curso {} creado sin producto de PayPal: {}", course_id, e.message);
+            return Ok(HttpError::new(
+                format!(
+                    "Course created but its PayPal product could not be saved: {}. Retry with POST {}",
+                    e.message, api_path(&format!("/courses/edit/{}/sync-paypal", course_id))
+                ),
+                StatusCode::BAD_GATEWAY,
+            )
+            .with_details(json!({ "courseId": course_id }))
+            .into_http_response());
+        }
+    }
+
+    Ok(ApiResponse::created(course))
+}
+
+#[derive(Deserialize)]
+pub struct SyncPaypalQuery {
+    #[serde(default)]
+    force: bool,
+}
+
+/// Crea el producto de PayPal de un curso que no lo tiene (falló al crearlo o
+/// el curso se cargó directo en la base de datos) y guarda su id. Con `force=true`
+/// vuelve a enlazar el curso a su producto aunque ya tenga uno guardado: es la única
+/// forma de cambiar `paypal_product_id`, que `update_course` nunca modifica.
+pub async fn sync_course_paypal_product(
+    CourseId(course_id): CourseId,
+    Query(q): Query<SyncPaypalQuery>,
+    app_state: Data<AppState>,
+    _auth: web::ReqData<JWTAuthMiddleware>
+) -> Result<HttpResponse, HttpError> {
+
+    let course = app_state.db_client.get_course(course_id).await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or_else(|| HttpError::not_found(ErrorMessage::CourseNotFound.to_string()))?;
+
+    if let Some(paypal_product_id) = course.paypal_product_id.clone().filter(|_| !q.force) {
+        return Ok(ApiResponse::ok(json!({
+            "created": false,
+            "paypalProductId": paypal_product_id,
+        })));
+    }
+
+    let product_body = course_product(&app_state, course_id, &course.title, &course.description, course.image.clone());
+    let product_id = create_product(app_state.clone(), product_body).await.map_err(|e| {
+        HttpError::new(format!("Failed to create product: {}", e.message), e.status)
+    })?;
+
+    if let Some(previous) = course.paypal_product_id.as_deref().filter(|p| *p != product_id) {
+        log::warn!("Curso {}: producto de PayPal {} reemplazado por {}", course_id, previous, product_id);
+    }
+
+    let course = app_state.db_client
+        .set_course_paypal_product_id(course_id, &product_id).await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    Ok(ApiResponse::ok(json!({
+        "created": true,
+        "paypalProductId": course.paypal_product_id,
+    })))
+}
+
+/// Reporte de cursos sin producto de PayPal (no se pueden comprar)
+pub async fn get_courses_missing_paypal_product(
+    app_state: Data<AppState>,
+) -> Result<HttpResponse, HttpError> {
+    let courses = app_state.db_client
+        .get_courses_missing_paypal_product().await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    Ok(ApiResponse::ok(json!({
+        "results": courses.len(),
+        "courses": courses,
+    })))
+}
+
+/// Traduce el resultado de `update_course`: `None` significa que la versión enviada quedó obsoleta.
+pub(crate) fn update_course_outcome(
+    result: Result<Option<CourseWithModulesDto>, SqlxError>,
+) -> Result<CourseWithModulesDto, HttpError> {
+    versioned_course_outcome(result)
+}
+
+fn versioned_course_outcome<T>(result: Result<Option<T>, SqlxError>) -> Result<T, HttpError> {
+    match result {
+        Ok(Some(course)) => Ok(course),
+        Ok(None) => Err(HttpError::unique_constraint_violation(ErrorMessage::CourseVersionConflict.to_string())
+            .with_details(json!({ "field": "version" }))),
+        Err(SqlxError::RowNotFound) => Err(HttpError::not_found(ErrorMessage::CourseNotFound.to_string())),
+        Err(e) => Err(HttpError::server_error(e.to_string())),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct UpdateCourseQuery {
+    #[serde(default)]
+    dry_run: bool,
 }
 
 pub async fn update_course(
-    path: Path<String>,
-    app_state: Data<Arc<AppState>>,
+    CourseId(course_id): CourseId,
+    app_state: Data<AppState>,
+    Query(q): Query<UpdateCourseQuery>,
     Json(body): Json<UpdateCourseDTO>,
-    _auth: web::ReqData<JWTAuthMiddleware>
+    auth: web::ReqData<JWTAuthMiddleware>
 ) -> Result<HttpResponse, HttpError> {
     body.validate().map_err(|e| HttpError::bad_request(e.to_string()))?;
 
-    let id_str = path.into_inner();
-    let course_id = Uuid::parse_str(&id_str).map_err(|e| HttpError::bad_request(e.to_string()))?;
+    // Vista previa: qué módulos/lecciones se insertarían, actualizarían o borrarían
+    if q.dry_run {
+        let result = app_state.db_client.preview_course_update(course_id, body).await;
+        return match versioned_course_outcome(result) {
+            Ok(changeset) => Ok(ApiResponse::ok(changeset)),
+            Err(e) => Ok(e.into_http_response()),
+        };
+    }
 
-    let updated = app_state.db_client
-        .update_course(course_id,body).await
-        .map_err(|e| {
-            match e {
-                SqlxError::RowNotFound =>
-                    HttpError::not_found(ErrorMessage::CourseNotFound.to_string()),
-                _ => HttpError::server_error(e.to_string()),
-            }
-        })?;
+    let result = app_state.db_client
+        .update_course(course_id, body, auth.user.id, app_state.env.course_revision_limit).await;
+
+    match update_course_outcome(result) {
+        Ok(updated) => Ok(ApiResponse::ok(updated)),
+        Err(e) => Ok(e.into_http_response()),
+    }
+}
+
+/// Historial de ediciones del curso, de la más reciente a la más antigua.
+pub async fn get_course_revisions(
+    CourseId(course_id): CourseId,
+    app_state: Data<AppState>,
+) -> Result<HttpResponse, HttpError> {
+    let revisions = app_state.db_client
+        .get_course_revisions(course_id).await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    // Sin revisiones: distinguir un curso nunca editado de uno que no existe
+    if revisions.is_empty() {
+        let course = app_state.db_client.get_course(course_id).await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+        if course.is_none() {
+            return Ok(HttpError::not_found(ErrorMessage::CourseNotFound.to_string()).into_http_response());
+        }
+    }
+
+    Ok(ApiResponse::ok(revisions))
+}
+
+#[derive(Deserialize)]
+pub struct RevisionPath {
+    rev: i32,
+}
+
+/// Devuelve el curso al contenido de una revisión; lo reemplazado queda como revisión nueva.
+pub async fn restore_course_revision(
+    CourseId(course_id): CourseId,
+    path: Path<RevisionPath>,
+    app_state: Data<AppState>,
+    auth: web::ReqData<JWTAuthMiddleware>
+) -> Result<HttpResponse, HttpError> {
+    let result = app_state.db_client
+        .restore_course_revision(course_id, path.rev, auth.user.id, app_state.env.course_revision_limit)
+        .await;
 
-    Ok(HttpResponse::Ok().json(updated))
+    let result = match result {
+        Ok(RevisionRestore::RevisionNotFound) => {
+            return Ok(HttpError::not_found(ErrorMessage::CourseRevisionNotFound.to_string()).into_http_response());
+        }
+        Ok(RevisionRestore::Restored(course)) => Ok(Some(*course)),
+        Ok(RevisionRestore::VersionConflict) => Ok(None),
+        Err(e) => Err(e),
+    };
+
+    match versioned_course_outcome(result) {
+        Ok(course) => Ok(ApiResponse::ok(course)),
+        Err(e) => Ok(e.into_http_response()),
+    }
 }
 
 pub async fn delete_course(
-    path: Path<String>,
-    app_state: Data<Arc<AppState>>,
+    CourseId(course_id): CourseId,
+    app_state: Data<AppState>,
     _auth: web::ReqData<JWTAuthMiddleware>
 ) -> Result<HttpResponse, HttpError> {
-    let id_str = path.into_inner();
-    let course_id = Uuid::parse_str(&id_str).map_err(|e| HttpError::bad_request(e.to_string()))?;
 
     // Obtener el curso para tener el paypal_product_id
     let course = app_state.db_client.get_course(course_id).await.map_err(|e| {
@@ -316,21 +912,18 @@ pub async fn delete_course(
 
 
 pub async fn update_lesson_progress(
-    path: Path<(String,String)>,
+    LessonId(lesson_uuid): LessonId,
     user: ReqData<JWTAuthMiddleware>,
-    state: Data<Arc<AppState>>,
+    state: Data<AppState>,
     Json(progress_data): Json<UpdateLessonProgressDTO>,
 ) -> Result<HttpResponse, HttpError> {
     log::debug!("ejecutando update_lesson_progress");
-    let  (_,lesson_id) = path.into_inner();
     let user_id = user.user.id;
 
-    let lesson_uuid = Uuid::parse_str(&lesson_id)
-        .map_err(|_| HttpError::bad_request("ID de lección inválido".to_string()))?;
     log::debug!("user_id: {}", user_id);
     log::debug!("lesson_uuid: {}", lesson_uuid);
     log::debug!("progress_data: {:?}", progress_data);
-    state.db_client.update_lesson_progress(
+    let course_progress = state.db_client.update_lesson_progress(
         user_id,
         lesson_uuid,
         progress_data.is_completed,
@@ -338,11 +931,233 @@ pub async fn update_lesson_progress(
     )
     .await
     .map_err(|e| HttpError::server_error(e.to_string()))?;
+    state.events.publish(DomainEvent::CourseProgressUpdated { user_id, progress: course_progress });
     
-    Ok(HttpResponse::Ok().json(json!({
-        "success": true,
+    Ok(ApiResponse::ok(json!({
         "lessonId": lesson_uuid,
         "progress": progress_data.progress,
     })))
 }
 
+/// Marca o desmarca una lección del curso y devuelve el progreso recalculado.
+pub async fn set_lesson_completed(
+    CourseId(course_id): CourseId,
+    LessonId(lesson_id): LessonId,
+    user: ReqData<JWTAuthMiddleware>,
+    app_state: Data<AppState>,
+    Json(body): Json<SetLessonCompletedDTO>,
+) -> Result<HttpResponse, HttpError> {
+    let user_id = user.user.id;
+
+    let has_access = app_state.db_client
+        .check_user_course_access(user_id, course_id).await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+    if has_access != Some(true) {
+        return Ok(HttpError::forbidden(ErrorMessage::PermissionDenied.to_string()).into_http_response());
+    }
+
+    let progress = app_state.db_client
+        .set_lesson_completed(user_id, course_id, lesson_id, body.completed).await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+    let Some(progress) = progress else {
+        return Ok(HttpError::not_found(ErrorMessage::LessonNotFound.to_string()).into_http_response());
+    };
+    app_state.events.publish(DomainEvent::CourseProgressUpdated { user_id, progress: progress.clone() });
+
+    Ok(ApiResponse::ok(progress))
+}
+
+/// Marca todas las lecciones del curso como completadas para un usuario (solo administradores).
+/// Usa el mismo `update_lesson_progress` que el reproductor, así que recalcula el progreso
+/// y otorga los logros de lección y de curso completado como en el flujo real.
+pub async fn complete_course_for_user(
+    CourseId(course_id): CourseId,
+    UserId(user_id): UserId,
+    app_state: Data<AppState>,
+) -> Result<HttpResponse, HttpError> {
+    let user = app_state.db_client
+        .get_user(Some(user_id), None, None, None).await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+    if user.is_none() {
+        return Ok(HttpError::not_found(ErrorMessage::UserNotFound.to_string()).into_http_response());
+    }
+
+    let lesson_ids = app_state.db_client
+        .get_course_lesson_ids(course_id).await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+    let Some(lesson_ids) = lesson_ids else {
+        return Ok(HttpError::not_found(ErrorMessage::CourseNotFound.to_string()).into_http_response());
+    };
+    if lesson_ids.is_empty() {
+        return Err(HttpError::bad_request(ErrorMessage::CourseHasNoLessons.to_string()));
+    }
+
+    for lesson_id in &lesson_ids {
+        let progress = app_state.db_client
+            .update_lesson_progress(user_id, *lesson_id, true, Some(100.0)).await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+        app_state.events.publish(DomainEvent::CourseProgressUpdated { user_id, progress });
+    }
+    log::info!("Curso {} marcado como completado para el usuario {} ({} lecciones)", course_id, user_id, lesson_ids.len());
+
+    let progress = app_state.db_client
+        .get_user_course_progress(user_id, course_id).await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    Ok(ApiResponse::ok(progress))
+}
+
+// Comentario SSE que mantiene viva la conexión a través de proxies
+const PROGRESS_STREAM_KEEP_ALIVE: std::time::Duration = std::time::Duration::from_secs(30);
+
+fn sse_progress_frame(progress: &CourseProgressEventDto) -> web::Bytes {
+    let data = serde_json::to_string(progress).unwrap_or_default();
+    web::Bytes::from(format!("event: progress\ndata: {}\n\n", data))
+}
+
+/// Eventos SSE del progreso de `user_id` en `course_id`: primero `initial` y luego cada
+/// actualización publicada en el bus, con un comentario de keep-alive si no hay actividad.
+/// Termina cuando se cierra el bus; si el cliente se desconecta actix suelta el flujo
+/// y con él la suscripción.
+pub(crate) fn progress_event_stream(
+    user_id: Uuid,
+    course_id: Uuid,
+    initial: CourseProgressEventDto,
+    events: broadcast::Receiver<DomainEvent>,
+    keep_alive: std::time::Duration,
+) -> impl Stream<Item = Result<web::Bytes, actix_web::Error>> {
+    let keep_alive_timer = interval_at(Instant::now() + keep_alive, keep_alive);
+
+    let updates = stream::unfold((events, keep_alive_timer), move |(mut events, mut timer)| async move {
+        loop {
+            tokio::select! {
+                _ = timer.tick() => {
+                    return Some((Ok(web::Bytes::from_static(b": keep-alive\n\n")), (events, timer)));
+                }
+                event = events.recv() => match event {
+                    Ok(DomainEvent::CourseProgressUpdated { user_id: event_user, progress })
+                        if event_user == user_id && progress.course_id == course_id =>
+                    {
+                        timer.reset();
+                        return Some((Ok(sse_progress_frame(&progress)), (events, timer)));
+                    }
+                    Ok(_) => continue,
+                    // Se perdieron eventos intermedios: el siguiente trae el progreso completo
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return None,
+                },
+            }
+        }
+    });
+
+    stream::once(async move { Ok(sse_progress_frame(&initial)) }).chain(updates)
+}
+
+/// Progreso en vivo del usuario en el curso (SSE), para sincronizar varios dispositivos.
+pub async fn stream_course_progress(
+    CourseId(course_id): CourseId,
+    app_state: Data<AppState>,
+    auth: ReqData<JWTAuthMiddleware>,
+) -> Result<HttpResponse, HttpError> {
+    if let Some(disabled) = feature_gate(&app_state, Feature::ProgressStream).await {
+        return Ok(disabled);
+    }
+    let user_id = auth.user.id;
+
+    // Suscribirse antes de leer el estado actual para no perder avances concurrentes
+    let events = app_state.events.subscribe();
+
+    let lesson_ids = app_state.db_client
+        .get_course_lesson_ids(course_id).await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+    let Some(lesson_ids) = lesson_ids else {
+        return Ok(HttpError::not_found(ErrorMessage::CourseNotFound.to_string()).into_http_response());
+    };
+
+    let current = app_state.db_client
+        .get_user_course_progress(user_id, course_id).await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+    let initial = CourseProgressEventDto {
+        course_id,
+        progress_percentage: current.as_ref().map_or(0.0, |p| p.progress_percentage),
+        completed_lessons: current.as_ref().and_then(|p| p.completed_lessons).unwrap_or(0) as i64,
+        total_lessons: lesson_ids.len() as i64,
+    };
+
+    Ok(HttpResponse::Ok()
+        .insert_header((header::CONTENT_TYPE, "text/event-stream"))
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .streaming(progress_event_stream(user_id, course_id, initial, events, PROGRESS_STREAM_KEEP_ALIVE)))
+}
+
+/// Alumnos inscritos en el curso con su progreso (solo administradores).
+pub async fn get_course_students(
+    CourseId(course_id): CourseId,
+    Query(query_params): Query<RequestQueryDto>,
+    app_state: Data<AppState>,
+) -> Result<HttpResponse, HttpError> {
+    query_params.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let page = query_params.page.unwrap_or(1);
+    let limit = query_params.limit.unwrap_or(10);
+
+    let students = app_state.db_client
+        .get_course_students(course_id, page, limit)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let Some((students, total)) = students else {
+        return Ok(HttpError::not_found(ErrorMessage::CourseNotFound.to_string()).into_http_response());
+    };
+
+    Ok(ApiResponse::paginated(students, page, limit, total))
+}
+
+/// Importa el progreso de alumnos migrados desde otra plataforma (solo administradores).
+/// Cada alumno se procesa por separado y la respuesta indica el resultado de cada uno.
+pub async fn import_course_progress(
+    CourseId(course_id): CourseId,
+    app_state: Data<AppState>,
+    Json(entries): Json<Vec<ProgressImportEntryDto>>,
+) -> Result<HttpResponse, HttpError> {
+    if entries.is_empty() || entries.len() as u64 > MAX_PROGRESS_IMPORT_USERS {
+        return Err(HttpError::bad_request(ErrorMessage::ProgressImportSize(MAX_PROGRESS_IMPORT_USERS).to_string()));
+    }
+
+    let results = app_state.db_client
+        .import_lesson_progress(course_id, &entries)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let Some(results) = results else {
+        return Ok(HttpError::not_found(ErrorMessage::CourseNotFound.to_string()).into_http_response());
+    };
+
+    Ok(ApiResponse::ok(ProgressImportResponseDto {
+        imported: results.iter().filter(|r| r.status == "imported").count(),
+        results,
+    }))
+}
+
+/// Indica si el usuario ya puede ver el curso (comprado, premium o admin) antes de
+/// ofrecerle la compra.
+pub async fn get_course_access(
+    CourseId(course_id): CourseId,
+    app_state: Data<AppState>,
+    auth: ReqData<JWTAuthMiddleware>,
+) -> Result<HttpResponse, HttpError> {
+    let reason = app_state.db_client
+        .course_access_reason(auth.user.id, course_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let Some(reason) = reason else {
+        return Ok(HttpError::not_found(ErrorMessage::CourseNotFound.to_string()).into_http_response());
+    };
+
+    Ok(ApiResponse::ok(CourseAccessDto {
+        has_access: reason != CourseAccessReason::None,
+        reason,
+    }))
+}
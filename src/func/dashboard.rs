@@ -0,0 +1,65 @@
+use actix_web::{HttpResponse, http::header::{self, HeaderValue}, web::{Data, ReqData}};
+use chrono::Utc;
+
+use crate::{
+    AppState,
+    config::dtos::{ApiResponse, CourseProgressStatus, DashboardDto, DashboardSubscriptionDto, FilterUserDto, UserAchievementDto},
+    db::db::{CoursePurchaseExt, NotificationExt, SubscriptionExt, UserAchievementExt},
+    errors::error::HttpError,
+    func::users::premium_until,
+    middleware::middleware::JWTAuthMiddleware,
+};
+
+const DASHBOARD_COURSES: usize = 5;
+const DASHBOARD_ACHIEVEMENTS: usize = 5;
+// Caché corta y solo del navegador: los datos son del usuario y cambian al avanzar en un curso
+const DASHBOARD_CACHE_CONTROL: &str = "private, max-age=30";
+
+/// Últimos logros ganados, del más reciente al más antiguo
+pub(crate) fn recent_achievements(achievements: Vec<UserAchievementDto>, limit: usize) -> Vec<UserAchievementDto> {
+    let mut earned: Vec<UserAchievementDto> = achievements
+        .into_iter()
+        .filter(|a| a.earned && a.earned_at.is_some())
+        .collect();
+    earned.sort_by_key(|a| std::cmp::Reverse(a.earned_at));
+    earned.truncate(limit);
+    earned
+}
+
+/// Pantalla de inicio: usuario, cursos en curso, notificaciones sin leer,
+/// logros recientes y suscripción. Las consultas se lanzan en paralelo.
+pub async fn get_dashboard(
+    app_state: Data<AppState>,
+    auth: ReqData<JWTAuthMiddleware>,
+) -> Result<HttpResponse, HttpError> {
+    let user_id = auth.user.id;
+    let db = &app_state.db_client;
+
+    let ((mut courses, _), unread_notifications, achievements, subscriptions) = futures::try_join!(
+        db.get_user_courses_by_status(user_id, Some(CourseProgressStatus::InProgress), 1, DASHBOARD_COURSES),
+        db.count_unread_notifications(user_id),
+        db.get_user_achievements(user_id),
+        db.get_user_subscriptions(user_id),
+    )
+    .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    courses.iter_mut().for_each(|c| c.course.apply_default_rating(app_state.env.default_course_rating));
+
+    let premium_until = premium_until(&subscriptions, Utc::now());
+    let dashboard = DashboardDto {
+        user: FilterUserDto::filter_user(&auth.user),
+        in_progress_courses: courses,
+        unread_notifications,
+        recent_achievements: recent_achievements(achievements, DASHBOARD_ACHIEVEMENTS),
+        subscription: DashboardSubscriptionDto {
+            is_premium: premium_until.is_some(),
+            premium_until,
+            status: subscriptions.iter().map(|s| s.status).find(|s| s.is_current()),
+        },
+    };
+
+    let mut res = ApiResponse::ok(dashboard);
+    res.headers_mut().insert(header::CACHE_CONTROL, HeaderValue::from_static(DASHBOARD_CACHE_CONTROL));
+    res.headers_mut().insert(header::VARY, HeaderValue::from_static("Authorization, Cookie"));
+    Ok(res)
+}
@@ -0,0 +1,61 @@
+use actix_web::{HttpResponse, web::{Data, Json, Path, ReqData}};
+
+use crate::{
+    AppState,
+    config::dtos::{ApiResponse, FeatureFlagDto, SetFeatureFlagDTO},
+    db::db::FeatureFlagExt,
+    errors::error::{ErrorMessage, HttpError},
+    middleware::middleware::JWTAuthMiddleware,
+    services::feature_flags::Feature,
+};
+
+/// Todas las funcionalidades conocidas con su estado actual (leído de la base, sin caché).
+pub async fn get_feature_flags(
+    app_state: Data<AppState>,
+) -> Result<HttpResponse, HttpError> {
+    let stored = app_state.db_client
+        .get_feature_flags()
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let flags: Vec<FeatureFlagDto> = Feature::ALL
+        .iter()
+        .map(|feature| {
+            let row = stored.iter().find(|f| f.key == feature.as_str());
+            FeatureFlagDto {
+                key: feature.as_str().to_string(),
+                enabled: row.is_none_or(|f| f.enabled),
+                updated_by: row.and_then(|f| f.updated_by),
+                updated_at: row.map(|f| f.updated_at),
+            }
+        })
+        .collect();
+
+    Ok(ApiResponse::ok(flags))
+}
+
+/// Enciende o apaga una funcionalidad; la caché de esta instancia se descarta al momento.
+pub async fn set_feature_flag(
+    path: Path<String>,
+    app_state: Data<AppState>,
+    user: ReqData<JWTAuthMiddleware>,
+    Json(body): Json<SetFeatureFlagDTO>,
+) -> Result<HttpResponse, HttpError> {
+    let Some(feature) = Feature::from_key(&path.into_inner()) else {
+        return Ok(HttpError::not_found(ErrorMessage::FeatureFlagNotFound.to_string()).into_http_response());
+    };
+
+    let flag = app_state.db_client
+        .set_feature_flag(feature.as_str(), body.enabled, user.user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+    app_state.feature_flags.invalidate().await;
+
+    log::info!("Funcionalidad {} {} por {}", flag.key, if flag.enabled { "activada" } else { "desactivada" }, user.user.id);
+    Ok(ApiResponse::ok(FeatureFlagDto {
+        key: flag.key,
+        enabled: flag.enabled,
+        updated_by: flag.updated_by,
+        updated_at: Some(flag.updated_at),
+    }))
+}
@@ -1,43 +1,90 @@
 use actix_web::{ 
-    HttpMessage, HttpRequest, HttpResponse, cookie::{Cookie, SameSite}, get, post, put, web::{ Data, Json, Query}
+    HttpMessage, HttpRequest, HttpResponse, cookie::{Cookie, SameSite}, get, http::header, post, put, web::{ Data, Json, Query}
 };
-use std::sync::Arc;
 use validator::Validate;
-use crate::db::db::{CourseExt, UserAchievementExt, UserExt, CoursePurchaseExt, PasswordResetTokenExt};
+use crate::db::db::{CourseExt, UserAchievementExt, UserExt, CoursePurchaseExt, PasswordReset, PasswordResetTokenExt, InviteExt};
 use serde_json::{json};
 use chrono::{ Duration, Utc };
 use uuid::Uuid;
 use crate::mail::mails::{ send_verification_email, send_welcome_email, send_forgot_password_email };
 use crate::utils::password::{hash_password, verify_password};
-use crate::utils::token::create_token_rsa;
+use crate::utils::token::{create_token_rsa, hash_token};
+use crate::utils::redirect::safe_redirect;
+use crate::services::google_oauth;
+use crate::services::feature_flags::{Feature, feature_gate};
 use crate::errors::error::{ ErrorMessage, HttpError };
-use crate::middleware::middleware::JWTAuthMiddleware;  
-use crate::config::dtos::{ RegisterDTO, LoginDTO, Response , UserLoginResponseDto, ResetPasswordRequestDTO, FilterUserDto, UserProfileResponse, UserProfileData, FilterAchievementDto, UpdateUserProfileDto, VerifyEmailQueryDTO, ForgotPasswordRequestDTO, FilterCourseDto };
+use crate::middleware::middleware::JWTAuthMiddleware;
+use crate::config::config::{AUTH_PREFIX, auth_path};
+use crate::config::dtos::{ ApiResponse, RegisterDTO, LoginDTO, ResetPasswordRequestDTO, FilterUserDto, UserData, UserProfileData, MessageDto, FilterAchievementDto, UpdateUserProfileDto, VerifyEmailQueryDTO, GoogleCallbackQueryDTO, GoogleLoginQueryDTO, AcceptInviteDTO, ForgotPasswordRequestDTO, FilterCourseDto, UserCourseDto };
 use crate::AppState;
+use crate::config::config::Config;
+use crate::models::models::{TriggerType, User, UserRole};
 
 
+/// Cookie de sesión con el JWT: caduca a la vez que el token (`jwt_maxage` en segundos).
+/// Todas las rutas que inician sesión la construyen aquí para no divergir en atributos.
+pub(crate) fn build_session_cookie(token: String, config: &Config) -> Cookie<'static> {
+    session_cookie(token, time::Duration::seconds(config.jwt_maxage), config)
+}
+
+/// Misma cookie de sesión, vacía y caducada, para cerrar sesión.
+pub(crate) fn clear_session_cookie(config: &Config) -> Cookie<'static> {
+    session_cookie(String::new(), time::Duration::seconds(0), config)
+}
+
+/// Cookie con el refresh token opaco: dura `refresh_token_maxage` segundos, mucho más
+/// que el JWT, y solo sirve para pedir uno nuevo en `POST /auth/refresh`. El navegador
+/// solo la envía bajo `AUTH_PREFIX` (refresh y logout), nunca al resto de la API.
+pub(crate) fn build_refresh_cookie(token: String, config: &Config) -> Cookie<'static> {
+    refresh_cookie(token, time::Duration::seconds(config.refresh_token_maxage), config)
+}
+
+pub(crate) fn clear_refresh_cookie(config: &Config) -> Cookie<'static> {
+    refresh_cookie(String::new(), time::Duration::seconds(0), config)
+}
+
+pub(crate) const REFRESH_COOKIE: &str = "refresh_token";
+
+fn session_cookie(value: String, max_age: time::Duration, config: &Config) -> Cookie<'static> {
+    cookie("token", value, "/", max_age, config)
+}
+
+fn refresh_cookie(value: String, max_age: time::Duration, config: &Config) -> Cookie<'static> {
+    cookie(REFRESH_COOKIE, value, AUTH_PREFIX, max_age, config)
+}
+
+fn cookie(name: &'static str, value: String, path: &'static str, max_age: time::Duration, config: &Config) -> Cookie<'static> {
+    Cookie::build(name, value)
+        .path(path)
+        .max_age(max_age)
+        .http_only(true)
+        .secure(config.cookie_secure)
+        .same_site(config.cookie_same_site)
+        .finish()
+}
+
 #[get("/mycourses")]
 pub async fn get_user_courses_api(
-    app_state: Data<Arc<AppState>>,
+    app_state: Data<AppState>,
     req: HttpRequest,
 ) -> Result<HttpResponse, HttpError> {
     let extensions = req.extensions();
     let user_data = extensions
         .get::<JWTAuthMiddleware>()
-        .ok_or_else(|| HttpError::unauthorized("Usuario no autenticado".to_string()))?;
+        .ok_or_else(|| HttpError::unauthorized(ErrorMessage::UserNotAuthenticated.to_string()))?;
 
     let user_id = user_data.user.id;
 
-    let courses = app_state.db_client.get_user_purchased_courses(user_id)
+    // Los premium ven todo el catálogo, igual que lo que les permite `AnyCourseAccess`
+    let courses = app_state.db_client.get_accessible_course_ids(user_id, app_state.env.premium_grants_all_courses)
         .await
         .map_err(|e| {
-            log::error!("Error al obtener cursos comprados: {}", e);
+            log::error!("Error al obtener cursos accesibles: {}", e);
             HttpError::server_error(e.to_string())
         })?;
 
     // Devolver un objeto JSON con la estructura esperada
-    Ok(HttpResponse::Ok().json(json!({
-        "success": true,
+    Ok(ApiResponse::ok(json!({
         "courseIds": courses
     })))
 }
@@ -49,7 +96,7 @@ pub async fn get_user_courses_api(
 /// Registrar usuario
 #[post("/register")]
 pub async fn register_user(
-    app_state: Data<Arc<AppState>>,
+    app_state: Data<AppState>,
     Json(body): Json<RegisterDTO>
 ) -> Result<HttpResponse, HttpError> {
     body.validate()
@@ -66,25 +113,16 @@ pub async fn register_user(
 
     match result {
         Ok(user) => {
-            let send_email_result = send_verification_email(&body.email, &body.name, &verification_token).await;
+            let send_email_result = send_verification_email(&body.email, &body.name, &verification_token, &app_state.env.api_base_url).await;
 
             if let Err(e) = send_email_result {
                return Err(HttpError::server_error(format!("Ocurrio un error: {}", e)))
             }
-            let token = create_token_rsa(user.id, user.role,None, &app_state.env.encoding_key, app_state.env.jwt_maxage)
-            .map_err(|e| HttpError::server_error(e.to_string()))?;
-            Ok(HttpResponse::Created().cookie(
-                Cookie::build("token", token.clone())
-                .path("/")
-                .max_age(time::Duration::minutes(app_state.env.jwt_maxage * 60))
-                .http_only(true)
-                .secure(true)
-                .same_site(SameSite::None)
-                .finish()
-                ).json(Response {
-                status: "success",
-                message: "Usuario registrado exitosamente. Por favor, verifica tu email.".to_string()
-            }))
+            let (session, refresh) = issue_session_cookies(&app_state, user.id, user.role).await?;
+            with_cookies(
+                ApiResponse::created(MessageDto::new("Usuario registrado exitosamente. Por favor, verifica tu email.")),
+                [session, refresh],
+            )
         },
         Err(sqlx::Error::Database(db_err)) => {
             if db_err.is_unique_violation() {
@@ -99,9 +137,51 @@ pub async fn register_user(
     }
 }
 
+/// Abre la sesión: JWT y refresh token con sus cookies. Todas las formas de entrar
+/// (registro, login, verificación, Google, invitación y renovación) pasan por aquí.
+/// Del refresh token solo se guarda el hash, igual que con los tokens de restablecimiento.
+async fn issue_session_cookies(app_state: &AppState, user_id: Uuid, role: UserRole) -> Result<(Cookie<'static>, Cookie<'static>), HttpError> {
+    let token = create_token_rsa(user_id, role, None, &app_state.env.encoding_key, app_state.env.jwt_maxage)
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+    let refresh_token = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+    let expires_at = Utc::now() + Duration::seconds(app_state.env.refresh_token_maxage);
+    app_state.db_client
+        .save_refresh_token(user_id, &hash_token(&refresh_token), expires_at)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+    Ok((
+        build_session_cookie(token, &app_state.env),
+        build_refresh_cookie(refresh_token, &app_state.env),
+    ))
+}
+
+/// Añade las cookies de sesión a una respuesta ya construida con `ApiResponse`.
+fn with_cookies(mut res: HttpResponse, cookies: [Cookie<'_>; 2]) -> Result<HttpResponse, HttpError> {
+    for cookie in &cookies {
+        res.add_cookie(cookie)
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+    }
+    Ok(res)
+}
+
+/// Respuesta de las rutas que abren sesión: las cookies y el usuario dentro del envoltorio.
+fn session_response(res: fn(UserData) -> HttpResponse, user: &User, cookies: [Cookie<'_>; 2]) -> Result<HttpResponse, HttpError> {
+    with_cookies(res(UserData { user: FilterUserDto::filter_user(user) }), cookies)
+}
+
+/// Guarda `last_login_at` en segundo plano para no retrasar la respuesta del login.
+fn record_login(app_state: &Data<AppState>, user_id: Uuid) {
+    let db = app_state.db_client.clone();
+    actix_web::rt::spawn(async move {
+        if let Err(e) = db.touch_last_login(user_id).await {
+            log::warn!("No se pudo registrar el último inicio de sesión de {}: {}", user_id, e);
+        }
+    });
+}
+
 /// Login usuario
 #[post("/login")]
-pub async fn login_user(app_state: Data<Arc<AppState>>, Json(body): Json<LoginDTO>) -> Result<HttpResponse, HttpError> {
+pub async fn login_user(app_state: Data<AppState>, Json(body): Json<LoginDTO>) -> Result<HttpResponse, HttpError> {
 
     body.validate()
        .map_err(|e| HttpError::bad_request(e.to_string()))?;
@@ -110,32 +190,21 @@ pub async fn login_user(app_state: Data<Arc<AppState>>, Json(body): Json<LoginDT
         .get_user(None, None, Some(&body.email), None)
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?
-        .ok_or_else(|| HttpError::bad_request("Usuario no encontrado".to_string()))?;
+        .ok_or_else(|| HttpError::bad_request(ErrorMessage::UserNotFound.to_string()))?;
 
-    if verify_password(&body.password, &user.password)
+    if verify_password(&body.password, user.password.as_deref())
         .map_err(|_| HttpError::bad_request(ErrorMessage::WrongCredentials.to_string()))? {
-        let token = create_token_rsa(user.id, user.role,None, &app_state.env.encoding_key, app_state.env.jwt_maxage)
-            .map_err(|e| HttpError::server_error(e.to_string()))?;
+        if user.locked {
+            return Err(HttpError::bad_request(ErrorMessage::UserLocked.to_string()));
+        }
+        let (session, refresh) = issue_session_cookies(&app_state, user.id, user.role).await?;
+        record_login(&app_state, user.id);
         // Incrementar contador de logins
         let _ = app_state.db_client.increment_user_stat(user.id, "login_streak").await;
         // Verificar logros de racha de logins
-        let _ = app_state.db_client.check_and_award_achievements(user.id, "login_streak", Some(1)).await;
-
-        Ok(
-            HttpResponse::Ok()
-            .cookie(
-                Cookie::build("token", token.clone())
-                .path("/")
-                .max_age(time::Duration::minutes(app_state.env.jwt_maxage * 60))
-                .http_only(true)
-                .secure(true) 
-                .same_site(SameSite::None)
-                .finish()
-                ).json(UserLoginResponseDto {
-                    status: "success".to_string(),
-                }
-            )
-        )
+        let _ = app_state.db_client.check_and_award_achievements(user.id, TriggerType::LoginStreak).await;
+
+        session_response(ApiResponse::ok, &user, [session, refresh])
     } 
     else {
         Err(HttpError::bad_request(ErrorMessage::WrongCredentials.to_string()))
@@ -143,23 +212,73 @@ pub async fn login_user(app_state: Data<Arc<AppState>>, Json(body): Json<LoginDT
 }
 
 #[post("/logout")]
-pub async fn logout_user() -> HttpResponse {
-    HttpResponse::Ok()
-        .cookie(
-            Cookie::build("token", "")
-                .path("/")
-                .max_age(time::Duration::seconds(0))
-                .http_only(true)
-                .secure(true)
-                .same_site(SameSite::None)
-                .finish()
-        )
-        .json(serde_json::json!({ "status": "success", "message": "Sesión cerrada" }))
+pub async fn logout_user(req: HttpRequest, app_state: Data<AppState>) -> Result<HttpResponse, HttpError> {
+    // Sin revocarlo, el refresh token robado seguiría abriendo sesiones
+    if let Some(refresh) = req.cookie(REFRESH_COOKIE)
+        && let Err(e) = app_state.db_client.revoke_refresh_token(&hash_token(refresh.value())).await
+    {
+        log::warn!("No se pudo revocar el refresh token al cerrar sesión: {}", e);
+    }
+
+    with_cookies(
+        ApiResponse::ok(MessageDto::new("Sesión cerrada")),
+        [clear_session_cookie(&app_state.env), clear_refresh_cookie(&app_state.env)],
+    )
+}
+
+/// Renueva la sesión con la cookie `refresh_token`: la revoca, emite otra (rotación) y
+/// devuelve un JWT nuevo. Un token revocado, caducado o desconocido responde `401`.
+#[post("/refresh")]
+pub async fn refresh_session(req: HttpRequest, app_state: Data<AppState>) -> Result<HttpResponse, HttpError> {
+    let unauthorized = |message: ErrorMessage| Ok(
+        HttpError::unauthorized(message.to_string())
+            .into_http_response()
+    );
+
+    let Some(refresh) = req.cookie(REFRESH_COOKIE) else {
+        return unauthorized(ErrorMessage::TokenNotProvided);
+    };
+    let token_hash = hash_token(refresh.value());
+
+    let stored = app_state.db_client
+        .get_refresh_token(&token_hash)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+    let Some(stored) = stored.filter(|t| !t.revoked && t.expires_at > Utc::now()) else {
+        return unauthorized(ErrorMessage::InvalidRefreshToken);
+    };
+
+    // Si otra petición lo rotó a la vez, esta llega tarde y ya está revocado
+    let claimed = app_state.db_client
+        .revoke_refresh_token(&token_hash)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+    if !claimed {
+        return unauthorized(ErrorMessage::InvalidRefreshToken);
+    }
+
+    let user = app_state.db_client
+        .get_user(Some(stored.user_id), None, None, None)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+    let Some(user) = user else {
+        return unauthorized(ErrorMessage::UserNoLongerExist);
+    };
+    if user.locked {
+        return unauthorized(ErrorMessage::UserLocked);
+    }
+
+    let (session, refresh) = issue_session_cookies(&app_state, user.id, user.role).await?;
+
+    session_response(ApiResponse::ok, &user, [session, refresh])
 }
 
 
+/// Verifica el correo. Desde un navegador redirige al frontend (o a `?redirect=` si su
+/// origen está en `allowed_redirect_origins`) con `?verified=true`;
+/// los clientes que envían `Accept: application/json` reciben la respuesta JSON.
 #[get("/verify")]
-pub async fn verify_email(Query(query_params): Query<VerifyEmailQueryDTO>, app_state: Data<Arc<AppState>>) -> Result<HttpResponse, HttpError> {
+pub async fn verify_email(req: HttpRequest, Query(query_params): Query<VerifyEmailQueryDTO>, app_state: Data<AppState>) -> Result<HttpResponse, HttpError> {
     query_params.validate()
         .map_err(|e| HttpError::bad_request(e.to_string()))?;
 
@@ -170,10 +289,10 @@ pub async fn verify_email(Query(query_params): Query<VerifyEmailQueryDTO>, app_s
 
     if let Some(expires_at) = user.token_expiry {
         if Utc::now() > expires_at {
-            return Err(HttpError::bad_request("Verificacion del token ha expirado.".to_string()));
+            return Err(HttpError::bad_request(ErrorMessage::VerificationTokenExpired.to_string()));
         }
     } else {
-        return Err(HttpError::bad_request("token inválido.".to_string()));
+        return Err(HttpError::bad_request(ErrorMessage::InvalidVerificationToken.to_string()));
     }
 
     app_state.db_client
@@ -185,30 +304,178 @@ pub async fn verify_email(Query(query_params): Query<VerifyEmailQueryDTO>, app_s
         return Err(HttpError::server_error(format!("Ocurrio un error: {}", e)))
     }
 
-    let token = create_token_rsa(user.id, user.role, None,&app_state.env.encoding_key, app_state.env.jwt_maxage)
+    let (session, refresh) = issue_session_cookies(&app_state, user.id, user.role).await?;
+
+    if wants_json(&req) {
+        return session_response(ApiResponse::ok, &user, [session, refresh]);
+    }
+
+    let target = safe_redirect(
+        query_params.redirect.as_deref(),
+        &app_state.env.allowed_redirect_origins,
+        &app_state.env.verify_redirect_url,
+    );
+    let mut redirect = reqwest::Url::parse(&target)
         .map_err(|e| HttpError::server_error(e.to_string()))?;
+    redirect.query_pairs_mut().append_pair("verified", "true");
 
     Ok(
-            HttpResponse::Ok()
-            .cookie(
-                Cookie::build("token", token.clone())
-                .path("/settings")
-                .max_age(time::Duration::minutes(app_state.env.jwt_maxage * 60))
-                .http_only(true)
-                .secure(true) 
-                .same_site(SameSite::None)
-                .finish()
-                ).json(UserLoginResponseDto {
-                    status: "success".to_string(),
-                }
-            )
-        )
+        HttpResponse::Found()
+            .cookie(session)
+            .cookie(refresh)
+            .insert_header((header::LOCATION, redirect.to_string()))
+            .finish()
+    )
+}
+
+/// Ruta de la cookie `oauth_state`: la carpeta del callback configurado, para que la
+/// cookie viaje de vuelta tanto con `/api/v1/auth` como con el alias `/auth`.
+pub(crate) fn oauth_state_cookie_path(redirect_url: &str) -> String {
+    reqwest::Url::parse(redirect_url)
+        .ok()
+        .and_then(|url| url.path().rsplit_once('/').map(|(dir, _)| dir.to_string()))
+        .filter(|dir| !dir.is_empty())
+        .unwrap_or_else(|| auth_path("/google"))
+}
+
+/// Inicia el inicio de sesión con Google. El `state` se guarda en una cookie
+/// y se compara en el callback para evitar CSRF; `?redirect=`, si su origen está
+/// permitido, se guarda también para volver ahí al terminar.
+#[get("/google")]
+pub async fn google_login(Query(query_params): Query<GoogleLoginQueryDTO>, app_state: Data<AppState>) -> HttpResponse {
+    if let Some(disabled) = feature_gate(&app_state, Feature::GoogleLogin).await {
+        return disabled;
+    }
+    let state = Uuid::new_v4().to_string();
+    let url = google_oauth::authorization_url(
+        &app_state.env.google_client_id,
+        &app_state.env.google_redirect_url,
+        &state,
+    );
+
+    let return_to = safe_redirect(
+        query_params.redirect.as_deref(),
+        &app_state.env.allowed_redirect_origins,
+        &app_state.env.frontend_base_url,
+    );
+
+    HttpResponse::Found()
+        .cookie(oauth_cookie("oauth_state", state, time::Duration::minutes(10), &app_state.env))
+        .cookie(oauth_cookie("oauth_redirect", return_to, time::Duration::minutes(10), &app_state.env))
+        .insert_header((header::LOCATION, url))
+        .finish()
 }
 
+/// Cookies del flujo de Google. Lax: tienen que viajar en la redirección de vuelta desde Google.
+fn oauth_cookie(name: &'static str, value: String, max_age: time::Duration, config: &Config) -> Cookie<'static> {
+    Cookie::build(name, value)
+        .path(oauth_state_cookie_path(&config.google_redirect_url))
+        .max_age(max_age)
+        .http_only(true)
+        .secure(config.cookie_secure)
+        .same_site(SameSite::Lax)
+        .finish()
+}
+
+/// Callback de Google: crea o vincula la cuenta por correo y abre la sesión igual que `login_user`
+#[get("/google/callback")]
+pub async fn google_callback(req: HttpRequest, Query(query_params): Query<GoogleCallbackQueryDTO>, app_state: Data<AppState>) -> Result<HttpResponse, HttpError> {
+    if let Some(disabled) = feature_gate(&app_state, Feature::GoogleLogin).await {
+        return Ok(disabled);
+    }
+    let expected_state = req.cookie("oauth_state").map(|c| c.value().to_string());
+    match (&query_params.state, &expected_state) {
+        (Some(state), Some(expected)) if state == expected => {}
+        _ => return Err(HttpError::bad_request(ErrorMessage::InvalidOAuthState.to_string())),
+    }
+
+    if let Some(error) = &query_params.error {
+        log::warn!("Google rechazó el inicio de sesión: {}", error);
+        return Err(HttpError::bad_request(ErrorMessage::OAuthProviderError.to_string()));
+    }
+    let code = query_params.code
+        .ok_or_else(|| HttpError::bad_request(ErrorMessage::OAuthProviderError.to_string()))?;
+
+    let profile = google_oauth::fetch_profile(
+        &app_state.client,
+        &app_state.env.google_client_id,
+        &app_state.env.google_client_secret,
+        &app_state.env.google_redirect_url,
+        &code,
+    )
+    .await
+    .map_err(|e| {
+        log::error!("Error al obtener el perfil de Google: {}", e);
+        HttpError::bad_gateway(ErrorMessage::OAuthProviderError.to_string())
+    })?;
+
+    // Sin correo verificado cualquiera podría apropiarse de una cuenta existente
+    if !profile.email_verified {
+        return Err(HttpError::bad_request(ErrorMessage::OAuthEmailNotVerified.to_string()));
+    }
+
+    let email = profile.email.to_lowercase();
+    let name = profile.name
+        .filter(|n| !n.trim().is_empty())
+        .unwrap_or_else(|| email.split('@').next().unwrap_or_default().to_string());
+
+    let (user, _created) = app_state.db_client
+        .save_or_get_user_by_email(&name, &email, None, "google", true, None)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if user.locked {
+        return Err(HttpError::bad_request(ErrorMessage::UserLocked.to_string()));
+    }
+
+    // Si el correo ya tenía cuenta con contraseña se vincula en lugar de duplicarla
+    let linked = app_state.db_client
+        .link_auth_provider(user.id, "google", &profile.sub)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+    if !linked {
+        return Err(HttpError::unique_constraint_violation(ErrorMessage::OAuthAccountConflict.to_string()));
+    }
+
+    let (session, refresh) = issue_session_cookies(&app_state, user.id, user.role).await?;
+    record_login(&app_state, user.id);
+    let _ = app_state.db_client.increment_user_stat(user.id, "login_streak").await;
+    let _ = app_state.db_client.check_and_award_achievements(user.id, TriggerType::LoginStreak).await;
+
+    // La cookie la puede alterar el cliente: el destino se valida otra vez
+    let return_to = safe_redirect(
+        req.cookie("oauth_redirect").as_ref().map(|c| c.value()),
+        &app_state.env.allowed_redirect_origins,
+        &app_state.env.frontend_base_url,
+    );
+
+    Ok(
+        HttpResponse::Found()
+            .cookie(session)
+            .cookie(refresh)
+            .cookie(oauth_cookie("oauth_state", String::new(), time::Duration::seconds(0), &app_state.env))
+            .cookie(oauth_cookie("oauth_redirect", String::new(), time::Duration::seconds(0), &app_state.env))
+            .insert_header((header::LOCATION, return_to))
+            .finish()
+    )
+}
+
+fn wants_json(req: &HttpRequest) -> bool {
+    req.headers()
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("application/json"))
+}
+
+
+/// Enlace a la página de restablecimiento del frontend; el token en claro solo viaja en él.
+pub(crate) fn password_reset_link(frontend_base_url: &str, token: &str) -> String {
+    format!("{}/reset-password?token={}", frontend_base_url, token)
+}
 
 #[post("/forgot-password")]
 pub async fn forgot_password(
-    app_state: Data<Arc<AppState>>,
+    app_state: Data<AppState>,
     Json(body): Json<ForgotPasswordRequestDTO>
 ) -> Result<HttpResponse, HttpError> {
     body.validate()
@@ -217,12 +484,12 @@ pub async fn forgot_password(
     let user = app_state.db_client
         .get_user(None, None, Some(&body.email), None)
         .await
-        .map_err(|e| HttpError::server_error(e.to_string()))?.ok_or(HttpError::bad_request("Email no encontrado.".to_string()))?;
+        .map_err(|e| HttpError::server_error(e.to_string()))?.ok_or(HttpError::bad_request(ErrorMessage::EmailNotFound.to_string()))?;
 
     let reset_token = Uuid::new_v4().to_string();
-    let token_hash = hash_password(&reset_token)
-        .map_err(|e| HttpError::server_error(e.to_string()))?;
-    let expires_at = Utc::now() + Duration::minutes(30);
+    // Hash determinista: `reset_password` busca el token por su hash
+    let token_hash = hash_token(&reset_token);
+    let expires_at = Utc::now() + Duration::minutes(app_state.env.password_reset_expiry_minutes);
 
     let user_id = Uuid::parse_str(&user.id.to_string()).unwrap();
 
@@ -238,7 +505,7 @@ pub async fn forgot_password(
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
-    let reset_link = format!("http://localhost:8000/reset-password?token={}", &reset_token);
+    let reset_link = password_reset_link(&app_state.env.frontend_base_url, &reset_token);
 
     let send_email_result = send_forgot_password_email(&user.email, &reset_link, &user.name).await;
 
@@ -246,56 +513,82 @@ pub async fn forgot_password(
         return Err(HttpError::server_error(format!("No se pudo enviar el email de restablecimiento de contraseña. Erro :{}", e)));
     }
 
-    Ok(HttpResponse::Ok().json(Response {
-        status: "success",
-        message: "Se ha enviado un enlace de restablecimiento de contraseña a su correo electrónico.".to_string()
-    }))
+    Ok(ApiResponse::ok(MessageDto::new(
+        "Se ha enviado un enlace de restablecimiento de contraseña a su correo electrónico."
+    )))
 }
 
 
+/// Error para un restablecimiento que no se pudo completar; `None` si se completó.
+pub(crate) fn password_reset_error(outcome: &PasswordReset) -> Option<HttpError> {
+    let message = match outcome {
+        PasswordReset::Reset(_) => return None,
+        PasswordReset::InvalidToken => ErrorMessage::InvalidResetToken,
+        PasswordReset::Expired => ErrorMessage::ResetTokenExpired,
+        PasswordReset::AlreadyUsed => ErrorMessage::ResetTokenAlreadyUsed,
+    };
+    Some(HttpError::bad_request(message.to_string()))
+}
+
 #[post("/reset-password")]
-pub async fn reset_password(app_state: Data<Arc<AppState>>, Json(body): Json<ResetPasswordRequestDTO>) -> Result<HttpResponse, HttpError> {
+pub async fn reset_password(app_state: Data<AppState>, Json(body): Json<ResetPasswordRequestDTO>) -> Result<HttpResponse, HttpError> {
     body.validate()
         .map_err(|e| HttpError::bad_request(e.to_string()))?;
 
-    let token_hash = hash_password(&body.token)
+    let new_password_hash = hash_password(&body.new_password)
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
-    let reset_token = app_state.db_client
-        .get_password_reset_token(&token_hash)
+    // El token solo vale una vez: se reclama en la misma transacción que cambia la contraseña
+    let outcome = app_state.db_client
+        .reset_password_with_token(&hash_token(&body.token), &new_password_hash)
         .await
-        .map_err(|e| HttpError::server_error(e.to_string()))?.ok_or(HttpError::bad_request("Token inválido o expirado.".to_string()))?;
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+    if let Some(err) = password_reset_error(&outcome) {
+        return Err(err);
+    }
 
-    let user_id = reset_token.user_id;
+    Ok(ApiResponse::ok(MessageDto::new("Contraseña restablecida exitosamente.")))
+}
+
+/// Crea la cuenta a partir de una invitación: el correo y el rol los fija la
+/// invitación y la cuenta queda verificada.
+#[post("/accept-invite")]
+pub async fn accept_invite(app_state: Data<AppState>, Json(body): Json<AcceptInviteDTO>) -> Result<HttpResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
 
-    let _ = app_state.db_client
-        .get_user(Some(user_id), None, None, None)
+    let invite = app_state.db_client
+        .get_valid_invite(&hash_token(&body.token))
         .await
-        .map_err(|e| HttpError::server_error(e.to_string()))?.ok_or(HttpError::bad_request("Usuario no encontrado.".to_string()))?;
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or_else(|| HttpError::bad_request(ErrorMessage::InvalidInvite.to_string()))?;
 
-    let new_password_hash = hash_password(&body.new_password)
+    let existing = app_state.db_client
+        .get_user(None, None, Some(&invite.email), None)
+        .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
+    if existing.is_some() {
+        return Err(HttpError::unique_constraint_violation(ErrorMessage::EmailExist.to_string()));
+    }
 
-    app_state.db_client
-        .update_user_password(user_id, new_password_hash)
-        .await
+    let password_hash = hash_password(&body.password)
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
-    app_state.db_client
-        .mark_token_used(&token_hash)
+    let user = app_state.db_client
+        .accept_invite(invite.id, &body.name, &password_hash)
         .await
-        .map_err(|e| HttpError::server_error(e.to_string()))?;
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or_else(|| HttpError::bad_request(ErrorMessage::InvalidInvite.to_string()))?;
 
-    Ok(HttpResponse::Ok().json(Response {
-        status: "success",
-        message: "Contraseña restablecida exitosamente.".to_string()
-    }))
- 
+    let (session, refresh) = issue_session_cookies(&app_state, user.id, user.role).await?;
+    record_login(&app_state, user.id);
+
+    session_response(ApiResponse::created, &user, [session, refresh])
 }
 
 /// Obtener perfil
 #[get("/profile")]
-pub async fn get_user_profile(req: HttpRequest, app_state: Data<Arc<AppState>>) -> Result<HttpResponse, HttpError> {
+pub async fn get_user_profile(req: HttpRequest, app_state: Data<AppState>) -> Result<HttpResponse, HttpError> {
     // Verifica si el middleware JWT añadió los datos del usuario autenticado
     match req.extensions().get::<JWTAuthMiddleware>() {
         Some(user_data) => {
@@ -308,6 +601,7 @@ pub async fn get_user_profile(req: HttpRequest, app_state: Data<Arc<AppState>>)
                 .map_err(|e| {
                     HttpError::server_error(e.to_string())
                 })?;
+            let courses = UserCourseDto::with_default_rating(courses, app_state.env.default_course_rating);
 
             let achievements = app_state.db_client
                 .get_user_achievements(user_id)
@@ -316,25 +610,20 @@ pub async fn get_user_profile(req: HttpRequest, app_state: Data<Arc<AppState>>)
                     HttpError::server_error(e.to_string())
                 })?;
 
-            let response = UserProfileResponse {
-                status: "success".into(),
-                data: UserProfileData {
-                    user: FilterUserDto::filter_user(&user_data.user),
-                    courses: FilterCourseDto::filter_courses(&courses),
-                    achievements,
-                }
-            };
-
-            Ok(HttpResponse::Ok().json(response))
+            Ok(ApiResponse::ok(UserProfileData {
+                user: FilterUserDto::filter_user(&user_data.user),
+                courses: FilterCourseDto::filter_courses(&courses),
+                achievements,
+            }))
         }
-        None => Err(HttpError::unauthorized("Usuario no autenticado".to_string())),
+        None => Err(HttpError::unauthorized(ErrorMessage::UserNotAuthenticated.to_string())),
     }
 }
 
 #[put("/users/profile")]
 pub async fn update_user_profile(
     req: HttpRequest,
-    app_state: Data<Arc<AppState>>,
+    app_state: Data<AppState>,
     body: Json<UpdateUserProfileDto>,
 ) -> Result<HttpResponse, HttpError> {
     match req.extensions().get::<JWTAuthMiddleware>() {
@@ -354,8 +643,8 @@ pub async fn update_user_profile(
                 .await
                 .map_err(|e| HttpError::server_error(e.to_string()))?;
 
-            Ok(HttpResponse::Ok().json(FilterUserDto::filter_user(&updated_user)))
+            Ok(ApiResponse::ok(FilterUserDto::filter_user(&updated_user)))
         }
-        None => Err(HttpError::unauthorized("Usuario no autenticado".to_string())),
+        None => Err(HttpError::unauthorized(ErrorMessage::UserNotAuthenticated.to_string())),
     }
 }
\ No newline at end of file
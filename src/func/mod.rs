@@ -4,4 +4,9 @@ pub mod courses;
 pub mod payments;
 pub mod achievements;
 pub mod subscriptions;
-pub mod notifications;
\ No newline at end of file
+pub mod notifications;
+pub mod dashboard;
+pub mod reports;
+pub mod feature_flags;
+pub mod stats;
+pub mod bundles;
@@ -1,8 +1,19 @@
-use actix_web::{web, HttpResponse, Result};
+use actix_web::{http::header, web, HttpRequest, HttpResponse, Result};
+use openssl::{hash::MessageDigest, memcmp, pkey::PKey, sign::Signer};
 use serde::{Deserialize};
 use uuid::Uuid;
-use crate::{AppState, errors::error::HttpError, db::db::NotificationExt};
-use std::sync::Arc;
+use validator::Validate;
+use crate::{
+    AppState,
+    config::dtos::{ApiResponse, MessageDto, BroadcastNotificationDTO, EmailBounceEventDto},
+    db::db::{BroadcastExt, EmailEventExt, NotificationExt, UserExt},
+    errors::error::{ErrorMessage, HttpError},
+    mail::{mails::send_campaign_email, sendmail::EmailTracking},
+    middleware::middleware::JWTAuthMiddleware,
+    models::models::{BroadcastSegment, BroadcastStatus},
+    services::broadcasts::send_broadcast_emails,
+    utils::{path_id::{BroadcastId, NotificationId}, redirect::safe_redirect},
+};
 
 // DTOs para notificaciones
 #[derive(Deserialize)]
@@ -20,7 +31,7 @@ pub struct MarkAsReadRequest {
 
 // Obtener notificaciones del usuario
 pub async fn get_notifications(
-    app_state: web::Data<Arc<AppState>>,
+    app_state: web::Data<AppState>,
     user_id: web::Path<Uuid>,
 ) -> Result<HttpResponse, HttpError> {
     let notifications = app_state.db_client
@@ -28,25 +39,25 @@ pub async fn get_notifications(
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
-    Ok(HttpResponse::Ok().json(notifications))
+    Ok(ApiResponse::ok(notifications))
 }
 
 // Marcar notificación como leída
 pub async fn mark_notification_as_read(
-    app_state: web::Data<Arc<AppState>>,
-    notification_id: web::Path<Uuid>,
+    app_state: web::Data<AppState>,
+    NotificationId(notification_id): NotificationId,
 ) -> Result<HttpResponse, HttpError> {
     app_state.db_client
-        .mark_notification_read(*notification_id)
+        .mark_notification_read(notification_id)
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
-    Ok(HttpResponse::Ok().json(serde_json::json!({"status": "success"})))
+    Ok(ApiResponse::ok(MessageDto::new("Notificación marcada como leída.")))
 }
 
 // Crear notificación (admin)
 pub async fn create_notification(
-    app_state: web::Data<Arc<AppState>>,
+    app_state: web::Data<AppState>,
     req: web::Json<CreateNotificationRequest>,
 ) -> Result<HttpResponse, HttpError> {
     let notification = app_state.db_client
@@ -54,5 +65,215 @@ pub async fn create_notification(
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
-    Ok(HttpResponse::Created().json(notification))
-}
\ No newline at end of file
+    // Las notificaciones por correo son de campaña: se envían con seguimiento salvo que el usuario lo haya desactivado
+    if req.sent_via == "email" {
+        let user = app_state.db_client
+            .get_user(Some(req.user_id), None, None, None)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?
+            .ok_or_else(|| HttpError::bad_request(ErrorMessage::UserNotFound.to_string()))?;
+
+        let bounced = app_state.db_client
+            .is_email_bounced(user.id)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+        if bounced {
+            log::info!("Correo de campaña omitido: el correo de {} rebota", user.id);
+            return Ok(ApiResponse::created(notification));
+        }
+
+        let tracking = app_state.db_client
+            .create_email_tracking(user.id, &req.title)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?
+            .map(|token| EmailTracking { api_base_url: app_state.env.api_base_url.clone(), token });
+
+        send_campaign_email(&user.email, &user.name, &req.title, &req.message, tracking.as_ref())
+            .await
+            .map_err(|e| HttpError::server_error(format!("Error sending notification email: {}", e)))?;
+    }
+
+    Ok(ApiResponse::created(notification))
+}
+
+/// Curso al que apunta un aviso: obligatorio para `course_students` y descartado en el resto.
+pub(crate) fn broadcast_course_id(segment: BroadcastSegment, course_id: Option<Uuid>) -> Result<Option<Uuid>, ErrorMessage> {
+    match segment {
+        BroadcastSegment::CourseStudents => course_id.map(Some).ok_or(ErrorMessage::BroadcastCourseRequired),
+        _ => Ok(None),
+    }
+}
+
+/// Aviso masivo a un segmento (admin). Las notificaciones in-app se crean al momento;
+/// los correos, si se piden, se envían en segundo plano y la respuesta es `202`.
+pub async fn broadcast_notification(
+    app_state: web::Data<AppState>,
+    user: web::ReqData<JWTAuthMiddleware>,
+    web::Json(body): web::Json<BroadcastNotificationDTO>,
+) -> Result<HttpResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+    let course_id = broadcast_course_id(body.segment, body.course_id)
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let broadcast = match app_state.db_client
+        .create_broadcast(user.user.id, body.segment, course_id, body.title.trim(), body.message.trim(), body.send_email)
+        .await
+    {
+        Ok(broadcast) => broadcast,
+        Err(sqlx::Error::RowNotFound) => {
+            return Ok(HttpError::not_found(ErrorMessage::CourseNotFound.to_string()).into_http_response());
+        }
+        Err(e) => return Err(HttpError::server_error(e.to_string())),
+    };
+
+    if broadcast.status != BroadcastStatus::Running {
+        return Ok(ApiResponse::created(broadcast));
+    }
+
+    actix_web::rt::spawn(send_broadcast_emails(
+        app_state.db_client.clone(),
+        app_state.env.api_base_url.clone(),
+        broadcast.clone(),
+    ));
+    Ok(ApiResponse::accepted(broadcast))
+}
+
+/// Estado y progreso de un aviso masivo (admin).
+pub async fn get_broadcast(
+    app_state: web::Data<AppState>,
+    BroadcastId(broadcast_id): BroadcastId,
+) -> Result<HttpResponse, HttpError> {
+    let broadcast = app_state.db_client
+        .get_broadcast(broadcast_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    match broadcast {
+        Some(broadcast) => Ok(ApiResponse::ok(broadcast)),
+        None => Ok(HttpError::not_found(ErrorMessage::BroadcastNotFound.to_string()).into_http_response()),
+    }
+}
+
+// GIF transparente de 1x1
+const TRACKING_PIXEL: &[u8] = &[
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+// Apertura de un correo de campaña: siempre responde el píxel
+pub async fn track_open(
+    app_state: web::Data<AppState>,
+    token: web::Path<Uuid>,
+) -> HttpResponse {
+    if let Err(e) = app_state.db_client.record_email_event(*token, "open", None).await {
+        log::error!("Error recording email open: {}", e);
+    }
+
+    HttpResponse::Ok()
+        .content_type("image/gif")
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .body(TRACKING_PIXEL)
+}
+
+#[derive(Deserialize)]
+pub struct TrackClickQuery {
+    pub u: String,
+}
+
+// Clic en un enlace de un correo de campaña. Solo se redirige a los orígenes de
+// `allowed_redirect_origins` para no servir de redirección abierta.
+pub async fn track_click(
+    app_state: web::Data<AppState>,
+    token: web::Path<Uuid>,
+    query: web::Query<TrackClickQuery>,
+) -> HttpResponse {
+    let target = safe_redirect(
+        Some(&query.u),
+        &app_state.env.allowed_redirect_origins,
+        &app_state.env.frontend_base_url,
+    );
+    if target == query.u.trim()
+        && let Err(e) = app_state.db_client.record_email_event(*token, "click", Some(&target)).await
+    {
+        log::error!("Error recording email click: {}", e);
+    }
+
+    HttpResponse::Found()
+        .insert_header((header::LOCATION, target))
+        .finish()
+}
+
+// Antigüedad máxima de la marca de tiempo firmada, para no aceptar webhooks reenviados
+const BOUNCE_SIGNATURE_TOLERANCE_SECS: i64 = 300;
+
+/// Verifica la firma del webhook de rebotes: HMAC-SHA256 en hex de `"{timestamp}.{body}"`
+/// con el secreto compartido. Sin secreto configurado se rechaza todo.
+pub(crate) fn verify_bounce_signature(secret: &str, timestamp: &str, body: &[u8], signature: &str, now: i64) -> bool {
+    if secret.is_empty() {
+        return false;
+    }
+    let Ok(sent_at) = timestamp.parse::<i64>() else {
+        return false;
+    };
+    if (now - sent_at).abs() > BOUNCE_SIGNATURE_TOLERANCE_SECS {
+        return false;
+    }
+
+    let expected = PKey::hmac(secret.as_bytes())
+        .and_then(|key| {
+            let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+            signer.update(timestamp.as_bytes())?;
+            signer.update(b".")?;
+            signer.update(body)?;
+            signer.sign_to_vec()
+        });
+    let Ok(expected) = expected else {
+        return false;
+    };
+    let expected: String = expected.iter().map(|b| format!("{:02x}", b)).collect();
+    let signature = signature.trim().to_ascii_lowercase();
+
+    expected.len() == signature.len() && memcmp::eq(expected.as_bytes(), signature.as_bytes())
+}
+
+// Webhook de rebotes y quejas del proveedor de correo
+pub async fn email_bounce_webhook(
+    app_state: web::Data<AppState>,
+    body: web::Bytes,
+    req: HttpRequest,
+) -> Result<HttpResponse, HttpError> {
+    let header_value = |name: &str| {
+        req.headers()
+            .get(name)
+            .and_then(|h| h.to_str().ok())
+            .map(str::to_string)
+            .ok_or_else(|| HttpError::bad_request(ErrorMessage::MissingWebhookHeader(name.to_string()).to_string()))
+    };
+    let timestamp = header_value("X-Webhook-Timestamp")?;
+    let signature = header_value("X-Webhook-Signature")?;
+
+    if !verify_bounce_signature(&app_state.env.bounce_webhook_secret, &timestamp, &body, &signature, chrono::Utc::now().timestamp()) {
+        return Err(HttpError::bad_request(ErrorMessage::InvalidBounceWebhook.to_string()));
+    }
+
+    let event: EmailBounceEventDto = serde_json::from_slice(&body)
+        .map_err(|e| HttpError::bad_request(format!("Invalid payload: {}", e)))?;
+
+    match event.event_type.as_str() {
+        "bounce" | "complaint" => {
+            let reason = event.reason.unwrap_or_else(|| event.event_type.clone());
+            let found = app_state.db_client
+                .mark_email_bounced(&event.email, &reason)
+                .await
+                .map_err(|e| HttpError::server_error(e.to_string()))?;
+            if !found {
+                log::info!("Rebote recibido para un correo desconocido");
+            }
+        }
+        other => log::info!("Unsupported email event type: {}", other),
+    }
+
+    Ok(HttpResponse::Ok().finish())
+}
@@ -1,44 +1,130 @@
-use std::{sync::Arc};
 use actix_web::{
-    HttpRequest, HttpResponse, post, get, web::{self, Data, Path, ReqData}
+    HttpRequest, HttpResponse, post, get, http::StatusCode, web::{self, Data, Path, ReqData}
 };
-use serde_json::{Value, json};
-use chrono::{Duration, Utc};
+use serde_json::{Value, json, value::RawValue};
+use validator::Validate;
+use chrono::{DateTime, Duration, Utc};
 use uuid::Uuid;
+use tokio::sync::{Mutex, RwLock};
 
 use crate::{
     AppState, 
     CachedToken, 
-    config::dtos::ProductDTO, 
-    db::db::{CourseExt, CoursePurchaseExt, SubscriptionExt}, 
-    errors::error::HttpError, 
-    middleware::middleware::JWTAuthMiddleware
+    config::dtos::{ApiResponse, CreatePaymentDTO, ProductDTO}, 
+    db::db::{AUDIT_MANUAL_PAYMENT, BundleExt, BundlePurchase, CourseExt, CoursePurchaseExt, NotificationExt, SubscriptionExt, UserExt, record_audit, register_course_purchase_in}, 
+    mail::mails::send_payment_failed_email,
+    errors::error::{ErrorMessage, HttpError}, 
+    middleware::middleware::JWTAuthMiddleware,
+    models::models::SubscriptionStatus,
+    config::config::api_path,
+    utils::{money::{Money, format_minor_units, minor_units_value, parse_minor_units, to_minor_units}, path_id::CourseId, redact::{redact, redact_json}}
 };
 
 // ===================== //
 //  Obtener token con cache
 // ===================== //
+const PAYPAL_TOKEN_ATTEMPTS: u32 = 3;
+const PAYPAL_TOKEN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+/// Tras un fallo no se vuelve a pedir token durante este tiempo
+pub(crate) const PAYPAL_TOKEN_FAILURE_COOLDOWN_SECS: i64 = 5;
+
+/// Espera antes del siguiente intento: 250ms, 500ms, 1s...
+pub(crate) fn paypal_token_backoff(attempt: u32) -> std::time::Duration {
+    std::time::Duration::from_millis(250 * 2u64.pow(attempt))
+}
+
 /// Obtiene un token de PayPal usando cache en memoria.
-/// - Usa RwLock para permitir múltiples lectores concurrentes
-/// - Evita I/O dentro del lock
-/// - Renueva el token solo cuando expira
-pub async fn get_paypal_token(state: &AppState) -> String {
+/// - Renueva el token solo cuando expira, reintentando fallos transitorios
+/// - Si PayPal no responde devuelve un 502 en lugar de tumbar el worker
+pub async fn get_paypal_token(state: &AppState) -> Result<String, HttpError> {
+    cached_token_or_refresh(&state.token_cache, &state.token_refresh, || fetch_paypal_token(state)).await
+}
+
+/// Devuelve el token en cache o lo renueva con `fetch`.
+/// - La cache se consulta con locks de lectura/escritura cortos, nunca durante la petición de red
+/// - `refresh_lock` hace de single-flight: si varias peticiones encuentran el token caducado,
+///   solo la primera llama a `fetch` y el resto espera y reutiliza su resultado
+/// - Si `fetch` falla, el lock guarda hasta cuándo no reintentar: quienes esperaban
+///   (y quienes lleguen durante `PAYPAL_TOKEN_FAILURE_COOLDOWN_SECS`) reciben el 502 sin repetir la petición
+pub(crate) async fn cached_token_or_refresh<F, Fut>(
+    cache: &RwLock<Option<CachedToken>>,
+    refresh_lock: &Mutex<Option<DateTime<Utc>>>,
+    fetch: F,
+) -> Result<String, HttpError>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<(String, i64), HttpError>>,
+{
     // =========================
     // 1️⃣ PRIMER CHECK (lectura concurrente, rápido)
     // =========================
-    {
-        let cache = state.token_cache.read().await;
+    if let Some(token) = valid_cached_token(cache).await {
+        return Ok(token);
+    }
+
+    // =========================
+    // 2️⃣ Guard de renovación: solo un request sale a PayPal
+    // =========================
+    let mut retry_after = refresh_lock.lock().await;
+
+    // Quien tenía el guard antes pudo haber renovado el token mientras esperábamos
+    if let Some(token) = valid_cached_token(cache).await {
+        return Ok(token);
+    }
+
+    // ...o haber fallado hace un momento: se comparte ese fallo en lugar de repetirlo
+    if retry_after.is_some_and(|until| until > Utc::now()) {
+        return Err(HttpError::bad_gateway(ErrorMessage::PayPalUnavailable.to_string()));
+    }
+
+    // =========================
+    // 3️⃣ Solicitar nuevo token (sin lock de la cache)
+    // =========================
+    let (access_token, expires_in) = match fetch().await {
+        Ok(token) => token,
+        Err(e) => {
+            *retry_after = Some(Utc::now() + Duration::seconds(PAYPAL_TOKEN_FAILURE_COOLDOWN_SECS));
+            return Err(e);
+        }
+    };
+    *retry_after = None;
+
+    *cache.write().await = Some(CachedToken {
+        access_token: access_token.clone(),
+        expires_at: Utc::now() + Duration::seconds(expires_in - 60),
+    });
 
-        if let Some(cached) = cache.as_ref() {
-            if cached.is_valid() {
-                return cached.access_token.clone();
+    Ok(access_token)
+}
+
+async fn valid_cached_token(cache: &RwLock<Option<CachedToken>>) -> Option<String> {
+    cache.read().await
+        .as_ref()
+        .filter(|cached| cached.is_valid())
+        .map(|cached| cached.access_token.clone())
+}
+
+/// Pide un token nuevo a PayPal con reintentos y backoff
+async fn fetch_paypal_token(state: &AppState) -> Result<(String, i64), HttpError> {
+    let mut attempt = 0;
+    loop {
+        match request_paypal_token(state).await {
+            Ok(token) => return Ok(token),
+            Err((e, retryable)) => {
+                attempt += 1;
+                if !retryable || attempt >= PAYPAL_TOKEN_ATTEMPTS {
+                    log::error!("No se pudo obtener el token de PayPal tras {} intento(s): {}", attempt, e);
+                    return Err(HttpError::bad_gateway(ErrorMessage::PayPalUnavailable.to_string()));
+                }
+                log::warn!("Fallo al obtener el token de PayPal (intento {}): {}", attempt, e);
+                actix_web::rt::time::sleep(paypal_token_backoff(attempt - 1)).await;
             }
         }
-    } // 🔓 el lock de lectura se libera aquí
+    }
+}
 
-     // =========================
-    // 2️⃣ Solicitar nuevo token (SIN lock)
-    // =========================
+/// Un solo intento contra `/v1/oauth2/token`. El `bool` del error indica si vale la pena reintentar.
+async fn request_paypal_token(state: &AppState) -> Result<(String, i64), (String, bool)> {
     let resp = state.client
         .post(format!("{}/v1/oauth2/token", state.env.paypal_api_mode))
         .basic_auth(
@@ -46,54 +132,39 @@ pub async fn get_paypal_token(state: &AppState) -> String {
             Some(&state.env.paypal_secret)
         )
         .form(&[("grant_type", "client_credentials")])
+        .timeout(PAYPAL_TOKEN_TIMEOUT)
         .send()
         .await
-        .expect("Error solicitando token PayPal");
+        .map_err(|e| (e.to_string(), true))?;
+
+    let status = resp.status();
+    if !status.is_success() {
+        // Credenciales inválidas no se arreglan reintentando
+        let retryable = status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS;
+        return Err((format!("PayPal respondió {}", status), retryable));
+    }
 
     let json: serde_json::Value = resp
         .json()
         .await
-        .expect("Error parseando JSON de token PayPal");
+        .map_err(|e| (e.to_string(), true))?;
 
     let access_token = json["access_token"]
         .as_str()
-        .expect("No se encontró access_token")
+        .ok_or_else(|| ("No se encontró access_token".to_string(), false))?
         .to_string();
 
     let expires_in = json["expires_in"].as_i64().unwrap_or(3600);
 
-    let new_token = CachedToken {
-        access_token: access_token.clone(),
-        expires_at: Utc::now() + Duration::seconds(expires_in - 60),
-    };
-
-    // ==================================================
-    // 3️⃣ SEGUNDO CHECK (lock de escritura corto)
-    // ==================================================
-    {
-        let mut cache = state.token_cache.write().await;
-
-        // Otro request pudo haber renovado el token
-        if let Some(cached) = cache.as_ref() {
-            if cached.is_valid() {
-                return cached.access_token.clone();
-            }
-        }
-
-        // Nadie lo renovó → este request lo guarda
-        *cache = Some(new_token);
-    }
-
-
-    access_token
+    Ok((access_token, expires_in))
 }
 
 
 pub async fn create_product(
-    app_state: Data<Arc<AppState>>,
+    app_state: Data<AppState>,
     body: ProductDTO,
 ) -> Result<String, HttpError> {
-       let access_token = get_paypal_token(&app_state).await;
+       let access_token = get_paypal_token(&app_state).await?;
 
        let res = app_state.client
            .post(format!("{}/v1/catalogs/products", app_state.env.paypal_api_mode))
@@ -108,25 +179,43 @@ pub async fn create_product(
         let text = res.text().await.unwrap_or_default();
 
         log::debug!("PayPal status: {} ", status);
-        log::debug!("PayPal body: {} ", text);
+        log::debug!("PayPal body: {} ", redact(&text));
 
-        if !status.is_success() {
-            let error_message = format!("PayPal API error: {} - {}", status, text);
-            log::error!("{}", error_message);
-            return Err(HttpError::server_error(error_message));
-        }
+        product_id_from_response(status, &text, body.id.as_deref())
+}
 
-        let product_response: serde_json::Value = serde_json::from_str(&text)
+/// Id del producto creado. Si se pidió un id propio y PayPal responde que ya existe,
+/// es un reintento de una creación que sí llegó a PayPal: se reutiliza ese producto.
+pub(crate) fn product_id_from_response(
+    status: reqwest::StatusCode,
+    text: &str,
+    requested_id: Option<&str>,
+) -> Result<String, HttpError> {
+    if let Some(requested_id) = requested_id
+        && status == reqwest::StatusCode::UNPROCESSABLE_ENTITY
+        && text.contains("DUPLICATE_RESOURCE_IDENTIFIER")
+    {
+        log::info!("El producto de PayPal {} ya existía; se reutiliza", requested_id);
+        return Ok(requested_id.to_string());
+    }
+
+    if !status.is_success() {
+        let error_message = format!("PayPal API error: {} - {}", status, text);
+        log::error!("{}", error_message);
+        return Err(HttpError::server_error(error_message));
+    }
+
+    let product_response: serde_json::Value = serde_json::from_str(text)
         .map_err(|e| HttpError::server_error(e.to_string()))?;
-        let product_id = product_response.get("id").and_then(|v| v.as_str())
-                .ok_or_else(|| HttpError::server_error(format!("Invalid Paypal response: {}", product_response)))?
-                .to_string();
+    let product_id = product_response.get("id").and_then(|v| v.as_str())
+        .ok_or_else(|| HttpError::server_error(format!("Invalid Paypal response: {}", product_response)))?
+        .to_string();
 
-        return Ok(product_id);
+    Ok(product_id)
 }
 
 pub async fn paypal_webhook(
-    app_state: Data<Arc<AppState>>,
+    app_state: Data<AppState>,
     body: web::Bytes,
     req: HttpRequest,
 ) -> Result<HttpResponse, HttpError> {
@@ -135,31 +224,31 @@ pub async fn paypal_webhook(
         .headers()
         .get("PAYPAL-TRANSMISSION-ID")
         .and_then(|h| h.to_str().ok())
-        .ok_or_else(|| HttpError::bad_request("Missing PAYPAL-TRANSMISSION-ID"))?;
+        .ok_or_else(|| HttpError::bad_request(ErrorMessage::MissingWebhookHeader("PAYPAL-TRANSMISSION-ID".to_string()).to_string()))?;
 
     let transmission_sig = req
         .headers()
         .get("PAYPAL-TRANSMISSION-SIG")
         .and_then(|h| h.to_str().ok())
-        .ok_or_else(|| HttpError::bad_request("Missing PAYPAL-TRANSMISSION-SIG"))?;
+        .ok_or_else(|| HttpError::bad_request(ErrorMessage::MissingWebhookHeader("PAYPAL-TRANSMISSION-SIG".to_string()).to_string()))?;
 
     let transmission_time = req
         .headers()
         .get("PAYPAL-TRANSMISSION-TIME")
         .and_then(|h| h.to_str().ok())
-        .ok_or_else(|| HttpError::bad_request("Missing PAYPAL-TRANSMISSION-TIME"))?;
+        .ok_or_else(|| HttpError::bad_request(ErrorMessage::MissingWebhookHeader("PAYPAL-TRANSMISSION-TIME".to_string()).to_string()))?;
 
     let cert_url = req
         .headers()
         .get("PAYPAL-CERT-URL")
         .and_then(|h| h.to_str().ok())
-        .ok_or_else(|| HttpError::bad_request("Missing PAYPAL-CERT-URL"))?;
+        .ok_or_else(|| HttpError::bad_request(ErrorMessage::MissingWebhookHeader("PAYPAL-CERT-URL".to_string()).to_string()))?;
 
     let auth_algo = req
         .headers()
         .get("PAYPAL-AUTH-ALGO")
         .and_then(|h| h.to_str().ok())
-        .ok_or_else(|| HttpError::bad_request("Missing PAYPAL-AUTH-ALGO"))?;
+        .ok_or_else(|| HttpError::bad_request(ErrorMessage::MissingWebhookHeader("PAYPAL-AUTH-ALGO".to_string()).to_string()))?;
 
     // Verificar la firma con tu función (más abajo)
     let is_verified = verify_paypal_webhook_signature(
@@ -173,12 +262,12 @@ pub async fn paypal_webhook(
     ).await;
 
     if !is_verified {
-        return Err(HttpError::bad_request("Invalid PayPal webhook signature"));
+        return Err(HttpError::bad_request(ErrorMessage::InvalidPayPalWebhook.to_string()));
     }
 
     let event: serde_json::Value = serde_json::from_slice(&body)
         .map_err(|e| HttpError::bad_request(format!("Invalid payload: {}", e)))?;
-    log::info!("Received PayPal webhook event: {:?}", event);
+    log::info!("Received PayPal webhook event: {}", redact_json(&event));
     match event["event_type"].as_str() {
         /* --- PAGOS DE PRODUCTOS / ORDENES --- */
         Some("PAYMENT.CAPTURE.COMPLETED") => {
@@ -214,25 +303,33 @@ pub async fn paypal_webhook(
             Ok(HttpResponse::Ok().finish())
         }
         Some("BILLING.SUBSCRIPTION.ACTIVATED") => {
-            // Activar y setear end_time
+            // Activar y setear end_time; PayPal reintenta los webhooks, un duplicado no hace nada.
+            // El acceso premium se consulta en la base en cada request: no hace falta reemitir el token
             if let Some(sub_id) = event["resource"]["id"].as_str() {
-                app_state.db_client.update_subscription_end_time(sub_id).await
-                    .map_err(|e| HttpError::server_error(format!("Error updating subscription: {}", e)))?;
-                app_state.db_client.update_subscription_status(sub_id, true).await
-                    .map_err(|e| HttpError::server_error(format!("Error activating subscription: {}", e)))?;
+                match app_state.db_client.activate_subscription(sub_id).await {
+                    Ok(true) => log::info!("Suscripción {} activada", sub_id),
+                    Ok(false) => log::info!("Suscripción {} ya estaba activa; evento duplicado ignorado", sub_id),
+                    // Suscripción desconocida: reintentar no la va a crear
+                    Err(sqlx::Error::RowNotFound) => log::warn!("Activación de una suscripción desconocida: {}", sub_id),
+                    Err(e) => return Err(HttpError::server_error(format!("Error activating subscription: {}", e))),
+                }
             }
             log::info!("Subscription activated event received.");
             Ok(HttpResponse::Ok().finish())
         }
         Some("BILLING.SUBSCRIPTION.UPDATED") => {
-            // Actualizar plan/estado - por ahora, solo log
+            // Cambio de plan aprobado: sincronizar el plan local
+            if let (Some(sub_id), Some(plan_id)) = (event["resource"]["id"].as_str(), event["resource"]["plan_id"].as_str()) {
+                app_state.db_client.apply_subscription_plan_change(sub_id, plan_id).await
+                    .map_err(|e| HttpError::server_error(format!("Error updating subscription plan: {}", e)))?;
+            }
             log::info!("Subscription updated event received.");
             Ok(HttpResponse::Ok().finish())
         }
         Some("BILLING.SUBSCRIPTION.CANCELLED") => {
             // Marcar como cancelada
             if let Some(sub_id) = event["resource"]["id"].as_str() {
-                app_state.db_client.update_subscription_status(sub_id, false).await
+                app_state.db_client.update_subscription_status(sub_id, SubscriptionStatus::Cancelled).await
                     .map_err(|e| HttpError::server_error(format!("Error cancelling subscription: {}", e)))?;
             }
             log::info!("Subscription cancelled event received.");
@@ -248,16 +345,20 @@ pub async fn paypal_webhook(
             Ok(HttpResponse::Ok().finish())
         }
         Some("BILLING.SUBSCRIPTION.SUSPENDED") => {
-            // Suspensión por pagos fallidos - desactivar
+            // Suspensión por pagos fallidos - periodo de gracia; solo EXPIRED/CANCELLED revocan
             if let Some(sub_id) = event["resource"]["id"].as_str() {
-                app_state.db_client.update_subscription_status(sub_id, false).await
+                start_grace_period(&app_state, sub_id).await?;
+                app_state.db_client.update_subscription_status(sub_id, SubscriptionStatus::Suspended).await
                     .map_err(|e| HttpError::server_error(format!("Error suspending subscription: {}", e)))?;
             }
             log::info!("Subscription suspended event received.");
             Ok(HttpResponse::Ok().finish())
         }
         Some("BILLING.SUBSCRIPTION.PAYMENT.FAILED") => {
-            // Pago fallido - log
+            // Pago fallido - periodo de gracia mientras PayPal reintenta el cobro
+            if let Some(sub_id) = event["resource"]["id"].as_str() {
+                start_grace_period(&app_state, sub_id).await?;
+            }
             log::info!("Subscription payment failed event received.");
             Ok(HttpResponse::Ok().finish())
         }
@@ -273,7 +374,7 @@ pub async fn paypal_webhook(
         Some("PAYMENT.SALE.REFUNDED") => {
             // Reembolso - quizás cancelar
             if let Some(sub_id) = event["resource"]["billing_agreement_id"].as_str() {
-                app_state.db_client.update_subscription_status(sub_id, false).await
+                app_state.db_client.update_subscription_status(sub_id, SubscriptionStatus::Cancelled).await
                     .map_err(|e| HttpError::server_error(format!("Error refunding subscription: {}", e)))?;
             }
             log::info!("Payment sale refunded event received.");
@@ -282,7 +383,7 @@ pub async fn paypal_webhook(
         Some("PAYMENT.SALE.REVERSED") => {
             // Reversión - cancelar
             if let Some(sub_id) = event["resource"]["billing_agreement_id"].as_str() {
-                app_state.db_client.update_subscription_status(sub_id, false).await
+                app_state.db_client.update_subscription_status(sub_id, SubscriptionStatus::Cancelled).await
                     .map_err(|e| HttpError::server_error(format!("Error reversing subscription: {}", e)))?;
             }
             log::info!("Payment sale reversed event received.");
@@ -313,22 +414,54 @@ pub async fn paypal_webhook(
     }
 }
 
+/// Mantiene el acceso `subscription_grace_days` días tras un pago fallido y avisa al
+/// usuario para que actualice su método de pago. Solo avisa al abrir el periodo.
+async fn start_grace_period(app_state: &AppState, paypal_subscription_id: &str) -> Result<(), HttpError> {
+    let grace_until = Utc::now() + Duration::days(app_state.env.subscription_grace_days);
+    let subscription = app_state.db_client
+        .start_subscription_grace_period(paypal_subscription_id, grace_until)
+        .await
+        .map_err(|e| HttpError::server_error(format!("Error starting grace period: {}", e)))?;
 
-pub async fn verify_paypal_webhook_signature(
-    app_state: &Data<Arc<AppState>>,
+    let Some(subscription) = subscription else {
+        return Ok(());
+    };
+
+    let grace_date = grace_until.format("%d/%m/%Y").to_string();
+    app_state.db_client
+        .create_notification(
+            subscription.user_id,
+            "Problema con el pago de tu suscripción",
+            &format!("No pudimos cobrar tu suscripción. Actualiza tu método de pago antes del {} para no perder el acceso.", grace_date),
+            "in_app",
+        )
+        .await
+        .map_err(|e| HttpError::server_error(format!("Error creating notification: {}", e)))?;
+
+    // El correo es un aviso adicional: si falla no se reintenta el webhook
+    if let Ok(Some(user)) = app_state.db_client.get_user(Some(subscription.user_id), None, None, None).await {
+        let billing_link = format!("{}/subscriptions", app_state.env.frontend_base_url);
+        if let Err(e) = send_payment_failed_email(&user.email, &user.name, &grace_date, &billing_link).await {
+            log::error!("Error sending payment failed email: {}", e);
+        }
+    }
+
+    Ok(())
+}
+
+
+/// Cuerpo para `verify-webhook-signature` con `webhook_event` copiado byte a byte del payload.
+/// Re-serializar el evento con `Value` reordena claves y cambia el formato de los números,
+/// y PayPal calcula la firma sobre los bytes originales.
+pub(crate) fn paypal_verify_request_body(
     transmission_id: &str,
     transmission_sig: &str,
     transmission_time: &str,
     cert_url: &str,
     auth_algo: &str,
-    body: &web::Bytes,
-) -> bool {
-    // Construye el JSON para PayPal
-    let webhook_event: serde_json::Value = match serde_json::from_slice(body) {
-        Ok(val) => val,
-        Err(_) => return false,
-    };
-
+    webhook_id: &str,
+    body: &[u8],
+) -> Result<String, serde_json::Error> {
     #[derive(serde::Serialize)]
     struct VerifyRequest<'a> {
         transmission_id: &'a str,
@@ -337,21 +470,53 @@ pub async fn verify_paypal_webhook_signature(
         auth_algo: &'a str,
         transmission_sig: &'a str,
         webhook_id: &'a str,
-        webhook_event: serde_json::Value,
+        webhook_event: &'a RawValue,
     }
 
-    let verify_body = VerifyRequest {
+    // `RawValue` valida que sea JSON pero conserva el texto original sin tocarlo
+    let webhook_event: &RawValue = serde_json::from_slice(body)?;
+    serde_json::to_string(&VerifyRequest {
         transmission_id,
         transmission_time,
         cert_url,
         auth_algo,
         transmission_sig,
-        webhook_id: &app_state.env.paypal_webhook_id,
+        webhook_id,
         webhook_event,
+    })
+}
+
+pub async fn verify_paypal_webhook_signature(
+    app_state: &Data<AppState>,
+    transmission_id: &str,
+    transmission_sig: &str,
+    transmission_time: &str,
+    cert_url: &str,
+    auth_algo: &str,
+    body: &web::Bytes,
+) -> bool {
+    // Construye el JSON para PayPal con el evento tal cual llegó
+    let verify_body = match paypal_verify_request_body(
+        transmission_id,
+        transmission_sig,
+        transmission_time,
+        cert_url,
+        auth_algo,
+        &app_state.env.paypal_webhook_id,
+        body,
+    ) {
+        Ok(b) => b,
+        Err(_) => return false,
     };
 
     // Obtiene token OAuth2 para PayPal
-    let token = get_paypal_token(&app_state).await;
+    let token = match get_paypal_token(app_state).await {
+        Ok(t) => t,
+        Err(e) => {
+            log::error!("No se pudo verificar la firma del webhook: {}", e);
+            return false;
+        }
+    };
 
     let client = reqwest::Client::new();
     let url = format!("{}/v1/notifications/verify-webhook-signature", app_state.env.paypal_api_mode);
@@ -359,7 +524,8 @@ pub async fn verify_paypal_webhook_signature(
     let resp = match client
         .post(&url)
         .bearer_auth(token)
-        .json(&verify_body)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(verify_body)
         .send()
         .await
     {
@@ -379,28 +545,61 @@ pub async fn verify_paypal_webhook_signature(
 // ===================== //
 //   Crear orden
 // ===================== //
+/// Páginas del sitio a las que PayPal devuelve al comprador (`host` ya viene normalizado con esquema)
+pub(crate) fn paypal_order_redirect_urls(host: &str, course_id: Uuid) -> (String, String) {
+    (
+        format!("{}/paypal/capture?course_id={}", host, course_id),
+        format!("{}/paypal/cancel?course_id={}", host, course_id),
+    )
+}
+
 pub async fn created_order(
-    state: Data<Arc<AppState>>, 
-    path: Path<(Uuid,)>,
+    state: Data<AppState>, 
+    CourseId(course_id): CourseId,
+    user: ReqData<JWTAuthMiddleware>,
 ) -> HttpResponse {
-    let course_id = path.into_inner().0;
     log::info!("creando orden");
+    match state.db_client.course_has_seat(course_id, user.user.id).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return HttpError::new(ErrorMessage::CourseFull.to_string(), StatusCode::CONFLICT)
+                .into_http_response();
+        }
+        Err(sqlx::Error::RowNotFound) => {
+            return HttpError::not_found(ErrorMessage::CourseNotFound.to_string()).into_http_response();
+        }
+        Err(e) => return HttpError::server_error(e.to_string()).into_http_response(),
+    }
     let course = match state.db_client.get_course(course_id).await {
         Ok(c) => c,
         Err(e) => {
             log::error!("Error: {}", e);
-            return HttpResponse::NotFound().body("Curso no encontrado");
+            return HttpError::not_found(ErrorMessage::CourseNotFound.to_string()).into_http_response();
         }
     };
     let invoice_id = Uuid::new_v4().to_string();
     let (paypal_product_id , title, price) = match course {
-        Some(c) => (c.paypal_product_id.clone(), c.title.clone(), c.price),
+        // Con una oferta vigente se cobra el precio de oferta
+        Some(c) => (c.paypal_product_id.clone(), c.title.clone(), c.effective_price(Utc::now())),
         None => {
             log::error!("Curso no encontrado");
-            return HttpResponse::NotFound().body("Curso no encontrado");
+            return HttpError::not_found(ErrorMessage::CourseNotFound.to_string()).into_http_response();
         }
     };
 
+    // Sin producto de PayPal la orden saldría con `sku: null`
+    let Some(paypal_product_id) = paypal_product_id else {
+        log::error!(
+            "El curso {} no tiene paypal_product_id; sincronízalo con POST {}",
+            course_id, api_path(&format!("/courses/edit/{}/sync-paypal", course_id))
+        );
+        return HttpError::new(ErrorMessage::CourseNotSyncedWithPayPal.to_string(), StatusCode::UNPROCESSABLE_ENTITY)
+            .into_http_response();
+    };
+
+    let (return_url, cancel_url) = paypal_order_redirect_urls(&state.env.host, course_id);
+    let currency = state.env.paypal_currency.as_str();
+    let value = minor_units_value(to_minor_units(price, currency), currency);
     let body =
         json!({
         "intent": "CAPTURE",
@@ -410,8 +609,8 @@ pub async fn created_order(
                     "payment_method_preference": "IMMEDIATE_PAYMENT_REQUIRED",
                     "landing_page": "LOGIN",
                     "user_action": "PAY_NOW",
-                    "return_url": format!("{}/paypal/capture?course_id={}", state.env.host, course_id),
-                    "cancel_url": format!("{}/paypal/cancel?course_id={}", state.env.host, course_id)
+                    "return_url": return_url,
+                    "cancel_url": cancel_url
                 }
             }
         },
@@ -419,12 +618,12 @@ pub async fn created_order(
             "invoice_id": invoice_id,
             "custom_id": course_id.to_string(),
             "amount": {
-                "currency_code": "USD",
-                "value": format!("{:.2}", price),
+                "currency_code": currency,
+                "value": value,
                 "breakdown": {
                     "item_total": {
-                        "currency_code": "USD",
-                        "value": format!("{:.2}", price)
+                        "currency_code": currency,
+                        "value": value
                     }
                 }
             },
@@ -432,8 +631,8 @@ pub async fn created_order(
                 "name": title,
                 "description": "Curso completo",
                 "unit_amount": {
-                    "currency_code": "USD",
-                    "value": format!("{:.2}", price)
+                    "currency_code": currency,
+                    "value": value
                 },
                 "quantity": "1",
                 "category": "DIGITAL_GOODS",
@@ -442,54 +641,122 @@ pub async fn created_order(
         }]
     });
 
-    let access_token = get_paypal_token(&state).await;
+    submit_paypal_order(&state, &body).await
+}
+
+/// Crea la orden en PayPal y responde solo con su id, que el frontend usa para aprobarla.
+pub(crate) async fn submit_paypal_order(state: &AppState, body: &Value) -> HttpResponse {
+    match create_paypal_order(state, body).await {
+        // Responder sólo con orderID
+        Ok(order_id) => ApiResponse::ok(json!({ "id": order_id })),
+        Err(res) => res,
+    }
+}
+
+/// Crea la orden en PayPal y devuelve su id; si falla, la respuesta de error para el cliente.
+pub(crate) async fn create_paypal_order(state: &AppState, body: &Value) -> Result<String, HttpResponse> {
+    let access_token = match get_paypal_token(state).await {
+        Ok(t) => t,
+        Err(e) => return Err(e.into_http_response()),
+    };
 
     let res = state.client
         .post(format!("{}/v2/checkout/orders", state.env.paypal_api_mode))
         .bearer_auth(&access_token)
-        .json(&body)
+        .json(body)
         .send().await
         .expect("Error al enviar la solicitud a PayPal");
 
     if res.status().is_client_error() || res.status().is_server_error() {
         log::error!("Respuesta inválida de PayPal: {:?}", res);
-        return HttpResponse::InternalServerError().body("Error creating order");
+        return Err(HttpError::server_error("Error creating order").into_http_response());
     }
 
     let response_json: Value = match res.json().await {
         Ok(v) => v,
         Err(_) => {
-            return HttpResponse::InternalServerError()
-                .body("Respuesta inválida de PayPal");
+            return Err(HttpError::server_error("Respuesta inválida de PayPal").into_http_response());
         }
     };
-    let order_id = match response_json.get("id").and_then(|v| v.as_str()) {
-        Some(id) => id.to_string(),
+    match response_json.get("id").and_then(|v| v.as_str()) {
+        Some(id) => Ok(id.to_string()),
         None => {
-            log::error!("PayPal no devolvió order id: {:?}", response_json);
-            return HttpResponse::InternalServerError()
-                .body("PayPal no devolvió order id");
+            log::error!("PayPal no devolvió order id: {}", redact_json(&response_json));
+            Err(HttpError::server_error("PayPal no devolvió order id").into_http_response())
         }
-    };
-
-    // Responder sólo con orderID
-    HttpResponse::Ok().json(json!({ "id": order_id }))
-
+    }
 }
 
 // ===================== //
 //   Capturar orden
 // ===================== //
 
+/// Monto cobrado en una captura de PayPal, en unidades menores y con su moneda.
+/// Usa el de la captura y, si falta, el de la unidad de compra.
+pub(crate) fn captured_amount(data: &Value) -> Option<Money> {
+    let unit = &data["purchase_units"][0];
+    let amount = [&unit["payments"]["captures"][0]["amount"], &unit["amount"]]
+        .into_iter()
+        .find(|amount| amount["value"].is_string())?;
+
+    let currency = amount["currency_code"].as_str().unwrap_or("USD").to_uppercase();
+    let amount_minor = parse_minor_units(amount["value"].as_str()?, &currency)?;
+    Some(Money { amount_minor, currency })
+}
+
+/// PayPal responde `422 ORDER_ALREADY_CAPTURED` si la captura se reintenta (el usuario
+/// vuelve a enviar el retorno): la orden ya está cobrada y no es un error.
+pub(crate) fn is_order_already_captured(status: reqwest::StatusCode, text: &str) -> bool {
+    status == reqwest::StatusCode::UNPROCESSABLE_ENTITY && text.contains("ORDER_ALREADY_CAPTURED")
+}
+
+/// Prefijo del `custom_id` de las órdenes de paquetes; las de un curso llevan solo su id.
+pub(crate) const BUNDLE_CUSTOM_ID_PREFIX: &str = "bundle:";
+
+/// Paquete de la orden si es una compra de paquete (`custom_id` = `bundle:{id}`).
+pub(crate) fn captured_bundle_id(data: &Value) -> Option<Uuid> {
+    data["purchase_units"][0]["payments"]["captures"][0]["custom_id"]
+        .as_str()
+        .and_then(|id| id.strip_prefix(BUNDLE_CUSTOM_ID_PREFIX))
+        .and_then(|id| Uuid::parse_str(id).ok())
+}
+
+/// Curso de la orden, guardado en el `custom_id` de la captura al crearla.
+pub(crate) fn captured_course_id(data: &Value) -> Option<Uuid> {
+    data["purchase_units"][0]["payments"]["captures"][0]["custom_id"]
+        .as_str()
+        .and_then(|id| Uuid::parse_str(id).ok())
+}
+
+/// Orden ya capturada, con la misma forma que la respuesta de la captura.
+async fn fetch_order(app_state: &AppState, access_token: &str, order_id: &str) -> Result<Value, HttpResponse> {
+    let res = app_state.client
+        .get(format!("{}/v2/checkout/orders/{}", app_state.env.paypal_api_mode, order_id))
+        .bearer_auth(access_token)
+        .send()
+        .await
+        .map_err(|err| HttpError::server_error(format!("Error al consultar la orden: {:?}", err)).into_http_response())?;
+
+    if !res.status().is_success() {
+        let error_body = res.text().await.unwrap_or_else(|_| "Error desconocido de PayPal".to_string());
+        return Err(HttpError::bad_request(format!("PayPal devolvió un error: {}", error_body)).into_http_response());
+    }
+
+    res.json().await.map_err(|e| HttpError::server_error(format!("Error al parsear la respuesta de PayPal: {}", e)).into_http_response())
+}
+
 #[post("/paypal/capture/{order_id}")]
 async fn capture_order(
     path: Path<(String,)>, 
-    app_state: Data<Arc<AppState>>,
+    app_state: Data<AppState>,
     user: ReqData<JWTAuthMiddleware>,
 ) -> HttpResponse {
     let order_id = path.into_inner().0;
     let user_id = user.user.id;
-    let access_token = get_paypal_token(&app_state).await;
+    let access_token = match get_paypal_token(&app_state).await {
+        Ok(t) => t,
+        Err(e) => return e.into_http_response(),
+    };
 
     let res =match  app_state.client
         .post(format!("{}/v2/checkout/orders/{}/capture", app_state.env.paypal_api_mode, order_id))
@@ -501,91 +768,242 @@ async fn capture_order(
     {
         Ok(res) => res,
         Err(err) => {
-            return HttpResponse::InternalServerError().json(json!({
-                "error": format!("Error al capturar la orden: {:?}", err)
-            }));
+            return HttpError::server_error(format!("Error al capturar la orden: {:?}", err)).into_http_response();
         }
     };
 
-    if !res.status().is_success() {
+    let mut already_captured = false;
+    let data: serde_json::Value = if res.status().is_success() {
+        match res.json().await {
+            Ok(json) => json,
+            Err(e) => {
+                return HttpError::server_error(format!("Error al parsear la respuesta de PayPal: {}", e)).into_http_response();
+            }
+        }
+    } else {
+        let status = res.status();
         let error_body = match res.text().await {
             Ok(text) => text,
             Err(_) => "Error desconocido de PayPal".to_string(),
         };
-        return HttpResponse::BadRequest().json(json!({
-            "error": format!("PayPal devolvió un error: {}", error_body)
-        }));
-    }
-
-    let data: serde_json::Value = match res.json().await {
-        Ok(json) => json,
-        Err(e) => {
-            return HttpResponse::InternalServerError().json(json!({
-                "error": format!("Error al parsear la respuesta de PayPal: {}", e)
-            }));
+        if !is_order_already_captured(status, &error_body) {
+            return HttpError::bad_request(format!("PayPal devolvió un error: {}", error_body)).into_http_response();
+        }
+        // Reintento: se sigue con la captura existente para asegurar que la compra quedó registrada
+        log::info!("La orden {} ya estaba capturada; se confirma la compra existente", order_id);
+        already_captured = true;
+        match fetch_order(&app_state, &access_token, &order_id).await {
+            Ok(order) => order,
+            Err(res) => return res,
         }
     };
     // Extraer el status de la respuesta de PayPal
     let status = data["status"].as_str().unwrap_or("").to_string();
+    if let Some(bundle_id) = captured_bundle_id(&data) {
+        return fulfill_bundle_order(&app_state, user_id, bundle_id, order_id, data, status, already_captured).await;
+    }
     // Extraer el course_id del custom_id en purchase_units
-   let custom_id = data["purchase_units"][0]["payments"]["captures"][0]["custom_id"]
-    .as_str()
-    .unwrap_or("");
-    let course_id = match Uuid::parse_str(custom_id) {
-        Ok(id) => id,
-        Err(e) => {
-            return HttpResponse::BadRequest().json(json!({
-                "error": format!("No se pudo obtener el ID del curso de la orden de PayPal: {}", e)
-            }));
-        }
-    };
-    let amount = match data["purchase_units"][0]["amount"]["value"].as_str() {
-        Some(value) => match value.parse::<i64>() {
-            Ok(amount) => amount,
-            Err(_) => 0,
-        },
-        None => 0,
+    let Some(course_id) = captured_course_id(&data) else {
+        return HttpError::bad_request("No se pudo obtener el ID del curso de la orden de PayPal").into_http_response();
     };
+    let amount = captured_amount(&data).unwrap_or_else(|| {
+        log::warn!("La orden {} no trae un monto legible; se registra en 0", order_id);
+        Money { amount_minor: 0, currency: app_state.env.paypal_currency.clone() }
+    });
 
      if status == "COMPLETED" {
         match app_state.db_client.register_course_purchase(
             user_id,
             course_id,
             order_id.clone(),
-            amount,
+            &amount,
             "paypal".to_string(),
             status.clone(),
         ).await {
-            Ok(_) => (),
+            Ok(true) => (),
+            // Solo en una carrera con la última plaza: `created_order` ya rechaza los cursos llenos
+            Ok(false) => {
+                log::error!(
+                    "Orden {} capturada para el curso lleno {} (usuario {}): requiere reembolso",
+                    order_id, course_id, user_id
+                );
+                return HttpError::new(ErrorMessage::CourseFull.to_string(), StatusCode::CONFLICT)
+                    .into_http_response();
+            }
+            // La primera captura ya registró esta transacción: basta con que el usuario tenga acceso
+            Err(sqlx::Error::Database(db_err)) if already_captured && db_err.is_unique_violation() => {
+                match app_state.db_client.check_user_course_access(user_id, course_id).await {
+                    Ok(Some(true)) => (),
+                    Ok(_) => {
+                        return HttpError::unique_constraint_violation(ErrorMessage::DuplicateTransaction.to_string())
+                            .into_http_response();
+                    }
+                    Err(e) => {
+                        return HttpError::server_error(format!("Error al registrar la compra: {}", e)).into_http_response();
+                    }
+                }
+            }
             Err(e) => {
-                return HttpResponse::InternalServerError().json(json!({
-                    "error": format!("Error al registrar la compra: {}", e)
-                }));
+                return HttpError::server_error(format!("Error al registrar la compra: {}", e)).into_http_response();
             }
         };
     } else {
-        return HttpResponse::BadRequest().json(json!({
-            "error": "El pago no se completó exitosamente"
-        }));
+        return HttpError::bad_request(ErrorMessage::PaymentNotCompleted.to_string()).into_http_response();
     }
     // Devolver un objeto con el status y otros datos relevantes
-    HttpResponse::Ok().json(json!({
+    ApiResponse::ok(json!({
         "status": status,
-        "order_id": order_id,
+        "orderId": order_id,
+        "alreadyCaptured": already_captured,
         "data": data  // Opcional: devolver toda la respuesta de PayPal si es necesario
     }))
 }
 
+/// Inscribe al comprador en los cursos del paquete capturado. En un reintento los cursos
+/// ya están inscritos y no se registra nada nuevo.
+async fn fulfill_bundle_order(
+    app_state: &AppState,
+    user_id: Uuid,
+    bundle_id: Uuid,
+    order_id: String,
+    data: Value,
+    status: String,
+    already_captured: bool,
+) -> HttpResponse {
+    if status != "COMPLETED" {
+        return HttpError::bad_request(ErrorMessage::PaymentNotCompleted.to_string()).into_http_response();
+    }
+    let amount = captured_amount(&data).unwrap_or_else(|| {
+        log::warn!("La orden {} no trae un monto legible; se registra en 0", order_id);
+        Money { amount_minor: 0, currency: app_state.env.paypal_currency.clone() }
+    });
+
+    let purchase = app_state.db_client.register_bundle_purchase(
+        user_id,
+        bundle_id,
+        &order_id,
+        &amount,
+        "paypal".to_string(),
+        status.clone(),
+    ).await;
+    let (granted, skipped) = match purchase {
+        Ok(BundlePurchase::Registered { granted, skipped }) => (granted, skipped),
+        Ok(BundlePurchase::CourseFull(course_id)) => {
+            log::error!(
+                "Orden {} del paquete {} capturada con el curso lleno {} (usuario {}): requiere reembolso",
+                order_id, bundle_id, course_id, user_id
+            );
+            return HttpError::new(ErrorMessage::CourseFull.to_string(), StatusCode::CONFLICT)
+                .into_http_response();
+        }
+        Err(sqlx::Error::RowNotFound) => {
+            return HttpError::not_found(ErrorMessage::BundleNotFound.to_string()).into_http_response();
+        }
+        Err(sqlx::Error::Database(db_err)) if db_err.is_unique_violation() => {
+            return HttpError::unique_constraint_violation(ErrorMessage::DuplicateTransaction.to_string())
+                .into_http_response();
+        }
+        Err(e) => {
+            return HttpError::server_error(format!("Error al registrar la compra: {}", e)).into_http_response();
+        }
+    };
+
+    ApiResponse::ok(json!({
+        "status": status,
+        "orderId": order_id,
+        "bundleId": bundle_id,
+        "grantedCourseIds": granted,
+        "skippedCourseIds": skipped,
+        "alreadyCaptured": already_captured,
+    }))
+}
+
+/// Registra un pago hecho fuera de PayPal (transferencia, efectivo...) e inscribe al
+/// usuario en el curso, igual que una compra capturada.
+pub async fn record_manual_payment(
+    app_state: Data<AppState>,
+    user: ReqData<JWTAuthMiddleware>,
+    web::Json(body): web::Json<CreatePaymentDTO>,
+) -> Result<HttpResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+    let amount = body.money()
+        .ok_or_else(|| HttpError::bad_request(ErrorMessage::InvalidPaymentAmount.to_string()))?;
+
+    // La compra y su registro en `audit_log` se guardan juntos o no se guarda ninguno
+    let mut tx = app_state.db_client.begin().await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+    let registered = match register_course_purchase_in(
+        &mut tx,
+        body.user_id,
+        body.course_id,
+        body.transaction_id.trim().to_string(),
+        &amount,
+        body.payment_method.trim().to_lowercase(),
+        "COMPLETED".to_string(),
+    ).await {
+        Ok(true) => record_audit(
+            &mut *tx,
+            user.user.id,
+            AUDIT_MANUAL_PAYMENT,
+            Some(body.user_id),
+            json!({
+                "courseId": body.course_id,
+                "transactionId": body.transaction_id.trim(),
+                "amount": amount.amount_minor,
+                "currency": amount.currency,
+            }),
+        ).await.map(|_| true),
+        other => other,
+    };
+
+    match registered {
+        Ok(true) => (),
+        Ok(false) => {
+            return Err(HttpError::new(ErrorMessage::CourseFull.to_string(), StatusCode::CONFLICT));
+        }
+        Err(sqlx::Error::RowNotFound) => {
+            return Ok(HttpError::not_found(ErrorMessage::CourseNotFound.to_string()).into_http_response());
+        }
+        Err(sqlx::Error::Database(db_err)) if db_err.is_unique_violation() => {
+            return Err(HttpError::unique_constraint_violation(ErrorMessage::DuplicateTransaction.to_string()));
+        }
+        Err(sqlx::Error::Database(db_err)) if db_err.is_foreign_key_violation() => {
+            return Ok(HttpError::not_found(ErrorMessage::UserNotFound.to_string()).into_http_response());
+        }
+        Err(e) => return Err(HttpError::server_error(e.to_string())),
+    }
+    tx.commit().await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+    app_state.db_client.award_purchase_achievements(body.user_id).await;
+
+    log::info!(
+        "Pago manual {} de {} registrado por {} para el usuario {} en el curso {}",
+        body.transaction_id, format_minor_units(amount.amount_minor, &amount.currency),
+        user.user.id, body.user_id, body.course_id
+    );
+    Ok(ApiResponse::created(json!({
+        "userId": body.user_id,
+        "courseId": body.course_id,
+        "transactionId": body.transaction_id.trim(),
+        "amount": amount.amount_minor,
+        "currency": amount.currency,
+    })))
+}
+
 #[post("/paypal/subscription/{subscription_id}")]
 async fn verify_subscription(
     path: Path<String>,
-    app_state: Data<Arc<AppState>>,
+    app_state: Data<AppState>,
     user: ReqData<JWTAuthMiddleware>,
 ) -> HttpResponse {
     let subscription_id = path.into_inner();
     let user_id = user.user.id;
 
-    let access_token = get_paypal_token(&app_state).await;
+    let access_token = match get_paypal_token(&app_state).await {
+        Ok(t) => t,
+        Err(e) => return e.into_http_response(),
+    };
 
     let res = app_state.client
         .get(format!(
@@ -600,16 +1018,12 @@ async fn verify_subscription(
     let res = match res {
         Ok(r) => r,
         Err(e) => {
-            return HttpResponse::InternalServerError().json(json!({
-                "error": format!("Error consultando PayPal: {}", e)
-            }));
+            return HttpError::server_error(format!("Error consultando PayPal: {}", e)).into_http_response();
         }
     };
 
     if !res.status().is_success() {
-        return HttpResponse::BadRequest().json(json!({
-            "error": "PayPal rechazó la suscripción"
-        }));
+        return HttpError::bad_request(ErrorMessage::SubscriptionRejectedByPayPal.to_string()).into_http_response();
     }
 
     let data: serde_json::Value = res.json().await.unwrap();
@@ -617,9 +1031,7 @@ async fn verify_subscription(
     let status = data["status"].as_str().unwrap_or("");
 
     if status != "ACTIVE" {
-        return HttpResponse::BadRequest().json(json!({
-            "error": "La suscripción no está activa"
-        }));
+        return HttpError::bad_request(ErrorMessage::SubscriptionNotActive.to_string()).into_http_response();
     }
     let paypal_plan_id = data["plan_id"].as_str().unwrap_or("");
 
@@ -636,8 +1048,8 @@ async fn verify_subscription(
         })
         .ok();
 
-    HttpResponse::Ok().json(json!({
+    ApiResponse::ok(json!({
         "status": "ACTIVE",
-        "subscription_id": subscription_id
+        "subscriptionId": subscription_id
     }))
 }
@@ -0,0 +1,95 @@
+use actix_web::{HttpResponse, web::{Data, Json, Query, ReqData}};
+use chrono::{Duration, Utc};
+use validator::Validate;
+
+use crate::{
+    AppState,
+    config::dtos::{ApiResponse, CreateReportDTO, ReportsQueryDto, UpdateReportDTO},
+    db::db::{NotificationExt, ReportExt},
+    errors::error::{ErrorMessage, HttpError},
+    middleware::middleware::JWTAuthMiddleware,
+    utils::path_id::{CourseId, ReportId},
+};
+
+/// `true` cuando el usuario ya agotó sus reportes de la última hora (0 desactiva el límite).
+pub(crate) fn report_limit_reached(recent: i64, limit_per_hour: i64) -> bool {
+    limit_per_hour > 0 && recent >= limit_per_hour
+}
+
+/// Reporta un problema con un curso o con una de sus lecciones y avisa a los administradores.
+pub async fn create_report(
+    CourseId(course_id): CourseId,
+    app_state: Data<AppState>,
+    user: ReqData<JWTAuthMiddleware>,
+    Json(body): Json<CreateReportDTO>,
+) -> Result<HttpResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let reporter_id = user.user.id;
+
+    let recent = app_state.db_client
+        .count_recent_reports(reporter_id, Utc::now() - Duration::hours(1))
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+    if report_limit_reached(recent, app_state.env.report_rate_limit_per_hour) {
+        return Ok(HttpError::too_many_requests(ErrorMessage::TooManyReports.to_string()).into_http_response());
+    }
+
+    let report = match app_state.db_client
+        .create_report(reporter_id, course_id, body.lesson_id, body.reason.trim(), body.details.as_deref())
+        .await
+    {
+        Ok(report) => report,
+        Err(sqlx::Error::RowNotFound) => {
+            return Ok(HttpError::not_found(ErrorMessage::CourseNotFound.to_string()).into_http_response());
+        }
+        Err(e) => return Err(HttpError::server_error(e.to_string())),
+    };
+
+    // El reporte ya quedó guardado: un fallo al notificar no debe perderlo
+    let message = format!("Nuevo reporte sobre el curso {}: {}", report.course_id, report.reason);
+    if let Err(e) = app_state.db_client.notify_admins("Nuevo reporte de contenido", &message).await {
+        log::warn!("No se pudo notificar a los administradores del reporte {}: {}", report.id, e);
+    }
+
+    Ok(ApiResponse::created(report))
+}
+
+/// Cola de reportes para los administradores, opcionalmente filtrada por estado.
+pub async fn get_reports(
+    Query(query_params): Query<ReportsQueryDto>,
+    app_state: Data<AppState>,
+) -> Result<HttpResponse, HttpError> {
+    query_params.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let page = query_params.page.unwrap_or(1);
+    let limit = query_params.limit.unwrap_or(10);
+
+    let (reports, total) = app_state.db_client
+        .get_reports(query_params.status, page as u32, limit)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    Ok(ApiResponse::paginated(reports, page, limit, total))
+}
+
+/// Resuelve, descarta o reabre un reporte.
+pub async fn update_report(
+    ReportId(report_id): ReportId,
+    app_state: Data<AppState>,
+    user: ReqData<JWTAuthMiddleware>,
+    Json(body): Json<UpdateReportDTO>,
+) -> Result<HttpResponse, HttpError> {
+    let report = app_state.db_client
+        .update_report_status(report_id, body.status, user.user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let Some(report) = report else {
+        return Ok(HttpError::not_found(ErrorMessage::ReportNotFound.to_string()).into_http_response());
+    };
+
+    Ok(ApiResponse::ok(report))
+}
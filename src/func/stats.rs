@@ -0,0 +1,57 @@
+use actix_web::{HttpResponse, web::Data};
+use chrono::{DateTime, Datelike, TimeZone, Utc};
+
+use crate::{
+    AppState,
+    config::dtos::{ApiResponse, CategoryCountDto, PlatformStatsDto, RevenueDto},
+    db::db::PlatformStatsExt,
+    errors::error::HttpError,
+};
+
+/// Inicio del mes de `now` (día 1 a medianoche, UTC).
+pub(crate) fn start_of_month(now: DateTime<Utc>) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(now.year(), now.month(), 1, 0, 0, 0)
+        .single()
+        .expect("el día 1 a medianoche siempre existe en UTC")
+}
+
+/// Porcentaje de `part` sobre `total` redondeado a un decimal; 0 si `total` es 0.
+pub(crate) fn percent(part: i64, total: i64) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    (part as f64 * 1000.0 / total as f64).round() / 10.0
+}
+
+/// Resumen de la plataforma para la portada de administración. Las consultas se lanzan en paralelo.
+pub async fn get_platform_stats(
+    app_state: Data<AppState>,
+) -> Result<HttpResponse, HttpError> {
+    let db = &app_state.db_client;
+    let month_start = start_of_month(Utc::now());
+
+    let ((total_users, verified_users), courses_by_category, total_enrollments, active_subscriptions, revenue, open_reports) = futures::try_join!(
+        db.count_users(),
+        db.count_courses_by_category(),
+        db.count_enrollments(),
+        db.count_active_subscriptions(),
+        db.get_revenue_since(month_start),
+        db.count_open_reports(),
+    )
+    .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    Ok(ApiResponse::ok(PlatformStatsDto {
+        total_users,
+        verified_users,
+        verified_percent: percent(verified_users, total_users),
+        total_courses: courses_by_category.iter().map(|(_, count)| count).sum(),
+        courses_by_category: courses_by_category
+            .into_iter()
+            .map(|(category, count)| CategoryCountDto { category, count })
+            .collect(),
+        total_enrollments,
+        active_subscriptions,
+        revenue_this_month: revenue.into_iter().map(RevenueDto::from).collect(),
+        open_reports,
+    }))
+}
@@ -1,8 +1,8 @@
 use actix_web::{web, HttpRequest, HttpResponse, Result, HttpMessage};
-use serde::{Deserialize};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
-use crate::{AppState, db::db::{SubscriptionExt, SubscriptionPlanExt}, errors::error::HttpError, middleware::middleware::JWTAuthMiddleware};
-use std::sync::Arc;
+use crate::{AppState, config::dtos::{ApiResponse, COURSE_CATEGORIES}, db::db::{NewSubscriptionPlan, SubscriptionExt, SubscriptionPlanChanges, SubscriptionPlanExt}, errors::error::{ErrorMessage, HttpError}, middleware::middleware::JWTAuthMiddleware, models::models::{Subscription, SubscriptionPlan, SubscriptionStatus}, services::paypal_client::PlanParams, utils::path_id::{PlanId, SubscriptionId}};
 
 // DTOs para suscripciones
 #[derive(Deserialize)]
@@ -13,6 +13,10 @@ pub struct CreateSubscriptionPlanRequest {
     pub duration_months: i32,
     pub features: Option<serde_json::Value>,
     pub paypal_plan_id: Option<String>,
+    // Sin categorías el plan da acceso a todo el catálogo
+    pub allowed_categories: Option<Vec<String>>,
+    // Días de prueba gratis antes del primer cobro (una vez por usuario)
+    pub trial_days: Option<i32>,
 }
 
 #[derive(Deserialize)]
@@ -21,40 +25,143 @@ pub struct SubscribeRequest {
     pub plan_id: String,
 }
 
+/// Rechaza categorías que ningún curso puede tener.
+pub(crate) fn validate_plan_categories(categories: Option<&[String]>) -> Result<(), HttpError> {
+    match categories.unwrap_or_default().iter().find(|c| !COURSE_CATEGORIES.contains(&c.as_str())) {
+        Some(unknown) => Err(HttpError::bad_request(ErrorMessage::InvalidCourseCategory(unknown.clone()).to_string())),
+        None => Ok(()),
+    }
+}
+
+// Límite de PayPal para un ciclo con unidad DAY
+pub(crate) const MAX_TRIAL_DAYS: i32 = 365;
+
+pub(crate) fn validate_trial_days(trial_days: i32) -> Result<(), HttpError> {
+    if (0..=MAX_TRIAL_DAYS).contains(&trial_days) {
+        Ok(())
+    } else {
+        Err(HttpError::bad_request(ErrorMessage::InvalidTrialDays(MAX_TRIAL_DAYS).to_string()))
+    }
+}
+
 // Crear un plan de suscripción (solo admin)
 pub async fn create_subscription_plan(
-    app_state: web::Data<Arc<AppState>>,
+    app_state: web::Data<AppState>,
     req: web::Json<CreateSubscriptionPlanRequest>,
 ) -> Result<HttpResponse, HttpError> {
+    validate_plan_categories(req.allowed_categories.as_deref())?;
+    let trial_days = req.trial_days.unwrap_or(0);
+    validate_trial_days(trial_days)?;
+
     // Crear producto en PayPal primero
     let product_id = app_state.paypal_client.create_product(&req.name, &req.description.clone().unwrap_or_else(|| req.name.clone()))
         .await
         .map_err(|e| HttpError::server_error(format!("Failed to create PayPal product: {}", e)))?;
 
     // Crear plan en PayPal
-    let plan_id = app_state.paypal_client.create_plan(&product_id, &req.name, &req.description.clone().unwrap_or_else(|| req.name.clone()), req.price, "MONTH", req.duration_months)
+    let description = req.description.clone().unwrap_or_else(|| req.name.clone());
+    let plan_id = app_state.paypal_client
+        .create_plan(PlanParams {
+            product_id: &product_id,
+            name: &req.name,
+            description: &description,
+            price: req.price,
+            interval: "MONTH",
+            interval_count: req.duration_months,
+            trial_days,
+        })
         .await
         .map_err(|e| HttpError::server_error(format!("Failed to create PayPal plan: {}", e)))?;
 
     // Guardar en la DB con el plan_id de PayPal
     let plan = app_state.db_client
-        .create_subscription_plan(&req.name, req.description.as_ref(), req.price, req.duration_months, req.features.as_ref(), Some(&plan_id))
+        .create_subscription_plan(NewSubscriptionPlan {
+            name: &req.name,
+            description: req.description.as_deref(),
+            price: req.price,
+            duration_months: req.duration_months,
+            features: req.features.as_ref(),
+            paypal_plan_id: Some(&plan_id),
+            allowed_categories: req.allowed_categories.as_deref(),
+            trial_days,
+        })
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
-    Ok(HttpResponse::Created().json(plan))
+    Ok(ApiResponse::created(plan))
 }
 
 // Obtener planes de suscripción
 pub async fn get_subscription_plans(
-    app_state: web::Data<Arc<AppState>>,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse, HttpError> {
+    let plans = app_state.db_client
+        .get_subscription_plans()
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    Ok(ApiResponse::ok(plans))
+}
+
+#[derive(Deserialize)]
+pub struct PlansQuery {
+    pub include: Option<String>, // "current"
+}
+
+/// Plan con la marca de "tu plan actual" para la página de precios
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubscriptionPlanWithStatus {
+    #[serde(flatten)]
+    pub plan: SubscriptionPlan,
+    pub is_current: bool,
+    pub renews_at: Option<DateTime<Utc>>,
+}
+
+/// Marca el plan de la suscripción activa. `subscription.plan_id` guarda el id del plan en PayPal.
+pub(crate) fn mark_current_plan(
+    plans: Vec<SubscriptionPlan>,
+    current: Option<&Subscription>,
+) -> Vec<SubscriptionPlanWithStatus> {
+    plans
+        .into_iter()
+        .map(|plan| {
+            let active = current.filter(|s| {
+                s.plan_id.is_some() && s.plan_id == plan.paypal_plan_id
+            });
+            SubscriptionPlanWithStatus {
+                is_current: active.is_some(),
+                renews_at: active.and_then(|s| s.end_time),
+                plan,
+            }
+        })
+        .collect()
+}
+
+// Planes para el usuario autenticado; con `?include=current` se marca su plan actual
+pub async fn get_subscription_plans_for_user(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    query: web::Query<PlansQuery>,
 ) -> Result<HttpResponse, HttpError> {
     let plans = app_state.db_client
         .get_subscription_plans()
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
-    Ok(HttpResponse::Ok().json(plans))
+    let subscriptions = if query.include.as_deref() == Some("current") {
+        let user = req.extensions().get::<JWTAuthMiddleware>().unwrap().user.clone();
+        app_state.db_client
+            .get_user_subscriptions(user.id)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?
+    } else {
+        vec![]
+    };
+    // Vienen ordenadas de la más reciente a la más antigua
+    let current = subscriptions.iter().find(|s| s.status.is_current());
+
+    Ok(ApiResponse::ok(mark_current_plan(plans, current)))
 }
 
 // Actualizar plan de suscripción
@@ -67,25 +174,38 @@ pub struct UpdateSubscriptionPlanRequest {
     pub features: Option<serde_json::Value>,
     pub paypal_plan_id: Option<String>,
     pub active: Option<bool>,
+    // Lista vacía: el plan vuelve a dar acceso a todo el catálogo
+    pub allowed_categories: Option<Vec<String>>,
 }
 
 pub async fn update_subscription_plan(
-    app_state: web::Data<Arc<AppState>>,
-    plan_id: web::Path<Uuid>,
+    app_state: web::Data<AppState>,
+    PlanId(plan_id): PlanId,
     req: web::Json<UpdateSubscriptionPlanRequest>,
 ) -> Result<HttpResponse, HttpError> {
+    validate_plan_categories(req.allowed_categories.as_deref())?;
+
     let plan = app_state.db_client
-        .update_subscription_plan(*plan_id, req.name.as_ref().map(|s| s.as_str()), req.description.as_ref(), req.price, req.duration_months, req.features.as_ref(), req.paypal_plan_id.as_ref().map(|s| s.as_str()), req.active)
+        .update_subscription_plan(plan_id, SubscriptionPlanChanges {
+            name: req.name.as_deref(),
+            description: req.description.as_deref(),
+            price: req.price,
+            duration_months: req.duration_months,
+            features: req.features.as_ref(),
+            paypal_plan_id: req.paypal_plan_id.as_deref(),
+            active: req.active,
+            allowed_categories: req.allowed_categories.as_deref(),
+        })
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
-    Ok(HttpResponse::Ok().json(plan))
+    Ok(ApiResponse::ok(plan))
 }
 
 // Eliminar plan de suscripción
 pub async fn delete_subscription_plan(
-    app_state: web::Data<Arc<AppState>>,
-    plan_id: web::Path<Uuid>,
+    app_state: web::Data<AppState>,
+    PlanId(plan_id): PlanId,
 ) -> Result<HttpResponse, HttpError> {
     // Obtener el plan para tener el paypal_plan_id
     let plans = app_state.db_client
@@ -93,8 +213,8 @@ pub async fn delete_subscription_plan(
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
-    let plan = plans.into_iter().find(|p| p.id == *plan_id)
-        .ok_or_else(|| HttpError::not_found("Plan not found".to_string()))?;
+    let plan = plans.into_iter().find(|p| p.id == plan_id)
+        .ok_or_else(|| HttpError::not_found(ErrorMessage::SubscriptionPlanNotFound.to_string()))?;
 
     // Eliminar plan en PayPal si existe
     if let Some(paypal_plan_id) = &plan.paypal_plan_id {
@@ -105,7 +225,7 @@ pub async fn delete_subscription_plan(
 
     // Eliminar de la DB
     app_state.db_client
-        .delete_subscription_plan(*plan_id)
+        .delete_subscription_plan(plan_id)
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
@@ -116,7 +236,7 @@ pub async fn delete_subscription_plan(
 // Obtener suscripciones del usuario autenticado
 pub async fn get_user_subscriptions(
     req: HttpRequest,
-    app_state: web::Data<Arc<AppState>>,
+    app_state: web::Data<AppState>,
 ) -> Result<HttpResponse, HttpError> {
     let user = req.extensions().get::<JWTAuthMiddleware>().unwrap().user.clone();
     let subscriptions = app_state.db_client
@@ -124,14 +244,33 @@ pub async fn get_user_subscriptions(
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
-    Ok(HttpResponse::Ok().json(subscriptions))
+    Ok(ApiResponse::ok(subscriptions))
+}
+
+/// Detalle de una suscripción del usuario autenticado; la de otro usuario responde 404.
+pub async fn get_subscription(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    SubscriptionId(subscription_id): SubscriptionId,
+) -> Result<HttpResponse, HttpError> {
+    let user = req.extensions().get::<JWTAuthMiddleware>().unwrap().user.clone();
+
+    let detail = app_state.db_client
+        .get_subscription_detail(user.id, subscription_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    match detail {
+        Some(detail) => Ok(ApiResponse::ok(detail)),
+        None => Ok(HttpError::not_found(ErrorMessage::SubscriptionNotFound.to_string()).into_http_response()),
+    }
 }
 
 // Cancelar suscripción
 pub async fn cancel_subscription(
     req: HttpRequest,
-    app_state: web::Data<Arc<AppState>>,
-    subscription_id: web::Path<Uuid>,
+    app_state: web::Data<AppState>,
+    SubscriptionId(subscription_id): SubscriptionId,
 ) -> Result<HttpResponse, HttpError> {
     let user = req.extensions().get::<JWTAuthMiddleware>().unwrap().user.clone();
 
@@ -141,8 +280,8 @@ pub async fn cancel_subscription(
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
-    let subscription = subscriptions.into_iter().find(|s| s.id == *subscription_id)
-        .ok_or_else(|| HttpError::not_found("Subscription not found or does not belong to user".to_string()))?;
+    let subscription = subscriptions.into_iter().find(|s| s.id == subscription_id)
+        .ok_or_else(|| HttpError::not_found(ErrorMessage::SubscriptionNotFound.to_string()))?;
 
     // Cancelar en PayPal
     app_state.paypal_client.cancel_subscription(&subscription.paypal_subscription_id)
@@ -151,9 +290,114 @@ pub async fn cancel_subscription(
 
     // Cancelar en la DB
     app_state.db_client
-        .cancel_subscription(*subscription_id)
+        .cancel_subscription(subscription_id)
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
     Ok(HttpResponse::Ok().finish())
-}
\ No newline at end of file
+}
+
+#[derive(Deserialize)]
+pub struct ChangePlanRequest {
+    pub plan_id: Uuid,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangePlanResponse {
+    pub status: &'static str, // "APPLIED" | "APPROVAL_REQUIRED"
+    pub plan_id: Uuid,
+    pub approval_url: Option<String>,
+}
+
+/// Un cambio es downgrade si el nuevo plan cuesta menos por mes; PayPal prorratea
+/// la diferencia y puede devolver dinero al cliente.
+pub(crate) fn is_plan_downgrade(current: &SubscriptionPlan, target: &SubscriptionPlan) -> bool {
+    let monthly = |p: &SubscriptionPlan| p.price / f64::from(p.duration_months.max(1));
+    monthly(target) < monthly(current)
+}
+
+// Cambiar el plan de una suscripción activa
+pub async fn change_subscription_plan(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    SubscriptionId(subscription_id): SubscriptionId,
+    body: web::Json<ChangePlanRequest>,
+) -> Result<HttpResponse, HttpError> {
+    let user = req.extensions().get::<JWTAuthMiddleware>().unwrap().user.clone();
+
+    // Se conserva el status (404/400/502) del error en la respuesta
+    match change_plan(&app_state, user.id, subscription_id, body.plan_id).await {
+        Ok(response) => Ok(ApiResponse::ok(response)),
+        Err(e) => Ok(e.into_http_response()),
+    }
+}
+
+async fn change_plan(
+    app_state: &AppState,
+    user_id: Uuid,
+    subscription_id: Uuid,
+    plan_id: Uuid,
+) -> Result<ChangePlanResponse, HttpError> {
+    // Verificar que la suscripción pertenece al usuario y sigue activa
+    let subscriptions = app_state.db_client
+        .get_user_subscriptions(user_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let subscription = subscriptions.into_iter().find(|s| s.id == subscription_id)
+        .ok_or_else(|| HttpError::not_found(ErrorMessage::SubscriptionNotFound.to_string()))?;
+
+    if subscription.status != SubscriptionStatus::Active {
+        return Err(HttpError::bad_request(ErrorMessage::SubscriptionNotActive.to_string()));
+    }
+
+    let plans = app_state.db_client
+        .get_subscription_plans()
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let target = plans.iter().find(|p| p.id == plan_id && p.active)
+        .ok_or_else(|| HttpError::not_found(ErrorMessage::SubscriptionPlanNotFound.to_string()))?;
+
+    let target_paypal_id = target.paypal_plan_id.as_deref()
+        .ok_or_else(|| HttpError::bad_request(ErrorMessage::PlanNotLinkedToPayPal.to_string()))?;
+
+    if subscription.plan_id.as_deref() == Some(target_paypal_id) {
+        return Err(HttpError::bad_request(ErrorMessage::SubscriptionAlreadyOnPlan.to_string()));
+    }
+
+    // `subscription.plan_id` guarda el id del plan en PayPal
+    let current = plans.iter().find(|p| p.paypal_plan_id.is_some() && p.paypal_plan_id == subscription.plan_id);
+    if let Some(current) = current
+        && !app_state.env.allow_subscription_downgrades
+        && is_plan_downgrade(current, target)
+    {
+        return Err(HttpError::bad_request(ErrorMessage::SubscriptionDowngradeNotAllowed.to_string()));
+    }
+
+    let return_url = format!("{}/subscriptions?planChange=approved", app_state.env.frontend_base_url);
+    let cancel_url = format!("{}/subscriptions?planChange=cancelled", app_state.env.frontend_base_url);
+
+    let approval_url = app_state.paypal_client
+        .revise_subscription(&subscription.paypal_subscription_id, target_paypal_id, &return_url, &cancel_url)
+        .await
+        .map_err(|e| HttpError::bad_gateway(format!("Failed to revise PayPal subscription: {}", e)))?;
+
+    app_state.db_client
+        .record_subscription_plan_change(
+            subscription.id,
+            user_id,
+            subscription.plan_id.as_deref(),
+            target_paypal_id,
+            approval_url.as_deref(),
+        )
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    Ok(ChangePlanResponse {
+        status: if approval_url.is_some() { "APPROVAL_REQUIRED" } else { "APPLIED" },
+        plan_id: target.id,
+        approval_url,
+    })
+}
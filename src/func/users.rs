@@ -1,32 +1,272 @@
-use std::sync::Arc;
 use actix_web::{ 
-   HttpResponse, Responder, web::{ ReqData,Data, Json, Query}
+   HttpRequest, HttpResponse, Responder, http::header, web::{ Bytes, ReqData,Data, Json, Query}
 };
+use chrono::{DateTime, Duration, Utc};
 use validator::Validate;
+use uuid::Uuid;
 
 use crate::{
     AppState, 
-    config::dtos::{FilterUserDto, NameUpdateDTO, RequestQueryDto, Response, RoleUpdateDTO, UserData, UserListResponseDto, UserPasswordUpdateDTO, UserResponseDto}, 
-    db::db::UserExt, errors::error::{ErrorMessage, HttpError}, 
+    config::dtos::{ApiResponse, BulkUserAction, BulkUserActionDTO, BulkUserActionResponseDto, BulkUserResultDto, CreateInviteDTO, EntitlementsDto, FilterCourseDto, FilterUserDto, InactiveUsersQueryDto, MessageDto, NameUpdateDTO, RequestQueryDto, RoleUpdateDTO, UserData, UserListResponseDto, UserCourseDto, UserCoursesQueryDto, UserPasswordUpdateDTO, UserSearchQueryDto, UserStorageDto}, 
+    db::db::{AdminPasswordReset, AuditLogExt, CourseExt, CoursePurchaseExt, InviteExt, PasswordResetTokenExt, SubscriptionExt, UserExt, UserUploadExt, normalize_search_term}, errors::error::{ErrorMessage, HttpError}, 
     middleware::middleware::{JWTAuthMiddleware}, 
-    utils::password
+    models::models::{Subscription, SubscriptionStatus, User}, 
+    func::handlers::password_reset_link,
+    mail::mails::{send_forgot_password_email, send_invite_email},
+    services::uploads::{delete_user_upload, put_user_upload},
+    utils::{password, path_id::{InviteId, UserId}, token}
 };
 
 
 pub async fn get_me(
     user: ReqData<JWTAuthMiddleware>
 ) ->  impl Responder {
-    HttpResponse::Ok().json(UserResponseDto {
-        status: "success".into(),
-        data: UserData {
-            user: FilterUserDto::filter_user(&user.user),
-        }
+    ApiResponse::ok(UserData {
+        user: FilterUserDto::filter_user(&user.user),
+    })
+}
+
+/// Fecha hasta la que el usuario es premium: igual que `check_user_has_active_subscription`,
+/// solo cuentan las suscripciones que dan acceso (`Subscription::grants_access`), con su
+/// periodo pagado (`end_time`, si está activa) y el de gracia (`grace_until`).
+pub(crate) fn premium_until(subscriptions: &[Subscription], now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    subscriptions
+        .iter()
+        .filter(|s| s.grants_access(now))
+        .flat_map(|s| [s.end_time.filter(|_| s.status == SubscriptionStatus::Active), s.grace_until])
+        .flatten()
+        .filter(|until| *until > now)
+        .max()
+}
+
+/// Calcula los permisos del usuario: rol, estado premium y cursos comprados.
+pub(crate) async fn load_entitlements(
+    app_state: &AppState,
+    user: &User,
+) -> Result<EntitlementsDto, HttpError> {
+    let subscriptions = app_state.db_client
+        .get_user_subscriptions(user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+    let owned_course_ids = app_state.db_client
+        .get_user_purchased_courses(user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let premium_until = premium_until(&subscriptions, Utc::now());
+    Ok(EntitlementsDto {
+        role: user.role,
+        is_premium: premium_until.is_some(),
+        premium_until,
+        owned_course_ids,
     })
 }
 
+/// Rol, estado premium y cursos comprados del usuario en una sola llamada.
+pub async fn get_my_entitlements(
+    app_state: Data<AppState>,
+    user: ReqData<JWTAuthMiddleware>,
+) -> Result<HttpResponse, HttpError> {
+    let entitlements = load_entitlements(&app_state, &user.user).await?;
+    Ok(ApiResponse::ok(entitlements))
+}
+
+/// Cursos del usuario autenticado, filtrables por `status` para las pestañas del panel.
+pub async fn get_my_courses(
+    Query(query_params): Query<UserCoursesQueryDto>,
+    app_state: Data<AppState>,
+    user: ReqData<JWTAuthMiddleware>,
+) -> Result<HttpResponse, HttpError> {
+    query_params.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let page = query_params.page.unwrap_or(1);
+    let limit = query_params.limit.unwrap_or(10);
+
+    let (mut courses, total) = app_state.db_client
+        .get_user_courses_by_status(user.user.id, query_params.status, page, limit)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+    courses.iter_mut().for_each(|c| c.course.apply_default_rating(app_state.env.default_course_rating));
+
+    Ok(ApiResponse::paginated(courses, page, limit, total))
+}
+
+/// Todos los cursos que el usuario puede abrir: comprados, los que cubre su plan premium
+/// y, si es admin, el catálogo completo. Es la lista de la biblioteca.
+pub async fn get_my_accessible_courses(
+    Query(query_params): Query<RequestQueryDto>,
+    app_state: Data<AppState>,
+    user: ReqData<JWTAuthMiddleware>,
+) -> Result<HttpResponse, HttpError> {
+    query_params.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let page = query_params.page.unwrap_or(1);
+    let limit = query_params.limit.unwrap_or(10);
+
+    let course_ids = app_state.db_client
+        .get_accessible_course_ids(user.user.id, app_state.env.premium_grants_all_courses)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+    let (courses, total) = app_state.db_client
+        .get_courses_by_ids(&course_ids, page as u32, limit)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+    let courses = UserCourseDto::with_default_rating(courses, app_state.env.default_course_rating);
+
+    Ok(ApiResponse::paginated(FilterCourseDto::filter_courses(&courses), page, limit, total))
+}
+
+// Formatos de avatar admitidos y la extensión con la que se guardan
+const AVATAR_TYPES: [(&str, &str); 3] = [("image/png", "png"), ("image/jpeg", "jpg"), ("image/webp", "webp")];
+/// Tamaño máximo del cuerpo en `PUT /users/me/avatar`; actix responde 413 por encima.
+pub const AVATAR_MAX_BYTES: usize = 2 * 1024 * 1024;
+
+pub(crate) fn avatar_key(user_id: Uuid, extension: &str) -> String {
+    format!("avatars/{}.{}", user_id, extension)
+}
+
+/// Extensión del avatar según el `Content-Type` (sin parámetros). `None` si no se admite.
+pub(crate) fn avatar_extension(content_type: &str) -> Option<&'static str> {
+    let mime = content_type.split(';').next().unwrap_or_default().trim();
+    AVATAR_TYPES.iter().find(|(t, _)| t.eq_ignore_ascii_case(mime)).map(|(_, ext)| *ext)
+}
+
+/// Sube la imagen de perfil (el cuerpo es la imagen). Cuenta para la cuota del usuario.
+pub async fn upload_my_avatar(
+    req: HttpRequest,
+    body: Bytes,
+    app_state: Data<AppState>,
+    user: ReqData<JWTAuthMiddleware>,
+) -> Result<HttpResponse, HttpError> {
+    let content_type = req.headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    let Some(extension) = avatar_extension(content_type).filter(|_| !body.is_empty()) else {
+        return Err(HttpError::bad_request(ErrorMessage::InvalidAvatar.to_string()));
+    };
+
+    let user_id = user.user.id;
+    let db = &app_state.db_client;
+    let storage = app_state.storage.as_ref();
+    let key = avatar_key(user_id, extension);
+    let url = match put_user_upload(db, storage, user_id, &key, body, content_type, app_state.env.user_storage_quota_bytes).await {
+        Ok(url) => url,
+        Err(e) => return Ok(e.into_http_error().into_http_response()),
+    };
+
+    // El avatar anterior con otra extensión ya no se usa
+    for (_, other) in AVATAR_TYPES.iter().filter(|(_, ext)| *ext != extension) {
+        delete_user_upload(db, storage, user_id, &avatar_key(user_id, other))
+            .await
+            .map_err(|e| e.into_http_error())?;
+    }
+
+    let updated = db.set_profile_image_url(user_id, Some(&url))
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+    Ok(ApiResponse::ok(FilterUserDto::filter_user(&updated)))
+}
+
+/// Quita la imagen de perfil y libera su espacio de la cuota.
+pub async fn delete_my_avatar(
+    app_state: Data<AppState>,
+    user: ReqData<JWTAuthMiddleware>,
+) -> Result<HttpResponse, HttpError> {
+    let user_id = user.user.id;
+    for (_, extension) in AVATAR_TYPES {
+        delete_user_upload(&app_state.db_client, app_state.storage.as_ref(), user_id, &avatar_key(user_id, extension))
+            .await
+            .map_err(|e| e.into_http_error())?;
+    }
+
+    app_state.db_client
+        .set_profile_image_url(user_id, None)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Espacio que ocupan los archivos subidos por el usuario frente a su cuota.
+pub async fn get_my_storage(
+    app_state: Data<AppState>,
+    user: ReqData<JWTAuthMiddleware>,
+) -> Result<HttpResponse, HttpError> {
+    let (used_bytes, file_count) = app_state.db_client
+        .get_user_storage_usage(user.user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    Ok(ApiResponse::ok(UserStorageDto::new(used_bytes, file_count, app_state.env.user_storage_quota_bytes)))
+}
+
+/// Usuarios cuyo correo rebota, para que un administrador los revise.
+pub async fn get_bounced_users(
+    Query(query_params): Query<RequestQueryDto>,
+    app_state: Data<AppState>
+) -> Result<HttpResponse, HttpError> {
+    query_params.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let page = query_params.page.unwrap_or(1);
+    let limit = query_params.limit.unwrap_or(10);
+
+    let (users, total) = app_state.db_client
+        .get_bounced_users(page as u32, limit)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    Ok(ApiResponse::paginated(users, page, limit, total))
+}
+
+/// Usuarios sin iniciar sesión en los últimos `days` días (30 por defecto).
+pub async fn get_inactive_users(
+    Query(query_params): Query<InactiveUsersQueryDto>,
+    app_state: Data<AppState>
+) -> Result<HttpResponse, HttpError> {
+    query_params.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let days = query_params.days.unwrap_or(30);
+    let page = query_params.page.unwrap_or(1);
+    let limit = query_params.limit.unwrap_or(10);
+
+    let (users, total) = app_state.db_client
+        .get_inactive_users(Utc::now() - Duration::days(days), page as u32, limit)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    Ok(ApiResponse::paginated(FilterUserDto::filter_users(&users), page, limit, total))
+}
+
+/// Búsqueda difusa de usuarios para soporte (`GET /api/v1/admin/users/search?q=`)
+pub async fn search_users(
+    Query(query_params): Query<UserSearchQueryDto>,
+    app_state: Data<AppState>
+) -> Result<HttpResponse, HttpError> {
+    query_params.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let term = normalize_search_term(&query_params.q)
+        .ok_or_else(|| HttpError::bad_request(ErrorMessage::SearchTermTooShort.to_string()))?;
+    let limit = query_params.limit.unwrap_or(20);
+
+    let users = app_state.db_client
+        .search_users(&term, limit)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    Ok(ApiResponse::ok(UserListResponseDto {
+        results: users.len() as i64,
+        users: FilterUserDto::filter_users(&users),
+    }))
+}
+
 pub async fn get_users(
     Query(query_params): Query<RequestQueryDto>,
-    app_state: Data<Arc<AppState>>
+    app_state: Data<AppState>
 ) -> Result<HttpResponse, HttpError> {
     query_params.validate()
         .map_err(|e| HttpError::bad_request(e.to_string()))?;
@@ -44,15 +284,14 @@ pub async fn get_users(
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
-    Ok(HttpResponse::Ok().json(UserListResponseDto {
-        status: "success".to_string(),
+    Ok(ApiResponse::ok(UserListResponseDto {
         users: FilterUserDto::filter_users(&users),
         results: user_count,
     }))
 }
 
 pub async fn update_user_name(
-    app_state: Data<Arc<AppState>>,
+    app_state: Data<AppState>,
     user: Data<JWTAuthMiddleware>,
     Json(body): Json<NameUpdateDTO>,
 ) -> Result<HttpResponse, HttpError> {
@@ -70,16 +309,13 @@ pub async fn update_user_name(
 
     let filtered_user = FilterUserDto::filter_user(&result);
 
-    Ok(HttpResponse::Ok().json(UserResponseDto {
-        data: UserData {
-            user: filtered_user,
-        },
-        status: "success".to_string(),
+    Ok(ApiResponse::ok(UserData {
+        user: filtered_user,
     }))
 }
 
 pub async fn update_user_role(
-    app_state: Data<Arc<AppState>>,
+    app_state: Data<AppState>,
     user: Data<JWTAuthMiddleware>,
     Json(body): Json<RoleUpdateDTO>,
 ) -> Result<HttpResponse, HttpError> {
@@ -97,16 +333,13 @@ pub async fn update_user_role(
 
     let filtered_user = FilterUserDto::filter_user(&result);
 
-    Ok(HttpResponse::Ok().json(UserResponseDto {
-        data: UserData {
-            user: filtered_user,
-        },
-        status: "success".to_string(),
+    Ok(ApiResponse::ok(UserData {
+        user: filtered_user,
     }))
 }
 
 pub async fn update_user_password(
-    app_state: Data<Arc<AppState>>,
+    app_state: Data<AppState>,
     user: Data<JWTAuthMiddleware>,
     Json(body): Json<UserPasswordUpdateDTO>,
 ) -> Result<HttpResponse, HttpError> {
@@ -124,11 +357,14 @@ pub async fn update_user_password(
 
     let user = result.ok_or(HttpError::unauthorized(ErrorMessage::InvalidToken.to_string()))?;
 
-    let password_match = password::verify_password(&body.old_password, &user.password)
-            .map_err(|e| HttpError::server_error(e.to_string()))?;
+    let password_match = password::verify_password(&body.old_password, user.password.as_deref())
+            .map_err(|e| match e {
+                ErrorMessage::PasswordlessAccount => HttpError::bad_request(e.to_string()),
+                _ => HttpError::server_error(e.to_string()),
+            })?;
 
     if !password_match {
-        return Err(HttpError::bad_request("Old password is incorrect".to_string()));
+        return Err(HttpError::bad_request(ErrorMessage::OldPasswordIncorrect.to_string()));
     }
 
     let hash_password = password::hash_password(&body.new_password)
@@ -139,9 +375,193 @@ pub async fn update_user_password(
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
-    Ok(HttpResponse::Ok().json(Response {
-        message: "Password updated Successfully".to_string(),
-        status: "success",
+    Ok(ApiResponse::ok(MessageDto::new("Password updated Successfully")))
+
+}
+
+//===================ADMIN===================//
+
+/// Aplica una acción administrativa a varios usuarios en una sola transacción.
+pub async fn bulk_user_action(
+    app_state: Data<AppState>,
+    Json(body): Json<BulkUserActionDTO>,
+) -> Result<HttpResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    // Los ids inválidos se reportan por separado en vez de abortar el lote
+    let mut user_ids: Vec<Uuid> = body.user_ids
+        .iter()
+        .filter_map(|id| Uuid::parse_str(id).ok())
+        .collect();
+    user_ids.sort();
+    user_ids.dedup();
+
+    let db = &app_state.db_client;
+    let updated = match body.action {
+        BulkUserAction::Verify => Some(
+            db.verify_users(&user_ids)
+                .await
+                .map_err(|e| HttpError::server_error(e.to_string()))?
+        ),
+        BulkUserAction::SetRole => {
+            let role = body.role
+                .ok_or_else(|| HttpError::bad_request(ErrorMessage::RoleRequired.to_string()))?;
+            db.set_users_role(&user_ids, role)
+                .await
+                .map_err(|e| HttpError::server_error(e.to_string()))?
+        }
+        BulkUserAction::Lock | BulkUserAction::Unlock => {
+            db.set_users_locked(&user_ids, body.action == BulkUserAction::Lock)
+                .await
+                .map_err(|e| HttpError::server_error(e.to_string()))?
+        }
+    };
+
+    let updated = updated
+        .ok_or_else(|| HttpError::unique_constraint_violation(ErrorMessage::LastActiveAdmin.to_string()))?;
+
+    Ok(ApiResponse::ok(BulkUserActionResponseDto {
+        action: body.action,
+        updated: updated.len(),
+        results: bulk_user_results(&body.user_ids, &updated),
     }))
+}
 
-}
\ No newline at end of file
+/// Invita a un usuario por correo con un rol ya asignado.
+pub async fn create_invite(
+    app_state: Data<AppState>,
+    user: ReqData<JWTAuthMiddleware>,
+    Json(body): Json<CreateInviteDTO>,
+) -> Result<HttpResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let existing = app_state.db_client
+        .get_user(None, None, Some(&body.email), None)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+    if existing.is_some() {
+        return Err(HttpError::unique_constraint_violation(ErrorMessage::EmailExist.to_string()));
+    }
+
+    // Solo se guarda el hash: el token en claro viaja únicamente en el correo
+    let invite_token = Uuid::new_v4().to_string();
+    let expires_at = Utc::now() + chrono::Duration::hours(app_state.env.invite_expiry_hours);
+
+    let invite = app_state.db_client
+        .create_invite(&body.email, body.role, &token::hash_token(&invite_token), user.user.id, expires_at)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let invite_link = format!("{}/accept-invite?token={}", app_state.env.frontend_base_url, invite_token);
+    if let Err(e) = send_invite_email(&invite.email, invite.role.to_str(), &invite_link).await {
+        return Err(HttpError::server_error(format!("No se pudo enviar la invitación: {}", e)));
+    }
+
+    Ok(ApiResponse::created(invite))
+}
+
+/// Invitaciones pendientes (sin usar, sin revocar y sin caducar).
+pub async fn get_invites(
+    Query(query_params): Query<RequestQueryDto>,
+    app_state: Data<AppState>
+) -> Result<HttpResponse, HttpError> {
+    query_params.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let page = query_params.page.unwrap_or(1);
+    let limit = query_params.limit.unwrap_or(10);
+
+    let (invites, total) = app_state.db_client
+        .get_pending_invites(page as u32, limit)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    Ok(ApiResponse::paginated(invites, page, limit, total))
+}
+
+pub async fn revoke_invite(
+    app_state: Data<AppState>,
+    InviteId(invite_id): InviteId,
+) -> Result<HttpResponse, HttpError> {
+    let revoked = app_state.db_client
+        .revoke_invite(invite_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if !revoked {
+        return Ok(HttpError::not_found(ErrorMessage::InviteNotFound.to_string()).into_http_response());
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Envía al usuario un enlace para elegir una contraseña nueva: el administrador nunca
+/// la ve ni la fija. Si la cuenta estaba bloqueada queda desbloqueada.
+pub async fn admin_reset_password(
+    UserId(user_id): UserId,
+    app_state: Data<AppState>,
+    admin: ReqData<JWTAuthMiddleware>,
+) -> Result<HttpResponse, HttpError> {
+    // Solo se guarda el hash: el token en claro viaja únicamente en el correo
+    let reset_token = Uuid::new_v4().to_string();
+    let outcome = app_state.db_client
+        .create_admin_password_reset(
+            admin.user.id,
+            user_id,
+            &token::hash_token(&reset_token),
+            Utc::now() + Duration::minutes(app_state.env.password_reset_expiry_minutes),
+            app_state.env.admin_password_reset_limit_per_hour,
+        )
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+    let user = match outcome {
+        AdminPasswordReset::Created(user) => *user,
+        AdminPasswordReset::UserNotFound => {
+            return Ok(HttpError::not_found(ErrorMessage::UserNotFound.to_string()).into_http_response());
+        }
+        AdminPasswordReset::RateLimited => {
+            return Ok(HttpError::too_many_requests(ErrorMessage::TooManyPasswordResets.to_string()).into_http_response());
+        }
+    };
+
+    let reset_link = password_reset_link(&app_state.env.frontend_base_url, &reset_token);
+    if let Err(e) = send_forgot_password_email(&user.email, &reset_link, &user.name).await {
+        return Err(HttpError::server_error(format!("No se pudo enviar el email de restablecimiento: {}", e)));
+    }
+
+    log::info!("El administrador {} pidió restablecer la contraseña de {}", admin.user.id, user_id);
+    Ok(ApiResponse::ok(MessageDto::new("Se envió al usuario un enlace para restablecer su contraseña.")))
+}
+
+/// Acciones administrativas registradas sobre un usuario, la más reciente primero.
+pub async fn get_user_audit_log(
+    UserId(user_id): UserId,
+    app_state: Data<AppState>,
+) -> Result<HttpResponse, HttpError> {
+    let entries = app_state.db_client
+        .get_user_audit_log(user_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    Ok(ApiResponse::ok(entries))
+}
+
+/// Resultado por id, en el mismo orden en que llegaron en la petición.
+pub(crate) fn bulk_user_results(requested: &[String], updated: &[Uuid]) -> Vec<BulkUserResultDto> {
+    requested
+        .iter()
+        .map(|raw| {
+            let status = match Uuid::parse_str(raw) {
+                Ok(id) if updated.contains(&id) => "updated",
+                Ok(_) => "not_found",
+                Err(_) => "invalid_id",
+            };
+            BulkUserResultDto {
+                user_id: raw.clone(),
+                status: status.to_string(),
+            }
+        })
+        .collect()
+}
@@ -1,13 +1,15 @@
-use super::sendmail::send_email;
+use crate::config::config::auth_path;
+use super::sendmail::{ send_email, send_email_with_tracking, EmailTracking };
 
 pub async fn send_verification_email(
     to_email: &str,
     username: &str,
-    token: &str
+    token: &str,
+    api_base_url: &str,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let subject = "Verificación de Correo Electrónico";
-    let base_url = "https://localhost:8000/api/auth/verify";
-    let verification_link = create_verification_link(base_url, token);
+    let base_url = format!("{}{}", api_base_url, auth_path("/verify"));
+    let verification_link = create_verification_link(&base_url, token);
 
     let body_html = format!(
         r#"
@@ -145,4 +147,221 @@ pub async fn send_forgot_password_email(
     );
 
     send_email(to_email, subject, &body_html, &placeholders).await
-}
\ No newline at end of file
+}
+
+pub async fn send_payment_failed_email(
+    to_email: &str,
+    username: &str,
+    grace_until: &str,
+    billing_link: &str
+) -> Result<(), Box<dyn std::error::Error>> {
+    let subject = "No pudimos procesar el pago de tu suscripción ⚠️";
+    let placeholders = vec![
+        ("{{username}}".to_string(), username.to_string()),
+        ("{{billing_link}}".to_string(), billing_link.to_string())
+    ];
+
+    let body_html = format!(
+        r#"
+        <html>
+            <head>
+                <style>
+                    body {{ font-family: Arial, sans-serif; max-width: 600px; margin: 0 auto; }}
+                    .header {{ background: linear-gradient(135deg, #f7b733 0%, #fc4a1a 100%); color: white; padding: 20px; text-align: center; }}
+                    .content {{ padding: 20px; }}
+                    .button {{ display: inline-block; background: #FF5722; color: white; padding: 10px 20px; text-decoration: none; border-radius: 5px; }}
+                    .footer {{ background: #f4f4f4; padding: 10px; text-align: center; font-size: 12px; color: #666; }}
+                </style>
+            </head>
+            <body>
+                <div class="header">
+                    <h1>Problema con tu pago ⚠️</h1>
+                </div>
+                <div class="content">
+                    <p>Hola, {}.</p>
+                    <p>No pudimos cobrar la renovación de tu suscripción. Mantendrás el acceso a tus cursos hasta el <strong>{}</strong> mientras se resuelve.</p>
+                    <p>Actualiza tu método de pago en PayPal para no perder el acceso:</p>
+                    <p style="text-align: center; margin: 30px 0;">
+                        <a href="{}" class="button">Revisar mi suscripción</a>
+                    </p>
+                </div>
+                <div class="footer">
+                    <p>Equipo de Vallenato Academy</p>
+                </div>
+            </body>
+        </html>
+        "#,
+        username,
+        grace_until,
+        billing_link
+    );
+
+    send_email(to_email, subject, &body_html, &placeholders).await
+}
+
+/// Correo de campaña (notificaciones de marketing); admite seguimiento de aperturas y clics.
+pub async fn send_campaign_email(
+    to_email: &str,
+    username: &str,
+    title: &str,
+    message: &str,
+    tracking: Option<&EmailTracking>
+) -> Result<(), Box<dyn std::error::Error>> {
+    // El nombre también puede acabar en el HTML a través del marcador
+    let placeholders = vec![
+        ("{{username}}".to_string(), escape_html(username))
+    ];
+    let body_html = campaign_email_body(username, title, message);
+
+    send_email_with_tracking(to_email, title, &body_html, &placeholders, tracking).await
+}
+
+/// Cuerpo del correo de campaña. Título, nombre y mensaje los escriben usuarios y
+/// administradores: se escapan para que no inyecten HTML en el correo.
+pub(crate) fn campaign_email_body(username: &str, title: &str, message: &str) -> String {
+    format!(
+        r#"
+        <html>
+            <head>
+                <style>
+                    body {{ font-family: Arial, sans-serif; max-width: 600px; margin: 0 auto; }}
+                    .header {{ background: linear-gradient(135deg, #764ba2 0%, #667eea 100%); color: white; padding: 20px; text-align: center; }}
+                    .content {{ padding: 20px; }}
+                    .footer {{ background: #f4f4f4; padding: 10px; text-align: center; font-size: 12px; color: #666; }}
+                </style>
+            </head>
+            <body>
+                <div class="header">
+                    <h1>{}</h1>
+                </div>
+                <div class="content">
+                    <p>Hola, {}.</p>
+                    <p>{}</p>
+                </div>
+                <div class="footer">
+                    <p>Equipo de Vallenato Academy</p>
+                </div>
+            </body>
+        </html>
+        "#,
+        escape_html(title),
+        escape_html(username),
+        escape_html(message)
+    )
+}
+
+/// Escapa los caracteres con significado en HTML.
+pub(crate) fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub async fn send_course_reminder_email(
+    to_email: &str,
+    username: &str,
+    course_title: &str,
+    lesson_title: &str,
+    lesson_link: &str
+) -> Result<(), Box<dyn std::error::Error>> {
+    let subject = "¡Sigue aprendiendo vallenato! 🎶";
+    let placeholders = vec![
+        ("{{username}}".to_string(), username.to_string()),
+        ("{{lesson_link}}".to_string(), lesson_link.to_string())
+    ];
+
+    let body_html = format!(
+        r#"
+        <html>
+            <head>
+                <style>
+                    body {{ font-family: Arial, sans-serif; max-width: 600px; margin: 0 auto; }}
+                    .header {{ background: linear-gradient(135deg, #764ba2 0%, #667eea 100%); color: white; padding: 20px; text-align: center; }}
+                    .content {{ padding: 20px; }}
+                    .button {{ display: inline-block; background: #667eea; color: white; padding: 10px 20px; text-decoration: none; border-radius: 5px; }}
+                    .footer {{ background: #f4f4f4; padding: 10px; text-align: center; font-size: 12px; color: #666; }}
+                </style>
+            </head>
+            <body>
+                <div class="header">
+                    <h1>Te esperamos en clase 🎶</h1>
+                </div>
+                <div class="content">
+                    <p>Hola, {}.</p>
+                    <p>Hace unos días que no avanzas en <strong>{}</strong>. Tu siguiente lección es:</p>
+                    <p><strong>{}</strong></p>
+                    <p style="text-align: center; margin: 30px 0;">
+                        <a href="{}" class="button">Continuar el curso</a>
+                    </p>
+                    <p>Puedes desactivar estos recordatorios desde la configuración de tu cuenta.</p>
+                </div>
+                <div class="footer">
+                    <p>Equipo de Vallenato Academy</p>
+                </div>
+            </body>
+        </html>
+        "#,
+        username,
+        course_title,
+        lesson_title,
+        lesson_link
+    );
+
+    send_email(to_email, subject, &body_html, &placeholders).await
+}
+
+pub async fn send_invite_email(
+    to_email: &str,
+    role: &str,
+    invite_link: &str
+) -> Result<(), Box<dyn std::error::Error>> {
+    let subject = "Te han invitado a Vallenato Academy";
+    let placeholders = vec![
+        ("{{invite_link}}".to_string(), invite_link.to_string())
+    ];
+
+    let body_html = format!(
+        r#"
+        <html>
+            <head>
+                <style>
+                    body {{ font-family: Arial, sans-serif; max-width: 600px; margin: 0 auto; }}
+                    .header {{ background: linear-gradient(135deg, #667eea 0%, #764ba2 100%); color: white; padding: 20px; text-align: center; }}
+                    .content {{ padding: 20px; }}
+                    .button {{ display: inline-block; background: #4CAF50; color: white; padding: 10px 20px; text-decoration: none; border-radius: 5px; }}
+                    .footer {{ background: #f4f4f4; padding: 10px; text-align: center; font-size: 12px; color: #666; }}
+                </style>
+            </head>
+            <body>
+                <div class="header">
+                    <h1>¡Te damos la bienvenida! 🎶</h1>
+                </div>
+                <div class="content">
+                    <p>Has sido invitado a unirte a Vallenato Academy con el rol <strong>{}</strong>.</p>
+                    <p>Para crear tu cuenta haz clic en el siguiente enlace:</p>
+                    <p style="text-align: center; margin: 30px 0;">
+                        <a href="{}" class="button">Aceptar invitación</a>
+                    </p>
+                    <p>El enlace solo puede usarse una vez. Si no esperabas esta invitación, puedes ignorar este correo.</p>
+                </div>
+                <div class="footer">
+                    <p>Equipo de Vallenato Academy</p>
+                </div>
+            </body>
+        </html>
+        "#,
+        role,
+        invite_link
+    );
+
+    send_email(to_email, subject, &body_html, &placeholders).await
+}
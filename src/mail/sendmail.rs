@@ -1,4 +1,6 @@
 use std::{env};
+use reqwest::Url;
+use uuid::Uuid;
 use lettre::{
     message::{header, SinglePart},
     transport::smtp::authentication::Credentials,
@@ -6,11 +8,65 @@ use lettre::{
     Transport,
 };
 
+/// Datos para el seguimiento de un correo de campaña.
+pub struct EmailTracking {
+    // Base pública de la API que sirve `/track/open` y `/track/click`
+    pub api_base_url: String,
+    pub token: Uuid,
+}
+
+/// Envuelve los enlaces http(s) para registrar clics y añade el píxel de apertura.
+pub fn inject_tracking(body: &str, tracking: &EmailTracking) -> String {
+    const HREF: &str = "href=\"";
+    let click_base = format!("{}/track/click/{}", tracking.api_base_url, tracking.token);
+
+    let mut out = String::with_capacity(body.len());
+    let mut rest = body;
+    while let Some(start) = rest.find(HREF) {
+        let (before, after) = rest.split_at(start + HREF.len());
+        out.push_str(before);
+        let end = after.find('"').unwrap_or(after.len());
+        let link = &after[..end];
+        if link.starts_with("http://") || link.starts_with("https://") {
+            match Url::parse_with_params(&click_base, &[("u", link)]) {
+                Ok(wrapped) => out.push_str(wrapped.as_str()),
+                Err(_) => out.push_str(link),
+            }
+        } else {
+            out.push_str(link);
+        }
+        rest = &after[end..];
+    }
+    out.push_str(rest);
+
+    let pixel = format!(
+        r#"<img src="{}/track/open/{}" width="1" height="1" alt="" style="display:none" />"#,
+        tracking.api_base_url, tracking.token
+    );
+    match out.rfind("</body>") {
+        Some(pos) => out.insert_str(pos, &pixel),
+        None => out.push_str(&pixel),
+    }
+    out
+}
+
+/// Correo transaccional (verificación, restablecimiento...): nunca lleva seguimiento.
 pub async fn send_email(
     to_email: &str,
     subject: &str,
     body_template: &String,
     placeholders: &[(String, String)]
+) -> Result<(), Box<dyn std::error::Error>> {
+    send_email_with_tracking(to_email, subject, body_template, placeholders, None).await
+}
+
+/// Igual que `send_email`, con seguimiento opcional de aperturas y clics.
+pub async fn send_email_with_tracking(
+    to_email: &str,
+    subject: &str,
+    body_template: &String,
+    placeholders: &[(String, String)],
+    tracking: Option<&EmailTracking>
 ) -> Result<(), Box<dyn std::error::Error>> {
      // Cargar variables de entorno
     let smtp_username = match env::var("SMTP_USERNAME") {
@@ -45,6 +101,9 @@ pub async fn send_email(
     for (key, value) in placeholders {
         body = body.replace(key, value);
     }
+    if let Some(tracking) = tracking {
+        body = inject_tracking(&body, tracking);
+    }
 
     let email = Message::builder()
         .from(smtp_username.parse()?)
@@ -2,6 +2,7 @@ mod models;
 mod func;
 mod auth;
 mod config;
+#[cfg(test)]
 mod test;
 mod errors;
 mod db;
@@ -12,35 +13,51 @@ mod routes;
 mod services;
 
 use actix_web::Responder;
-use actix_web::web::{ scope };
+use actix_web::web::{ scope, to };
 // use actix_web::middleware::Compress;
-use actix_web::{ web::{ Data, Json }, App, HttpServer, HttpResponse };
+use actix_web::{ web::{ Data, Json }, App, HttpServer, HttpResponse, http::KeepAlive };
 use chrono::{ DateTime, Utc };
-use openssl::ssl::{ SslAcceptor, SslFiletype, SslMethod };
+use openssl::ssl::{ SslAcceptor, SslAcceptorBuilder, SslFiletype, SslMethod };
 use config::config::Config;
 use reqwest::Client;
+use services::events::EventBus;
+use services::feature_flags::FeatureFlags;
 use services::paypal_client::PayPalClient;
+use services::storage::{ Storage, media_service, storage_from_config };
 use serde_json::Value;
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use db::db::DBClient;
+use std::time::Duration;
+use tokio::sync::{ Mutex, RwLock };
+use db::db::{ DBClient, CourseExt, SubscriptionExt };
 use sqlx::postgres::PgPoolOptions;
 use dotenvy;
-use middleware::middleware::AuthMiddlewareFactory;
-use crate::routes::routes::{ auth_scope, course_scope, global_scope };
+use middleware::middleware::{ ApiVersionCompat, AuthMiddlewareFactory, CatchPanic, CorsPolicy, RequestIdMiddlewareFactory, RequestTimeout, SecurityHeaders, json_method_not_allowed };
+use crate::services::reminders::send_course_reminders;
+use crate::config::config::{ API_PREFIX, AUTH_PREFIX, LEGACY_API_PREFIX, LEGACY_AUTH_PREFIX, api_path };
+use crate::routes::routes::{ COURSE_PROGRESS_STREAM, auth_scope, global_scope, not_found, public_routes, tracking_scope, webhooks_scope };
 use env_logger::Env;
+use std::io::Write;
 use actix_web::middleware::Logger;
+use actix_web::middleware::ErrorHandlers;
+use actix_web::http::StatusCode;
 
 //==================== //
 //      APP STATE
 // ==================== //
-#[derive(Clone, Debug)]
+// Se comparte como `Data<AppState>` (un `Arc`); no hace falta clonar el estado completo
+#[derive(Debug)]
 pub struct AppState {
     pub env: Config,
     pub client: Client,
     pub token_cache: Arc<RwLock<Option<CachedToken>>>,
+    // Solo una renovación del token de PayPal a la vez (single-flight); guarda
+    // hasta cuándo no se reintenta tras un fallo
+    pub token_refresh: Arc<Mutex<Option<DateTime<Utc>>>>,
     pub db_client: DBClient,
     pub paypal_client: PayPalClient,
+    pub events: EventBus,
+    pub storage: Arc<dyn Storage>,
+    pub feature_flags: FeatureFlags,
 }
 
 #[derive(Clone, Debug)]
@@ -77,18 +94,32 @@ pub async fn ping(Json(json): Json<Value>) -> impl Responder {
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenvy::dotenv().expect("No se pudo cargar el archivo .env");
-    let current_dir = std::env::current_dir().expect("No se pudo obtener el directorio actual");
-    env_logger::Builder::from_env(Env::default().default_filter_or("debug, actix_server=info")).init();
-
-    let key_path = current_dir.join("key.pem");
-    let cert_path = current_dir.join("cert.pem");
-
-    let mut builder = SslAcceptor::mozilla_intermediate(SslMethod::tls()).unwrap();
-    builder.set_private_key_file(key_path, SslFiletype::PEM).expect("No se pudo leer key.pem");
-    builder.set_certificate_chain_file(cert_path).expect("No se pudo leer cert.pem");
-
     // Crear conexión a Postgres
     let config = Config::init();
+
+    // Todo lo que se registra pasa por la redacción de campos sensibles (correos, tokens, ...)
+    utils::redact::init(config.log_redact_fields.clone());
+    env_logger::Builder::from_env(Env::default().default_filter_or("debug, actix_server=info"))
+        .format(|buf, record| {
+            writeln!(
+                buf,
+                "[{} {} {}] {}",
+                buf.timestamp(),
+                record.level(),
+                record.target(),
+                utils::redact::redact(&record.args().to_string())
+            )
+        })
+        .init();
+    let keep_alive = match config.keep_alive_secs {
+        0 => KeepAlive::Disabled,
+        secs => KeepAlive::Timeout(Duration::from_secs(secs)),
+    };
+    let client_request_timeout = Duration::from_millis(config.client_request_timeout_ms);
+    let client_disconnect_timeout = Duration::from_millis(config.client_disconnect_timeout_ms);
+    let tls_enabled = config.tls_enabled;
+    let security_headers = SecurityHeaders::from_config(&config).expect("Cabeceras de seguridad inválidas");
+    let cors_policy = CorsPolicy::from_config(&config).expect("Configuración CORS inválida");
     let pool = match PgPoolOptions::new().connect(&config.database_url).await {
         Ok(pool) => { pool }
         Err(err) => {
@@ -101,54 +132,132 @@ async fn main() -> std::io::Result<()> {
         }
     };
     let db: DBClient = DBClient::new(pool);
+
+    // Reporte de cursos que no se pueden comprar por no tener producto en PayPal
+    match db.get_courses_missing_paypal_product().await {
+        Ok(courses) => {
+            for course in courses {
+                log::warn!(
+                    "Curso sin paypal_product_id: {} ({}). Sincronizar con POST {}",
+                    course.title, course.id, api_path(&format!("/courses/edit/{}/sync-paypal", course.id))
+                );
+            }
+        }
+        Err(e) => log::error!("No se pudo revisar los cursos sin producto de PayPal: {}", e),
+    }
     let paypal_client = PayPalClient::new(
         config.paypal_client_id.clone(),
         config.paypal_secret.clone(),
         config.paypal_api_mode.contains("sandbox")
     ).await;
 
+    let client = Client::new();
+    let storage = storage_from_config(&config, client.clone());
+    let feature_flags = FeatureFlags::new(Duration::from_secs(config.feature_flag_cache_secs));
     let state = AppState {
         env: config,
-        client: Client::new(),
+        client,
         token_cache: Arc::new(RwLock::new(None)),
+        token_refresh: Arc::new(Mutex::new(None)),
         db_client: db.clone(),
         paypal_client,
+        events: EventBus::new(),
+        storage,
+        feature_flags,
     };
-    let app_state = Arc::new(state.clone());
-    HttpServer::new(move || {
+    // Revocar suscripciones cuyo periodo pagado y de gracia ya terminaron
+    let expiry_db = db.clone();
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(std::time::Duration::from_secs(60 * 60));
+        loop {
+            interval.tick().await;
+            match expiry_db.expire_lapsed_subscriptions().await {
+                Ok(0) => {}
+                Ok(n) => log::info!("Suscripciones expiradas tras el periodo de gracia: {}", n),
+                Err(e) => log::error!("No se pudo expirar suscripciones: {}", e),
+            }
+        }
+    });
+
+    // Recordatorios de curso para usuarios inactivos
+    let reminders_db = db.clone();
+    let reminders_config = state.env.clone();
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(std::time::Duration::from_secs(60 * 60));
+        loop {
+            interval.tick().await;
+            match send_course_reminders(&reminders_db, &reminders_config).await {
+                Ok(0) => {}
+                Ok(n) => log::info!("Recordatorios de curso enviados: {}", n),
+                Err(e) => log::error!("No se pudo enviar recordatorios de curso: {}", e),
+            }
+        }
+    });
+
+    // `Data` ya envuelve el estado en un `Arc`: se registra una sola vez como `Data<AppState>`
+    let app_state = Data::new(state);
+    let server = HttpServer::new(move || {
         App::new()
-            .app_data(Data::new(app_state.clone()))
+            .app_data(app_state.clone())
+            // Ningún middleware lee ni transforma el cuerpo de la petición: el webhook de PayPal
+            // recibe `web::Bytes` intactos. Si se reactiva `Compress`, sólo codifica respuestas
             // .wrap(Compress::default())
+            .wrap(ErrorHandlers::new().handler(StatusCode::METHOD_NOT_ALLOWED, json_method_not_allowed))
             .wrap(
-                actix_cors::Cors
-                    ::permissive()
-                    .allowed_origin_fn(|origin, _req_head| {
-                        let origin = origin.to_str().unwrap_or("");
-                        origin.as_bytes().ends_with(b"8080")
-                    })
-                    .allowed_origin_fn(|origin, _req_head| {
-                        let origin = origin.to_str().unwrap_or("");
-                        origin.as_bytes().ends_with(b"4173")
-                    })
-                    .allowed_origin_fn(|origin, _req_head| {
-                        let origin = origin.to_str().unwrap_or("");
-                        origin.as_bytes().ends_with(b".trycloudflare.com")
-                    })
-                    .allow_any_method()
-                    .allow_any_header()
-                    .supports_credentials()
-                    .max_age(3600)
+                RequestTimeout::new(Duration::from_secs(app_state.env.request_timeout_secs))
+                    // El SSE de progreso queda abierto mientras el alumno ve el curso
+                    .exclude(api_path(COURSE_PROGRESS_STREAM))
+                    .exclude(format!("{}{}", LEGACY_API_PREFIX, COURSE_PROGRESS_STREAM))
             )
-            .service(auth_scope())
-            .service(course_scope())
+            .wrap(CatchPanic)
+            .wrap(ApiVersionCompat)
+            .wrap(Logger::new(r#"[%{x-request-id}i] %a "%r" %s %b %T"#))
+            .wrap(RequestIdMiddlewareFactory)
+            .wrap(security_headers.clone())
+            .wrap(cors_policy.cors())
+            .service(auth_scope(AUTH_PREFIX))
+            .service(auth_scope(LEGACY_AUTH_PREFIX))
+            .service(tracking_scope())
+            .service(webhooks_scope())
+            // Antes de cada scope autenticado: el catálogo se consulta sin sesión
+            .configure(|cfg| public_routes(cfg, API_PREFIX))
+            .configure(|cfg| public_routes(cfg, LEGACY_API_PREFIX))
+            .configure(|cfg| media_service(cfg, &app_state.env))
             .service(
-                scope("")
+                // Con prefijo `/api/v1` las rutas desconocidas fuera de él no pasan por la autenticación
+                scope(API_PREFIX)
                     .wrap(AuthMiddlewareFactory::new(app_state.clone()))
                     .service(global_scope())
             )
+            // Registrado después de `API_PREFIX` para que `/api/v1/...` no caiga en el alias
+            .service(
+                scope(LEGACY_API_PREFIX)
+                    .wrap(AuthMiddlewareFactory::new(app_state.clone()))
+                    .service(global_scope())
+            )
+            .default_service(to(not_found))
     })
         .workers(8)
-        // .bind_openssl("127.0.0.1:8000", builder)?
-        .bind("0.0.0.0:8000")?
-        .run().await
+        .keep_alive(keep_alive)
+        .client_request_timeout(client_request_timeout)
+        .client_disconnect_timeout(client_disconnect_timeout);
+
+    // Con TLS, `bind_openssl` negocia HTTP/2 por ALPN (con HTTP/1.1 como respaldo)
+    let server = if tls_enabled {
+        server.bind_openssl("0.0.0.0:8000", ssl_acceptor())?
+    } else {
+        server.bind("0.0.0.0:8000")?
+    };
+    server.run().await
+}
+
+fn ssl_acceptor() -> SslAcceptorBuilder {
+    let current_dir = std::env::current_dir().expect("No se pudo obtener el directorio actual");
+    let key_path = current_dir.join("key.pem");
+    let cert_path = current_dir.join("cert.pem");
+
+    let mut builder = SslAcceptor::mozilla_intermediate(SslMethod::tls()).unwrap();
+    builder.set_private_key_file(key_path, SslFiletype::PEM).expect("No se pudo leer key.pem");
+    builder.set_certificate_chain_file(cert_path).expect("No se pudo leer cert.pem");
+    builder
 }
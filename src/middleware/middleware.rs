@@ -1,6 +1,6 @@
-use std::{rc::Rc, sync::Arc, future::Future};
+use std::{rc::Rc, future::Future, panic::AssertUnwindSafe, time::Duration};
 use actix_web::{
-    Error, HttpMessage, web::Data, HttpResponse, body::{EitherBody}, dev::{Service, ServiceRequest, ServiceResponse, Transform, forward_ready}, http::header
+    Error, HttpMessage, web::Data, HttpResponse, body::{BoxBody, EitherBody, MessageBody}, dev::{Service, ServiceRequest, ServiceResponse, Transform, forward_ready}, error::InternalError, http::{Method, StatusCode, header::{self, HeaderName, HeaderValue}}, middleware::ErrorHandlerResponse
 };
 use futures::{FutureExt, future::{LocalBoxFuture, Ready, ready}};
 use uuid::Uuid;
@@ -8,7 +8,7 @@ use std::pin::Pin;
 
 
 use crate::{
-    AppState, auth::auth::verify_jwt, db::db::{UserExt, CoursePurchaseExt, SubscriptionExt}, errors::error::{ErrorMessage, HttpError}, models::models::{User, UserRole}, utils::token::{TokenClaims, decode_token}
+    AppState, auth::auth::verify_jwt, config::{config::Config, dtos::ApiEnvelope}, db::db::{UserExt, CoursePurchaseExt, SubscriptionExt}, errors::error::{ErrorMessage, HttpError}, models::models::{User, UserRole}, utils::token::{TokenClaims, decode_token}
 };
 
 /// Estructura que contendrá al usuario autenticado
@@ -19,12 +19,12 @@ pub struct JWTAuthMiddleware {
 
 /// Middleware principal de autenticación JWT
 pub struct AuthMiddlewareFactory {
-    pub app_state: Arc<AppState>,
+    pub app_state: Data<AppState>,
 }
 
 impl AuthMiddlewareFactory {
     /// Middleware solo para autenticación
-    pub fn new(app_state: Arc<AppState>) -> Self {
+    pub fn new(app_state: Data<AppState>) -> Self {
         Self { app_state }
     }
 }
@@ -50,7 +50,7 @@ where
 
 pub struct AuthMiddleware<S> {
     service: Rc<S>,
-    app_state: Arc<AppState>,
+    app_state: Data<AppState>,
 }
 
 impl<S, B> Service<ServiceRequest> for AuthMiddleware<S>
@@ -89,7 +89,7 @@ where
             };
 
             // Verificar JWT
-            let user_id = match verify_jwt(&token) {
+            let user_id = match verify_jwt(&token, &app_state.env.decoding_key) {
                 Some(id) => id,
                 None => {
                     let err = HttpError::unauthorized(ErrorMessage::InvalidToken.to_string());
@@ -109,7 +109,10 @@ where
             let user = app_state.db_client
                 .get_user(Some(user_id), None, None, None)
                 .await
-                .map_err(|_| actix_web::error::ErrorUnauthorized("Usuario no encontrado"))?;
+                .map_err(|_| {
+                    let err = HttpError::unauthorized(ErrorMessage::UserNotFound.to_string());
+                    actix_web::error::ErrorUnauthorized(err.to_string())
+                })?;
 
             let user = match user {
                 Some(u) => u,
@@ -119,6 +122,11 @@ where
                 }
             };
 
+            if user.locked {
+                let err = HttpError::unauthorized(ErrorMessage::UserLocked.to_string());
+                return Err(actix_web::error::ErrorUnauthorized(err.to_string()));
+            }
+
             // Guardar usuario autenticado en la request
             req.extensions_mut().insert(JWTAuthMiddleware { user });
 
@@ -212,7 +220,7 @@ where
 
 // 🔹 Ejemplo básico de extracción de rol (puedes adaptarlo a tu JWT o base de datos)
 fn extract_user_role(req: &ServiceRequest) -> UserRole {
-    let app_data = req.app_data::<actix_web::web::Data<Arc<AppState>>>();
+    let app_data = req.app_data::<actix_web::web::Data<AppState>>();
     if app_data.is_none() {
         return UserRole::User;
     }
@@ -304,14 +312,14 @@ where
         let required = self.required.clone();
 
         async move {
-            let app_data = req.app_data::<Data<Arc<AppState>>>().unwrap();
+            let app_data = req.app_data::<Data<AppState>>().unwrap();
             let db_client = &app_data.db_client;
             let claims = extract_token_claims(&req, app_data.clone());
 
             if claims.is_none() {
                 let (req, _) = req.into_parts();
                 let res = HttpResponse::Unauthorized()
-                    .json(serde_json::json!({"error": "Token missing or invalid"}))
+                    .json(serde_json::json!({"error": ErrorMessage::InvalidToken.to_string()}))
                     .map_into_right_body();
                 return Ok(ServiceResponse::new(req, res));
             }
@@ -342,17 +350,11 @@ where
                         }
                     }
                     RequiredAccess::AnyCourseAccess => {
-                        // Verificar si el usuario tiene suscripción activa o cancelada pero no expirada
-                        let has_subscription = db_client.check_user_has_active_subscription(claims.sub).await;
-                        if has_subscription.is_ok() && has_subscription.unwrap() {
-                            allowed = true;
-                            continue;
-                        }
-
-                        // Si no tiene suscripción, verificar si tiene acceso a algún curso comprado
-                        let purchased_courses = db_client.get_user_purchased_courses(claims.sub)
+                        // Misma regla que la biblioteca: cursos comprados y, si es premium, todos
+                        let accessible = db_client
+                            .get_accessible_course_ids(claims.sub, app_data.env.premium_grants_all_courses)
                             .await;
-                        if purchased_courses.is_ok() && !purchased_courses.unwrap().is_empty() {
+                        if accessible.is_ok_and(|ids| !ids.is_empty()) {
                             allowed = true;
                         }
                     }
@@ -362,7 +364,7 @@ where
             if !allowed {
                 let (req, _) = req.into_parts();
                 let res = HttpResponse::Forbidden()
-                    .json(serde_json::json!({"error": "Permission denied"}))
+                    .json(serde_json::json!({"error": ErrorMessage::PermissionDenied.to_string()}))
                     .map_into_right_body();
                 return Ok(ServiceResponse::new(req, res));
             }
@@ -375,7 +377,7 @@ where
 }
 
 /// 🔹 Igual que `extract_user_role` pero devuelve todos los claims
-fn extract_token_claims(req: &ServiceRequest, app_data: Data<Arc<AppState>>) -> Option<TokenClaims> {
+fn extract_token_claims(req: &ServiceRequest, app_data: Data<AppState>) -> Option<TokenClaims> {
     let app_state = app_data.as_ref();
 
     let token = req.cookie("token")
@@ -391,3 +393,600 @@ fn extract_token_claims(req: &ServiceRequest, app_data: Data<Arc<AppState>>) ->
     let decoded = crate::utils::token::decode_token(token, app_state.env.decoding_key.clone()).ok()?;
     Some(decoded)
 }
+
+
+// ==================================
+// Middleware de timeout por request
+// ==================================
+/// Corta cualquier request que tarde más que el timeout configurado y responde
+/// `504 Gateway Timeout`. Al soltar el future del handler se cancela su trabajo
+/// pendiente (llamadas a PayPal, SMTP, etc.).
+#[derive(Clone)]
+pub struct RequestTimeout {
+    timeout: Duration,
+    // prefijo de ruta -> timeout propio (`None` = sin timeout); `{...}` acepta cualquier segmento
+    overrides: Vec<(String, Option<Duration>)>,
+}
+
+impl RequestTimeout {
+    pub fn new(timeout: Duration) -> Self {
+        Self { timeout, overrides: Vec::new() }
+    }
+
+    /// Usa un timeout distinto para las rutas que empiezan con `prefix`
+    #[allow(dead_code)]
+    pub fn with_override(mut self, prefix: impl Into<String>, timeout: Duration) -> Self {
+        self.overrides.push((prefix.into(), Some(timeout)));
+        self
+    }
+
+    /// Excluye del timeout las rutas que empiezan con `prefix` (streaming, websockets),
+    /// p. ej. `/api/v1/courses/{id}/progress/stream`
+    pub fn exclude(mut self, prefix: impl Into<String>) -> Self {
+        self.overrides.push((prefix.into(), None));
+        self
+    }
+
+    fn timeout_for(&self, req: &ServiceRequest) -> Option<Duration> {
+        // Websockets y SSE son conexiones largas por diseño
+        let is_websocket = req.headers()
+            .get(header::UPGRADE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.eq_ignore_ascii_case("websocket"));
+        let is_event_stream = req.headers()
+            .get(header::ACCEPT)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.contains("text/event-stream"));
+        if is_websocket || is_event_stream {
+            return None;
+        }
+
+        // Gana el prefijo más específico: el de más segmentos
+        self.overrides
+            .iter()
+            .filter(|(prefix, _)| path_has_prefix(req.path(), prefix))
+            .max_by_key(|(prefix, _)| prefix.split('/').count())
+            .map(|(_, timeout)| *timeout)
+            .unwrap_or(Some(self.timeout))
+    }
+}
+
+/// `true` si los primeros segmentos de `path` coinciden con los de `prefix`.
+fn path_has_prefix(path: &str, prefix: &str) -> bool {
+    let mut segments = path.split('/');
+    prefix.split('/').all(|expected| match segments.next() {
+        Some(segment) if expected.starts_with('{') && expected.ends_with('}') => !segment.is_empty(),
+        Some(segment) => segment == expected,
+        None => false,
+    })
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RequestTimeout
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RequestTimeoutMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestTimeoutMiddleware {
+            service: Rc::new(service),
+            config: self.clone(),
+        }))
+    }
+}
+
+pub struct RequestTimeoutMiddleware<S> {
+    service: Rc<S>,
+    config: RequestTimeout,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestTimeoutMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let srv = self.service.clone();
+        let timeout = self.config.timeout_for(&req);
+
+        async move {
+            let Some(timeout) = timeout else {
+                return srv.call(req).await;
+            };
+
+            // No se puede clonar el `HttpRequest` antes de llamar al servicio: el router
+            // necesita ser su único dueño. La respuesta de timeout viaja como error.
+            let method = req.method().clone();
+            let path = req.path().to_string();
+            let request_id = req.headers()
+                .get(REQUEST_ID_HEADER)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or("-")
+                .to_string();
+            match actix_web::rt::time::timeout(timeout, srv.call(req)).await {
+                Ok(res) => res,
+                Err(_) => {
+                    log::warn!("[{}] Request {} {} excedió el timeout de {:?}", request_id, method, path, timeout);
+                    let res = HttpError::gateway_timeout(ErrorMessage::RequestTimeout.to_string())
+                        .into_http_response();
+                    Err(InternalError::from_response(ErrorMessage::RequestTimeout.to_string(), res).into())
+                }
+            }
+        }
+        .boxed_local()
+    }
+}
+
+// ==================================
+// Middleware de cabeceras de seguridad
+// ==================================
+/// Añade las cabeceras de seguridad a todas las respuestas sin pisar las que ya
+/// haya puesto el handler. `Strict-Transport-Security` solo se envía con TLS.
+#[derive(Clone)]
+pub struct SecurityHeaders {
+    headers: Vec<(HeaderName, HeaderValue)>,
+}
+
+impl SecurityHeaders {
+    pub fn from_config(config: &Config) -> Result<Self, String> {
+        let mut headers = vec![
+            (header::X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff")),
+        ];
+        if let Some(value) = optional_header_value("CONTENT_SECURITY_POLICY", &config.content_security_policy)? {
+            headers.push((header::CONTENT_SECURITY_POLICY, value));
+        }
+        if let Some(value) = optional_header_value("X_FRAME_OPTIONS", &config.frame_options)? {
+            headers.push((header::X_FRAME_OPTIONS, value));
+        }
+        if let Some(value) = optional_header_value("REFERRER_POLICY", &config.referrer_policy)? {
+            headers.push((header::REFERRER_POLICY, value));
+        }
+        if config.tls_enabled && config.hsts_max_age_secs > 0 {
+            let hsts = format!("max-age={}; includeSubDomains", config.hsts_max_age_secs);
+            headers.push((header::STRICT_TRANSPORT_SECURITY, HeaderValue::from_str(&hsts).map_err(|e| e.to_string())?));
+        }
+
+        Ok(Self { headers })
+    }
+}
+
+fn optional_header_value(name: &str, value: &str) -> Result<Option<HeaderValue>, String> {
+    if value.trim().is_empty() {
+        return Ok(None);
+    }
+    HeaderValue::from_str(value.trim())
+        .map(Some)
+        .map_err(|e| format!("{} no es un valor de cabecera válido: {}", name, e))
+}
+
+impl<S, B> Transform<S, ServiceRequest> for SecurityHeaders
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = SecurityHeadersMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(SecurityHeadersMiddleware {
+            service: Rc::new(service),
+            config: self.clone(),
+        }))
+    }
+}
+
+pub struct SecurityHeadersMiddleware<S> {
+    service: Rc<S>,
+    config: SecurityHeaders,
+}
+
+impl<S, B> Service<ServiceRequest> for SecurityHeadersMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let srv = self.service.clone();
+        let headers = self.config.headers.clone();
+
+        async move {
+            match srv.call(req).await {
+                Ok(mut res) => {
+                    apply_headers(res.headers_mut(), &headers);
+                    Ok(res)
+                }
+                Err(err) => {
+                    let mut res = err.error_response();
+                    apply_headers(res.headers_mut(), &headers);
+                    Err(InternalError::from_response(err.to_string(), res).into())
+                }
+            }
+        }
+        .boxed_local()
+    }
+}
+
+fn apply_headers(target: &mut header::HeaderMap, headers: &[(HeaderName, HeaderValue)]) {
+    for (name, value) in headers {
+        if !target.contains_key(name) {
+            target.insert(name.clone(), value.clone());
+        }
+    }
+}
+
+// ==================================
+// Política CORS
+// ==================================
+/// Métodos y cabeceras que el frontend puede usar en peticiones cross-origin y cuánto
+/// tiempo puede el navegador cachear la respuesta del preflight.
+#[derive(Debug, Clone)]
+pub struct CorsPolicy {
+    methods: Vec<Method>,
+    headers: Vec<HeaderName>,
+    max_age_secs: usize,
+}
+
+impl CorsPolicy {
+    pub fn from_config(config: &Config) -> Result<Self, String> {
+        let methods = parse_list(&config.cors_allowed_methods)
+            .map(|m| Method::from_bytes(m.to_ascii_uppercase().as_bytes())
+                .map_err(|_| format!("CORS_ALLOWED_METHODS contiene un método inválido: {}", m)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let headers = parse_list(&config.cors_allowed_headers)
+            .map(|h| HeaderName::from_bytes(h.as_bytes())
+                .map_err(|_| format!("CORS_ALLOWED_HEADERS contiene una cabecera inválida: {}", h)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if methods.is_empty() {
+            return Err("CORS_ALLOWED_METHODS no puede estar vacío".to_string());
+        }
+        // Los navegadores recortan valores mayores (Firefox a 24 h, Chromium a 2 h)
+        if config.cors_max_age_secs > 86_400 {
+            return Err(format!("CORS_MAX_AGE_SECS debe estar entre 0 y 86400: {}", config.cors_max_age_secs));
+        }
+
+        Ok(CorsPolicy { methods, headers, max_age_secs: config.cors_max_age_secs })
+    }
+
+    /// `Cors` no es `Send`: se construye uno por worker a partir de la política validada.
+    pub fn cors(&self) -> actix_cors::Cors {
+        actix_cors::Cors::default()
+            .allowed_origin_fn(|origin, _req_head| {
+                let origin = origin.to_str().unwrap_or("");
+                origin.as_bytes().ends_with(b"8080")
+            })
+            .allowed_origin_fn(|origin, _req_head| {
+                let origin = origin.to_str().unwrap_or("");
+                origin.as_bytes().ends_with(b"4173")
+            })
+            .allowed_origin_fn(|origin, _req_head| {
+                let origin = origin.to_str().unwrap_or("");
+                origin.as_bytes().ends_with(b".trycloudflare.com")
+            })
+            .allowed_methods(self.methods.clone())
+            .allowed_headers(self.headers.clone())
+            .expose_any_header()
+            .supports_credentials()
+            .max_age(self.max_age_secs)
+    }
+}
+
+fn parse_list(list: &str) -> impl Iterator<Item = &str> {
+    list.split(',').map(str::trim).filter(|item| !item.is_empty())
+}
+
+// ==================================
+// Middleware de identificador de request
+// ==================================
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Identificador de la request, disponible en las extensiones para los handlers
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+/// Acepta el `X-Request-Id` del cliente si es razonable (1-128 caracteres
+/// alfanuméricos, `-`, `_` o `.`); si no, la request recibe uno nuevo.
+pub(crate) fn valid_request_id(value: Option<&str>) -> Option<String> {
+    let value = value?.trim();
+    let valid = !value.is_empty()
+        && value.len() <= 128
+        && value.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    valid.then(|| value.to_string())
+}
+
+/// Asigna un id a cada request, lo deja en las extensiones y en la cabecera de la
+/// request (para los logs y el resto de middlewares) y lo devuelve en la respuesta:
+/// como cabecera y, en los errores JSON, como `requestId` del `ErrorResponse`.
+#[derive(Clone, Default)]
+pub struct RequestIdMiddlewareFactory;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestIdMiddlewareFactory
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RequestIdMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestIdMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct RequestIdMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestIdMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let srv = self.service.clone();
+        let request_id = valid_request_id(
+            req.headers().get(REQUEST_ID_HEADER).and_then(|v| v.to_str().ok())
+        ).unwrap_or_else(|| Uuid::new_v4().to_string());
+
+        let header_value = HeaderValue::from_str(&request_id)
+            .expect("el id de request solo contiene caracteres válidos");
+        req.headers_mut().insert(HeaderName::from_static(REQUEST_ID_HEADER), header_value.clone());
+        req.extensions_mut().insert(RequestId(request_id.clone()));
+
+        async move {
+            let res = match srv.call(req).await {
+                Ok(res) => res,
+                // Errores que no llegaron a ser respuesta (timeout, pánico, auth): se
+                // completan aquí porque ya no hay `HttpRequest` para construir una
+                Err(err) => {
+                    let res = with_request_id(err.error_response(), &request_id, &header_value).await;
+                    return Err(InternalError::from_response(err.to_string(), res).into());
+                }
+            };
+
+            if !is_json_error(res.response()) {
+                let mut res = res.map_into_left_body();
+                res.headers_mut().insert(HeaderName::from_static(REQUEST_ID_HEADER), header_value);
+                return Ok(res);
+            }
+
+            let (req, res) = res.into_parts();
+            let res = with_request_id(res.map_into_boxed_body(), &request_id, &header_value).await;
+            Ok(ServiceResponse::new(req, res).map_into_right_body())
+        }
+        .boxed_local()
+    }
+}
+
+fn is_json_error<B>(res: &HttpResponse<B>) -> bool {
+    (res.status().is_client_error() || res.status().is_server_error())
+        && res.headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("application/json"))
+}
+
+/// Añade la cabecera y, si el cuerpo es un `ErrorResponse` JSON, el campo `requestId`
+async fn with_request_id(res: HttpResponse, request_id: &str, header_value: &HeaderValue) -> HttpResponse {
+    let json_error = is_json_error(&res);
+    let (mut res, body) = res.into_parts();
+    res.headers_mut().insert(HeaderName::from_static(REQUEST_ID_HEADER), header_value.clone());
+
+    let bytes = match actix_web::body::to_bytes(body).await {
+        Ok(bytes) => bytes,
+        Err(_) => return res.set_body(BoxBody::new(())),
+    };
+    if !json_error {
+        return res.set_body(BoxBody::new(bytes));
+    }
+
+    match serde_json::from_slice::<serde_json::Value>(&bytes) {
+        Ok(serde_json::Value::Object(mut map)) => {
+            map.insert("requestId".to_string(), serde_json::Value::String(request_id.to_string()));
+            res.set_body(BoxBody::new(serde_json::Value::Object(map).to_string()))
+        }
+        _ => res.set_body(BoxBody::new(bytes)),
+    }
+}
+
+// ==================================
+// Compatibilidad de versiones de la API
+// ==================================
+pub const API_VERSION_HEADER: &str = "x-api-version";
+/// Versión en la que varios endpoints devolvían el recurso sin `{ status, data }`.
+pub const LEGACY_API_VERSION: &str = "1";
+
+/// Extrae `data` de un cuerpo `ApiResponse`; `None` si el cuerpo no tiene esa forma.
+pub(crate) fn unwrap_api_envelope(body: &[u8]) -> Option<String> {
+    match serde_json::from_slice::<serde_json::Value>(body) {
+        Ok(serde_json::Value::Object(mut map)) => map.remove("data").map(|data| data.to_string()),
+        _ => None,
+    }
+}
+
+/// Los clientes que envían `X-API-Version: 1` siguen recibiendo solo `data` en las
+/// respuestas construidas con `ApiResponse`; el resto recibe el envoltorio.
+#[derive(Clone, Default)]
+pub struct ApiVersionCompat;
+
+impl<S, B> Transform<S, ServiceRequest> for ApiVersionCompat
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = ApiVersionCompatMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(ApiVersionCompatMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct ApiVersionCompatMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for ApiVersionCompatMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let srv = self.service.clone();
+        let legacy = req.headers()
+            .get(API_VERSION_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.trim() == LEGACY_API_VERSION);
+
+        async move {
+            let res = srv.call(req).await?;
+            if !legacy || res.response().extensions().get::<ApiEnvelope>().is_none() {
+                return Ok(res.map_into_left_body());
+            }
+
+            let (req, res) = res.into_parts();
+            let (res, body) = res.into_parts();
+            let bytes = actix_web::body::to_bytes(body).await
+                .map_err(|_| HttpError::server_error(ErrorMessage::ServerError.to_string()))?;
+            let body = match unwrap_api_envelope(&bytes) {
+                Some(data) => BoxBody::new(data),
+                None => BoxBody::new(bytes),
+            };
+            Ok(ServiceResponse::new(req, res.set_body(body)).map_into_right_body())
+        }
+        .boxed_local()
+    }
+}
+
+// ==================================
+// Middleware de captura de pánicos
+// ==================================
+/// Red de seguridad mientras quedan `.unwrap()` en los handlers: un pánico se
+/// registra y se responde `500` con el `ErrorResponse` habitual en lugar de
+/// cortar la conexión.
+#[derive(Clone, Default)]
+pub struct CatchPanic;
+
+impl<S, B> Transform<S, ServiceRequest> for CatchPanic
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = CatchPanicMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(CatchPanicMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct CatchPanicMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for CatchPanicMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let srv = self.service.clone();
+        // Igual que en `RequestTimeout`, el `HttpRequest` no se puede clonar antes del router
+        let method = req.method().clone();
+        let path = req.path().to_string();
+        let request_id = req.headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string())
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+        async move {
+            match AssertUnwindSafe(srv.call(req)).catch_unwind().await {
+                Ok(res) => res,
+                Err(payload) => {
+                    log::error!(
+                        "[{}] Pánico en {} {}: {}",
+                        request_id,
+                        method,
+                        path,
+                        panic_message(payload.as_ref())
+                    );
+                    let res = HttpError::server_error(ErrorMessage::ServerError.to_string())
+                        .into_http_response();
+                    Err(InternalError::from_response(ErrorMessage::ServerError.to_string(), res).into())
+                }
+            }
+        }
+        .boxed_local()
+    }
+}
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> &str {
+    payload.downcast_ref::<&str>().copied()
+        .or_else(|| payload.downcast_ref::<String>().map(|s| s.as_str()))
+        .unwrap_or("pánico sin mensaje")
+}
+
+/// `ErrorHandlers` para el 405 que genera actix (sin cuerpo) cuando la ruta existe
+/// pero no con ese método: lo devuelve con el mismo `ErrorResponse` que el resto.
+pub fn json_method_not_allowed<B>(res: ServiceResponse<B>) -> actix_web::Result<ErrorHandlerResponse<B>> {
+    let (req, _) = res.into_parts();
+    let res = HttpError::new(ErrorMessage::MethodNotAllowed.to_string(), StatusCode::METHOD_NOT_ALLOWED)
+        .into_http_response()
+        .map_into_right_body();
+    Ok(ErrorHandlerResponse::Response(ServiceResponse::new(req, res)))
+}
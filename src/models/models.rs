@@ -1,6 +1,15 @@
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use chrono::{DateTime, Utc, NaiveDate};
+use crate::config::dtos::PaymentResponseDTO;
+
+// Todas las respuestas JSON usan camelCase: cada modelo lleva
+// `#[serde(rename_all = "camelCase")]` en lugar de renombrar campo por campo.
+//
+// Fechas: siempre `DateTime<Utc>` (columnas TIMESTAMPTZ), serializadas en RFC3339
+// con sufijo `Z`. `created_at`/`updated_at` no son opcionales: la base de datos
+// las garantiza con NOT NULL y DEFAULT NOW(). Solo son `Option` las fechas de
+// eventos que pueden no haber ocurrido (`completed_at`, `used_at`...).
 
 // ===================== //
 //    ROLES DE USUARIO
@@ -26,6 +35,7 @@ impl UserRole {
 // ===================== //
 
 #[derive(Serialize, Deserialize, sqlx::FromRow, Debug, sqlx::Type, Clone)]
+#[serde(rename_all = "camelCase")]
 pub struct User {
     pub id: Uuid,
     pub name: String,
@@ -33,43 +43,36 @@ pub struct User {
     pub phone: Option<String>,
     pub location: Option<String>,
     pub bio: Option<String>,
-    #[serde(rename = "birthDate")]
     pub birth_date: Option<NaiveDate>, 
     pub verified: bool,
-    pub password: String,
+    // `None` en cuentas externas (SSO, invitaciones) que no inician sesión con contraseña
+    pub password: Option<String>,
     pub role: UserRole,
     pub verification_token: Option<String>,
     pub token_expiry: Option<DateTime<Utc>>,
-    #[serde(rename = "profileImageUrl", skip_serializing_if = "Option::is_none")]
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub profile_image_url: Option<String>,
-    #[serde(rename = "createdAt")]
-    pub created_at
-: Option<DateTime<Utc>>,
-    #[serde(rename = "updatedAt")]
-    pub updated_at: Option<DateTime<Utc>>,
-    #[serde(rename = "subscriptionExpiresAt")]
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
     pub subscription_expires_at: Option<DateTime<Utc>>, 
+    pub locked: bool,
+    pub auth_provider: String,
+    pub last_login_at: Option<DateTime<Utc>>,
 }
 
 #[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
 pub struct UserSettings {
     pub id: Uuid,
-    #[serde(rename = "userId")]
     pub user_id: Uuid,
-    #[serde(rename = "emailNotifications")]
     pub email_notifications: bool,
-    #[serde(rename = "pushNotifications")]
     pub push_notifications: bool,
-    #[serde(rename = "courseReminders")]
     pub course_reminders: bool,
-    #[serde(rename = "newContent")]
     pub new_content: bool,
-    #[serde(rename = "twoFactorEnabled")]
     pub two_factor_enabled: bool,
-    #[serde(rename = "createdAt")]
+    pub no_tracking: bool,
     pub created_at: DateTime<Utc>,
-    #[serde(rename = "updatedAt")]
     pub updated_at: DateTime<Utc>,
 }
 
@@ -78,9 +81,11 @@ pub struct UserSettings {
 // ===================== //
 #[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow, PartialEq)]
+#[serde(rename_all = "camelCase")]
 pub struct Course {
     pub id: Uuid,
     pub title: String,                       
+    pub slug: String,
     pub description: String,                  
     pub long_description: Option<String>,    
     pub level: String,                        
@@ -88,15 +93,67 @@ pub struct Course {
     pub duration: Option<String>,            
     pub students: i32,                                              
     pub image: Option<String>,                
+    pub trailer_url: Option<String>,
     pub category: String,                     
     pub features: Option<serde_json::Value>,
     pub paypal_product_id: Option<String>,
+    pub version: i32,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub featured: bool,
+    // Posición en la portada; sin valor va después de los ordenados
+    pub featured_order: Option<i32>,
+    // Cupo de alumnos; `None` = sin límite
+    pub max_students: Option<i32>,
+    // Oferta con fecha: `sale_price` rige entre `sale_starts_at` y `sale_ends_at`
+    pub sale_price: Option<f64>,
+    pub sale_starts_at: Option<DateTime<Utc>>,
+    pub sale_ends_at: Option<DateTime<Utc>>,
 }
 
+impl Course {
+    /// Precio a cobrar en `now`: `sale_price` dentro de la ventana de la oferta (inicio
+    /// incluido, fin excluido) y `price` fuera de ella. Es la misma regla que la función
+    /// SQL `course_effective_price` que usan los listados.
+    pub fn effective_price(&self, now: DateTime<Utc>) -> f64 {
+        match self.sale_price {
+            Some(sale_price)
+                if self.sale_starts_at.is_none_or(|start| start <= now)
+                    && self.sale_ends_at.is_none_or(|end| now < end) => sale_price,
+            _ => self.price,
+        }
+    }
+}
+
+
+/// Paquete de cursos que se vende como una sola compra, con descuento sobre la suma.
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Bundle {
+    pub id: Uuid,
+    pub title: String,
+    pub description: String,
+    pub discount_percent: i32,
+    pub active: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Textos de un curso en otro idioma; lo que no se traduce se toma del curso base.
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CourseTranslation {
+    pub course_id: Uuid,
+    pub locale: String,
+    pub title: String,
+    pub description: String,
+    pub long_description: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
 
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
+#[serde(rename_all = "camelCase")]
 pub struct Module {
     pub id: Uuid,
     pub course_id: Uuid,
@@ -105,6 +162,7 @@ pub struct Module {
 }
 
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
+#[serde(rename_all = "camelCase")]
 pub struct Lesson {
     pub id: Uuid,
     pub module_id: Uuid,
@@ -116,50 +174,39 @@ pub struct Lesson {
     pub content_url: Option<String>,
     pub description: Option<String>,
     pub order: i32, // orden dentro del módulo
+    pub is_preview: bool, // muestra gratuita visible sin comprar el curso
 }
 
 
 #[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
 pub struct UserCourse {
     pub id: Uuid,
-    #[serde(rename = "userId")]
     pub user_id: Uuid,
-    #[serde(rename = "courseId")]
     pub course_id: Uuid,
 
     #[serde(rename = "purchaseDate")]
     pub purchased_at: DateTime<Utc>,
 
-    #[serde(rename = "createdAt")]
     pub created_at: DateTime<Utc>,
-    #[serde(rename = "updatedAt")]
     pub updated_at: DateTime<Utc>,
 }
 
 #[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
 pub struct CourseProgress {
     pub id: Uuid,
-    #[serde(rename = "userId")]
     pub user_id: Uuid,
-    #[serde(rename = "courseId")]
     pub course_id: Uuid,
-    #[serde(rename = "progressPercentage")]
     pub progress_percentage: f32,
-    #[serde(rename = "totalLessons")]
     pub total_lessons: Option<i32>,
-    #[serde(rename = "completedLessons")]
     pub completed_lessons: Option<i32>,
-    #[serde(rename = "lastAccessed")]
     pub last_accessed: DateTime<Utc>,
-    #[serde(rename = "startedAt")]
     pub started_at: DateTime<Utc>,
-    #[serde(rename = "completedAt")]
     pub completed_at: Option<DateTime<Utc>>,
-    #[serde(rename = "createdAt")]
     pub created_at: DateTime<Utc>,
-    #[serde(rename = "updatedAt")]
     pub updated_at: DateTime<Utc>,
 }
 
@@ -168,19 +215,72 @@ pub struct CourseProgress {
 // ===================== //
 #[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
 pub struct Achievement {
     pub id: Uuid,
     pub name: String,
     pub description: Option<String>,
     pub icon: Option<String>,
-    pub trigger_type: String, // 'course_completed', 'lesson_completed', etc.
+    pub trigger_type: String, // ver `TriggerType`
     pub trigger_value: i32, // cantidad necesaria
     pub active: bool,
     pub created_at: DateTime<Utc>,
 }
 
+/// Acciones que pueden desbloquear un logro. Cada variante tiene su estadística
+/// en el registro de `db::trigger_stat_query`; añadir un disparador es añadir
+/// la variante y su consulta.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum TriggerType {
+    CourseCompleted,
+    LessonCompleted,
+    CoursesEnrolled,
+    CommentsCreated,
+    LoginStreak,
+    TotalSpend,
+}
+
+impl TriggerType {
+    pub const ALL: [TriggerType; 6] = [
+        TriggerType::CourseCompleted,
+        TriggerType::LessonCompleted,
+        TriggerType::CoursesEnrolled,
+        TriggerType::CommentsCreated,
+        TriggerType::LoginStreak,
+        TriggerType::TotalSpend,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TriggerType::CourseCompleted => "course_completed",
+            TriggerType::LessonCompleted => "lesson_completed",
+            TriggerType::CoursesEnrolled => "courses_enrolled",
+            TriggerType::CommentsCreated => "comments_created",
+            TriggerType::LoginStreak => "login_streak",
+            TriggerType::TotalSpend => "total_spend",
+        }
+    }
+}
+
+impl std::str::FromStr for TriggerType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        TriggerType::ALL
+            .into_iter()
+            .find(|t| t.as_str() == s)
+            .ok_or_else(|| format!(
+                "Unknown trigger type '{}'. Valid types: {}",
+                s,
+                TriggerType::ALL.map(|t| t.as_str()).join(", ")
+            ))
+    }
+}
+
 #[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
 pub struct UserAchievement {
     pub id: Uuid,
     pub user_id: Uuid,
@@ -194,6 +294,7 @@ pub struct UserAchievement {
 // ===================== //
 #[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
 pub struct Notification {
     pub id: Uuid,
     pub user_id: Uuid,
@@ -204,11 +305,35 @@ pub struct Notification {
     pub read: bool,
 }
 
+// ===================== //
+//  ESTADO DE SUSCRIPCIÓN
+// ===================== //
+/// Estados de la suscripción tal y como los notifica PayPal
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, sqlx::Type)]
+#[sqlx(type_name = "subscription_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum SubscriptionStatus {
+    Pending,
+    Active,
+    // Pago fallido: PayPal reintenta el cobro y el usuario está en periodo de gracia
+    Suspended,
+    Cancelled,
+    Expired,
+}
+
+impl SubscriptionStatus {
+    /// Suscripción en vigor (activa o suspendida pendiente de cobro)
+    pub fn is_current(&self) -> bool {
+        matches!(self, SubscriptionStatus::Active | SubscriptionStatus::Suspended)
+    }
+}
+
 // ===================== //
 // PLANES DE SUSCRIPCIÓN
 // ===================== //
 #[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
 pub struct SubscriptionPlan {
     pub id: Uuid,
     pub name: String,
@@ -220,26 +345,43 @@ pub struct SubscriptionPlan {
     pub active: bool,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub allowed_categories: Option<Vec<String>>, // None: todo el catálogo
+    pub trial_days: i32, // 0: sin periodo de prueba
 }
 #[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
 pub struct Subscription {
     pub id: Uuid,
     pub user_id: Uuid,
     pub paypal_subscription_id: String,
-    pub status: bool,
+    pub status: SubscriptionStatus,
     pub plan_id: Option<String>,
     pub start_time: DateTime<Utc>,
     pub end_time: Option<DateTime<Utc>>,
+    // Fin del periodo de gracia tras un pago fallido
+    pub grace_until: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+impl Subscription {
+    /// Da acceso premium en `now`: activa con el periodo pagado en curso, o activa o
+    /// suspendida dentro del periodo de gracia. Es la misma regla que la función SQL
+    /// `subscription_grants_access` que usan las consultas.
+    pub fn grants_access(&self, now: DateTime<Utc>) -> bool {
+        let paid = self.status == SubscriptionStatus::Active && self.end_time.is_some_and(|end| end > now);
+        let in_grace = self.status.is_current() && self.grace_until.is_some_and(|grace| grace > now);
+        paid || in_grace
+    }
+}
+
 // ===================== //
 // RELACIONES ENTRE MODELOS
 // ===================== //
 #[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct UserWithSettings {
     pub user: User,
     pub settings: Option<UserSettings>,
@@ -247,6 +389,7 @@ pub struct UserWithSettings {
 
 #[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct UserCourseWithProgress {
     pub user_course: UserCourse,
     pub progress: Option<CourseProgress>,
@@ -254,6 +397,7 @@ pub struct UserCourseWithProgress {
 
 #[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct UserWithAchievements {
     pub user: User,
     pub achievements: Vec<UserAchievement>,
@@ -261,6 +405,7 @@ pub struct UserWithAchievements {
 
 #[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct FullUserProfile {
     pub user: User,
     pub settings: Option<UserSettings>,
@@ -270,17 +415,21 @@ pub struct FullUserProfile {
     pub subscriptions: Vec<Subscription>,
 }
 
+/// Se serializa como `PaymentResponseDTO` (`amountMinor` + `amountDisplay`).
 #[derive(Debug, Clone, sqlx::FromRow, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", into = "PaymentResponseDTO")]
 pub struct Payment {
     pub id: Uuid,
     pub user_id: Uuid,
     pub course_id: Uuid,
+    // Unidades menores de `currency` (centavos)
     pub amount: i64,
+    pub currency: String,
     pub payment_method: String,
     pub transaction_id: String,
     pub status: String, // "pending", "completed", "failed"
     pub created_at: DateTime<Utc>,
-    pub updated_at: Option<DateTime<Utc>>,
+    pub updated_at: DateTime<Utc>,
 }
 
 // #[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
@@ -329,6 +478,7 @@ pub struct Payment {
 // ===================== //
 #[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
 pub struct PasswordResetToken {
     pub id: Uuid,
     pub user_id: Uuid,
@@ -337,4 +487,136 @@ pub struct PasswordResetToken {
     pub expires_at: DateTime<Utc>,
     pub used: bool,
     pub created_at: DateTime<Utc>,
-}
\ No newline at end of file
+}
+
+/// Lo que `/auth/refresh` necesita de un refresh token; el hash solo sirve para buscarlo.
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct RefreshToken {
+    pub user_id: Uuid,
+    pub expires_at: DateTime<Utc>,
+    pub revoked: bool,
+}
+// ===================== //
+// INVITACIONES DE USUARIOS
+// ===================== //
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct Invite {
+    pub id: Uuid,
+    pub email: String,
+    pub role: UserRole,
+    pub invited_by: Option<Uuid>,
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+// ===================== //
+// REPORTES DE CONTENIDO
+// ===================== //
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, sqlx::Type)]
+#[sqlx(type_name = "report_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum ReportStatus {
+    Open,
+    Resolved,
+    Dismissed,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct ContentReport {
+    pub id: Uuid,
+    pub reporter_id: Uuid,
+    pub course_id: Uuid,
+    pub lesson_id: Option<Uuid>,
+    pub reason: String,
+    pub details: Option<String>,
+    pub status: ReportStatus,
+    pub resolved_by: Option<Uuid>,
+    pub resolved_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+// ===================== //
+// AUDITORÍA
+// ===================== //
+#[allow(dead_code)]
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditLogEntry {
+    pub id: Uuid,
+    pub actor_id: Option<Uuid>,
+    pub action: String,
+    pub target_user_id: Option<Uuid>,
+    pub details: Option<serde_json::Value>,
+    pub created_at: DateTime<Utc>,
+}
+
+// ===================== //
+// AVISOS MASIVOS
+// ===================== //
+/// A quién llega un aviso masivo.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, sqlx::Type)]
+#[sqlx(type_name = "broadcast_segment", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum BroadcastSegment {
+    /// Todos los usuarios activos.
+    All,
+    /// Quienes tienen acceso a un curso (`course_id` obligatorio).
+    CourseStudents,
+    /// Usuarios con una suscripción activa.
+    Premium,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, sqlx::Type)]
+#[sqlx(type_name = "broadcast_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum BroadcastStatus {
+    Running,
+    Completed,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationBroadcast {
+    pub id: Uuid,
+    pub created_by: Option<Uuid>,
+    pub segment: BroadcastSegment,
+    pub course_id: Option<Uuid>,
+    pub title: String,
+    pub message: String,
+    pub send_email: bool,
+    pub status: BroadcastStatus,
+    pub recipients: i32,
+    pub emails_total: i32,
+    pub emails_sent: i32,
+    pub emails_failed: i32,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+/// Contenido de un curso antes de una edición (ver `CourseRevisionExt`).
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct CourseRevision {
+    pub id: Uuid,
+    pub course_id: Uuid,
+    pub revision: i32,
+    pub snapshot: serde_json::Value,
+    pub edited_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Estado guardado de un interruptor de funcionalidad (ver `services::feature_flags`).
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct FeatureFlag {
+    pub key: String,
+    pub enabled: bool,
+    pub updated_by: Option<Uuid>,
+    pub updated_at: DateTime<Utc>,
+}
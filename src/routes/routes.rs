@@ -1,9 +1,11 @@
-use actix_web::{dev::HttpServiceFactory, web::{resource, scope, get, put, post, delete}};
+use actix_web::{HttpResponse, dev::HttpServiceFactory, guard, web::{PayloadConfig, ServiceConfig, resource, scope, get, put, post, delete}};
 use uuid::Uuid;
 
 use crate::func::handlers;
 use crate::func::courses;
 use crate::func::payments;
+use crate::func::bundles;
+use crate::func::feature_flags::{get_feature_flags, set_feature_flag};
 use crate::func::{
     achievements::{
         create_achievement,
@@ -20,65 +22,178 @@ use crate::func::{
     subscriptions::{
         create_subscription_plan,
         get_subscription_plans,
+        get_subscription_plans_for_user,
         get_user_subscriptions,
         update_subscription_plan,
         delete_subscription_plan,
-        cancel_subscription
+        cancel_subscription,
+        get_subscription,
+        change_subscription_plan
     },
     notifications::{
+        broadcast_notification,
+        get_broadcast,
         get_notifications,
         mark_notification_as_read,
-        create_notification
+        create_notification,
+        track_open,
+        track_click,
+        email_bounce_webhook
     },
     courses::{
         create_course,
+        complete_course_for_user,
         create_lesson_comment,
         create_or_update_rating,
+        get_course_access,
+        stream_course_progress,
         delete_comment,
         delete_course,
         get_course_with_modules,
         get_course_with_modules_preview,
+        get_course_students,
+        get_related_courses,
+        import_course_progress,
+        get_courses_missing_paypal_product,
         get_courses_with_modules,
         get_lesson_comments,
         get_rating,
+        sync_course_paypal_product,
+        set_course_featured,
+        export_course,
+        export_courses,
+        set_course_sale,
+        clear_course_sale,
+        delete_course_translation,
+        get_course_translations,
+        set_course_translation,
+        set_lesson_completed,
+        set_course_max_students,
+        set_course_tags,
         update_course,
+        get_course_revisions,
+        restore_course_revision,
         update_lesson_progress
     },
     payments::{
         created_order,
-        paypal_webhook
+        paypal_webhook,
+        record_manual_payment
     },
     users::{
+        admin_reset_password,
+        bulk_user_action,
+        get_user_audit_log,
+        create_invite,
+        get_invites,
+        revoke_invite,
         get_me,
+        get_my_courses,
+        get_my_accessible_courses,
+        get_my_entitlements,
+        get_my_storage,
+        upload_my_avatar,
+        delete_my_avatar,
+        AVATAR_MAX_BYTES,
+        get_bounced_users,
+        get_inactive_users,
         get_users,
+        search_users,
         update_user_name,
         update_user_password,
         update_user_role
-    }
+    },
+    dashboard::get_dashboard,
+    stats::get_platform_stats,
+    reports::{create_report, get_reports, update_report}
 };
 use crate::middleware::middleware::{AccessCheck, RequiredAccess, RoleCheck};
 use crate::models::models::UserRole;
+use crate::errors::error::{ErrorMessage, HttpError};
+
+/// Ruta del SSE de progreso de un curso, relativa a `API_PREFIX`; no tiene timeout.
+pub const COURSE_PROGRESS_STREAM: &str = "/courses/{id}/progress/stream";
 
-pub fn auth_scope() -> impl HttpServiceFactory {
-    scope("/auth")
+/// Id de curso en las rutas públicas: solo un UUID, para que `/courses/videos` y
+/// demás rutas autenticadas del mismo nivel sigan llegando al scope de la API.
+const PUBLIC_COURSE_ID: &str = "{id:[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}}";
+
+pub fn auth_scope(prefix: &str) -> impl HttpServiceFactory {
+    scope(prefix)
         .service(handlers::register_user)
         .service(handlers::login_user)
         .service(handlers::verify_email)
         .service(handlers::logout_user)
+        .service(handlers::refresh_session)
+        .service(handlers::google_login)
+        .service(handlers::google_callback)
+        .service(handlers::accept_invite)
+        .service(handlers::forgot_password)
+        .service(handlers::reset_password)
         .service(
                     resource("/plans/subscriptions")
                         .route(get().to(get_subscription_plans))
         )
 }
 
-pub fn course_scope() -> impl HttpServiceFactory {
-    scope("/courses")
-        .route("", get().to(courses::get_courses))
+/// Servicio por defecto: rutas desconocidas responden con el `ErrorResponse` JSON habitual.
+pub async fn not_found() -> HttpResponse {
+    HttpError::not_found(ErrorMessage::RouteNotFound.to_string()).into_http_response()
 }
 
+/// Seguimiento de correos de campaña (público: lo piden los clientes de correo).
+pub fn tracking_scope() -> impl HttpServiceFactory {
+    scope("/track")
+        .route("/open/{token}", get().to(track_open))
+        .route("/click/{token}", get().to(track_click))
+}
 
+/// Webhooks de terceros (público: cada uno verifica su propia firma).
+pub fn webhooks_scope() -> impl HttpServiceFactory {
+    scope("/webhooks")
+        .route("/email-bounce", post().to(email_bounce_webhook))
+}
+
+/// Catálogo público (cursos y paquetes) bajo `prefix`, sin sesión. `main` lo registra
+/// antes del scope autenticado de `prefix`: cada ruta es un `resource` exacto con guard
+/// `GET`, así que cualquier otra petición sigue hacia ese scope.
+pub fn public_routes(cfg: &mut ServiceConfig, prefix: &str) {
+    let path = |p: &str| format!("{}{}", prefix, p);
+    cfg.service(resource(path("/courses")).guard(guard::Get()).to(courses::get_courses))
+        .service(resource(path("/courses/changes")).guard(guard::Get()).to(courses::get_course_changes))
+        .service(resource(path("/courses/tags")).guard(guard::Get()).to(courses::get_tags))
+        .service(resource(path("/courses/featured")).guard(guard::Get()).to(courses::get_featured_courses))
+        .service(resource(path("/courses/slug/{slug}")).guard(guard::Get()).to(courses::get_course_by_slug))
+        .service(resource(path(&format!("/courses/{}/outline", PUBLIC_COURSE_ID))).guard(guard::Get()).to(courses::get_course_outline))
+        .service(resource(path(&format!("/courses/{}", PUBLIC_COURSE_ID))).guard(guard::Get()).to(courses::get_course))
+        .service(resource(path("/bundles")).guard(guard::Get()).to(bundles::get_bundles));
+}
+
+
+/// Rutas de la API; `main` las monta bajo `API_PREFIX` (y el alias `LEGACY_API_PREFIX`)
+/// junto al middleware de autenticación.
 pub fn global_scope() -> impl HttpServiceFactory {
-    scope("/api")
+    scope("")
+        .service(
+            resource("/me/entitlements")
+                .route(get().to(get_my_entitlements))
+                .wrap(RoleCheck::new(vec![UserRole::User, UserRole::Admin])),
+        )
+        .service(
+            resource("/me/courses")
+                .route(get().to(get_my_accessible_courses))
+                .wrap(RoleCheck::new(vec![UserRole::User, UserRole::Admin])),
+        )
+        .service(
+            resource("/bundles/{bundle_id}/create-order")
+                .route(post().to(bundles::created_bundle_order))
+                .wrap(RoleCheck::new(vec![UserRole::User, UserRole::Admin])),
+        )
+        .service(
+            resource("/dashboard")
+                .route(get().to(get_dashboard))
+                .wrap(RoleCheck::new(vec![UserRole::User, UserRole::Admin])),
+        )
         .service(
             scope("/users")
                 .service(
@@ -86,6 +201,23 @@ pub fn global_scope() -> impl HttpServiceFactory {
                         .route(get().to(get_me))
                         .wrap(RoleCheck::new(vec![UserRole::User, UserRole::Admin])),
                 )
+                .service(
+                    resource("/me/courses")
+                        .route(get().to(get_my_courses))
+                        .wrap(RoleCheck::new(vec![UserRole::User, UserRole::Admin])),
+                )
+                .service(
+                    resource("/me/avatar")
+                        .app_data(PayloadConfig::new(AVATAR_MAX_BYTES))
+                        .route(put().to(upload_my_avatar))
+                        .route(delete().to(delete_my_avatar))
+                        .wrap(RoleCheck::new(vec![UserRole::User, UserRole::Admin])),
+                )
+                .service(
+                    resource("/me/storage")
+                        .route(get().to(get_my_storage))
+                        .wrap(RoleCheck::new(vec![UserRole::User, UserRole::Admin])),
+                )
                 .service(
                     resource("")
                         .route(get().to(get_users))
@@ -95,6 +227,33 @@ pub fn global_scope() -> impl HttpServiceFactory {
                 .service(resource("/role").route(put().to(update_user_role)))
                 .service(resource("/password").route(put().to(update_user_password)))
         )
+        .service(
+            scope("/admin")
+                .wrap(RoleCheck::new(vec![UserRole::Admin]))
+                .route("/stats", get().to(get_platform_stats))
+                .route("/bundles", post().to(bundles::create_bundle))
+                .service(
+                    resource("/bundles/{bundle_id}")
+                        .route(put().to(bundles::update_bundle))
+                        .route(delete().to(bundles::delete_bundle)),
+                )
+                .route("/users/bulk", post().to(bulk_user_action))
+                .route("/users/bounced", get().to(get_bounced_users))
+                .route("/users/inactive", get().to(get_inactive_users))
+                .route("/users/search", get().to(search_users))
+                .route("/users/{user_id}/reset-password", post().to(admin_reset_password))
+                .route("/users/{user_id}/audit-log", get().to(get_user_audit_log))
+                .route("/invites", post().to(create_invite))
+                .route("/invites", get().to(get_invites))
+                .route("/invites/{invite_id}", delete().to(revoke_invite))
+                .route("/reports", get().to(get_reports))
+                .route("/reports/{report_id}", put().to(update_report))
+                .route("/notifications/broadcast", post().to(broadcast_notification))
+                .route("/notifications/broadcasts/{broadcast_id}", get().to(get_broadcast))
+                .route("/feature-flags", get().to(get_feature_flags))
+                .route("/feature-flags/{flag}", put().to(set_feature_flag))
+                .route("/payments", post().to(record_manual_payment))
+        )
         .service(
             scope("/payments")
                 .route("/webhooks/paypal", post().to(paypal_webhook))
@@ -105,18 +264,67 @@ pub fn global_scope() -> impl HttpServiceFactory {
                     scope("/edit")
                     .wrap(RoleCheck::new(vec![UserRole::Admin]))
                     .route("", post().to(create_course))
+                    .route("/paypal-missing", get().to(get_courses_missing_paypal_product))
                     .route("/{id}", put().to(update_course))
                     .route("/{id}", delete().to(delete_course))
+                    .route("/{id}/sync-paypal", post().to(sync_course_paypal_product))
+                    .route("/{id}/tags", put().to(set_course_tags))
+                    .route("/{id}/featured", put().to(set_course_featured))
+                    .route("/{id}/max-students", put().to(set_course_max_students))
+                    .route("/{id}/sale", put().to(set_course_sale))
+                    .route("/{id}/sale", delete().to(clear_course_sale))
+                    .route("/{id}/translations", get().to(get_course_translations))
+                    .route("/{id}/translations/{locale}", put().to(set_course_translation))
+                    .route("/{id}/translations/{locale}", delete().to(delete_course_translation))
                 )
                 .service(
                     scope("/videos")
                         .wrap(RoleCheck::new(vec![UserRole::Admin]))
                         .route("", get().to(get_courses_with_modules))
                 )
+                .service(
+                    resource("/export")
+                        .route(get().to(export_courses))
+                        .wrap(RoleCheck::new(vec![UserRole::Admin])),
+                )
                 .service(
                     scope("/{id}")
                         .route("/videos/preview", get().to(get_course_with_modules_preview))
+                        .route("/related", get().to(get_related_courses))
                         .route("/createorder", post().to(created_order))
+                        .route("/report", post().to(create_report))
+                        .route("/access", get().to(get_course_access))
+                        .route("/progress/stream", get().to(stream_course_progress))
+                        .service(
+                            resource("/students")
+                                .route(get().to(get_course_students))
+                                .wrap(RoleCheck::new(vec![UserRole::Admin])),
+                        )
+                        .service(
+                            resource("/export")
+                                .route(get().to(export_course))
+                                .wrap(RoleCheck::new(vec![UserRole::Admin])),
+                        )
+                        .service(
+                            resource("/revisions")
+                                .route(get().to(get_course_revisions))
+                                .wrap(RoleCheck::new(vec![UserRole::Admin])),
+                        )
+                        .service(
+                            resource("/revisions/{rev}/restore")
+                                .route(post().to(restore_course_revision))
+                                .wrap(RoleCheck::new(vec![UserRole::Admin])),
+                        )
+                        .service(
+                            resource("/users/{user_id}/complete")
+                                .route(post().to(complete_course_for_user))
+                                .wrap(RoleCheck::new(vec![UserRole::Admin])),
+                        )
+                        .service(
+                            resource("/progress/import")
+                                .route(post().to(import_course_progress))
+                                .wrap(RoleCheck::new(vec![UserRole::Admin])),
+                        )
                         .service(
                             scope("/videos")
                             .wrap(AccessCheck::new(vec![
@@ -141,6 +349,7 @@ pub fn global_scope() -> impl HttpServiceFactory {
                         .service(
                             scope("/lessons")
                                 .route("/{lesson_id}/progress", put().to(update_lesson_progress))
+                                .route("/{lesson_id}/complete", post().to(set_lesson_completed))
                         )
                 )
         )
@@ -189,6 +398,7 @@ pub fn global_scope() -> impl HttpServiceFactory {
                 .service(
                     resource("/plans")
                         .route(post().to(create_subscription_plan))
+                        .route(get().to(get_subscription_plans_for_user))
                         .wrap(RoleCheck::new(vec![UserRole::User, UserRole::Admin])),
                 )
                 .service(
@@ -202,11 +412,21 @@ pub fn global_scope() -> impl HttpServiceFactory {
                         .route(get().to(get_user_subscriptions))
                         .wrap(RoleCheck::new(vec![UserRole::User, UserRole::Admin])),
                 )
+                .service(
+                    resource("/{subscription_id}")
+                        .route(get().to(get_subscription))
+                        .wrap(RoleCheck::new(vec![UserRole::User, UserRole::Admin])),
+                )
                 .service(
                     resource("/{subscription_id}/cancel")
                         .route(post().to(cancel_subscription))
                         .wrap(RoleCheck::new(vec![UserRole::User, UserRole::Admin])),
                 )
+                .service(
+                    resource("/{subscription_id}/change-plan")
+                        .route(post().to(change_subscription_plan))
+                        .wrap(RoleCheck::new(vec![UserRole::User, UserRole::Admin])),
+                )
         )
         .service(
             scope("/notifications")
@@ -0,0 +1,56 @@
+use crate::{
+    db::db::{BroadcastExt, DBClient, EmailEventExt},
+    mail::{mails::send_campaign_email, sendmail::EmailTracking},
+    models::models::{BroadcastStatus, NotificationBroadcast},
+};
+
+// Cada cuántos correos se guarda el progreso del envío
+const BROADCAST_PROGRESS_BATCH: i32 = 25;
+
+/// Envía en segundo plano los correos de un aviso masivo y va guardando el progreso
+/// para que `GET /api/v1/admin/notifications/broadcasts/{id}` lo muestre.
+pub async fn send_broadcast_emails(db: DBClient, api_base_url: String, broadcast: NotificationBroadcast) {
+    let recipients = match db.get_broadcast_email_recipients(broadcast.id).await {
+        Ok(recipients) => recipients,
+        Err(e) => {
+            log::error!("No se pudieron leer los destinatarios del aviso {}: {}", broadcast.id, e);
+            if let Err(e) = db.finish_broadcast(broadcast.id, BroadcastStatus::Failed).await {
+                log::error!("No se pudo cerrar el aviso {}: {}", broadcast.id, e);
+            }
+            return;
+        }
+    };
+
+    let (mut sent, mut failed) = (0, 0);
+    for recipient in recipients {
+        let tracking = match db.create_email_tracking(recipient.user_id, &broadcast.title).await {
+            Ok(token) => token.map(|token| EmailTracking { api_base_url: api_base_url.clone(), token }),
+            Err(e) => {
+                log::warn!("Aviso {} sin seguimiento para {}: {}", broadcast.id, recipient.user_id, e);
+                None
+            }
+        };
+
+        match send_campaign_email(&recipient.email, &recipient.name, &broadcast.title, &broadcast.message, tracking.as_ref()).await {
+            Ok(()) => sent += 1,
+            Err(e) => {
+                log::error!("No se pudo enviar el aviso {} a {}: {}", broadcast.id, recipient.user_id, e);
+                failed += 1;
+            }
+        }
+
+        if sent + failed >= BROADCAST_PROGRESS_BATCH {
+            if let Err(e) = db.record_broadcast_progress(broadcast.id, sent, failed).await {
+                log::error!("No se pudo guardar el progreso del aviso {}: {}", broadcast.id, e);
+            }
+            (sent, failed) = (0, 0);
+        }
+    }
+
+    if let Err(e) = db.record_broadcast_progress(broadcast.id, sent, failed).await {
+        log::error!("No se pudo guardar el progreso del aviso {}: {}", broadcast.id, e);
+    }
+    if let Err(e) = db.finish_broadcast(broadcast.id, BroadcastStatus::Completed).await {
+        log::error!("No se pudo cerrar el aviso {}: {}", broadcast.id, e);
+    }
+}
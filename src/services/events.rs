@@ -0,0 +1,46 @@
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::config::dtos::CourseProgressEventDto;
+
+// Eventos pendientes por suscriptor antes de que empiece a perderlos (`Lagged`)
+const EVENT_BUS_CAPACITY: usize = 256;
+
+/// Eventos de dominio que otras partes de la aplicación pueden escuchar.
+#[derive(Debug, Clone)]
+pub enum DomainEvent {
+    /// Se registró el avance de una lección y se recalculó el progreso del curso.
+    CourseProgressUpdated {
+        user_id: Uuid,
+        progress: CourseProgressEventDto,
+    },
+}
+
+/// Bus de eventos en memoria del proceso. Publicar sin suscriptores no es un error:
+/// el evento simplemente se descarta.
+#[derive(Clone, Debug)]
+pub struct EventBus {
+    sender: broadcast::Sender<DomainEvent>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUS_CAPACITY);
+        EventBus { sender }
+    }
+
+    pub fn publish(&self, event: DomainEvent) {
+        let _ = self.sender.send(event);
+    }
+
+    /// La suscripción se libera al soltar el `Receiver` (p. ej. cuando el cliente se desconecta).
+    pub fn subscribe(&self) -> broadcast::Receiver<DomainEvent> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,97 @@
+use std::{collections::HashMap, sync::Arc, time::{Duration, Instant}};
+
+use actix_web::HttpResponse;
+use tokio::sync::RwLock;
+
+use crate::{
+    AppState,
+    db::db::FeatureFlagExt,
+    errors::error::{ErrorMessage, HttpError},
+};
+
+/// Funcionalidades que se pueden apagar sin redesplegar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Feature {
+    /// Avance del curso en vivo por SSE (`/courses/{id}/progress/stream`).
+    ProgressStream,
+    /// Inicio de sesión con Google.
+    GoogleLogin,
+}
+
+impl Feature {
+    pub const ALL: [Feature; 2] = [Feature::ProgressStream, Feature::GoogleLogin];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Feature::ProgressStream => "progress_stream",
+            Feature::GoogleLogin => "google_login",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Feature> {
+        Feature::ALL.into_iter().find(|f| f.as_str() == key)
+    }
+}
+
+#[derive(Debug)]
+struct CachedFlags {
+    flags: HashMap<String, bool>,
+    loaded_at: Instant,
+}
+
+/// Interruptores leídos de `feature_flags` y guardados en memoria durante `ttl`. Cada
+/// instancia tiene su propia caché, así que un cambio tarda como mucho `ttl` en
+/// llegar a las demás.
+#[derive(Debug, Clone)]
+pub struct FeatureFlags {
+    cache: Arc<RwLock<Option<CachedFlags>>>,
+    ttl: Duration,
+}
+
+impl FeatureFlags {
+    pub fn new(ttl: Duration) -> Self {
+        FeatureFlags { cache: Arc::new(RwLock::new(None)), ttl }
+    }
+
+    /// Una funcionalidad sin fila queda activada. Si la base falla se usa el último valor
+    /// conocido (o activada) para no apagar nada por un error transitorio.
+    pub async fn is_enabled(&self, db: &impl FeatureFlagExt, feature: Feature) -> bool {
+        if let Some(cached) = self.cache.read().await.as_ref()
+            && cached.loaded_at.elapsed() < self.ttl
+        {
+            return cached.flags.get(feature.as_str()).copied().unwrap_or(true);
+        }
+
+        match db.get_feature_flags().await {
+            Ok(rows) => {
+                let flags: HashMap<String, bool> = rows.into_iter().map(|f| (f.key, f.enabled)).collect();
+                let enabled = flags.get(feature.as_str()).copied().unwrap_or(true);
+                self.store(flags).await;
+                enabled
+            }
+            Err(e) => {
+                log::error!("No se pudo leer los interruptores de funcionalidades: {}", e);
+                self.cache.read().await.as_ref()
+                    .and_then(|cached| cached.flags.get(feature.as_str()).copied())
+                    .unwrap_or(true)
+            }
+        }
+    }
+
+    pub(crate) async fn store(&self, flags: HashMap<String, bool>) {
+        *self.cache.write().await = Some(CachedFlags { flags, loaded_at: Instant::now() });
+    }
+
+    /// Fuerza la relectura en la próxima consulta (tras cambiar un interruptor).
+    pub async fn invalidate(&self) {
+        *self.cache.write().await = None;
+    }
+}
+
+/// `Some(404)` si la funcionalidad está apagada: para el cliente la ruta no existe.
+pub async fn feature_gate(app_state: &AppState, feature: Feature) -> Option<HttpResponse> {
+    if app_state.feature_flags.is_enabled(&app_state.db_client, feature).await {
+        return None;
+    }
+    Some(HttpError::not_found(ErrorMessage::FeatureDisabled.to_string()).into_http_response())
+}
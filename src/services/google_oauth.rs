@@ -0,0 +1,62 @@
+use reqwest::{Client, Url};
+use serde::Deserialize;
+
+const GOOGLE_AUTH_URL: &str = "https://accounts.google.com/o/oauth2/v2/auth";
+const GOOGLE_TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+const GOOGLE_USERINFO_URL: &str = "https://openidconnect.googleapis.com/v1/userinfo";
+
+/// Perfil devuelto por el endpoint `userinfo` de Google
+#[derive(Debug, Deserialize)]
+pub struct GoogleProfile {
+    pub sub: String,
+    pub email: String,
+    #[serde(default)]
+    pub email_verified: bool,
+    pub name: Option<String>,
+}
+
+/// URL de Google a la que se redirige al usuario para iniciar sesión.
+/// `state` se devuelve intacto en el callback y sirve de protección CSRF.
+pub fn authorization_url(client_id: &str, redirect_url: &str, state: &str) -> String {
+    let mut url = Url::parse(GOOGLE_AUTH_URL).expect("URL de Google válida");
+    url.query_pairs_mut()
+        .append_pair("client_id", client_id)
+        .append_pair("redirect_uri", redirect_url)
+        .append_pair("response_type", "code")
+        .append_pair("scope", "openid email profile")
+        .append_pair("state", state)
+        .append_pair("prompt", "select_account");
+    url.to_string()
+}
+
+/// Intercambia el código de autorización por un token y obtiene el perfil del usuario
+pub async fn fetch_profile(
+    client: &Client,
+    client_id: &str,
+    client_secret: &str,
+    redirect_url: &str,
+    code: &str,
+) -> Result<GoogleProfile, reqwest::Error> {
+    #[derive(Deserialize)]
+    struct TokenRes {
+        access_token: String,
+    }
+
+    let token: TokenRes = client.post(GOOGLE_TOKEN_URL)
+        .form(&[
+            ("code", code),
+            ("client_id", client_id),
+            ("client_secret", client_secret),
+            ("redirect_uri", redirect_url),
+            ("grant_type", "authorization_code"),
+        ])
+        .send().await?
+        .error_for_status()?
+        .json().await?;
+
+    client.get(GOOGLE_USERINFO_URL)
+        .bearer_auth(token.access_token)
+        .send().await?
+        .error_for_status()?
+        .json().await
+}
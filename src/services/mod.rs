@@ -1 +1,8 @@
-pub mod paypal_client;
\ No newline at end of file
+pub mod broadcasts;
+pub mod events;
+pub mod feature_flags;
+pub mod google_oauth;
+pub mod paypal_client;
+pub mod reminders;
+pub mod storage;
+pub mod uploads;
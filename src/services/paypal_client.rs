@@ -2,6 +2,17 @@ use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
+/// Datos del plan de facturación que se crea en PayPal.
+pub struct PlanParams<'a> {
+    pub product_id: &'a str,
+    pub name: &'a str,
+    pub description: &'a str,
+    pub price: f64,
+    pub interval: &'a str,
+    pub interval_count: i32,
+    pub trial_days: i32,
+}
+
 #[derive(Clone, Debug)]
 pub struct PayPalClient {
     pub client: Client,
@@ -193,9 +204,13 @@ impl PayPalClient {
         let body: SubRes = res.json().await?;
         Ok(body.id)
     }
-    pub async fn create_plan(&self, product_id: &str, name: &str, description: &str, price: f64, interval: &str, interval_count: i32)
+    /// Con `trial_days > 0` el plan empieza con un ciclo de prueba gratis: PayPal cobra
+    /// el primer periodo al terminar la prueba.
+    pub async fn create_plan(&self, params: PlanParams<'_>)
         -> Result<String, reqwest::Error>
     {
+        let PlanParams { product_id, name, description, price, interval, interval_count, trial_days } = params;
+
         #[derive(Serialize)]
         struct PricingScheme {
             fixed_price: Amount,
@@ -213,7 +228,8 @@ impl PayPalClient {
             tenure_type: String,
             sequence: i32,
             total_cycles: i32,
-            pricing_scheme: PricingScheme,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pricing_scheme: Option<PricingScheme>,
         }
 
         #[derive(Serialize)]
@@ -244,6 +260,36 @@ impl PayPalClient {
             id: String,
         }
 
+        let mut billing_cycles = Vec::new();
+        if trial_days > 0 {
+            // Ciclo de prueba sin `pricing_scheme`: gratis
+            billing_cycles.push(BillingCycle {
+                frequency: Frequency {
+                    interval_unit: "DAY".to_string(),
+                    interval_count: trial_days,
+                },
+                tenure_type: "TRIAL".to_string(),
+                sequence: 1,
+                total_cycles: 1,
+                pricing_scheme: None,
+            });
+        }
+        billing_cycles.push(BillingCycle {
+            frequency: Frequency {
+                interval_unit: interval.to_uppercase(),
+                interval_count,
+            },
+            tenure_type: "REGULAR".to_string(),
+            sequence: billing_cycles.len() as i32 + 1,
+            total_cycles: 0, // 0 significa indefinido
+            pricing_scheme: Some(PricingScheme {
+                fixed_price: Amount {
+                    currency_code: "USD".to_string(),
+                    value: format!("{:.2}", price),
+                },
+            }),
+        });
+
         let (h, v) = self.auth_header().await;
 
         let body = PlanReq {
@@ -251,21 +297,7 @@ impl PayPalClient {
             name,
             description,
             status: "ACTIVE",
-            billing_cycles: vec![BillingCycle {
-                frequency: Frequency {
-                    interval_unit: interval.to_uppercase(),
-                    interval_count,
-                },
-                tenure_type: "REGULAR".to_string(),
-                sequence: 1,
-                total_cycles: 0, // 0 significa indefinido
-                pricing_scheme: PricingScheme {
-                    fixed_price: Amount {
-                        currency_code: "USD".to_string(),
-                        value: format!("{:.2}", price),
-                    },
-                },
-            }],
+            billing_cycles,
             payment_preferences: PaymentPreferences {
                 auto_bill_outstanding: true,
                 setup_fee_failure_action: "CANCEL".to_string(),
@@ -330,4 +362,50 @@ impl PayPalClient {
 
         Ok(())
     }
+
+    // -----------------------------------------------------------
+    // 9. Cambiar el PLAN de una SUSCRIPCIÓN
+    // -----------------------------------------------------------
+    /// Devuelve la URL de aprobación si PayPal exige que el cliente apruebe el cambio.
+    pub async fn revise_subscription(&self, subscription_id: &str, plan_id: &str, return_url: &str, cancel_url: &str)
+        -> Result<Option<String>, reqwest::Error>
+    {
+        #[derive(Serialize)]
+        struct ApplicationContext<'a> {
+            return_url: &'a str,
+            cancel_url: &'a str,
+        }
+
+        #[derive(Serialize)]
+        struct ReviseReq<'a> {
+            plan_id: &'a str,
+            application_context: ApplicationContext<'a>,
+        }
+
+        #[derive(Deserialize)]
+        struct Link {
+            href: String,
+            rel: String,
+        }
+
+        #[derive(Deserialize)]
+        struct ReviseRes {
+            #[serde(default)]
+            links: Vec<Link>,
+        }
+
+        let (h, v) = self.auth_header().await;
+
+        let res = self.client.post(format!("{}/v1/billing/subscriptions/{}/revise", self.base_url, subscription_id))
+            .header(h, v)
+            .json(&ReviseReq {
+                plan_id,
+                application_context: ApplicationContext { return_url, cancel_url },
+            })
+            .send().await?
+            .error_for_status()?;
+
+        let body: ReviseRes = res.json().await?;
+        Ok(body.links.into_iter().find(|l| l.rel == "approve").map(|l| l.href))
+    }
 }
@@ -0,0 +1,47 @@
+use chrono::{Duration, Utc};
+
+use crate::{
+    config::config::Config,
+    db::db::{CourseReminderExt, DBClient},
+    mail::mails::send_course_reminder_email,
+};
+
+// Máximo de correos por ejecución para no saturar el SMTP
+const REMINDER_BATCH_SIZE: i64 = 100;
+
+/// Envía el recordatorio de la siguiente lección a los usuarios inactivos.
+/// Devuelve cuántos recordatorios se enviaron.
+pub async fn send_course_reminders(db: &DBClient, config: &Config) -> Result<usize, sqlx::Error> {
+    let now = Utc::now();
+    let reminders = db
+        .get_pending_course_reminders(
+            now - Duration::days(config.reminder_inactive_days),
+            now - Duration::days(config.reminder_interval_days),
+            REMINDER_BATCH_SIZE,
+        )
+        .await?;
+
+    let mut sent = 0;
+    for reminder in reminders {
+        let lesson_link = format!(
+            "{}/courses/{}?lesson={}",
+            config.frontend_base_url, reminder.course_id, reminder.lesson_id
+        );
+
+        if let Err(e) = send_course_reminder_email(
+            &reminder.email,
+            &reminder.name,
+            &reminder.course_title,
+            &reminder.lesson_title,
+            &lesson_link,
+        ).await {
+            log::error!("No se pudo enviar el recordatorio a {}: {}", reminder.user_id, e);
+            continue;
+        }
+
+        db.record_course_reminder(reminder.user_id, reminder.course_id, reminder.lesson_id).await?;
+        sent += 1;
+    }
+
+    Ok(sent)
+}
@@ -0,0 +1,312 @@
+use std::{fmt, path::PathBuf, sync::Arc};
+
+use actix_files::Files;
+use actix_web::{http::header, middleware::DefaultHeaders, web::{scope, Bytes, ServiceConfig}};
+use async_trait::async_trait;
+use chrono::Utc;
+use openssl::{hash::MessageDigest, pkey::PKey, sign::Signer};
+use reqwest::Client;
+
+use crate::config::config::{Config, StorageBackend};
+
+#[derive(Debug)]
+pub enum StorageError {
+    InvalidKey,
+    Io(std::io::Error),
+    Remote(String),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::InvalidKey => write!(f, "Clave de archivo inválida"),
+            StorageError::Io(e) => write!(f, "Error de disco: {}", e),
+            StorageError::Remote(e) => write!(f, "Error del almacenamiento remoto: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+/// Almacenamiento de archivos subidos. Los handlers solo conocen la clave
+/// (p. ej. `avatars/<id>.png`) y la URL pública que devuelve `put`.
+#[async_trait]
+pub trait Storage: Send + Sync + fmt::Debug {
+    /// Guarda el archivo (reemplazándolo si ya existe) y devuelve su URL pública.
+    async fn put(&self, key: &str, bytes: Bytes, content_type: &str) -> Result<String, StorageError>;
+    /// `None` si el archivo no existe.
+    async fn get(&self, key: &str) -> Result<Option<Bytes>, StorageError>;
+    /// Borrar un archivo que no existe no es un error.
+    async fn delete(&self, key: &str) -> Result<(), StorageError>;
+    fn public_url(&self, key: &str) -> String;
+    /// URL de descarga válida durante `expires_in_secs` aunque el archivo no sea público.
+    fn signed_url(&self, key: &str, expires_in_secs: u64) -> Result<String, StorageError>;
+}
+
+/// Valida una clave de archivo: segmentos no vacíos de `[A-Za-z0-9._-]` separados por `/`,
+/// sin `.`/`..` ni archivos ocultos. Así ninguna clave escapa del directorio de medios y
+/// no hace falta codificarla en las URLs.
+pub fn sanitize_key(key: &str) -> Option<&str> {
+    let valid_segment = |segment: &str| {
+        !segment.is_empty()
+            && !segment.starts_with('.')
+            && segment.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+    };
+    (!key.is_empty() && key.split('/').all(valid_segment)).then_some(key)
+}
+
+/// Archivos en disco, servidos por `media_service` bajo `Config::media_path`.
+#[derive(Debug, Clone)]
+pub struct LocalStorage {
+    pub root: PathBuf,
+    pub base_url: String,
+}
+
+#[async_trait]
+impl Storage for LocalStorage {
+    async fn put(&self, key: &str, bytes: Bytes, _content_type: &str) -> Result<String, StorageError> {
+        let key = sanitize_key(key).ok_or(StorageError::InvalidKey)?;
+        let path = self.root.join(key);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await.map_err(StorageError::Io)?;
+        }
+        tokio::fs::write(&path, &bytes).await.map_err(StorageError::Io)?;
+        Ok(self.public_url(key))
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Bytes>, StorageError> {
+        let key = sanitize_key(key).ok_or(StorageError::InvalidKey)?;
+        match tokio::fs::read(self.root.join(key)).await {
+            Ok(bytes) => Ok(Some(Bytes::from(bytes))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(StorageError::Io(e)),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        let key = sanitize_key(key).ok_or(StorageError::InvalidKey)?;
+        match tokio::fs::remove_file(self.root.join(key)).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(StorageError::Io(e)),
+            _ => Ok(()),
+        }
+    }
+
+    fn public_url(&self, key: &str) -> String {
+        format!("{}/{}", self.base_url, key)
+    }
+
+    // El directorio de medios se sirve públicamente: la URL no necesita firma
+    fn signed_url(&self, key: &str, _expires_in_secs: u64) -> Result<String, StorageError> {
+        let key = sanitize_key(key).ok_or(StorageError::InvalidKey)?;
+        Ok(self.public_url(key))
+    }
+}
+
+/// Bucket S3 (o compatible, p. ej. MinIO o R2) con URLs de estilo ruta y firma SigV4.
+#[derive(Clone)]
+pub struct S3Storage {
+    pub client: Client,
+    pub bucket: String,
+    pub region: String,
+    pub endpoint: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub public_url: String,
+}
+
+// Sin el secreto: `AppState` se puede imprimir en los logs
+impl fmt::Debug for S3Storage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("S3Storage")
+            .field("bucket", &self.bucket)
+            .field("region", &self.region)
+            .field("endpoint", &self.endpoint)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Segundos máximos de validez de una URL prefirmada de S3 (7 días).
+pub const S3_MAX_SIGNED_URL_SECS: u64 = 604_800;
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    PKey::hmac(key)
+        .and_then(|key| {
+            let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+            signer.update(data.as_bytes())?;
+            signer.sign_to_vec()
+        })
+        .expect("HMAC-SHA256 no disponible en OpenSSL")
+}
+
+/// Codificación de URI de SigV4: todo salvo `A-Za-z0-9-._~` va como `%XX`.
+fn aws_uri_encode(value: &str) -> String {
+    value.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+impl S3Storage {
+    fn object_url(&self, key: &str) -> String {
+        format!("{}/{}/{}", self.endpoint, self.bucket, key)
+    }
+
+    fn host(&self) -> &str {
+        self.endpoint.split("://").nth(1).unwrap_or(&self.endpoint)
+    }
+
+    fn credential_scope(&self, amz_date: &str) -> String {
+        format!("{}/{}/s3/aws4_request", &amz_date[..8], self.region)
+    }
+
+    /// Firma SigV4 en hex de una petición canónica ya armada.
+    fn signature(&self, amz_date: &str, canonical_request: &str) -> String {
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date, self.credential_scope(amz_date), hex(&openssl::sha::sha256(canonical_request.as_bytes()))
+        );
+        let date_key = hmac_sha256(format!("AWS4{}", self.secret_access_key).as_bytes(), &amz_date[..8]);
+        let region_key = hmac_sha256(&date_key, &self.region);
+        let service_key = hmac_sha256(&region_key, "s3");
+        let signing_key = hmac_sha256(&service_key, "aws4_request");
+        hex(&hmac_sha256(&signing_key, &string_to_sign))
+    }
+
+    /// Cabecera `Authorization` de AWS Signature V4 para `method` sobre la clave `key`
+    /// (ya validada, por lo que la ruta canónica no necesita codificarse).
+    pub(crate) fn authorization(&self, method: &str, key: &str, payload_hash: &str, amz_date: &str) -> String {
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "{}\n/{}/{}\n\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+            method, self.bucket, key, self.host(), payload_hash, amz_date, signed_headers, payload_hash
+        );
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key_id, self.credential_scope(amz_date), signed_headers, self.signature(amz_date, &canonical_request)
+        )
+    }
+
+    /// URL `GET` prefirmada (firma en la query, sin cabeceras) para la clave `key`.
+    pub(crate) fn presigned_get_url(&self, key: &str, expires_in_secs: u64, amz_date: &str) -> String {
+        let credential = format!("{}/{}", self.access_key_id, self.credential_scope(amz_date));
+        // Ya en orden alfabético, como exige la query canónica
+        let query = format!(
+            "X-Amz-Algorithm=AWS4-HMAC-SHA256&X-Amz-Credential={}&X-Amz-Date={}&X-Amz-Expires={}&X-Amz-SignedHeaders=host",
+            aws_uri_encode(&credential), amz_date, expires_in_secs.min(S3_MAX_SIGNED_URL_SECS)
+        );
+        let canonical_request = format!(
+            "GET\n/{}/{}\n{}\nhost:{}\n\nhost\nUNSIGNED-PAYLOAD",
+            self.bucket, key, query, self.host()
+        );
+        format!("{}?{}&X-Amz-Signature={}", self.object_url(key), query, self.signature(amz_date, &canonical_request))
+    }
+
+    /// Envía la petición firmada. Un 404 devuelve `None`; cualquier otro error del bucket falla.
+    async fn send(&self, method: reqwest::Method, key: &str, body: Bytes, content_type: Option<&str>) -> Result<Option<Bytes>, StorageError> {
+        let key = sanitize_key(key).ok_or(StorageError::InvalidKey)?;
+        let payload_hash = hex(&openssl::sha::sha256(&body));
+        let amz_date = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+
+        let mut request = self.client
+            .request(method.clone(), self.object_url(key))
+            .header("x-amz-content-sha256", &payload_hash)
+            .header("x-amz-date", &amz_date)
+            .header(header::AUTHORIZATION.as_str(), self.authorization(method.as_str(), key, &payload_hash, &amz_date));
+        if let Some(content_type) = content_type {
+            request = request.header(header::CONTENT_TYPE.as_str(), content_type);
+        }
+
+        let res = request.body(body).send().await
+            .map_err(|e| StorageError::Remote(e.to_string()))?;
+        if res.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !res.status().is_success() {
+            let status = res.status();
+            let text = res.text().await.unwrap_or_default();
+            return Err(StorageError::Remote(format!("{}: {}", status, text)));
+        }
+        res.bytes().await
+            .map(Some)
+            .map_err(|e| StorageError::Remote(e.to_string()))
+    }
+}
+
+#[async_trait]
+impl Storage for S3Storage {
+    async fn put(&self, key: &str, bytes: Bytes, content_type: &str) -> Result<String, StorageError> {
+        self.send(reqwest::Method::PUT, key, bytes, Some(content_type)).await?;
+        Ok(self.public_url(key))
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Bytes>, StorageError> {
+        self.send(reqwest::Method::GET, key, Bytes::new(), None).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        // S3 responde 204 también cuando el objeto no existe
+        self.send(reqwest::Method::DELETE, key, Bytes::new(), None).await.map(|_| ())
+    }
+
+    fn public_url(&self, key: &str) -> String {
+        if self.public_url.is_empty() {
+            self.object_url(key)
+        } else {
+            format!("{}/{}", self.public_url, key)
+        }
+    }
+
+    fn signed_url(&self, key: &str, expires_in_secs: u64) -> Result<String, StorageError> {
+        let key = sanitize_key(key).ok_or(StorageError::InvalidKey)?;
+        Ok(self.presigned_get_url(key, expires_in_secs, &Utc::now().format("%Y%m%dT%H%M%SZ").to_string()))
+    }
+}
+
+/// Backend elegido por `STORAGE_BACKEND`.
+pub fn storage_from_config(config: &Config, client: Client) -> Arc<dyn Storage> {
+    match config.storage_backend {
+        StorageBackend::Local => Arc::new(LocalStorage {
+            root: PathBuf::from(&config.media_dir),
+            base_url: format!("{}{}", config.api_base_url, config.media_path),
+        }),
+        StorageBackend::S3 => {
+            let endpoint = if config.s3_endpoint.is_empty() {
+                format!("https://s3.{}.amazonaws.com", config.s3_region)
+            } else {
+                config.s3_endpoint.trim_end_matches('/').to_string()
+            };
+            Arc::new(S3Storage {
+                client,
+                bucket: config.s3_bucket.clone(),
+                region: config.s3_region.clone(),
+                endpoint,
+                access_key_id: config.s3_access_key_id.clone(),
+                secret_access_key: config.s3_secret_access_key.clone(),
+                public_url: config.s3_public_url.trim_end_matches('/').to_string(),
+            })
+        }
+    }
+}
+
+/// Sirve `media_dir` bajo `media_path` con caché pública. `Files` ya rechaza rutas con `..`
+/// y archivos ocultos, y no lista directorios. Con S3 no se monta: los archivos se
+/// sirven desde el bucket.
+pub fn media_service(cfg: &mut ServiceConfig, config: &Config) {
+    if config.storage_backend != StorageBackend::Local {
+        return;
+    }
+    cfg.service(
+        scope(&config.media_path)
+            .wrap(DefaultHeaders::new().add((
+                header::CACHE_CONTROL,
+                format!("public, max-age={}", config.media_cache_max_age_secs),
+            )))
+            .service(Files::new("", &config.media_dir).use_etag(true).use_last_modified(true))
+    );
+}
@@ -0,0 +1,84 @@
+use actix_web::web::Bytes;
+use uuid::Uuid;
+
+use crate::{
+    db::db::{DBClient, UploadReservation, UserUploadExt},
+    errors::error::{ErrorMessage, HttpError},
+    services::storage::{Storage, StorageError, sanitize_key},
+};
+
+#[derive(Debug)]
+pub enum UploadError {
+    QuotaExceeded { used_bytes: i64, quota_bytes: i64 },
+    // La clave es de un archivo de otro usuario
+    KeyTaken,
+    Storage(StorageError),
+    Db(sqlx::Error),
+}
+
+impl UploadError {
+    /// `413` si no cabe en la cuota, con el uso actual en `details`. Como los demás
+    /// estados que no son 400, se responde con `into_http_response`.
+    pub fn into_http_error(self) -> HttpError {
+        match self {
+            UploadError::QuotaExceeded { used_bytes, quota_bytes } => {
+                HttpError::payload_too_large(ErrorMessage::StorageQuotaExceeded.to_string())
+                    .with_details(serde_json::json!({ "usedBytes": used_bytes, "quotaBytes": quota_bytes }))
+            }
+            UploadError::KeyTaken => HttpError::forbidden(ErrorMessage::PermissionDenied.to_string()),
+            UploadError::Storage(StorageError::InvalidKey) => HttpError::bad_request(StorageError::InvalidKey.to_string()),
+            UploadError::Storage(e) => HttpError::server_error(e.to_string()),
+            UploadError::Db(e) => HttpError::server_error(e.to_string()),
+        }
+    }
+}
+
+/// Sube un archivo del usuario descontándolo de su cuota y devuelve su URL pública.
+/// El espacio se reserva antes de escribir; si la escritura falla un archivo nuevo se
+/// libera, y uno reemplazado sigue contando con el tamaño nuevo (nunca por debajo).
+pub async fn put_user_upload(
+    db: &DBClient,
+    storage: &dyn Storage,
+    user_id: Uuid,
+    key: &str,
+    bytes: Bytes,
+    content_type: &str,
+    quota_bytes: i64,
+) -> Result<String, UploadError> {
+    let key = sanitize_key(key).ok_or(UploadError::Storage(StorageError::InvalidKey))?;
+    let existed = storage.get(key).await.map_err(UploadError::Storage)?.is_some();
+
+    match db.reserve_user_upload(user_id, key, bytes.len() as i64, quota_bytes).await.map_err(UploadError::Db)? {
+        UploadReservation::Reserved => {}
+        UploadReservation::QuotaExceeded { used_bytes } => {
+            return Err(UploadError::QuotaExceeded { used_bytes, quota_bytes });
+        }
+        UploadReservation::KeyTaken => return Err(UploadError::KeyTaken),
+    }
+
+    match storage.put(key, bytes, content_type).await {
+        Ok(url) => Ok(url),
+        Err(e) => {
+            if !existed {
+                let _ = db.release_user_upload(user_id, key).await;
+            }
+            Err(UploadError::Storage(e))
+        }
+    }
+}
+
+/// Borra un archivo del usuario y devuelve su espacio a la cuota. `false` si la clave
+/// no es de un archivo suyo.
+pub async fn delete_user_upload(
+    db: &DBClient,
+    storage: &dyn Storage,
+    user_id: Uuid,
+    key: &str,
+) -> Result<bool, UploadError> {
+    let key = sanitize_key(key).ok_or(UploadError::Storage(StorageError::InvalidKey))?;
+    if !db.release_user_upload(user_id, key).await.map_err(UploadError::Db)? {
+        return Ok(false);
+    }
+    storage.delete(key).await.map_err(UploadError::Storage)?;
+    Ok(true)
+}
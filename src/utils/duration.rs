@@ -0,0 +1,51 @@
+/// Convierte la duración escrita de una lección a segundos. Admite reloj
+/// (`"12:30"` = mm:ss, `"1:05:00"` = h:mm:ss) y unidades (`"1h 05m"`, `"45 min"`,
+/// `"30s"`). Devuelve `None` si no se entiende, incluido un número sin unidad.
+pub fn parse_duration_seconds(raw: &str) -> Option<u64> {
+    let raw = raw.trim().to_lowercase();
+    if raw.is_empty() {
+        return None;
+    }
+    if raw.contains(':') {
+        parse_clock(&raw)
+    } else {
+        parse_units(&raw)
+    }
+}
+
+fn parse_clock(raw: &str) -> Option<u64> {
+    let parts = raw.split(':')
+        .map(|p| p.trim().parse::<u64>().ok())
+        .collect::<Option<Vec<_>>>()?;
+
+    let (hours, minutes, seconds) = match parts.as_slice() {
+        [m, s] => (0, *m, *s),
+        [h, m, s] if *m < 60 => (*h, *m, *s),
+        _ => return None,
+    };
+    if seconds >= 60 {
+        return None;
+    }
+    Some(hours * 3600 + minutes * 60 + seconds)
+}
+
+fn parse_units(raw: &str) -> Option<u64> {
+    let mut total = 0;
+    let mut rest = raw;
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        let value: u64 = rest[..digits].parse().ok()?;
+        rest = rest[digits..].trim_start();
+
+        let unit_len = rest.find(|c: char| !c.is_alphabetic()).unwrap_or(rest.len());
+        let factor = match &rest[..unit_len] {
+            "h" | "hr" | "hrs" | "hora" | "horas" => 3600,
+            "m" | "min" | "mins" | "minuto" | "minutos" => 60,
+            "s" | "seg" | "sec" | "segundo" | "segundos" => 1,
+            _ => return None,
+        };
+        total += value * factor;
+        rest = rest[unit_len..].trim_start();
+    }
+    Some(total)
+}
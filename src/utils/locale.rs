@@ -0,0 +1,51 @@
+/// Normaliza una etiqueta de idioma BCP 47 ("pt_BR" -> "pt-br"). `None` si no tiene la
+/// forma `idioma[-subetiqueta...]` (idioma de 2-3 letras, subetiquetas de 1-8 alfanuméricos).
+pub fn normalize_locale(raw: &str) -> Option<String> {
+    let locale = raw.trim().replace('_', "-").to_ascii_lowercase();
+    let mut parts = locale.split('-');
+
+    let language = parts.next()?;
+    if !(2..=3).contains(&language.len()) || !language.bytes().all(|b| b.is_ascii_lowercase()) {
+        return None;
+    }
+    if !parts.all(|p| (1..=8).contains(&p.len()) && p.bytes().all(|b| b.is_ascii_alphanumeric())) {
+        return None;
+    }
+    (locale.len() <= 35).then_some(locale)
+}
+
+/// Idiomas a probar según `Accept-Language`, del preferido al menos preferido. Cada
+/// etiqueta regional va seguida de su idioma ("es-CO" -> "es-co", "es"). La lista se
+/// corta al llegar a `default_locale`: desde ahí el contenido base ya es la mejor opción.
+pub fn preferred_locales(accept_language: &str, default_locale: &str) -> Vec<String> {
+    let mut ranges: Vec<(&str, f32)> = accept_language
+        .split(',')
+        .filter_map(|entry| {
+            let mut params = entry.split(';');
+            let tag = params.next()?.trim();
+            let q = params
+                .find_map(|p| p.trim().strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok())?;
+            (q > 0.0 && tag != "*").then_some((tag, q))
+        })
+        .collect();
+    // Orden estable: a igual `q` se respeta el orden del encabezado
+    ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    let mut locales: Vec<String> = Vec::new();
+    for (tag, _) in ranges {
+        let Some(locale) = normalize_locale(tag) else {
+            continue;
+        };
+        let language = locale.split('-').next().unwrap_or_default().to_string();
+        for candidate in [locale, language] {
+            if candidate == default_locale {
+                return locales;
+            }
+            if !locales.contains(&candidate) {
+                locales.push(candidate);
+            }
+        }
+    }
+    locales
+}
@@ -1,2 +1,8 @@
 pub mod password;
-pub mod token;
\ No newline at end of file
+pub mod token;
+pub mod slug;pub mod redact;
+pub mod path_id;
+pub mod duration;
+pub mod money;
+pub mod locale;
+pub mod redirect;
@@ -0,0 +1,96 @@
+/// Monedas de PayPal que no usan decimales.
+const ZERO_DECIMAL_CURRENCIES: [&str; 3] = ["HUF", "JPY", "TWD"];
+
+/// Monto exacto en unidades menores (centavos) de una moneda ISO 4217.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Money {
+    pub amount_minor: i64,
+    pub currency: String,
+}
+
+/// Decimales de la unidad menor de `currency` (2 salvo las monedas sin decimales).
+pub fn minor_unit_digits(currency: &str) -> u32 {
+    if ZERO_DECIMAL_CURRENCIES.contains(&currency) { 0 } else { 2 }
+}
+
+/// Convierte un monto decimal como lo envía PayPal (`"10.50"`) a unidades menores
+/// (`1050` centavos). `None` si no es un número o trae más decimales de los que admite la moneda.
+pub fn parse_minor_units(value: &str, currency: &str) -> Option<i64> {
+    let digits = minor_unit_digits(currency);
+    let value = value.trim();
+    let (negative, value) = match value.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, value),
+    };
+    let (whole, fraction) = value.split_once('.').unwrap_or((value, ""));
+    if whole.is_empty() || fraction.len() > digits as usize
+        || !whole.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit())
+    {
+        return None;
+    }
+
+    // "10.5" -> "1050": se completa la fracción con ceros hasta los decimales de la moneda
+    let minor = format!("{}{:0<width$}", whole, fraction, width = digits as usize);
+    let amount: i64 = minor.parse().ok()?;
+    Some(if negative { -amount } else { amount })
+}
+
+/// Monto legible con su moneda: `1050, "USD"` -> `"10.50 USD"`.
+pub fn format_minor_units(amount_minor: i64, currency: &str) -> String {
+    let digits = minor_unit_digits(currency);
+    if digits == 0 {
+        return format!("{} {}", amount_minor, currency);
+    }
+    let scale = 10_i64.pow(digits);
+    let sign = if amount_minor < 0 { "-" } else { "" };
+    let abs = amount_minor.unsigned_abs();
+    format!(
+        "{}{}.{:0width$} {}",
+        sign,
+        abs / scale as u64,
+        abs % scale as u64,
+        currency,
+        width = digits as usize
+    )
+}
+
+/// Monto en unidades menores como lo espera PayPal en `value`: `1050, "USD"` -> `"10.50"`.
+pub fn minor_units_value(amount_minor: i64, currency: &str) -> String {
+    let formatted = format_minor_units(amount_minor, currency);
+    formatted.trim_end_matches(currency).trim_end().to_string()
+}
+
+/// Precio decimal (como `Course::price`) en unidades menores, redondeado al centavo.
+pub fn to_minor_units(amount: f64, currency: &str) -> i64 {
+    (amount * 10_f64.powi(minor_unit_digits(currency) as i32)).round() as i64
+}
+
+/// Inverso de `to_minor_units`: `1050, "USD"` -> `10.5`.
+pub fn from_minor_units(amount_minor: i64, currency: &str) -> f64 {
+    amount_minor as f64 / 10_f64.powi(minor_unit_digits(currency) as i32)
+}
+
+/// Aplica un descuento porcentual (0-100) redondeando a favor del comprador.
+pub fn apply_discount(amount_minor: i64, discount_percent: i32) -> i64 {
+    let percent = i64::from(discount_percent.clamp(0, 100));
+    amount_minor * (100 - percent) / 100
+}
+
+/// Reparte `total_minor` en proporción a `weights`; lo que sobra del redondeo va a la
+/// última parte, así que la suma siempre es `total_minor`. Con pesos en cero reparte por igual.
+pub fn split_minor_units(total_minor: i64, weights: &[i64]) -> Vec<i64> {
+    if weights.is_empty() {
+        return vec![];
+    }
+    let weight_sum: i64 = weights.iter().sum();
+    let mut parts: Vec<i64> = if weight_sum > 0 {
+        weights.iter().map(|w| total_minor * w / weight_sum).collect()
+    } else {
+        vec![total_minor / weights.len() as i64; weights.len()]
+    };
+    let assigned: i64 = parts.iter().sum();
+    if let Some(last) = parts.last_mut() {
+        *last += total_minor - assigned;
+    }
+    parts
+}
@@ -29,10 +29,15 @@ pub fn hash_password(password: impl Into<String>) -> Result<String, ErrorMessage
     )
 }
 
+/// Las cuentas sin contraseña (`hashed_password = None`) nunca pasan la verificación.
 pub fn verify_password(
     password: &str,
-    hashed_password: &str,
+    hashed_password: Option<&str>,
 ) -> Result<bool, ErrorMessage> {
+    let Some(hashed_password) = hashed_password else {
+        return Err(ErrorMessage::PasswordlessAccount);
+    };
+
     if password.is_empty() {
         return Err(ErrorMessage::EmptyPassword);
     }
@@ -41,6 +46,6 @@ pub fn verify_password(
         return Err(ErrorMessage::ExceededMaxPasswordLength(MAX_PASSWORD_LENGTH));
     }
 
-    Ok(Argon2::default().verify_password(password.as_bytes(), &PasswordHash::new(&hashed_password)
-        .map_err(|_| ErrorMessage::InvalidHashFormat)?).map_or(false, |_| true))
+    Ok(Argon2::default().verify_password(password.as_bytes(), &PasswordHash::new(hashed_password)
+        .map_err(|_| ErrorMessage::InvalidHashFormat)?).is_ok())
 }
\ No newline at end of file
@@ -0,0 +1,80 @@
+use actix_web::{ dev::Payload, error::InternalError, FromRequest, HttpRequest };
+use std::future::{ ready, Ready };
+use uuid::Uuid;
+
+use crate::errors::error::{ ErrorMessage, HttpError };
+
+/// Lee el segmento `name` de la ruta como `Uuid`. Un id ausente o mal formado
+/// responde siempre `400 Invalid id`, sin filtrar el error del parser.
+pub fn uuid_from_path(req: &HttpRequest, name: &str) -> Result<Uuid, actix_web::Error> {
+    req.match_info()
+        .get(name)
+        .and_then(|raw| Uuid::parse_str(raw).ok())
+        .ok_or_else(|| {
+            let message = ErrorMessage::InvalidId.to_string();
+            InternalError::from_response(message.clone(), HttpError::bad_request(message).into_http_response()).into()
+        })
+}
+
+/// Declara un extractor de id para el segmento `$segment` de la ruta.
+macro_rules! path_id {
+    ($(#[$doc:meta])* $name:ident, $segment:literal) => {
+        $(#[$doc])*
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        pub struct $name(pub Uuid);
+
+        impl FromRequest for $name {
+            type Error = actix_web::Error;
+            type Future = Ready<Result<Self, Self::Error>>;
+
+            fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+                ready(uuid_from_path(req, $segment).map($name))
+            }
+        }
+    };
+}
+
+path_id!(
+    /// Id del curso tomado de `{id}`.
+    CourseId, "id"
+);
+path_id!(
+    /// Id del usuario tomado de `{user_id}`.
+    UserId, "user_id"
+);
+path_id!(
+    /// Id de la lección tomado de `{lesson_id}`.
+    LessonId, "lesson_id"
+);
+path_id!(
+    /// Id del paquete de cursos tomado de `{bundle_id}`.
+    BundleId, "bundle_id"
+);
+path_id!(
+    /// Id del logro tomado de `{achievement_id}`.
+    AchievementId, "achievement_id"
+);
+path_id!(
+    /// Id del plan de suscripción tomado de `{plan_id}`.
+    PlanId, "plan_id"
+);
+path_id!(
+    /// Id de la suscripción tomado de `{subscription_id}`.
+    SubscriptionId, "subscription_id"
+);
+path_id!(
+    /// Id de la notificación tomado de `{notification_id}`.
+    NotificationId, "notification_id"
+);
+path_id!(
+    /// Id del aviso masivo tomado de `{broadcast_id}`.
+    BroadcastId, "broadcast_id"
+);
+path_id!(
+    /// Id del reporte tomado de `{report_id}`.
+    ReportId, "report_id"
+);
+path_id!(
+    /// Id de la invitación tomado de `{invite_id}`.
+    InviteId, "invite_id"
+);
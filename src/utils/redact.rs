@@ -0,0 +1,128 @@
+use std::sync::OnceLock;
+
+use serde_json::Value;
+
+const REDACTED: &str = "[REDACTED]";
+
+/// Campos enmascarados si no se configura `LOG_REDACT_FIELDS`
+pub const DEFAULT_REDACT_FIELDS: &[&str] = &[
+    "email",
+    "email_address",
+    "token",
+    "access_token",
+    "authorization",
+    "password",
+    "secret",
+    "card",
+    "card_number",
+    "transaction_id",
+    "payer_id",
+];
+
+static REDACT_FIELDS: OnceLock<Vec<String>> = OnceLock::new();
+
+/// Fija la lista de campos sensibles para todo el proceso. Se llama una vez al
+/// arrancar; las llamadas posteriores se ignoran.
+pub fn init(fields: Vec<String>) {
+    let fields = fields
+        .into_iter()
+        .map(|f| f.trim().to_lowercase())
+        .filter(|f| !f.is_empty())
+        .collect();
+    let _ = REDACT_FIELDS.set(fields);
+}
+
+fn fields() -> &'static [String] {
+    REDACT_FIELDS.get_or_init(|| DEFAULT_REDACT_FIELDS.iter().map(|f| f.to_string()).collect())
+}
+
+fn is_sensitive(key: &str, fields: &[String]) -> bool {
+    let key = key.to_lowercase();
+    fields.iter().any(|f| key == *f || key.ends_with(&format!("_{}", f)))
+}
+
+/// Copia del JSON con los valores de los campos sensibles enmascarados, a cualquier profundidad
+pub fn redact_json(value: &Value) -> Value {
+    redact_json_with(value, fields())
+}
+
+pub fn redact_json_with(value: &Value, fields: &[String]) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(k, v)| {
+                    let v = if is_sensitive(k, fields) {
+                        Value::String(REDACTED.to_string())
+                    } else {
+                        redact_json_with(v, fields)
+                    };
+                    (k.clone(), v)
+                })
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(|v| redact_json_with(v, fields)).collect()),
+        Value::String(s) => Value::String(redact_with(s, fields)),
+        other => other.clone(),
+    }
+}
+
+/// Enmascara texto libre (errores de SQL, cuerpos de respuesta): correos,
+/// tokens `Bearer` y pares `campo=valor` / `campo: valor` de campos sensibles.
+pub fn redact(text: &str) -> String {
+    redact_with(text, fields())
+}
+
+pub fn redact_with(text: &str, fields: &[String]) -> String {
+    if let Ok(json) = serde_json::from_str::<Value>(text)
+        && (json.is_object() || json.is_array()) {
+        return redact_json_with(&json, fields).to_string();
+    }
+
+    let mut out = Vec::new();
+    let mut mask_next = false;
+    for word in text.split(' ') {
+        if word.eq_ignore_ascii_case("bearer") {
+            mask_next = true;
+            out.push(word.to_string());
+            continue;
+        }
+        if mask_next && !word.is_empty() {
+            out.push(REDACTED.to_string());
+            mask_next = false;
+            continue;
+        }
+        out.push(redact_word(word, fields, &mut mask_next));
+    }
+    out.join(" ")
+}
+
+fn redact_word(word: &str, fields: &[String], mask_next: &mut bool) -> String {
+    // `campo=valor`, `campo:valor` o `campo:` seguido del valor en la siguiente palabra
+    for sep in ['=', ':'] {
+        if let Some((key, value)) = word.split_once(sep) {
+            let key_name = key.trim_matches(|c: char| !c.is_alphanumeric() && c != '_');
+            if is_sensitive(key_name, fields) {
+                if value.is_empty() {
+                    *mask_next = true;
+                    return word.to_string();
+                }
+                return format!("{}{}{}", key, sep, REDACTED);
+            }
+        }
+    }
+
+    // Correos sueltos, p. ej. en el detalle de una violación de unicidad
+    if is_sensitive("email", fields) && looks_like_email(word) {
+        return REDACTED.to_string();
+    }
+
+    word.to_string()
+}
+
+fn looks_like_email(word: &str) -> bool {
+    let word = word.trim_matches(|c: char| !c.is_alphanumeric());
+    match word.split_once('@') {
+        Some((user, domain)) => !user.is_empty() && domain.contains('.') && !domain.starts_with('.'),
+        None => false,
+    }
+}
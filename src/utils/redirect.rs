@@ -0,0 +1,22 @@
+use reqwest::Url;
+
+/// Origen (`esquema://host[:puerto]`) de una URL absoluta, como lo compara `safe_redirect`.
+pub fn url_origin(url: &str) -> Option<String> {
+    let parsed = Url::parse(url).ok()?;
+    if !matches!(parsed.scheme(), "http" | "https") || parsed.host_str().is_none() {
+        return None;
+    }
+    Some(parsed.origin().ascii_serialization())
+}
+
+/// Destino de una redirección pedido por el cliente: solo se acepta una URL absoluta
+/// `http(s)` cuyo origen esté en `allowed_origins`; cualquier otra cosa (otro dominio,
+/// rutas relativas, `//evil.com`, esquemas como `javascript:`) se cambia por `fallback`.
+pub fn safe_redirect(target: Option<&str>, allowed_origins: &[String], fallback: &str) -> String {
+    target
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .filter(|t| url_origin(t).is_some_and(|origin| allowed_origins.contains(&origin)))
+        .unwrap_or(fallback)
+        .to_string()
+}
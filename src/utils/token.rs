@@ -44,4 +44,12 @@ pub fn decode_token<T: Into<String>>(token: T, secret: DecodingKey) -> Result<To
         &secret,
         &Validation::new(Algorithm::RS256),
     )?.claims)
-}
\ No newline at end of file
+}
+/// Hash SHA-256 en hexadecimal para guardar tokens de un solo uso sin poder
+/// reconstruirlos. A diferencia de Argon2 es determinista y permite buscarlos.
+pub fn hash_token(token: &str) -> String {
+    openssl::sha::sha256(token.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}